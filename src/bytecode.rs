@@ -1,3 +1,6 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, LowerExp};
+
 #[derive(Debug, Clone)]
 pub enum Instruction {
     Nop,
@@ -29,7 +32,8 @@ pub enum Instruction {
     And(PrimitiveType),
     Or(PrimitiveType),
     Xor(PrimitiveType),
-    IInc(usize, i8),
+    /// The local to increment and the amount, which only the wide form can hold beyond an i8.
+    IInc(usize, i16),
     Convert(PrimitiveType, PrimitiveType),
     LCmp,
     FCmpL,
@@ -41,8 +45,12 @@ pub enum Instruction {
     Goto(usize),
     Jsr(usize),
     Ret(usize),
-    // TableSwitch(usize, usize, usize), // TODO: Properly implement this.
-    // LookupSwitch(usize, usize, usize),
+    /// Jump to the target at the index of the int on the stack minus the low value, or to the
+    /// default target when it is out of range: default, low, targets.
+    TableSwitch(usize, i32, Vec<usize>),
+    /// Jump to the target paired with the int on the stack, or to the default target if there is
+    /// none: default, (value, target) pairs sorted by value.
+    LookupSwitch(usize, Vec<(i32, usize)>),
     Return(PrimitiveType),
     GetStatic(usize),
     PutStatic(usize),
@@ -51,24 +59,333 @@ pub enum Instruction {
     InvokeVirtual(usize),
     InvokeSpecial(usize),
     InvokeStatic(usize),
-    InvokeInterface(usize), // TODO: 4: indexbyte1, indexbyte2, count, 0
-    InvokeDynamic(usize),   // TODO: 4: indexbyte1, indexbyte2, 0, 0
+    InvokeInterface(usize),
+    InvokeDynamic(usize),
     New(usize),
     NewArray(PrimitiveType),
-    ANewArray(PrimitiveType), // TODO: Perhaps this should be removed?
+    /// Create an array of references whose element class is at the constant pool index.
+    ANewArray(usize),
     ArrayLength,
     AThrow,
     CheckCast(usize),
     InstanceOf(usize),
     MonitorEnter,
     MonitorExit,
-    // Wide(usize),
-    // MultiANewArray(usize, usize),
+    MultiANewArray(usize, usize),
     IfNull(usize),
     IfNonNull(usize),
     Breakpoint,
 }
 
+impl Instruction {
+    /// The name of the kind of instruction, without its operands.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Nop => "Nop",
+            Instruction::AConstNull => "AConstNull",
+            Instruction::Const(..) => "Const",
+            Instruction::LoadConst(..) => "LoadConst",
+            Instruction::Load(..) => "Load",
+            Instruction::ALoad(..) => "ALoad",
+            Instruction::Store(..) => "Store",
+            Instruction::AStore(..) => "AStore",
+            Instruction::Pop => "Pop",
+            Instruction::Pop2 => "Pop2",
+            Instruction::Dup => "Dup",
+            Instruction::DupX1 => "DupX1",
+            Instruction::DupX2 => "DupX2",
+            Instruction::Dup2 => "Dup2",
+            Instruction::Dup2X1 => "Dup2X1",
+            Instruction::Dup2X2 => "Dup2X2",
+            Instruction::Swap => "Swap",
+            Instruction::Add(..) => "Add",
+            Instruction::Sub(..) => "Sub",
+            Instruction::Mul(..) => "Mul",
+            Instruction::Div(..) => "Div",
+            Instruction::Rem(..) => "Rem",
+            Instruction::Neg(..) => "Neg",
+            Instruction::Shl(..) => "Shl",
+            Instruction::Shr(..) => "Shr",
+            Instruction::UShr(..) => "UShr",
+            Instruction::And(..) => "And",
+            Instruction::Or(..) => "Or",
+            Instruction::Xor(..) => "Xor",
+            Instruction::IInc(..) => "IInc",
+            Instruction::Convert(..) => "Convert",
+            Instruction::LCmp => "LCmp",
+            Instruction::FCmpL => "FCmpL",
+            Instruction::FCmpG => "FCmpG",
+            Instruction::DCmpL => "DCmpL",
+            Instruction::DCmpG => "DCmpG",
+            Instruction::If(..) => "If",
+            Instruction::IfICmp(..) => "IfICmp",
+            Instruction::Goto(..) => "Goto",
+            Instruction::Jsr(..) => "Jsr",
+            Instruction::Ret(..) => "Ret",
+            Instruction::TableSwitch(..) => "TableSwitch",
+            Instruction::LookupSwitch(..) => "LookupSwitch",
+            Instruction::Return(..) => "Return",
+            Instruction::GetStatic(..) => "GetStatic",
+            Instruction::PutStatic(..) => "PutStatic",
+            Instruction::GetField(..) => "GetField",
+            Instruction::PutField(..) => "PutField",
+            Instruction::InvokeVirtual(..) => "InvokeVirtual",
+            Instruction::InvokeSpecial(..) => "InvokeSpecial",
+            Instruction::InvokeStatic(..) => "InvokeStatic",
+            Instruction::InvokeInterface(..) => "InvokeInterface",
+            Instruction::InvokeDynamic(..) => "InvokeDynamic",
+            Instruction::New(..) => "New",
+            Instruction::NewArray(..) => "NewArray",
+            Instruction::ANewArray(..) => "ANewArray",
+            Instruction::ArrayLength => "ArrayLength",
+            Instruction::AThrow => "AThrow",
+            Instruction::CheckCast(..) => "CheckCast",
+            Instruction::InstanceOf(..) => "InstanceOf",
+            Instruction::MonitorEnter => "MonitorEnter",
+            Instruction::MonitorExit => "MonitorExit",
+            Instruction::MultiANewArray(..) => "MultiANewArray",
+            Instruction::IfNull(..) => "IfNull",
+            Instruction::IfNonNull(..) => "IfNonNull",
+            Instruction::Breakpoint => "Breakpoint",
+        }
+    }
+
+    /// The instruction indices jumped to by a branch instruction, which is empty for any other
+    /// instruction. Switches have several targets.
+    pub fn branch_targets(&self) -> Vec<usize> {
+        match self {
+            Instruction::If(target, _)
+            | Instruction::IfICmp(target, _)
+            | Instruction::Goto(target)
+            | Instruction::Jsr(target)
+            | Instruction::IfNull(target)
+            | Instruction::IfNonNull(target) => vec![*target],
+            Instruction::TableSwitch(default, _, targets) => {
+                let mut all_targets = vec![*default];
+                all_targets.extend(targets);
+                all_targets
+            }
+            Instruction::LookupSwitch(default, pairs) => {
+                let mut all_targets = vec![*default];
+                all_targets.extend(pairs.iter().map(|(_, target)| *target));
+                all_targets
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn branch_targets_mut(&mut self) -> Vec<&mut usize> {
+        match self {
+            Instruction::If(target, _)
+            | Instruction::IfICmp(target, _)
+            | Instruction::Goto(target)
+            | Instruction::Jsr(target)
+            | Instruction::IfNull(target)
+            | Instruction::IfNonNull(target) => vec![target],
+            Instruction::TableSwitch(default, _, targets) => {
+                let mut all_targets = vec![default];
+                all_targets.extend(targets.iter_mut());
+                all_targets
+            }
+            Instruction::LookupSwitch(default, pairs) => {
+                let mut all_targets = vec![default];
+                all_targets.extend(pairs.iter_mut().map(|(_, target)| target));
+                all_targets
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The letter starting the mnemonics of instructions that operate on values of a type on the
+/// stack, where the types smaller than int are ints.
+fn stack_prefix(primitive_type: &PrimitiveType) -> char {
+    match primitive_type {
+        PrimitiveType::Long => 'l',
+        PrimitiveType::Float => 'f',
+        PrimitiveType::Double => 'd',
+        PrimitiveType::Reference | PrimitiveType::Null => 'a',
+        _ => 'i',
+    }
+}
+
+/// The letter starting the mnemonics of array loads and stores, which do distinguish the types
+/// smaller than int. Booleans are stored in byte arrays.
+fn array_prefix(primitive_type: &PrimitiveType) -> char {
+    match primitive_type {
+        PrimitiveType::Boolean | PrimitiveType::Byte => 'b',
+        PrimitiveType::Char => 'c',
+        PrimitiveType::Short => 's',
+        primitive_type => stack_prefix(primitive_type),
+    }
+}
+
+/// The suffix of conditional branch mnemonics such as `ifge`.
+fn condition_suffix(comparison: &Comparison) -> &'static str {
+    match comparison {
+        Comparison::Equal => "eq",
+        Comparison::NotEqual => "ne",
+        Comparison::LessThan => "lt",
+        Comparison::GreaterThan => "gt",
+        Comparison::LessThanOrEqual => "le",
+        Comparison::GreaterThanOrEqual => "ge",
+    }
+}
+
+/// The name `newarray` uses for an element type.
+fn array_type_name(primitive_type: &PrimitiveType) -> &'static str {
+    match primitive_type {
+        PrimitiveType::Boolean => "boolean",
+        PrimitiveType::Byte => "byte",
+        PrimitiveType::Char => "char",
+        PrimitiveType::Short => "short",
+        PrimitiveType::Int => "int",
+        PrimitiveType::Long => "long",
+        PrimitiveType::Float => "float",
+        PrimitiveType::Double => "double",
+        PrimitiveType::Reference | PrimitiveType::Null => "java/lang/Object",
+    }
+}
+
+impl Display for Instruction {
+    /// Write the instruction as the JVM mnemonic javap would show for it, followed by its
+    /// operands. Constant pool indices are prefixed with `#`, and branch targets are instruction
+    /// indices rather than byte offsets.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Nop => write!(f, "nop"),
+            Instruction::AConstNull | Instruction::Const(Primitive::Null) => {
+                write!(f, "aconst_null")
+            }
+            Instruction::Const(Primitive::Long(value @ 0..=1)) => write!(f, "lconst_{}", value),
+            Instruction::Const(Primitive::Long(value)) => write!(f, "ldc2_w {}", value),
+            // Negative zero equals zero, but only positive zero has a constant instruction
+            Instruction::Const(Primitive::Float(value))
+                if value.to_bits() == 0 || *value == 1.0 || *value == 2.0 =>
+            {
+                write!(f, "fconst_{}", value)
+            }
+            Instruction::Const(Primitive::Float(value)) => write!(f, "ldc {:?}", value),
+            Instruction::Const(Primitive::Double(value))
+                if value.to_bits() == 0 || *value == 1.0 =>
+            {
+                write!(f, "dconst_{}", value)
+            }
+            Instruction::Const(Primitive::Double(value)) => write!(f, "ldc2_w {:?}", value),
+            Instruction::Const(value) => match value.clone().promoted() {
+                Primitive::Int(-1) => write!(f, "iconst_m1"),
+                Primitive::Int(value @ 0..=5) => write!(f, "iconst_{}", value),
+                Primitive::Int(value @ -128..=127) => write!(f, "bipush {}", value),
+                Primitive::Int(value @ -32768..=32767) => write!(f, "sipush {}", value),
+                value => write!(f, "ldc {}", value.pretty_print()),
+            },
+            Instruction::LoadConst(index) => write!(f, "ldc #{}", index),
+            Instruction::Load(index @ 0..=3, primitive_type) => {
+                write!(f, "{}load_{}", stack_prefix(primitive_type), index)
+            }
+            Instruction::Load(index, primitive_type) => {
+                write!(f, "{}load {}", stack_prefix(primitive_type), index)
+            }
+            Instruction::Store(index @ 0..=3, primitive_type) => {
+                write!(f, "{}store_{}", stack_prefix(primitive_type), index)
+            }
+            Instruction::Store(index, primitive_type) => {
+                write!(f, "{}store {}", stack_prefix(primitive_type), index)
+            }
+            Instruction::ALoad(primitive_type) => {
+                write!(f, "{}aload", array_prefix(primitive_type))
+            }
+            Instruction::AStore(primitive_type) => {
+                write!(f, "{}astore", array_prefix(primitive_type))
+            }
+            Instruction::Pop => write!(f, "pop"),
+            Instruction::Pop2 => write!(f, "pop2"),
+            Instruction::Dup => write!(f, "dup"),
+            Instruction::DupX1 => write!(f, "dup_x1"),
+            Instruction::DupX2 => write!(f, "dup_x2"),
+            Instruction::Dup2 => write!(f, "dup2"),
+            Instruction::Dup2X1 => write!(f, "dup2_x1"),
+            Instruction::Dup2X2 => write!(f, "dup2_x2"),
+            Instruction::Swap => write!(f, "swap"),
+            Instruction::Add(primitive_type) => write!(f, "{}add", stack_prefix(primitive_type)),
+            Instruction::Sub(primitive_type) => write!(f, "{}sub", stack_prefix(primitive_type)),
+            Instruction::Mul(primitive_type) => write!(f, "{}mul", stack_prefix(primitive_type)),
+            Instruction::Div(primitive_type) => write!(f, "{}div", stack_prefix(primitive_type)),
+            Instruction::Rem(primitive_type) => write!(f, "{}rem", stack_prefix(primitive_type)),
+            Instruction::Neg(primitive_type) => write!(f, "{}neg", stack_prefix(primitive_type)),
+            Instruction::Shl(primitive_type) => write!(f, "{}shl", stack_prefix(primitive_type)),
+            Instruction::Shr(primitive_type) => write!(f, "{}shr", stack_prefix(primitive_type)),
+            Instruction::UShr(primitive_type) => write!(f, "{}ushr", stack_prefix(primitive_type)),
+            Instruction::And(primitive_type) => write!(f, "{}and", stack_prefix(primitive_type)),
+            Instruction::Or(primitive_type) => write!(f, "{}or", stack_prefix(primitive_type)),
+            Instruction::Xor(primitive_type) => write!(f, "{}xor", stack_prefix(primitive_type)),
+            Instruction::IInc(index, value) => write!(f, "iinc {}, {}", index, value),
+            Instruction::Convert(from, to) => {
+                write!(f, "{}2{}", stack_prefix(from), array_prefix(to))
+            }
+            Instruction::LCmp => write!(f, "lcmp"),
+            Instruction::FCmpL => write!(f, "fcmpl"),
+            Instruction::FCmpG => write!(f, "fcmpg"),
+            Instruction::DCmpL => write!(f, "dcmpl"),
+            Instruction::DCmpG => write!(f, "dcmpg"),
+            Instruction::If(target, comparison) => {
+                write!(f, "if{} {}", condition_suffix(comparison), target)
+            }
+            Instruction::IfICmp(target, comparison) => {
+                write!(f, "if_icmp{} {}", condition_suffix(comparison), target)
+            }
+            Instruction::Goto(target) => write!(f, "goto {}", target),
+            Instruction::Jsr(target) => write!(f, "jsr {}", target),
+            Instruction::Ret(index) => write!(f, "ret {}", index),
+            Instruction::TableSwitch(default, low, targets) => {
+                write!(f, "tableswitch {{ ")?;
+                for (value, target) in (*low..).zip(targets) {
+                    write!(f, "{}: {}, ", value, target)?;
+                }
+                write!(f, "default: {} }}", default)
+            }
+            Instruction::LookupSwitch(default, pairs) => {
+                write!(f, "lookupswitch {{ ")?;
+                for (value, target) in pairs {
+                    write!(f, "{}: {}, ", value, target)?;
+                }
+                write!(f, "default: {} }}", default)
+            }
+            Instruction::Return(PrimitiveType::Null) => write!(f, "return"),
+            Instruction::Return(primitive_type) => {
+                write!(f, "{}return", stack_prefix(primitive_type))
+            }
+            Instruction::GetStatic(index) => write!(f, "getstatic #{}", index),
+            Instruction::PutStatic(index) => write!(f, "putstatic #{}", index),
+            Instruction::GetField(index) => write!(f, "getfield #{}", index),
+            Instruction::PutField(index) => write!(f, "putfield #{}", index),
+            Instruction::InvokeVirtual(index) => write!(f, "invokevirtual #{}", index),
+            Instruction::InvokeSpecial(index) => write!(f, "invokespecial #{}", index),
+            Instruction::InvokeStatic(index) => write!(f, "invokestatic #{}", index),
+            Instruction::InvokeInterface(index) => write!(f, "invokeinterface #{}", index),
+            Instruction::InvokeDynamic(index) => write!(f, "invokedynamic #{}", index),
+            Instruction::New(index) => write!(f, "new #{}", index),
+            Instruction::NewArray(primitive_type) => {
+                write!(f, "newarray {}", array_type_name(primitive_type))
+            }
+            Instruction::ANewArray(index) => write!(f, "anewarray #{}", index),
+            Instruction::ArrayLength => write!(f, "arraylength"),
+            Instruction::AThrow => write!(f, "athrow"),
+            Instruction::CheckCast(index) => write!(f, "checkcast #{}", index),
+            Instruction::InstanceOf(index) => write!(f, "instanceof #{}", index),
+            Instruction::MonitorEnter => write!(f, "monitorenter"),
+            Instruction::MonitorExit => write!(f, "monitorexit"),
+            Instruction::MultiANewArray(index, dimensions) => {
+                write!(f, "multianewarray #{}, {}", index, dimensions)
+            }
+            Instruction::IfNull(target) => write!(f, "ifnull {}", target),
+            Instruction::IfNonNull(target) => write!(f, "ifnonnull {}", target),
+            Instruction::Breakpoint => write!(f, "breakpoint"),
+        }
+    }
+}
+
+#[allow(dead_code)]
 pub trait InstructionVec {
     fn pretty_print(&self);
 }
@@ -76,7 +393,7 @@ pub trait InstructionVec {
 impl InstructionVec for Vec<Instruction> {
     fn pretty_print(&self) {
         for (i, instruction) in self.iter().enumerate() {
-            println!("{:3} | {:?}", i, instruction);
+            println!("{:3} | {}", i, instruction);
         }
     }
 }
@@ -92,6 +409,19 @@ pub enum Comparison {
 }
 
 impl Comparison {
+    /// The comparison performed by a Java relational or equality operator, such as `<=`.
+    pub fn from_operator(operator: &str) -> Option<Comparison> {
+        match operator {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            ">" => Some(Comparison::GreaterThan),
+            ">=" => Some(Comparison::GreaterThanOrEqual),
+            "<" => Some(Comparison::LessThan),
+            "<=" => Some(Comparison::LessThanOrEqual),
+            _ => None,
+        }
+    }
+
     pub fn negate(&self) -> Comparison {
         match self {
             Comparison::Equal => Comparison::NotEqual,
@@ -102,6 +432,24 @@ impl Comparison {
             Comparison::GreaterThanOrEqual => Comparison::LessThan,
         }
     }
+
+    /// Whether the comparison holds for two values with the passed ordering. Values are unordered
+    /// when either of them is NaN, in which case only `NotEqual` holds.
+    pub fn holds_for(&self, ordering: Option<Ordering>) -> bool {
+        let ordering = match ordering {
+            Some(ordering) => ordering,
+            None => return matches!(self, Comparison::NotEqual),
+        };
+
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::LessThan => ordering == Ordering::Less,
+            Comparison::GreaterThan => ordering == Ordering::Greater,
+            Comparison::LessThanOrEqual => ordering != Ordering::Greater,
+            Comparison::GreaterThanOrEqual => ordering != Ordering::Less,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -135,7 +483,7 @@ pub enum PrimitiveType {
     Boolean, // TODO: java representation of boolean is just a byte (0 or 1)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Primitive {
     Null,
     Byte(i8),
@@ -146,19 +494,35 @@ pub enum Primitive {
     Float(f32),
     Double(f64),
     Reference(usize),
+    /// The instruction index `jsr` pushes for `ret` to return to. It isn't a reference, so the
+    /// garbage collector leaves it alone.
+    ReturnAddress(usize),
 }
 
 impl Primitive {
+    /// Widen a byte, short or char to an int, as the JVM does before any arithmetic or comparison
+    /// since it has no instructions that operate on the smaller types.
+    pub fn promoted(self) -> Primitive {
+        match self {
+            Primitive::Byte(b) => Primitive::Int(b as i32),
+            Primitive::Short(s) => Primitive::Int(s as i32),
+            Primitive::Char(c) => Primitive::Int(c as i32),
+            value => value,
+        }
+    }
+
     pub fn eval(self, o: Operator) -> Result<Primitive, String> {
+        let value = self.promoted();
+
         Ok(match o {
-            Operator::Neg => match self {
+            Operator::Neg => match value {
                 Primitive::Int(i) => Primitive::Int(-i),
                 Primitive::Long(l) => Primitive::Long(-l),
                 Primitive::Float(f) => Primitive::Float(-f),
                 Primitive::Double(d) => Primitive::Double(-d),
                 _ => return Err(String::from("Could not negate passed value")),
             },
-            Operator::Convert(source, destination) => match (self, source) {
+            Operator::Convert(source, destination) => match (value, source) {
                 (Primitive::Int(i), PrimitiveType::Int) => match destination {
                     PrimitiveType::Byte => Primitive::Byte(i as i8),
                     PrimitiveType::Short => Primitive::Short(i as i16),
@@ -174,6 +538,8 @@ impl Primitive {
                     PrimitiveType::Double => Primitive::Double(l as f64),
                     _ => return Err(String::from("Could not convert long to passed type")),
                 },
+                // Casting a float to an integer with as rounds towards zero, turns NaN into 0 and
+                // saturates values out of range, exactly like f2i, f2l, d2i and d2l do
                 (Primitive::Float(f), PrimitiveType::Float) => match destination {
                     PrimitiveType::Int => Primitive::Int(f as i32),
                     PrimitiveType::Long => Primitive::Long(f as i64),
@@ -197,38 +563,52 @@ impl Primitive {
     }
 
     pub fn eval2(a: Primitive, b: Primitive, o: Operator) -> Result<Primitive, String> {
+        let (a, b) = (a.promoted(), b.promoted());
+
         Ok(match o {
+            // Integer arithmetic wraps around on overflow like it does in java
             Operator::Add => match (a, b) {
-                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i + j),
-                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l + j),
+                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i.wrapping_add(j)),
+                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l.wrapping_add(j)),
                 (Primitive::Float(f), Primitive::Float(j)) => Primitive::Float(f + j),
                 (Primitive::Double(d), Primitive::Double(j)) => Primitive::Double(d + j),
                 _ => return Err(String::from("Could not add passed values")),
             },
             Operator::Sub => match (a, b) {
-                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i - j),
-                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l - j),
+                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i.wrapping_sub(j)),
+                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l.wrapping_sub(j)),
                 (Primitive::Float(f), Primitive::Float(j)) => Primitive::Float(f - j),
                 (Primitive::Double(d), Primitive::Double(j)) => Primitive::Double(d - j),
                 _ => return Err(String::from("Could not subtract passed values")),
             },
             Operator::Mul => match (a, b) {
-                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i * j),
-                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l * j),
+                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i.wrapping_mul(j)),
+                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l.wrapping_mul(j)),
                 (Primitive::Float(f), Primitive::Float(j)) => Primitive::Float(f * j),
                 (Primitive::Double(d), Primitive::Double(j)) => Primitive::Double(d * j),
                 _ => return Err(String::from("Could not multiply passed values")),
             },
             Operator::Div => match (a, b) {
-                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i / j),
-                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l / j),
+                (Primitive::Int(_), Primitive::Int(0))
+                | (Primitive::Long(_), Primitive::Long(0)) => {
+                    return Err(String::from("java/lang/ArithmeticException: / by zero"))
+                }
+                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i.wrapping_div(j)),
+                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l.wrapping_div(j)),
                 (Primitive::Float(f), Primitive::Float(j)) => Primitive::Float(f / j),
                 (Primitive::Double(d), Primitive::Double(j)) => Primitive::Double(d / j),
                 _ => return Err(String::from("Could not divide passed values")),
             },
             Operator::Rem => match (a, b) {
-                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i % j),
-                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l % j),
+                (Primitive::Int(_), Primitive::Int(0))
+                | (Primitive::Long(_), Primitive::Long(0)) => {
+                    return Err(String::from("java/lang/ArithmeticException: / by zero"))
+                }
+                (Primitive::Int(i), Primitive::Int(j)) => Primitive::Int(i.wrapping_rem(j)),
+                (Primitive::Long(l), Primitive::Long(j)) => Primitive::Long(l.wrapping_rem(j)),
+                // Floating point remainders truncate like fmod rather than rounding like
+                // Math.IEEEremainder, so the result takes the dividend's sign, which is what Rust's
+                // % does too
                 (Primitive::Float(f), Primitive::Float(j)) => Primitive::Float(f % j),
                 (Primitive::Double(d), Primitive::Double(j)) => Primitive::Double(d % j),
                 _ => return Err(String::from("Could not modulo passed values")),
@@ -286,28 +666,15 @@ impl Primitive {
                 Comparison::GreaterThan => x > 0,
                 Comparison::LessThanOrEqual => x <= 0,
             },
-            Primitive::Float(x) => match comparator {
-                Comparison::Equal => x == 0.0,
-                Comparison::NotEqual => x != 0.0,
-                Comparison::LessThan => x < 0.0,
-                Comparison::GreaterThanOrEqual => x >= 0.0,
-                Comparison::GreaterThan => x > 0.0,
-                Comparison::LessThanOrEqual => x <= 0.0,
-            },
-            Primitive::Double(x) => match comparator {
-                Comparison::Equal => x == 0.0,
-                Comparison::NotEqual => x != 0.0,
-                Comparison::LessThan => x < 0.0,
-                Comparison::GreaterThanOrEqual => x >= 0.0,
-                Comparison::GreaterThan => x > 0.0,
-                Comparison::LessThanOrEqual => x <= 0.0,
-            },
+            // NaN is unordered, so it is never equal to, less than or greater than zero
+            Primitive::Float(x) => comparator.holds_for(x.partial_cmp(&0.0)),
+            Primitive::Double(x) => comparator.holds_for(x.partial_cmp(&0.0)),
             _ => return Err(String::from("Could not compare passed value to zero")),
         })
     }
 
     pub fn integer_compare(self, other: Primitive, comparator: Comparison) -> Result<bool, String> {
-        Ok(match (self, other) {
+        Ok(match (self.promoted(), other.promoted()) {
             (Primitive::Int(x), Primitive::Int(y)) => match comparator {
                 Comparison::Equal => x == y,
                 Comparison::NotEqual => x != y,
@@ -316,6 +683,15 @@ impl Primitive {
                 Comparison::GreaterThan => x > y,
                 Comparison::LessThanOrEqual => x <= y,
             },
+            // if_acmpeq and if_acmpne compare references by identity
+            (
+                x @ (Primitive::Reference(_) | Primitive::Null),
+                y @ (Primitive::Reference(_) | Primitive::Null),
+            ) => match comparator {
+                Comparison::Equal => x == y,
+                Comparison::NotEqual => x != y,
+                _ => return Err(String::from("References can only be compared for equality")),
+            },
             _ => {
                 return Err(String::from(
                     "Could not perform integer compare on passed values",
@@ -324,6 +700,18 @@ impl Primitive {
         })
     }
 
+    /// The value a field or array element of the given type descriptor holds before it's assigned.
+    pub fn default_for_descriptor(descriptor: &str) -> Primitive {
+        match descriptor.chars().next() {
+            Some('J') => Primitive::Long(0),
+            Some('F') => Primitive::Float(0.0),
+            Some('D') => Primitive::Double(0.0),
+            // Booleans, bytes, chars and shorts are all represented as ints
+            Some('Z' | 'B' | 'C' | 'S' | 'I') => Primitive::Int(0),
+            _ => Primitive::Null,
+        }
+    }
+
     pub fn is_wide(&self) -> bool {
         matches!(self, Primitive::Long(_) | Primitive::Double(_))
     }
@@ -351,13 +739,44 @@ impl Primitive {
             Primitive::Char(x) => x.to_string(),
             Primitive::Int(x) => x.to_string(),
             Primitive::Long(x) => x.to_string(),
-            Primitive::Float(x) => x.to_string(),
-            Primitive::Double(x) => x.to_string(),
-            Primitive::Reference(x) => x.to_string(),
+            Primitive::Float(x) => java_float_string(*x),
+            Primitive::Double(x) => java_float_string(*x),
+            Primitive::Reference(x) | Primitive::ReturnAddress(x) => x.to_string(),
         }
     }
 }
 
+/// Format a floating point value the way java's `Double.toString` does, e.g. `4.0` or `1.0E10`.
+fn java_float_string<T: Into<f64> + Display + LowerExp + Copy>(value: T) -> String {
+    let x: f64 = value.into();
+
+    if x.is_nan() {
+        return String::from("NaN");
+    }
+
+    if x.is_infinite() {
+        return String::from(if x > 0.0 { "Infinity" } else { "-Infinity" });
+    }
+
+    if x == 0.0 || (1e-3..1e7).contains(&x.abs()) {
+        let text = value.to_string();
+        return if text.contains('.') {
+            text
+        } else {
+            format!("{}.0", text)
+        };
+    }
+
+    let text = format!("{:e}", value);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+
+    if mantissa.contains('.') {
+        format!("{}E{}", mantissa, exponent)
+    } else {
+        format!("{}.0E{}", mantissa, exponent)
+    }
+}
+
 impl PrimitiveType {
     pub fn as_letter(&self) -> char {
         match self {
@@ -374,6 +793,81 @@ impl PrimitiveType {
         }
     }
 
+    /// The descriptor of the type. The class of a reference isn't known, so it is described as an
+    /// Object.
+    pub fn as_descriptor(&self) -> String {
+        match self {
+            PrimitiveType::Reference => String::from("Ljava/lang/Object;"),
+            primitive_type => primitive_type.as_letter().to_string(),
+        }
+    }
+
+    /// The number of local variable or operand stack slots a value of this type takes up.
+    pub fn slot_count(&self) -> usize {
+        if self.is_category_two() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether this is a long or double, which take up two slots instead of one.
+    pub fn is_category_two(&self) -> bool {
+        matches!(self, PrimitiveType::Long | PrimitiveType::Double)
+    }
+
+    /// The type a value described by the passed field descriptor is held as, with classes and
+    /// arrays being references.
+    pub fn from_descriptor(descriptor: &str) -> PrimitiveType {
+        match descriptor.chars().next() {
+            Some('Z') => PrimitiveType::Boolean,
+            Some('B') => PrimitiveType::Byte,
+            Some('S') => PrimitiveType::Short,
+            Some('C') => PrimitiveType::Char,
+            Some('I') => PrimitiveType::Int,
+            Some('J') => PrimitiveType::Long,
+            Some('F') => PrimitiveType::Float,
+            Some('D') => PrimitiveType::Double,
+            _ => PrimitiveType::Reference,
+        }
+    }
+
+    /// Narrow an int to the range of this type, e.g. truncating it when storing into a `byte[]`.
+    /// Other values are left as they are, so they should be checked with `accepts` first.
+    pub fn narrow(&self, value: Primitive) -> Primitive {
+        match (self, value) {
+            (PrimitiveType::Boolean, Primitive::Int(value)) => Primitive::Int(value & 1),
+            (PrimitiveType::Byte, Primitive::Int(value)) => Primitive::Int(value as i8 as i32),
+            (PrimitiveType::Short, Primitive::Int(value)) => Primitive::Int(value as i16 as i32),
+            (PrimitiveType::Char, Primitive::Int(value)) => Primitive::Int(value as u16 as i32),
+            (_, value) => value,
+        }
+    }
+
+    /// Whether a value can be held in a local or on the stack as this type. Booleans, bytes, chars
+    /// and shorts are held as ints, and null is a reference.
+    pub fn accepts(&self, value: &Primitive) -> bool {
+        match self {
+            PrimitiveType::Boolean
+            | PrimitiveType::Byte
+            | PrimitiveType::Short
+            | PrimitiveType::Char
+            | PrimitiveType::Int => matches!(
+                value,
+                Primitive::Int(_) | Primitive::Byte(_) | Primitive::Short(_) | Primitive::Char(_)
+            ),
+            PrimitiveType::Long => matches!(value, Primitive::Long(_)),
+            PrimitiveType::Float => matches!(value, Primitive::Float(_)),
+            PrimitiveType::Double => matches!(value, Primitive::Double(_)),
+            // astore also stores the return addresses pushed by jsr
+            PrimitiveType::Reference => matches!(
+                value,
+                Primitive::Reference(_) | Primitive::Null | Primitive::ReturnAddress(_)
+            ),
+            PrimitiveType::Null => true,
+        }
+    }
+
     pub fn matches(&self, other: &PrimitiveType) -> bool {
         matches!(
             (self, other),
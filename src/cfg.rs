@@ -0,0 +1,132 @@
+//! Splits methods into basic blocks, so that their control flow can be drawn with Graphviz.
+use crate::jvm::Method;
+use crate::stack_map::successors;
+use std::fmt::Write;
+
+/// A run of instructions which can only be entered at its first instruction and only left after
+/// its last one, apart from by throwing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// The index after the last instruction of the block.
+    pub end: usize,
+    /// The blocks that can run after this one, by falling through or branching.
+    pub successors: Vec<usize>,
+    /// The blocks which handle exceptions thrown by this one.
+    pub handlers: Vec<usize>,
+}
+
+/// Split a method into basic blocks, which start at the method's entry, at branch targets and
+/// handlers, and after any instruction that doesn't always continue to the next one.
+pub fn basic_blocks(method: &Method) -> Vec<BasicBlock> {
+    let instructions = &method.instructions;
+    let mut is_leader = vec![false; instructions.len() + 1];
+
+    if !instructions.is_empty() {
+        is_leader[0] = true;
+    }
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let next = successors(index, instruction);
+
+        if next != [index + 1] {
+            is_leader[index + 1] = true;
+        }
+
+        for target in instruction.branch_targets() {
+            if let Some(is_leader) = is_leader.get_mut(target) {
+                *is_leader = true;
+            }
+        }
+    }
+
+    for entry in &method.exception_table {
+        for pc in [entry.start_pc, entry.end_pc, entry.handler_pc] {
+            if let Some(is_leader) = is_leader.get_mut(pc) {
+                *is_leader = true;
+            }
+        }
+    }
+
+    let starts = (0..instructions.len())
+        .filter(|index| is_leader[*index])
+        .collect::<Vec<_>>();
+    let block_of = |pc: usize| starts.iter().rposition(|start| *start <= pc);
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(block, start)| {
+            let end = starts.get(block + 1).copied().unwrap_or(instructions.len());
+            let last = end - 1;
+
+            let mut block_successors = Vec::new();
+            for successor in successors(last, &instructions[last]) {
+                if successor < instructions.len() {
+                    let successor = block_of(successor).unwrap();
+                    if !block_successors.contains(&successor) {
+                        block_successors.push(successor);
+                    }
+                }
+            }
+
+            let mut handlers = Vec::new();
+            for entry in &method.exception_table {
+                // Handler regions start and end at block boundaries
+                if entry.start_pc <= *start && end <= entry.end_pc {
+                    if let Some(handler) = block_of(entry.handler_pc) {
+                        if !handlers.contains(&handler) {
+                            handlers.push(handler);
+                        }
+                    }
+                }
+            }
+
+            BasicBlock {
+                start: *start,
+                end,
+                successors: block_successors,
+                handlers,
+            }
+        })
+        .collect()
+}
+
+/// Draw the control flow graph of a method in Graphviz's DOT language. Each basic block is a node
+/// listing its instructions, with solid edges for falling through or branching and dashed edges
+/// to exception handlers.
+pub fn cfg_dot(method: &Method) -> String {
+    let blocks = basic_blocks(method);
+    let mut dot = String::from("digraph {\n    node [shape=box, fontname=\"monospace\"];\n");
+
+    for (index, block) in blocks.iter().enumerate() {
+        let mut label = String::new();
+
+        for pc in block.start..block.end {
+            let text = format!("{}: {}", pc, method.instructions[pc]);
+            label.push_str(&text.replace('\\', "\\\\").replace('"', "\\\""));
+            // Left-align each line
+            label.push_str("\\l");
+        }
+
+        writeln!(dot, "    block{} [label=\"{}\"];", index, label).unwrap();
+    }
+
+    for (index, block) in blocks.iter().enumerate() {
+        for successor in &block.successors {
+            writeln!(dot, "    block{} -> block{};", index, successor).unwrap();
+        }
+
+        for handler in &block.handlers {
+            writeln!(
+                dot,
+                "    block{} -> block{} [style=dashed];",
+                index, handler
+            )
+            .unwrap();
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
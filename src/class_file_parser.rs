@@ -4,55 +4,111 @@ use crate::java_class::*;
 use crate::jvm::{Class, Method};
 use crate::reader::Reader;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-fn parse_constant_pool(r: &mut Reader, constant_pool_count: u16) -> Vec<ConstantPoolEntry> {
+/// Check that at least the passed number of bytes are left, so that a truncated class file is
+/// reported instead of being read past its end.
+fn expect_bytes(r: &Reader, length: usize, context: &str) -> Result<(), String> {
+    if r.remaining() < length {
+        return Err(format!(
+            "Class file ends unexpectedly in {}: {} more bytes were expected but only {} remain",
+            context,
+            length,
+            r.remaining()
+        ));
+    }
+
+    Ok(())
+}
+
+fn parse_constant_pool(
+    r: &mut Reader,
+    constant_pool_count: u16,
+) -> Result<Vec<ConstantPoolEntry>, String> {
     let mut constant_pool = Vec::new();
 
-    for _ in 1..constant_pool_count {
-        constant_pool.push(match r.g1() {
+    // Entries are numbered from 1, and longs and doubles take up two of the numbers
+    while constant_pool.len() + 1 < constant_pool_count as usize {
+        let context = format!("constant pool entry {}", constant_pool.len() + 1);
+        expect_bytes(r, 1, &context)?;
+
+        let tag = r.g1()?;
+        let size = match tag {
+            15 => 3,
+            1 | 7 | 8 | 16 => 2,
+            3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => 4,
+            5 | 6 => 8,
+            _ => return Err(format!("Unsupported constant pool tag {}", tag)),
+        };
+        expect_bytes(r, size, &context)?;
+
+        let entry = match tag {
             1 => {
-                let length = r.g2u();
-                ConstantPoolEntry::Utf8(String::from_utf8(r.g(length)).unwrap())
+                let length = r.g2u()?;
+                expect_bytes(r, length, &context)?;
+
+                match String::from_utf8(r.g(length)?) {
+                    Ok(text) => ConstantPoolEntry::Utf8(text),
+                    Err(err) => return Err(format!("Invalid utf8 in {}: {}", context, err)),
+                }
             }
-            3 => ConstantPoolEntry::Integer(i32::from_be_bytes(r.g4_array())),
-            4 => ConstantPoolEntry::Float(f32::from_be_bytes(r.g4_array())),
-            5 => ConstantPoolEntry::Long(i64::from_be_bytes(r.g8_array())),
-            6 => ConstantPoolEntry::Double(f64::from_be_bytes(r.g8_array())),
-            7 => ConstantPoolEntry::Class(r.g2u()),
-            8 => ConstantPoolEntry::String(r.g2u()),
-            9 => ConstantPoolEntry::FieldRef(r.g2u(), r.g2u()),
-            10 => ConstantPoolEntry::MethodRef(r.g2u(), r.g2u()),
-            11 => ConstantPoolEntry::InterfaceMethodRef(r.g2u(), r.g2u()),
-            12 => ConstantPoolEntry::NameAndType(r.g2u(), r.g2u()),
-            15 => ConstantPoolEntry::MethodHandle(r.g1(), r.g2u()),
-            16 => ConstantPoolEntry::MethodType(r.g2u()),
-            18 => ConstantPoolEntry::InvokeDynamic(r.g2u(), r.g2u()),
-            _ => panic!("unsupported constant pool entry"),
-        });
+            3 => ConstantPoolEntry::Integer(i32::from_be_bytes(r.g4_array()?)),
+            4 => ConstantPoolEntry::Float(f32::from_be_bytes(r.g4_array()?)),
+            5 => ConstantPoolEntry::Long(i64::from_be_bytes(r.g8_array()?)),
+            6 => ConstantPoolEntry::Double(f64::from_be_bytes(r.g8_array()?)),
+            7 => ConstantPoolEntry::Class(r.g2u()?),
+            8 => ConstantPoolEntry::String(r.g2u()?),
+            9 => ConstantPoolEntry::FieldRef(r.g2u()?, r.g2u()?),
+            10 => ConstantPoolEntry::MethodRef(r.g2u()?, r.g2u()?),
+            11 => ConstantPoolEntry::InterfaceMethodRef(r.g2u()?, r.g2u()?),
+            12 => ConstantPoolEntry::NameAndType(r.g2u()?, r.g2u()?),
+            15 => ConstantPoolEntry::MethodHandle(r.g1()?, r.g2u()?),
+            16 => ConstantPoolEntry::MethodType(r.g2u()?),
+            17 => ConstantPoolEntry::Dynamic(r.g2u()?, r.g2u()?),
+            18 => ConstantPoolEntry::InvokeDynamic(r.g2u()?, r.g2u()?),
+            _ => unreachable!(),
+        };
+        let is_wide = matches!(
+            entry,
+            ConstantPoolEntry::Long(_) | ConstantPoolEntry::Double(_)
+        );
+
+        constant_pool.push(entry);
+        if is_wide {
+            constant_pool.push(ConstantPoolEntry::Unusable);
+        }
     }
 
-    constant_pool
+    Ok(constant_pool)
 }
 
-fn parse_interfaces(r: &mut Reader, interfaces_count: u16) -> Vec<Interface> {
+fn parse_interfaces(r: &mut Reader, interfaces_count: u16) -> Result<Vec<Interface>, String> {
+    expect_bytes(r, interfaces_count as usize * 2, "interfaces")?;
+
     let mut interfaces = Vec::new();
 
     for _ in 0..interfaces_count {
-        interfaces.push(Interface { name: r.g2() });
+        interfaces.push(Interface { name: r.g2()? });
     }
 
-    interfaces
+    Ok(interfaces)
 }
 
-fn parse_fields(r: &mut Reader, ct: &[ConstantPoolEntry], fields_count: u16) -> Vec<Field> {
+fn parse_fields(
+    r: &mut Reader,
+    ct: &[ConstantPoolEntry],
+    fields_count: u16,
+) -> Result<Vec<Field>, String> {
     let mut fields = Vec::new();
 
-    for _ in 0..fields_count {
-        let access_flags = r.g2();
-        let name = r.g2();
-        let descriptor = r.g2();
-        let attributes_count = r.g2();
-        let attributes = parse_attributes(r, ct, attributes_count);
+    for i in 0..fields_count {
+        expect_bytes(r, 8, &format!("field {}", i))?;
+
+        let access_flags = r.g2()?;
+        let name = r.g2()?;
+        let descriptor = r.g2()?;
+        let attributes_count = r.g2()?;
+        let attributes = parse_attributes(r, ct, attributes_count)?;
 
         fields.push(Field {
             access_flags,
@@ -63,22 +119,24 @@ fn parse_fields(r: &mut Reader, ct: &[ConstantPoolEntry], fields_count: u16) ->
         });
     }
 
-    fields
+    Ok(fields)
 }
 
 fn parse_methods(
     r: &mut Reader,
     ct: &[ConstantPoolEntry],
     methods_count: u16,
-) -> Vec<UnparsedMethod> {
+) -> Result<Vec<UnparsedMethod>, String> {
     let mut methods = Vec::new();
 
-    for _i in 0..methods_count {
-        let access_flags = r.g2();
-        let name_index = r.g2();
-        let descriptor_index = r.g2();
-        let attributes_count = r.g2();
-        let attributes = parse_attributes(r, ct, attributes_count);
+    for i in 0..methods_count {
+        expect_bytes(r, 8, &format!("method {}", i))?;
+
+        let access_flags = r.g2()?;
+        let name_index = r.g2()?;
+        let descriptor_index = r.g2()?;
+        let attributes_count = r.g2()?;
+        let attributes = parse_attributes(r, ct, attributes_count)?;
 
         methods.push(UnparsedMethod {
             access_flags,
@@ -89,43 +147,61 @@ fn parse_methods(
         });
     }
 
-    methods
+    Ok(methods)
 }
 
 fn parse_attributes(
     r: &mut Reader,
     ct: &[ConstantPoolEntry],
     attributes_count: u16,
-) -> Vec<Attribute> {
+) -> Result<Vec<Attribute>, String> {
     let mut attributes = Vec::new();
 
     for _i in 0..attributes_count {
-        let attribute_name_index = r.g2() - 1;
-        let attribute_length = r.g4();
+        expect_bytes(r, 6, "attribute header")?;
+
+        let attribute_name_index = r.g2()?.wrapping_sub(1);
+        let attribute_length = r.g4()?;
         let attribute_start_position = r.pos();
-        let attribute_str_name = match ct[attribute_name_index as usize] {
-            ConstantPoolEntry::Utf8(ref s) => s,
-            _ => {
-                println!("{:?}", ct[attribute_name_index as usize]);
-                panic!("attribute name is not a utf8 string")
-            }
+        let attribute_str_name = match ct.get(attribute_name_index as usize) {
+            Some(ConstantPoolEntry::Utf8(s)) => s,
+            entry => return Err(format!("Attribute name {:?} is not a utf8 string", entry)),
         };
 
+        // The attribute has to fit in the rest of the file, while the lengths within it are
+        // checked as they are read
+        expect_bytes(
+            r,
+            attribute_length as usize,
+            &format!("{} attribute", attribute_str_name),
+        )?;
+
         attributes.push(match &attribute_str_name[..] {
             "ConstantValue" => Attribute::ConstantValue(ConstantValueAttribute {
                 attribute_name_index,
                 attribute_length,
-                constant_value_index: r.g2(),
+                constant_value_index: r.g2()?,
             }),
             "Code" => {
-                let max_stack = r.g2();
-                let max_locals = r.g2();
-                let code_length = r.g4();
-                let code = r.g(code_length as usize);
-                let exception_table_length = r.g2();
-                let exception_table = r.g(exception_table_length as usize);
-                let attributes_count = r.g2();
-                let attributes = parse_attributes(r, ct, attributes_count);
+                let max_stack = r.g2()?;
+                let max_locals = r.g2()?;
+                let code_length = r.g4()?;
+                let code = r.g(code_length as usize)?;
+                let exception_table_length = r.g2()?;
+                // The handlers are given as byte offsets, which are converted to instruction
+                // indices once the code is parsed
+                let exception_table = (0..exception_table_length)
+                    .map(|_| {
+                        Ok(ExceptionTableEntry {
+                            start_pc: r.g2()? as usize,
+                            end_pc: r.g2()? as usize,
+                            handler_pc: r.g2()? as usize,
+                            catch_type: r.g2()?,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?;
+                let attributes_count = r.g2()?;
+                let attributes = parse_attributes(r, ct, attributes_count)?;
 
                 Attribute::Code(CodeAttribute {
                     attribute_name_index,
@@ -140,28 +216,37 @@ fn parse_attributes(
                     attributes,
                 })
             }
-            "StackMapTable" => Attribute::StackMapTable(StackMapTableAttribute {
-                attribute_name_index,
-                attribute_length,
-                number_of_entries: r.g2(),
-                entries: r.g(attribute_length as usize),
-            }),
+            "StackMapTable" => {
+                let number_of_entries = r.g2()?;
+                let mut entries = Vec::new();
+
+                for _ in 0..number_of_entries {
+                    entries.push(parse_stack_map_frame(r)?);
+                }
+
+                Attribute::StackMapTable(StackMapTableAttribute {
+                    attribute_name_index,
+                    attribute_length,
+                    number_of_entries,
+                    entries,
+                })
+            }
             "Exceptions" => Attribute::Exceptions(ExceptionsAttribute {
                 attribute_name_index,
                 attribute_length,
-                number_of_exceptions: r.g2(),
-                exception_index_table: r.g(attribute_length as usize),
+                number_of_exceptions: r.g2()?,
+                exception_index_table: r.g((attribute_length as usize).saturating_sub(2))?,
             }),
             "InnerClasses" => {
-                let number_of_classes = r.g2();
+                let number_of_classes = r.g2()?;
                 let mut classes = Vec::new();
 
                 for _ in 0..number_of_classes {
                     classes.push(InnerClassElement {
-                        inner_class_info_index: r.g2(),
-                        outer_class_info_index: r.g2(),
-                        inner_name_index: r.g2(),
-                        inner_class_access_flags: r.g2(),
+                        inner_class_info_index: r.g2()?,
+                        outer_class_info_index: r.g2()?,
+                        inner_name_index: r.g2()?,
+                        inner_class_access_flags: r.g2()?,
                     });
                 }
 
@@ -175,8 +260,8 @@ fn parse_attributes(
             "EnclosingMethod" => Attribute::EnclosingMethod(EnclosingMethodAttribute {
                 attribute_name_index,
                 attribute_length,
-                class_index: r.g2(),
-                method_index: r.g2(),
+                class_index: r.g2()?,
+                method_index: r.g2()?,
             }),
             "Synthetic" => Attribute::Synthetic(SyntheticAttribute {
                 attribute_name_index,
@@ -185,21 +270,21 @@ fn parse_attributes(
             "Signature" => Attribute::Signature(SignatureAttribute {
                 attribute_name_index,
                 attribute_length,
-                signature_index: r.g2(),
+                signature_index: r.g2()?,
             }),
             "SourceFile" => Attribute::SourceFile(SourceFileAttribute {
                 attribute_name_index,
                 attribute_length,
-                sourcefile_index: r.g2(),
+                sourcefile_index: r.g2()?,
             }),
             "LineNumberTable" => {
-                let line_number_table_length = r.g2();
+                let line_number_table_length = r.g2()?;
                 let mut line_number_table = Vec::new();
 
                 for _ in 0..line_number_table_length {
                     line_number_table.push(LineNumberTableElement {
-                        start_pc: r.g2(),
-                        line_number: r.g2(),
+                        start_pc: r.g2()?,
+                        line_number: r.g2()?,
                     });
                 }
 
@@ -211,16 +296,16 @@ fn parse_attributes(
                 })
             }
             "LocalVariableTable" => {
-                let local_variable_table_length = r.g2();
+                let local_variable_table_length = r.g2()?;
                 let mut local_variable_table = Vec::new();
 
                 for _ in 0..local_variable_table_length {
                     local_variable_table.push(LocalVariableTableElement {
-                        start_pc: r.g2(),
-                        length: r.g2(),
-                        name_index: r.g2(),
-                        descriptor_index: r.g2(),
-                        index: r.g2(),
+                        start_pc: r.g2()?,
+                        length: r.g2()?,
+                        name_index: r.g2()?,
+                        descriptor_index: r.g2()?,
+                        index: r.g2()?,
                     });
                 }
 
@@ -232,16 +317,16 @@ fn parse_attributes(
                 })
             }
             "LocalVariableTypeTable" => {
-                let local_variable_type_table_length = r.g2();
+                let local_variable_type_table_length = r.g2()?;
                 let mut local_variable_type_table = Vec::new();
 
                 for _ in 0..local_variable_type_table_length {
                     local_variable_type_table.push(LocalVariableTypeTableElement {
-                        start_pc: r.g2(),
-                        length: r.g2(),
-                        name_index: r.g2(),
-                        signature_index: r.g2(),
-                        index: r.g2(),
+                        start_pc: r.g2()?,
+                        length: r.g2()?,
+                        name_index: r.g2()?,
+                        signature_index: r.g2()?,
+                        index: r.g2()?,
                     });
                 }
 
@@ -256,50 +341,256 @@ fn parse_attributes(
                 attribute_name_index,
                 attribute_length,
             }),
-            _ => panic!("{} is an unsupported attribute type", attribute_str_name),
+            "BootstrapMethods" => {
+                let num_bootstrap_methods = r.g2()?;
+                let mut bootstrap_methods = Vec::new();
+
+                for _ in 0..num_bootstrap_methods {
+                    let bootstrap_method_ref = r.g2()?;
+                    let num_bootstrap_arguments = r.g2()?;
+                    let mut bootstrap_arguments = Vec::new();
+
+                    for _ in 0..num_bootstrap_arguments {
+                        bootstrap_arguments.push(r.g2()?);
+                    }
+
+                    bootstrap_methods.push(BootstrapMethod {
+                        bootstrap_method_ref,
+                        num_bootstrap_arguments,
+                        bootstrap_arguments,
+                    });
+                }
+
+                Attribute::BootstrapMethods(BootstrapMethodsAttribute {
+                    attribute_name_index,
+                    attribute_length,
+                    num_bootstrap_methods,
+                    bootstrap_methods,
+                })
+            }
+            _ => {
+                return Err(format!(
+                    "{} is an unsupported attribute type",
+                    attribute_str_name
+                ))
+            }
         });
 
-        // if r.pos() != attribute_start_position + attribute_length as usize {
-        //     println!(
-        //         "{:?} was not parsed completely",
-        //         ct[attribute_name_index as usize]
-        //     );
-        // }
+        // The lengths inside an attribute can disagree with the length of the attribute itself
+        if r.pos() > attribute_start_position + attribute_length as usize {
+            return Err(format!(
+                "{} attribute is longer than its length of {} bytes",
+                attribute_str_name, attribute_length
+            ));
+        }
 
         r.set_pos(attribute_start_position + attribute_length as usize);
     }
 
-    attributes
+    Ok(attributes)
+}
+
+fn parse_verification_type(r: &mut Reader) -> Result<VerificationType, String> {
+    Ok(match r.g1()? {
+        0 => VerificationType::Top,
+        1 => VerificationType::Integer,
+        2 => VerificationType::Float,
+        3 => VerificationType::Double,
+        4 => VerificationType::Long,
+        5 => VerificationType::Null,
+        6 => VerificationType::UninitializedThis,
+        7 => VerificationType::Object(r.g2()?),
+        8 => VerificationType::Uninitialized(r.g2()?),
+        tag => return Err(format!("{} is an unsupported verification type", tag)),
+    })
+}
+
+fn parse_verification_types(r: &mut Reader, count: usize) -> Result<Vec<VerificationType>, String> {
+    (0..count).map(|_| parse_verification_type(r)).collect()
+}
+
+fn parse_stack_map_frame(r: &mut Reader) -> Result<StackMapFrame, String> {
+    let frame_type = r.g1()?;
+
+    // The smallest frame types store their offset delta in the frame type itself
+    let (offset_delta, frame_type) = match frame_type {
+        0..=63 => (frame_type as u16, StackMapFrameType::Same),
+        64..=127 => (
+            frame_type as u16 - 64,
+            StackMapFrameType::SameLocals1StackItem(parse_verification_type(r)?),
+        ),
+        247 => (
+            r.g2()?,
+            StackMapFrameType::SameLocals1StackItem(parse_verification_type(r)?),
+        ),
+        248..=250 => (r.g2()?, StackMapFrameType::Chop(251 - frame_type)),
+        251 => (r.g2()?, StackMapFrameType::Same),
+        252..=254 => {
+            let offset_delta = r.g2()?;
+            let locals = parse_verification_types(r, frame_type as usize - 251)?;
+            (offset_delta, StackMapFrameType::Append(locals))
+        }
+        255 => {
+            let offset_delta = r.g2()?;
+            let number_of_locals = r.g2u()?;
+            let locals = parse_verification_types(r, number_of_locals)?;
+            let number_of_stack_items = r.g2u()?;
+            let stack = parse_verification_types(r, number_of_stack_items)?;
+            (offset_delta, StackMapFrameType::Full(locals, stack))
+        }
+        _ => {
+            return Err(format!(
+                "{} is an unsupported stack map frame type",
+                frame_type
+            ))
+        }
+    };
+
+    Ok(StackMapFrame {
+        offset_delta,
+        frame_type,
+    })
+}
+
+/// Convert the byte offsets of exception handlers to instruction indices. The end of a handler's
+/// range may be the end of the code, which becomes the number of instructions.
+fn handlers_to_instruction_offsets(
+    exception_table: &[ExceptionTableEntry],
+    instruction_indices: &[Option<usize>],
+    instruction_count: usize,
+) -> Result<Vec<ExceptionTableEntry>, String> {
+    let instruction_index = |byte_offset: usize| match instruction_indices.get(byte_offset) {
+        Some(Some(index)) => Ok(*index),
+        None if byte_offset == instruction_indices.len() => Ok(instruction_count),
+        _ => Err(format!(
+            "Exception handler refers to byte offset {} which is not an instruction",
+            byte_offset
+        )),
+    };
+
+    exception_table
+        .iter()
+        .map(|entry| {
+            Ok(ExceptionTableEntry {
+                start_pc: instruction_index(entry.start_pc)?,
+                end_pc: instruction_index(entry.end_pc)?,
+                handler_pc: instruction_index(entry.handler_pc)?,
+                catch_type: entry.catch_type,
+            })
+        })
+        .collect()
+}
+
+/// Stack map frames describe byte offsets, but methods count instructions, so convert the offsets
+/// of the frames and of any uninitialized objects in them into instruction indices.
+fn frames_to_instruction_offsets(
+    frames: &[StackMapFrame],
+    instruction_indices: &[Option<usize>],
+) -> Result<Vec<StackMapFrame>, String> {
+    let instruction_index = |byte_offset: usize| match instruction_indices.get(byte_offset) {
+        Some(Some(index)) => Ok(*index),
+        _ => Err(format!(
+            "Stack map frame refers to byte offset {} which is not an instruction",
+            byte_offset
+        )),
+    };
+
+    let convert_types = |types: &[VerificationType]| {
+        types
+            .iter()
+            .map(|verification_type| match verification_type {
+                VerificationType::Uninitialized(offset) => Ok(VerificationType::Uninitialized(
+                    instruction_index(*offset as usize)? as u16,
+                )),
+                verification_type => Ok(verification_type.clone()),
+            })
+            .collect::<Result<Vec<_>, String>>()
+    };
+
+    let mut converted = Vec::new();
+    let mut previous: Option<(usize, usize)> = None;
+
+    for frame in frames {
+        let byte_offset = match previous {
+            Some((byte_offset, _)) => byte_offset + frame.offset_delta as usize + 1,
+            None => frame.offset_delta as usize,
+        };
+        let index = instruction_index(byte_offset)?;
+        let offset_delta = match previous {
+            Some((_, previous_index)) => index - previous_index - 1,
+            None => index,
+        };
+
+        let frame_type = match &frame.frame_type {
+            StackMapFrameType::SameLocals1StackItem(stack) => {
+                StackMapFrameType::SameLocals1StackItem(
+                    convert_types(std::slice::from_ref(stack))?.remove(0),
+                )
+            }
+            StackMapFrameType::Append(locals) => StackMapFrameType::Append(convert_types(locals)?),
+            StackMapFrameType::Full(locals, stack) => {
+                StackMapFrameType::Full(convert_types(locals)?, convert_types(stack)?)
+            }
+            frame_type => frame_type.clone(),
+        };
+
+        converted.push(StackMapFrame {
+            offset_delta: offset_delta as u16,
+            frame_type,
+        });
+        previous = Some((byte_offset, index));
+    }
+
+    Ok(converted)
+}
+
+/// The next count operand bytes after the passed byte offset, advancing the offset past them.
+fn operands<'a>(code: &'a [u8], pc: &mut usize, count: usize) -> Result<&'a [u8], String> {
+    match code.get(*pc + 1..*pc + 1 + count) {
+        Some(bytes) => {
+            *pc += count;
+            Ok(bytes)
+        }
+        None => Err(format!(
+            "Code ends in the middle of the operands after byte offset {}",
+            pc
+        )),
+    }
 }
 
-fn u1(code: &[u8], pc: &mut usize) -> usize {
-    let b = code[*pc + 1];
-    *pc += 1;
-    b as usize
+fn u1(code: &[u8], pc: &mut usize) -> Result<usize, String> {
+    Ok(operands(code, pc, 1)?[0] as usize)
 }
 
-fn u2(code: &[u8], pc: &mut usize) -> usize {
-    let b1 = code[*pc + 1];
-    let b2 = code[*pc + 2];
-    *pc += 2;
-    (((b1 as i16) << 8) | (b2 as i16)) as usize
+fn u2(code: &[u8], pc: &mut usize) -> Result<usize, String> {
+    let b = operands(code, pc, 2)?;
+    Ok((((b[0] as i16) << 8) | (b[1] as i16)) as usize)
 }
 
-fn u4(code: &[u8], pc: &mut usize) -> usize {
-    let b1 = code[*pc + 1];
-    let b2 = code[*pc + 2];
-    let b3 = code[*pc + 3];
-    let b4 = code[*pc + 4];
-    *pc += 4;
-    (((b1 as i32) << 24) | ((b2 as i32) << 16) | ((b3 as i32) << 8) | (b4 as i32)) as usize
+fn u4(code: &[u8], pc: &mut usize) -> Result<usize, String> {
+    let b = operands(code, pc, 4)?;
+    Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
 }
 
-pub fn bytes_to_bytecode(code: Vec<u8>) -> Vec<Instruction> {
+pub fn bytes_to_bytecode(code: Vec<u8>) -> Result<Vec<Instruction>, String> {
+    Ok(parse_code(code)?.0)
+}
+
+/// Parse the code of a method into instructions, along with the index of the instruction starting
+/// at each byte offset.
+fn parse_code(code: Vec<u8>) -> Result<(Vec<Instruction>, Vec<Option<usize>>), String> {
     let mut instructions: Vec<Instruction> = Vec::new();
     let mut pc: usize = 0;
-    let mut past_byte_pos: usize = 0;
 
-    while pc < code.len() as usize {
+    // The instruction index starting at each byte offset, and the byte offset of each instruction
+    let mut instruction_indices: Vec<Option<usize>> = vec![None; code.len()];
+    let mut byte_offsets: Vec<usize> = Vec::new();
+
+    while pc < code.len() {
+        let byte_offset = pc;
+        instruction_indices[byte_offset] = Some(instructions.len());
+        byte_offsets.push(byte_offset);
+
         instructions.push(match code[pc] {
             0 => Instruction::Nop,
             1 => Instruction::AConstNull,
@@ -317,16 +608,17 @@ pub fn bytes_to_bytecode(code: Vec<u8>) -> Vec<Instruction> {
             13 => Instruction::Const(Primitive::Float(2.0)),
             14 => Instruction::Const(Primitive::Double(0.0)),
             15 => Instruction::Const(Primitive::Double(1.0)),
-            16 => Instruction::Const(Primitive::Int(u1(&code, &mut pc) as i32)),
-            17 => Instruction::Const(Primitive::Int(u2(&code, &mut pc) as i32)),
-            18 => Instruction::LoadConst(u1(&code, &mut pc)),
-            19 => Instruction::LoadConst(u2(&code, &mut pc)),
-            20 => Instruction::LoadConst(u2(&code, &mut pc)),
-            21 => Instruction::Load(u1(&code, &mut pc), PrimitiveType::Int),
-            22 => Instruction::Load(u1(&code, &mut pc), PrimitiveType::Long),
-            23 => Instruction::Load(u1(&code, &mut pc), PrimitiveType::Float),
-            24 => Instruction::Load(u1(&code, &mut pc), PrimitiveType::Double),
-            25 => Instruction::Load(u1(&code, &mut pc), PrimitiveType::Reference),
+            16 => Instruction::Const(Primitive::Int(u1(&code, &mut pc)? as i8 as i32)),
+            17 => Instruction::Const(Primitive::Int(u2(&code, &mut pc)? as i32)),
+            18 => Instruction::LoadConst(u1(&code, &mut pc)?),
+            19 => Instruction::LoadConst(u2(&code, &mut pc)?),
+            // ldc2_w is ldc_w for the constants which are longs or doubles
+            20 => Instruction::LoadConst(u2(&code, &mut pc)?),
+            21 => Instruction::Load(u1(&code, &mut pc)?, PrimitiveType::Int),
+            22 => Instruction::Load(u1(&code, &mut pc)?, PrimitiveType::Long),
+            23 => Instruction::Load(u1(&code, &mut pc)?, PrimitiveType::Float),
+            24 => Instruction::Load(u1(&code, &mut pc)?, PrimitiveType::Double),
+            25 => Instruction::Load(u1(&code, &mut pc)?, PrimitiveType::Reference),
             26 => Instruction::Load(0, PrimitiveType::Int),
             27 => Instruction::Load(1, PrimitiveType::Int),
             28 => Instruction::Load(2, PrimitiveType::Int),
@@ -355,11 +647,11 @@ pub fn bytes_to_bytecode(code: Vec<u8>) -> Vec<Instruction> {
             51 => Instruction::ALoad(PrimitiveType::Byte),
             52 => Instruction::ALoad(PrimitiveType::Char),
             53 => Instruction::ALoad(PrimitiveType::Short),
-            54 => Instruction::Store(u1(&code, &mut pc), PrimitiveType::Int),
-            55 => Instruction::Store(u1(&code, &mut pc), PrimitiveType::Long),
-            56 => Instruction::Store(u1(&code, &mut pc), PrimitiveType::Float),
-            57 => Instruction::Store(u1(&code, &mut pc), PrimitiveType::Double),
-            58 => Instruction::Store(u1(&code, &mut pc), PrimitiveType::Reference),
+            54 => Instruction::Store(u1(&code, &mut pc)?, PrimitiveType::Int),
+            55 => Instruction::Store(u1(&code, &mut pc)?, PrimitiveType::Long),
+            56 => Instruction::Store(u1(&code, &mut pc)?, PrimitiveType::Float),
+            57 => Instruction::Store(u1(&code, &mut pc)?, PrimitiveType::Double),
+            58 => Instruction::Store(u1(&code, &mut pc)?, PrimitiveType::Reference),
             59 => Instruction::Store(0, PrimitiveType::Int),
             60 => Instruction::Store(1, PrimitiveType::Int),
             61 => Instruction::Store(2, PrimitiveType::Int),
@@ -433,7 +725,7 @@ pub fn bytes_to_bytecode(code: Vec<u8>) -> Vec<Instruction> {
             129 => Instruction::Or(PrimitiveType::Long),
             130 => Instruction::Xor(PrimitiveType::Int),
             131 => Instruction::Xor(PrimitiveType::Long),
-            132 => Instruction::IInc(u1(&code, &mut pc), u1(&code, &mut pc) as i8),
+            132 => Instruction::IInc(u1(&code, &mut pc)?, u1(&code, &mut pc)? as i8 as i16),
             133 => Instruction::Convert(PrimitiveType::Int, PrimitiveType::Long),
             134 => Instruction::Convert(PrimitiveType::Int, PrimitiveType::Float),
             135 => Instruction::Convert(PrimitiveType::Int, PrimitiveType::Double),
@@ -454,147 +746,364 @@ pub fn bytes_to_bytecode(code: Vec<u8>) -> Vec<Instruction> {
             150 => Instruction::FCmpG,
             151 => Instruction::DCmpL,
             152 => Instruction::DCmpG,
-            153 => Instruction::If(u2(&code, &mut pc), Comparison::Equal),
-            154 => Instruction::If(u2(&code, &mut pc), Comparison::NotEqual),
-            155 => Instruction::If(u2(&code, &mut pc), Comparison::LessThan),
-            156 => Instruction::If(u2(&code, &mut pc), Comparison::GreaterThanOrEqual),
-            157 => Instruction::If(u2(&code, &mut pc), Comparison::GreaterThan),
-            158 => Instruction::If(u2(&code, &mut pc), Comparison::LessThanOrEqual),
-            159 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::Equal),
-            160 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::NotEqual),
-            161 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::LessThan),
-            162 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::GreaterThanOrEqual),
-            163 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::GreaterThan),
-            164 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::LessThanOrEqual),
-            165 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::Equal),
-            166 => Instruction::IfICmp(u2(&code, &mut pc), Comparison::NotEqual),
-            167 => Instruction::Goto(u2(&code, &mut pc)),
-            168 => Instruction::Jsr(u2(&code, &mut pc)),
-            169 => Instruction::Ret(u1(&code, &mut pc)),
-            170 => panic!("Unsupported instruction: {}", 170),
-            171 => panic!("Unsupported instruction: {}", 171),
+            153 => Instruction::If(u2(&code, &mut pc)?, Comparison::Equal),
+            154 => Instruction::If(u2(&code, &mut pc)?, Comparison::NotEqual),
+            155 => Instruction::If(u2(&code, &mut pc)?, Comparison::LessThan),
+            156 => Instruction::If(u2(&code, &mut pc)?, Comparison::GreaterThanOrEqual),
+            157 => Instruction::If(u2(&code, &mut pc)?, Comparison::GreaterThan),
+            158 => Instruction::If(u2(&code, &mut pc)?, Comparison::LessThanOrEqual),
+            159 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::Equal),
+            160 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::NotEqual),
+            161 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::LessThan),
+            162 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::GreaterThanOrEqual),
+            163 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::GreaterThan),
+            164 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::LessThanOrEqual),
+            165 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::Equal),
+            166 => Instruction::IfICmp(u2(&code, &mut pc)?, Comparison::NotEqual),
+            167 => Instruction::Goto(u2(&code, &mut pc)?),
+            168 => Instruction::Jsr(u2(&code, &mut pc)?),
+            169 => Instruction::Ret(u1(&code, &mut pc)?),
+            170 => {
+                // The operands start at the next multiple of four bytes after the opcode
+                pc = (pc + 4) / 4 * 4 - 1;
+
+                let default = u4(&code, &mut pc)?;
+                let low = u4(&code, &mut pc)? as i32;
+                let high = u4(&code, &mut pc)? as i32;
+                let targets = (low..=high)
+                    .map(|_| u4(&code, &mut pc))
+                    .collect::<Result<_, _>>()?;
+
+                Instruction::TableSwitch(default, low, targets)
+            }
+            171 => {
+                pc = (pc + 4) / 4 * 4 - 1;
+
+                let default = u4(&code, &mut pc)?;
+                let pair_count = u4(&code, &mut pc)?;
+                let pairs = (0..pair_count)
+                    .map(|_| Ok((u4(&code, &mut pc)? as i32, u4(&code, &mut pc)?)))
+                    .collect::<Result<_, String>>()?;
+
+                Instruction::LookupSwitch(default, pairs)
+            }
             172 => Instruction::Return(PrimitiveType::Int),
             173 => Instruction::Return(PrimitiveType::Long),
             174 => Instruction::Return(PrimitiveType::Float),
             175 => Instruction::Return(PrimitiveType::Double),
             176 => Instruction::Return(PrimitiveType::Reference),
             177 => Instruction::Return(PrimitiveType::Null),
-            178 => Instruction::GetStatic(u2(&code, &mut pc) as usize),
-            179 => Instruction::PutStatic(u2(&code, &mut pc) as usize),
-            180 => Instruction::GetField(u2(&code, &mut pc) as usize),
-            181 => Instruction::PutField(u2(&code, &mut pc) as usize),
-            182 => Instruction::InvokeVirtual(u2(&code, &mut pc) as usize),
-            183 => Instruction::InvokeSpecial(u2(&code, &mut pc) as usize),
-            184 => Instruction::InvokeStatic(u2(&code, &mut pc) as usize),
-            185 => Instruction::InvokeInterface(u2(&code, &mut pc) as usize),
-            186 => Instruction::InvokeDynamic(u2(&code, &mut pc) as usize),
-            187 => Instruction::New(u2(&code, &mut pc) as usize),
-            188 => Instruction::NewArray(PrimitiveType::from_type_id(u1(&code, &mut pc)).unwrap()),
-            189 => Instruction::ANewArray(PrimitiveType::from_type_id(u2(&code, &mut pc)).unwrap()),
+            178 => Instruction::GetStatic(u2(&code, &mut pc)?),
+            179 => Instruction::PutStatic(u2(&code, &mut pc)?),
+            180 => Instruction::GetField(u2(&code, &mut pc)?),
+            181 => Instruction::PutField(u2(&code, &mut pc)?),
+            182 => Instruction::InvokeVirtual(u2(&code, &mut pc)?),
+            183 => Instruction::InvokeSpecial(u2(&code, &mut pc)?),
+            184 => Instruction::InvokeStatic(u2(&code, &mut pc)?),
+            185 => {
+                let index = u2(&code, &mut pc)?;
+                // Skip the redundant argument count and the trailing zero byte
+                pc += 2;
+                Instruction::InvokeInterface(index)
+            }
+            186 => {
+                let index = u2(&code, &mut pc)?;
+                // Skip the two zero bytes
+                pc += 2;
+                Instruction::InvokeDynamic(index)
+            }
+            187 => Instruction::New(u2(&code, &mut pc)?),
+            188 => {
+                let atype = u1(&code, &mut pc)?;
+                match PrimitiveType::from_type_id(atype) {
+                    Ok(element_type) => Instruction::NewArray(element_type),
+                    Err(_) => {
+                        return Err(format!(
+                            "Invalid newarray type code {} at byte offset {}",
+                            atype,
+                            pc - 1
+                        ))
+                    }
+                }
+            }
+            189 => Instruction::ANewArray(u2(&code, &mut pc)?),
             190 => Instruction::ArrayLength,
             191 => Instruction::AThrow,
-            192 => Instruction::CheckCast(u2(&code, &mut pc) as usize),
-            193 => Instruction::InstanceOf(u2(&code, &mut pc) as usize),
+            192 => Instruction::CheckCast(u2(&code, &mut pc)?),
+            193 => Instruction::InstanceOf(u2(&code, &mut pc)?),
             194 => Instruction::MonitorEnter,
             195 => Instruction::MonitorExit,
-            196 => panic!("Unsupported instruction: {}", 196),
-            197 => panic!("Unsupported instruction: {}", 197),
-            198 => Instruction::IfNull(u2(&code, &mut pc) as usize),
-            199 => Instruction::IfNonNull(u2(&code, &mut pc) as usize),
-            200 => Instruction::Goto(u4(&code, &mut pc) as usize),
-            201 => Instruction::Jsr(u4(&code, &mut pc) as usize),
+            196 => {
+                // The wide prefix widens the local index of the next instruction to 2 bytes
+                let opcode = u1(&code, &mut pc)?;
+                let index = u2(&code, &mut pc)? as u16 as usize;
+
+                match opcode {
+                    21 => Instruction::Load(index, PrimitiveType::Int),
+                    22 => Instruction::Load(index, PrimitiveType::Long),
+                    23 => Instruction::Load(index, PrimitiveType::Float),
+                    24 => Instruction::Load(index, PrimitiveType::Double),
+                    25 => Instruction::Load(index, PrimitiveType::Reference),
+                    54 => Instruction::Store(index, PrimitiveType::Int),
+                    55 => Instruction::Store(index, PrimitiveType::Long),
+                    56 => Instruction::Store(index, PrimitiveType::Float),
+                    57 => Instruction::Store(index, PrimitiveType::Double),
+                    58 => Instruction::Store(index, PrimitiveType::Reference),
+                    169 => Instruction::Ret(index),
+                    132 => Instruction::IInc(index, u2(&code, &mut pc)? as i16),
+                    _ => {
+                        return Err(format!(
+                            "Unsupported instruction {} after wide at byte offset {}",
+                            opcode, byte_offset
+                        ))
+                    }
+                }
+            }
+            197 => Instruction::MultiANewArray(u2(&code, &mut pc)?, u1(&code, &mut pc)?),
+            198 => Instruction::IfNull(u2(&code, &mut pc)?),
+            199 => Instruction::IfNonNull(u2(&code, &mut pc)?),
+            200 => Instruction::Goto(u4(&code, &mut pc)?),
+            201 => Instruction::Jsr(u4(&code, &mut pc)?),
             202 => Instruction::Breakpoint,
-            _ => panic!("unsupported instruction"),
+            opcode => {
+                return Err(format!(
+                    "Unsupported instruction {} at byte offset {}",
+                    opcode, byte_offset
+                ))
+            }
         });
 
-        for _ in past_byte_pos..pc {
-            instructions.push(Instruction::Nop);
-        }
-
         pc += 1;
-        past_byte_pos = pc;
     }
 
-    instructions
+    // Branch offsets are decoded relative to the byte offset of their instruction, so convert
+    // them to the absolute index of the instruction they jump to
+    for (instruction, byte_offset) in instructions.iter_mut().zip(byte_offsets) {
+        for target in instruction.branch_targets_mut() {
+            let target_offset = byte_offset.wrapping_add(*target);
+            *target = match instruction_indices.get(target_offset) {
+                Some(Some(index)) => *index,
+                _ => {
+                    return Err(format!(
+                        "Branch to byte offset {} is not an instruction",
+                        target_offset
+                    ))
+                }
+            };
+        }
+    }
+
+    Ok((instructions, instruction_indices))
 }
 
-pub fn parse_file_to_class(filename: String) -> Class {
-    let mut r = Reader::new(filename);
+/// The newest class file version the interpreter understands, which is the one used by Java 17.
+const MAX_MAJOR_VERSION: u16 = 61;
 
-    let magic = r.g4();
+pub fn parse_file_to_class(filename: String) -> Result<Class, String> {
+    let mut r = Reader::new(filename.clone())?;
 
-    if magic != 0xCAFEBABE {
-        panic!("invalid magic number");
+    // The magic number and version take up the first eight bytes
+    if r.bytes.len() < 8 || r.g4()? != 0xCAFEBABE {
+        return Err(format!(
+            "{} is not a class file: invalid magic number",
+            filename
+        ));
     }
 
-    let _minor_version = r.g2();
-    let _major_version = r.g2();
+    let minor_version = r.g2()?;
+    let major_version = r.g2()?;
 
-    let constant_pool_count = r.g2();
-    let constant_pool = parse_constant_pool(&mut r, constant_pool_count);
+    if major_version > MAX_MAJOR_VERSION {
+        return Err(format!(
+            "{} has unsupported class file version {}.{}, the newest supported is {}",
+            filename, major_version, minor_version, MAX_MAJOR_VERSION
+        ));
+    }
 
-    let _access_flags = ClassFlags::parse(r.g2());
-    let this_class = r.g2();
-    let _super_class = r.g2();
+    expect_bytes(&r, 2, "constant pool count")?;
+    let constant_pool_count = r.g2()?;
+    let constant_pool = parse_constant_pool(&mut r, constant_pool_count)?;
 
-    let interfaces_count = r.g2();
-    let _interfaces = parse_interfaces(&mut r, interfaces_count);
+    expect_bytes(&r, 8, "class header")?;
+    let access_flags = r.g2()?;
+    let this_class = r.g2()?;
+    let super_class = r.g2()?;
 
-    let fields_count = r.g2();
-    let _fields = parse_fields(&mut r, &constant_pool, fields_count);
+    let interfaces_count = r.g2()?;
+    let interfaces = parse_interfaces(&mut r, interfaces_count)?;
 
-    let methods_count = r.g2();
-    let unparsed_methods = parse_methods(&mut r, &constant_pool, methods_count);
+    expect_bytes(&r, 2, "fields count")?;
+    let fields_count = r.g2()?;
+    let fields = parse_fields(&mut r, &constant_pool, fields_count)?;
 
-    let attributes_count = r.g2();
-    let _attributes = parse_attributes(&mut r, &constant_pool, attributes_count);
+    expect_bytes(&r, 2, "methods count")?;
+    let methods_count = r.g2()?;
+    let unparsed_methods = parse_methods(&mut r, &constant_pool, methods_count)?;
 
-    let name_as_cpe = &constant_pool[this_class as usize - 1];
-    let name = match name_as_cpe {
-        ConstantPoolEntry::Class(name_index) => match &constant_pool[*name_index as usize - 1] {
-            ConstantPoolEntry::Utf8(name_as_utf8) => name_as_utf8.clone(),
-            _ => panic!("this_class is not a Utf8Info"),
-        },
-        _ => panic!("this_class is not a ClassInfo"),
-    };
+    expect_bytes(&r, 2, "attributes count")?;
+    let attributes_count = r.g2()?;
+    let attributes = parse_attributes(&mut r, &constant_pool, attributes_count)?;
 
-    let mut methods: HashMap<String, Method> = HashMap::new();
+    if !r.is_eof() {
+        return Err(format!(
+            "{} has {} extra bytes after the end of the class",
+            filename,
+            r.remaining()
+        ));
+    }
 
-    for up_method in unparsed_methods {
-        let name_as_cpe = &constant_pool[up_method.name_index as usize - 1];
+    let bootstrap_methods = attributes
+        .into_iter()
+        .find_map(|attribute| match attribute {
+            Attribute::BootstrapMethods(bootstrap_methods) => {
+                Some(bootstrap_methods.bootstrap_methods)
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
 
-        let name = match name_as_cpe {
-            ConstantPoolEntry::Utf8(name_as_utf8) => name_as_utf8.clone(),
-            _ => panic!("method name is not a Utf8Info"),
-        };
+    let name = constant_pool.class_parser(&(this_class as usize))?;
 
-        let signature = match &constant_pool[up_method.descriptor_index as usize - 1] {
-            ConstantPoolEntry::Utf8(signature_as_utf8) => signature_as_utf8.clone(),
-            _ => panic!("method signature is not a Utf8Info"),
-        };
+    let mut methods: HashMap<String, Rc<Method>> = HashMap::new();
+
+    for up_method in unparsed_methods {
+        let name = constant_pool.utf8_parser(&(up_method.name_index as usize))?;
+        let signature = constant_pool.utf8_parser(&(up_method.descriptor_index as usize))?;
 
         let name_and_signature = format!("{}{}", name, signature);
 
-        let unparsed_attribute = &up_method.attributes[0];
+        // Abstract and native methods have no code to run, but are still loaded so that calls to
+        // them can fail with the right error
+        let code_attribute = match up_method
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Code(code_attribute) => Some(code_attribute),
+                _ => None,
+            }) {
+            Some(code_attribute) => code_attribute,
+            None => {
+                let parsed_method = Method {
+                    access_flags: up_method.access_flags,
+                    instructions: Vec::new(),
+                    stack_map: Vec::new(),
+                    exception_table: Vec::new(),
+                };
 
-        let code_attribute = match unparsed_attribute {
-            Attribute::Code(code_attribute) => code_attribute,
-            _ => panic!("method attribute is not a CodeAttribute"),
+                methods.insert(name_and_signature, Rc::new(parsed_method));
+                continue;
+            }
+        };
+
+        let (parsed_bytecode, instruction_indices) = parse_code(code_attribute.code.clone())?;
+
+        let stack_map = match code_attribute
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::StackMapTable(stack_map_table) => Some(stack_map_table),
+                _ => None,
+            }) {
+            Some(stack_map_table) => {
+                frames_to_instruction_offsets(&stack_map_table.entries, &instruction_indices)?
+            }
+            None => Vec::new(),
         };
 
-        let parsed_bytecode = bytes_to_bytecode(code_attribute.code.clone());
+        let exception_table = handlers_to_instruction_offsets(
+            &code_attribute.exception_table,
+            &instruction_indices,
+            parsed_bytecode.len(),
+        )?;
 
         let parsed_method = Method {
+            access_flags: up_method.access_flags,
             instructions: parsed_bytecode,
+            stack_map,
+            exception_table,
         };
 
-        methods.insert(name_and_signature, parsed_method);
+        methods.insert(name_and_signature, Rc::new(parsed_method));
     }
 
-    Class {
+    // Object is the only class without a superclass, which is represented by index 0
+    let super_class = match super_class {
+        0 => None,
+        index => Some(constant_pool.class_parser(&(index as usize))?),
+    };
+
+    // Static fields hold the default value for their type until they are first assigned, apart
+    // from constants which start out with the value of their ConstantValue attribute. String
+    // constants need a string on the heap, so they are null until the class is initialized
+    let mut string_constants = HashMap::new();
+    let static_fields = fields
+        .iter()
+        .filter(|field| field.access_flags & FieldFlags::Static as u16 != 0)
+        .map(|field| {
+            let name = constant_pool.utf8_parser(&(field.name as usize))?;
+            let descriptor = constant_pool.utf8_parser(&(field.descriptor as usize))?;
+
+            let constant_value = field
+                .attributes
+                .iter()
+                .find_map(|attribute| match attribute {
+                    Attribute::ConstantValue(constant_value) => {
+                        let index = constant_value.constant_value_index as usize;
+                        index
+                            .checked_sub(1)
+                            .and_then(|index| constant_pool.get(index))
+                    }
+                    _ => None,
+                });
+
+            let value = match constant_value {
+                Some(ConstantPoolEntry::String(index)) => {
+                    let text = constant_pool.utf8_parser(index)?;
+                    string_constants.insert(name.clone(), text);
+                    Primitive::Null
+                }
+                None => Primitive::default_for_descriptor(&descriptor),
+                Some(entry) => entry.get_primitive()?,
+            };
+
+            Ok((name, value))
+        })
+        .collect::<Result<_, String>>()?;
+
+    let instance_fields = fields
+        .iter()
+        .filter(|field| field.access_flags & FieldFlags::Static as u16 == 0)
+        .map(|field| {
+            let name = constant_pool.utf8_parser(&(field.name as usize))?;
+            let descriptor = constant_pool.utf8_parser(&(field.descriptor as usize))?;
+            Ok((name, Primitive::default_for_descriptor(&descriptor)))
+        })
+        .collect::<Result<_, String>>()?;
+
+    let final_fields = fields
+        .iter()
+        .filter(|field| field.access_flags & FieldFlags::Final as u16 != 0)
+        .map(|field| constant_pool.utf8_parser(&(field.name as usize)))
+        .collect::<Result<_, String>>()?;
+
+    let interfaces = interfaces
+        .iter()
+        .map(|interface| constant_pool.class_parser(&(interface.name as usize)))
+        .collect::<Result<_, String>>()?;
+
+    Ok(Class {
         name,
+        access_flags,
+        super_class,
+        interfaces,
+        bootstrap_methods,
         constant_pool,
-        static_fields: HashMap::new(),
+        initialized: false,
+        static_fields,
+        instance_fields,
+        string_constants,
+        final_fields,
         methods,
-    }
+        resolved_refs: HashMap::new(),
+    })
 }
@@ -0,0 +1,156 @@
+//! Parsing of the descriptors which describe the types of fields and methods in class files.
+use crate::Primitive;
+use std::fmt::{Display, Formatter};
+
+/// The type of a field, parameter or return value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    /// An instance of the class with the passed name, such as `java/lang/String`.
+    Object(String),
+    Array(Box<FieldType>),
+    /// Only valid as the return type of a method.
+    Void,
+}
+
+impl FieldType {
+    /// Parse a single field descriptor such as `I`, `[J` or `Ljava/lang/String;`.
+    pub fn parse(descriptor: &str) -> Result<FieldType, String> {
+        match parse_field_type(descriptor)? {
+            (field_type, "") => Ok(field_type),
+            _ => Err(format!("Invalid field descriptor {}", descriptor)),
+        }
+    }
+
+    /// Whether values of this type take up two slots in the locals.
+    pub fn is_wide(&self) -> bool {
+        matches!(self, FieldType::Long | FieldType::Double)
+    }
+
+    /// Whether a value can be passed where this type is expected. Booleans, bytes, chars and
+    /// shorts may be held as any of the int-like primitives.
+    pub fn accepts(&self, value: &Primitive) -> bool {
+        match self {
+            FieldType::Boolean
+            | FieldType::Byte
+            | FieldType::Char
+            | FieldType::Short
+            | FieldType::Int => matches!(
+                value,
+                Primitive::Int(_) | Primitive::Byte(_) | Primitive::Char(_) | Primitive::Short(_)
+            ),
+            FieldType::Long => matches!(value, Primitive::Long(_)),
+            FieldType::Float => matches!(value, Primitive::Float(_)),
+            FieldType::Double => matches!(value, Primitive::Double(_)),
+            FieldType::Object(_) | FieldType::Array(_) => {
+                matches!(value, Primitive::Reference(_) | Primitive::Null)
+            }
+            FieldType::Void => false,
+        }
+    }
+}
+
+impl Display for FieldType {
+    /// Write the type back out as a descriptor.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldType::Byte => write!(f, "B"),
+            FieldType::Char => write!(f, "C"),
+            FieldType::Double => write!(f, "D"),
+            FieldType::Float => write!(f, "F"),
+            FieldType::Int => write!(f, "I"),
+            FieldType::Long => write!(f, "J"),
+            FieldType::Short => write!(f, "S"),
+            FieldType::Boolean => write!(f, "Z"),
+            FieldType::Object(class_name) => write!(f, "L{};", class_name),
+            FieldType::Array(element_type) => write!(f, "[{}", element_type),
+            FieldType::Void => write!(f, "V"),
+        }
+    }
+}
+
+/// Parse the field type at the start of a descriptor, returning it along with the rest of the
+/// descriptor.
+fn parse_field_type(descriptor: &str) -> Result<(FieldType, &str), String> {
+    let mut chars = descriptor.chars();
+
+    let field_type = match chars.next() {
+        Some('B') => FieldType::Byte,
+        Some('C') => FieldType::Char,
+        Some('D') => FieldType::Double,
+        Some('F') => FieldType::Float,
+        Some('I') => FieldType::Int,
+        Some('J') => FieldType::Long,
+        Some('S') => FieldType::Short,
+        Some('Z') => FieldType::Boolean,
+        Some('V') => FieldType::Void,
+        Some('L') => {
+            let rest = chars.as_str();
+
+            return match rest.find(';') {
+                Some(end) if end > 0 => {
+                    Ok((FieldType::Object(rest[..end].to_string()), &rest[end + 1..]))
+                }
+                _ => Err(format!(
+                    "Unterminated class name in descriptor {}",
+                    descriptor
+                )),
+            };
+        }
+        Some('[') => {
+            let (element_type, rest) = parse_field_type(chars.as_str())?;
+
+            if element_type == FieldType::Void {
+                return Err(format!("Array of void in descriptor {}", descriptor));
+            }
+
+            return Ok((FieldType::Array(Box::new(element_type)), rest));
+        }
+        Some(c) => return Err(format!("Unknown type {} in descriptor {}", c, descriptor)),
+        None => return Err(String::from("Descriptor ended before its type")),
+    };
+
+    Ok((field_type, chars.as_str()))
+}
+
+/// Split a method descriptor such as `(I[JLjava/lang/String;)V` into its parameter types and its
+/// return type.
+pub fn parse_descriptor(descriptor: &str) -> Result<(Vec<FieldType>, FieldType), String> {
+    let mut rest = match descriptor.strip_prefix('(') {
+        Some(rest) => rest,
+        None => return Err(format!("Invalid method descriptor {}", descriptor)),
+    };
+
+    let mut parameters = Vec::new();
+
+    while !rest.starts_with(')') {
+        let (parameter, remaining) = match parse_field_type(rest) {
+            Ok(parsed) => parsed,
+            Err(err) => return Err(format!("Invalid method descriptor {}: {}", descriptor, err)),
+        };
+
+        if parameter == FieldType::Void {
+            return Err(format!(
+                "Void parameter in method descriptor {}",
+                descriptor
+            ));
+        }
+
+        parameters.push(parameter);
+        rest = remaining;
+    }
+
+    let return_type = match FieldType::parse(&rest[1..]) {
+        Ok(return_type) => return_type,
+        Err(err) => return Err(format!("Invalid method descriptor {}: {}", descriptor, err)),
+    };
+
+    Ok((parameters, return_type))
+}
@@ -0,0 +1,122 @@
+//! A mark-sweep garbage collector which compacts the heap, since references are heap indices.
+use crate::jvm::{Jvm, Object, ObjectData};
+use crate::Primitive;
+
+/// The values held by an object, which may reference other objects.
+fn object_values(object: &Object) -> impl Iterator<Item = &Primitive> {
+    let data: &[Primitive] = match &object.data {
+        ObjectData::Array(_, elements) => elements,
+        ObjectData::Lambda(lambda) => &lambda.captured,
+        ObjectData::Thread { target, .. } => std::slice::from_ref(target),
+        ObjectData::None | ObjectData::String(_) => &[],
+    };
+
+    object.fields.values().chain(data.iter())
+}
+
+fn object_values_mut(object: &mut Object) -> impl Iterator<Item = &mut Primitive> {
+    let data: &mut [Primitive] = match &mut object.data {
+        ObjectData::Array(_, elements) => elements,
+        ObjectData::Lambda(lambda) => &mut lambda.captured,
+        ObjectData::Thread { target, .. } => std::slice::from_mut(target),
+        ObjectData::None | ObjectData::String(_) => &mut [],
+    };
+
+    object.fields.values_mut().chain(data.iter_mut())
+}
+
+/// Point a reference at the new index of the object it referenced.
+fn relocate(value: &mut Primitive, new_indices: &[Option<usize>]) {
+    if let Primitive::Reference(reference) = value {
+        if let Some(Some(new_index)) = new_indices.get(*reference) {
+            *reference = *new_index;
+        }
+    }
+}
+
+impl Jvm {
+    /// Whether enough objects have been allocated since the last collection to collect again.
+    pub fn needs_collection(&self) -> bool {
+        self.heap.len().saturating_sub(self.heap_after_collection) >= self.gc_threshold
+    }
+
+    /// Free every object which can't be reached from the stack frames of any thread or static
+    /// fields, moving the remaining objects to the front of the heap and updating the references
+    /// to them. Returns the number of objects freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut marked = vec![false; self.heap.len()];
+
+        let mut pending = self
+            .stack_frames
+            .iter()
+            .chain(self.threads.iter().flat_map(|thread| &thread.stack_frames))
+            .flat_map(|sf| sf.locals.iter().chain(sf.stack.iter()))
+            .chain(
+                self.class_area
+                    .values()
+                    .flat_map(|class| class.static_fields.values()),
+            )
+            .filter_map(|value| match value {
+                Primitive::Reference(reference) => Some(*reference),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        while let Some(reference) = pending.pop() {
+            // Skip objects which were already marked, and invalid references
+            if marked.get(reference) != Some(&false) {
+                continue;
+            }
+            marked[reference] = true;
+
+            pending.extend(
+                object_values(&self.heap[reference]).filter_map(|value| match value {
+                    Primitive::Reference(reference) => Some(*reference),
+                    _ => None,
+                }),
+            );
+        }
+
+        let mut new_indices = Vec::with_capacity(marked.len());
+        let mut live_objects = 0;
+
+        for is_marked in &marked {
+            if *is_marked {
+                new_indices.push(Some(live_objects));
+                live_objects += 1;
+            } else {
+                new_indices.push(None);
+            }
+        }
+
+        let mut index = 0;
+        self.heap.retain(|_| {
+            index += 1;
+            marked[index - 1]
+        });
+
+        let values = self
+            .stack_frames
+            .iter_mut()
+            .chain(
+                self.threads
+                    .iter_mut()
+                    .flat_map(|thread| &mut thread.stack_frames),
+            )
+            .flat_map(|sf| sf.locals.iter_mut().chain(sf.stack.iter_mut()))
+            .chain(
+                self.class_area
+                    .values_mut()
+                    .flat_map(|class| class.static_fields.values_mut()),
+            )
+            .chain(self.heap.iter_mut().flat_map(object_values_mut));
+
+        for value in values {
+            relocate(value, &new_indices);
+        }
+
+        self.heap_after_collection = self.heap.len();
+        self.heap_size = self.heap.iter().map(Object::size).sum();
+        marked.len() - live_objects
+    }
+}
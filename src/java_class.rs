@@ -16,7 +16,10 @@ pub enum ConstantPoolEntry {
     NameAndType(usize, usize),        // name_index, descriptor_index
     MethodHandle(u8, usize),          // reference_kind, reference_index
     MethodType(usize),                // descriptor_index
+    Dynamic(usize, usize),            // bootstrap_method_attr_index, name_and_type_index
     InvokeDynamic(usize, usize),      // bootstrap_method_attr_index, name_and_type_index
+    /// The slot after a long or double, which take up two entries of the constant pool.
+    Unusable,
 }
 
 impl ConstantPoolEntry {
@@ -26,8 +29,6 @@ impl ConstantPoolEntry {
             ConstantPoolEntry::Float(f) => Primitive::Float(*f),
             ConstantPoolEntry::Long(l) => Primitive::Long(*l),
             ConstantPoolEntry::Double(d) => Primitive::Double(*d),
-            ConstantPoolEntry::Class(r) => Primitive::Reference(*r),
-            ConstantPoolEntry::String(r) => Primitive::Reference(*r), // TODO: this may be wrong
             ConstantPoolEntry::MethodHandle(_, r) => Primitive::Reference(*r),
             ConstantPoolEntry::MethodType(r) => Primitive::Reference(*r),
             _ => {
@@ -42,19 +43,30 @@ impl ConstantPoolEntry {
 pub trait ConstantPoolExt {
     fn find_utf8(&self, utf8: &str) -> Option<usize>;
     fn find_class(&self, class_name: &str) -> Option<usize>;
+    fn find_string(&self, value: &str) -> Option<usize>;
     fn find_name_and_type(&self, name: &str, type_: &str) -> Option<usize>;
     fn find_field_ref(&self, class_name: &str, name: &str, type_: &str) -> Option<usize>;
     fn find_method_ref(&self, class_name: &str, name: &str, type_: &str) -> Option<usize>;
+    fn find_interface_method_ref(&self, class_name: &str, name: &str, type_: &str)
+        -> Option<usize>;
     fn find_or_add_utf8(&mut self, value: &str) -> usize;
     fn find_or_add_class(&mut self, name: &str) -> usize;
+    fn find_or_add_string(&mut self, value: &str) -> usize;
     fn find_or_add_name_and_type(&mut self, name: &str, descriptor: &str) -> usize;
     fn find_or_add_method_ref(&mut self, class_name: &str, name: &str, descriptor: &str) -> usize;
+    fn find_or_add_interface_method_ref(
+        &mut self,
+        class_name: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> usize;
     fn find_or_add_field_ref(&mut self, class_name: &str, name: &str, descriptor: &str) -> usize;
-    fn utf8_parser(&self, index: &usize) -> Option<String>;
-    fn class_parser(&self, index: &usize) -> Option<String>;
-    fn name_and_type_parser(&self, index: &usize) -> Option<(String, String)>;
-    fn method_ref_parser(&self, index: &usize) -> Option<(String, String, String)>;
-    fn field_ref_parser(&self, index: &usize) -> Option<(String, String, String)>;
+    fn utf8_parser(&self, index: &usize) -> Result<String, String>;
+    fn class_parser(&self, index: &usize) -> Result<String, String>;
+    fn name_and_type_parser(&self, index: &usize) -> Result<(String, String), String>;
+    fn method_ref_parser(&self, index: &usize) -> Result<(String, String, String), String>;
+    fn field_ref_parser(&self, index: &usize) -> Result<(String, String, String), String>;
+    fn method_handle_parser(&self, index: &usize) -> Result<(u8, String, String, String), String>;
 }
 
 impl ConstantPoolExt for Vec<ConstantPoolEntry> {
@@ -81,6 +93,18 @@ impl ConstantPoolExt for Vec<ConstantPoolEntry> {
         None
     }
 
+    fn find_string(&self, value: &str) -> Option<usize> {
+        let value_index = self.find_utf8(value)?;
+        for (i, entry) in self.iter().enumerate() {
+            if let ConstantPoolEntry::String(string_index) = entry {
+                if *string_index == value_index {
+                    return Some(i + 1);
+                }
+            }
+        }
+        None
+    }
+
     fn find_name_and_type(&self, name: &str, descriptor: &str) -> Option<usize> {
         let name_index = self.find_utf8(name)?;
         let type_index = self.find_utf8(descriptor)?;
@@ -120,6 +144,24 @@ impl ConstantPoolExt for Vec<ConstantPoolEntry> {
         None
     }
 
+    fn find_interface_method_ref(
+        &self,
+        class_name: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<usize> {
+        let class_index = self.find_class(class_name)?;
+        let name_and_type_index = self.find_name_and_type(name, descriptor)?;
+        for (i, entry) in self.iter().enumerate() {
+            if let ConstantPoolEntry::InterfaceMethodRef(c, n) = entry {
+                if *c == class_index && *n == name_and_type_index {
+                    return Some(i + 1);
+                }
+            }
+        }
+        None
+    }
+
     fn find_or_add_utf8(&mut self, value: &str) -> usize {
         match self.find_utf8(value) {
             Some(index) => index,
@@ -141,6 +183,17 @@ impl ConstantPoolExt for Vec<ConstantPoolEntry> {
         }
     }
 
+    fn find_or_add_string(&mut self, value: &str) -> usize {
+        match self.find_string(value) {
+            Some(index) => index,
+            None => {
+                let value_index = self.find_or_add_utf8(value);
+                self.push(ConstantPoolEntry::String(value_index));
+                self.len()
+            }
+        }
+    }
+
     fn find_or_add_name_and_type(&mut self, name: &str, descriptor: &str) -> usize {
         match self.find_name_and_type(name, descriptor) {
             Some(index) => index,
@@ -168,6 +221,26 @@ impl ConstantPoolExt for Vec<ConstantPoolEntry> {
         }
     }
 
+    fn find_or_add_interface_method_ref(
+        &mut self,
+        class_name: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> usize {
+        match self.find_interface_method_ref(class_name, name, descriptor) {
+            Some(index) => index,
+            None => {
+                let class_index = self.find_or_add_class(class_name);
+                let name_and_type_index = self.find_or_add_name_and_type(name, descriptor);
+                self.push(ConstantPoolEntry::InterfaceMethodRef(
+                    class_index,
+                    name_and_type_index,
+                ));
+                self.len()
+            }
+        }
+    }
+
     fn find_or_add_field_ref(&mut self, class_name: &str, name: &str, descriptor: &str) -> usize {
         match self.find_field_ref(class_name, name, descriptor) {
             Some(index) => index,
@@ -183,49 +256,72 @@ impl ConstantPoolExt for Vec<ConstantPoolEntry> {
         }
     }
 
-    fn utf8_parser(&self, index: &usize) -> Option<String> {
-        if let ConstantPoolEntry::Utf8(value) = &self[index - 1] {
-            return Some(value.clone());
+    fn utf8_parser(&self, index: &usize) -> Result<String, String> {
+        match self.get(index.wrapping_sub(1)) {
+            Some(ConstantPoolEntry::Utf8(value)) => Ok(value.clone()),
+            _ => Err(format!("Constant pool entry {} is not Utf8", index)),
         }
-        None
     }
 
-    fn class_parser(&self, index: &usize) -> Option<String> {
-        if let ConstantPoolEntry::Class(name_index) = self.get(index - 1)? {
-            return self.utf8_parser(name_index);
+    fn class_parser(&self, index: &usize) -> Result<String, String> {
+        match self.get(index.wrapping_sub(1)) {
+            Some(ConstantPoolEntry::Class(name_index)) => self.utf8_parser(name_index),
+            _ => Err(format!("Constant pool entry {} is not a Class", index)),
         }
-        None
     }
 
-    fn name_and_type_parser(&self, index: &usize) -> Option<(String, String)> {
-        if let ConstantPoolEntry::NameAndType(name_index, type_index) = self.get(index - 1)? {
-            let name = self.utf8_parser(name_index)?;
-            let descriptor = self.utf8_parser(type_index)?;
-            return Some((name, descriptor));
+    fn name_and_type_parser(&self, index: &usize) -> Result<(String, String), String> {
+        match self.get(index.wrapping_sub(1)) {
+            Some(ConstantPoolEntry::NameAndType(name_index, type_index)) => {
+                let name = self.utf8_parser(name_index)?;
+                let descriptor = self.utf8_parser(type_index)?;
+                Ok((name, descriptor))
+            }
+            _ => Err(format!(
+                "Constant pool entry {} is not a NameAndType",
+                index
+            )),
         }
-        None
     }
 
-    fn method_ref_parser(&self, index: &usize) -> Option<(String, String, String)> {
-        if let ConstantPoolEntry::MethodRef(class_index, name_and_type_index) =
-            self.get(index - 1)?
-        {
-            let class_name = self.class_parser(class_index)?;
-            let (name, descriptor) = self.name_and_type_parser(name_and_type_index)?;
-            return Some((class_name, name, descriptor));
+    fn method_ref_parser(&self, index: &usize) -> Result<(String, String, String), String> {
+        match self.get(index.wrapping_sub(1)) {
+            Some(
+                ConstantPoolEntry::MethodRef(class_index, name_and_type_index)
+                | ConstantPoolEntry::InterfaceMethodRef(class_index, name_and_type_index),
+            ) => {
+                let class_name = self.class_parser(class_index)?;
+                let (name, descriptor) = self.name_and_type_parser(name_and_type_index)?;
+                Ok((class_name, name, descriptor))
+            }
+            _ => Err(format!("Constant pool entry {} is not a MethodRef", index)),
+        }
+    }
+
+    fn field_ref_parser(&self, index: &usize) -> Result<(String, String, String), String> {
+        match self.get(index.wrapping_sub(1)) {
+            Some(ConstantPoolEntry::FieldRef(class_index, name_and_type_index)) => {
+                let class_name = self.class_parser(class_index)?;
+                let (name, descriptor) = self.name_and_type_parser(name_and_type_index)?;
+                Ok((class_name, name, descriptor))
+            }
+            _ => Err(format!("Constant pool entry {} is not a FieldRef", index)),
         }
-        None
     }
 
-    fn field_ref_parser(&self, index: &usize) -> Option<(String, String, String)> {
-        if let ConstantPoolEntry::FieldRef(class_index, name_and_type_index) =
-            self.get(index - 1)?
-        {
-            let class_name = self.class_parser(class_index)?;
-            let (name, descriptor) = self.name_and_type_parser(name_and_type_index)?;
-            return Some((class_name, name, descriptor));
+    /// Parse a method handle into its reference kind and the class, name and descriptor of the
+    /// method it refers to.
+    fn method_handle_parser(&self, index: &usize) -> Result<(u8, String, String, String), String> {
+        match self.get(index.wrapping_sub(1)) {
+            Some(ConstantPoolEntry::MethodHandle(reference_kind, reference_index)) => {
+                let (class_name, name, descriptor) = self.method_ref_parser(reference_index)?;
+                Ok((*reference_kind, class_name, name, descriptor))
+            }
+            _ => Err(format!(
+                "Constant pool entry {} is not a MethodHandle",
+                index
+            )),
         }
-        None
     }
 }
 
@@ -276,6 +372,35 @@ impl ClassFlags {
     }
 }
 
+#[derive(Debug)]
+pub enum MethodFlags {
+    Public = 0x0001,
+    Private = 0x0002,
+    Protected = 0x0004,
+    Static = 0x0008,
+    Final = 0x0010,
+    Synchronized = 0x0020,
+    Bridge = 0x0040,
+    Varargs = 0x0080,
+    Native = 0x0100,
+    Abstract = 0x0400,
+    Strict = 0x0800,
+    Synthetic = 0x1000,
+}
+
+#[derive(Debug)]
+pub enum FieldFlags {
+    Public = 0x0001,
+    Private = 0x0002,
+    Protected = 0x0004,
+    Static = 0x0008,
+    Final = 0x0010,
+    Volatile = 0x0040,
+    Transient = 0x0080,
+    Synthetic = 0x1000,
+    Enum = 0x4000,
+}
+
 #[derive(Debug)]
 pub struct Interface {
     pub name: u16,
@@ -315,6 +440,7 @@ pub enum Attribute {
     LocalVariableTable(LocalVariableTableAttribute),
     LocalVariableTypeTable(LocalVariableTypeTableAttribute),
     Deprecated(DeprecatedAttribute),
+    BootstrapMethods(BootstrapMethodsAttribute),
 }
 
 #[derive(Debug)]
@@ -333,17 +459,69 @@ pub struct CodeAttribute {
     pub code_length: u32,
     pub code: Vec<u8>,
     pub exception_table_length: u16,
-    pub exception_table: Vec<u8>,
+    /// The handlers of the code, with byte offsets rather than instruction indices.
+    pub exception_table: Vec<ExceptionTableEntry>,
     pub attributes_count: u16,
     pub attributes: Vec<Attribute>,
 }
 
+/// A handler in the exception table of a method. When an instruction in `start_pc..end_pc` throws,
+/// the handlers covering it are tried in order and the first that catches the exception is jumped
+/// to, with the exception as the only value on the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExceptionTableEntry {
+    pub start_pc: usize,
+    pub end_pc: usize,
+    pub handler_pc: usize,
+    /// The constant pool index of the class caught by the handler, or 0 to catch any exception.
+    pub catch_type: u16,
+}
+
 #[derive(Debug)]
 pub struct StackMapTableAttribute {
     pub attribute_name_index: u16,
     pub attribute_length: u32,
     pub number_of_entries: u16,
-    pub entries: Vec<u8>,
+    pub entries: Vec<StackMapFrame>,
+}
+
+/// The types of the locals and stack at an offset, described relative to the previous frame. The
+/// first frame is relative to the locals implied by the method descriptor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackMapFrame {
+    /// The offset of this frame is the offset of the previous frame plus this delta plus one, or
+    /// just this delta for the first frame.
+    pub offset_delta: u16,
+    pub frame_type: StackMapFrameType,
+}
+
+/// How the locals and stack of a stack map frame differ from those of the previous frame. Longs
+/// and doubles take up a single entry in the locals and stack of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackMapFrameType {
+    /// The same locals as the previous frame and an empty stack.
+    Same,
+    /// The same locals as the previous frame and a single value on the stack.
+    SameLocals1StackItem(VerificationType),
+    /// The previous frame with the passed number of locals removed and an empty stack.
+    Chop(u8),
+    /// The previous frame with the passed locals added and an empty stack.
+    Append(Vec<VerificationType>),
+    /// Locals and stack given in full.
+    Full(Vec<VerificationType>, Vec<VerificationType>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object(u16),        // class_index
+    Uninitialized(u16), // offset of the new instruction
 }
 
 #[derive(Debug)]
@@ -451,3 +629,19 @@ pub struct DeprecatedAttribute {
     pub attribute_name_index: u16,
     pub attribute_length: u32,
 }
+
+#[derive(Debug)]
+pub struct BootstrapMethodsAttribute {
+    pub attribute_name_index: u16,
+    pub attribute_length: u32,
+    pub num_bootstrap_methods: u16,
+    pub bootstrap_methods: Vec<BootstrapMethod>,
+}
+
+/// The method handle and static arguments used to link an invokedynamic call site.
+#[derive(Debug, Clone)]
+pub struct BootstrapMethod {
+    pub bootstrap_method_ref: u16,
+    pub num_bootstrap_arguments: u16,
+    pub bootstrap_arguments: Vec<u16>,
+}
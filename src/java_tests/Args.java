@@ -0,0 +1,6 @@
+public class Args {
+    public static void main(String[] args) {
+        System.out.println(args.length);
+        System.out.println(args[0]);
+    }
+}
@@ -0,0 +1,13 @@
+import java.util.Arrays;
+
+public class ArraysToString {
+    public static void main(String[] args) {
+        System.out.println(Arrays.toString(new int[]{1, 2, 3}));
+        System.out.println(Arrays.toString(new double[]{1, 0}));
+        System.out.println(Arrays.toString(new long[]{}));
+        System.out.println(Arrays.toString(new char[]{'h', 'i'}));
+
+        int[] missing = null;
+        System.out.println(Arrays.toString(missing));
+    }
+}
@@ -0,0 +1,9 @@
+public class Assert {
+    public static void main(String[] args) {
+        int x = 3;
+        assert x > 0;
+        System.out.println(x);
+        assert x > 5 : "x is " + x;
+        System.out.println(x + 1);
+    }
+}
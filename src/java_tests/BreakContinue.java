@@ -0,0 +1,32 @@
+public class BreakContinue {
+    public static void main(String[] args) {
+        int i = 0;
+        do {
+            i = i + 1;
+            if (i == 3) {
+                continue;
+            }
+            if (i == 5) {
+                break;
+            }
+            System.out.println(i);
+        } while (i < 10);
+        System.out.println(i);
+
+        // Breaking out of the inner loop carries on with the outer one
+        int outer = 0;
+        int total = 0;
+        do {
+            int inner = 0;
+            do {
+                inner = inner + 1;
+                if (inner == 2) {
+                    break;
+                }
+                total = total + 1;
+            } while (inner < 10);
+            outer = outer + 1;
+        } while (outer < 3);
+        System.out.println(total);
+    }
+}
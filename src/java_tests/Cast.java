@@ -0,0 +1,10 @@
+public class Cast {
+    public static void main(String[] args) {
+        int x = (int) 3.9;
+        System.out.println(x);
+        double y = (double) 5;
+        System.out.println(y);
+        long z = (long) x;
+        System.out.println((int) z);
+    }
+}
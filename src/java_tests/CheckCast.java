@@ -0,0 +1,27 @@
+class CastBase {
+}
+
+class CastDerived extends CastBase {
+}
+
+public class CheckCast {
+    public static void main(String[] args) {
+        Object o = new CastDerived();
+        CastBase base = (CastBase) o;
+        if (o instanceof CastDerived derived) {
+            System.out.println(1);
+        }
+
+        Object s = "text";
+        if (s instanceof String str) {
+            System.out.println(str.length());
+        }
+
+        try {
+            CastDerived bad = (CastDerived) new CastBase();
+            System.out.println(0);
+        } catch (ClassCastException e) {
+            System.out.println(2);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+public class Clock {
+    public static void main(String[] args) {
+        long start = System.currentTimeMillis();
+        long end = System.currentTimeMillis();
+
+        System.out.println(start);
+        System.out.println(end - start);
+    }
+}
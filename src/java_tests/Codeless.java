@@ -0,0 +1,19 @@
+abstract class Animal {
+    abstract int legs();
+}
+
+class Dog extends Animal {
+    int legs() {
+        return 4;
+    }
+}
+
+public class Codeless {
+    static native int nativeValue();
+
+    public static void main(String[] args) {
+        Animal animal = new Dog();
+        System.out.println(animal.legs());
+        System.out.println(nativeValue());
+    }
+}
@@ -0,0 +1,13 @@
+public class Constants {
+    static final int ANSWER = 42;
+    static final float HALF = 0.5f;
+    static final char LETTER = 'j';
+    static final boolean ENABLED = true;
+    static final String NAME = "rustjava";
+    static int counter;
+
+    public static void main(String[] args) {
+        counter = ANSWER + 1;
+        System.out.println(counter);
+    }
+}
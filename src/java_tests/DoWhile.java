@@ -0,0 +1,16 @@
+public class DoWhile {
+    public static void main(String[] args) {
+        // The body runs once even though the condition is false from the start
+        int x = 10;
+        do {
+            System.out.println(x);
+            x = x + 1;
+        } while (x < 5);
+
+        int i = 0;
+        do {
+            i = i + 1;
+        } while (i < 3);
+        System.out.println(i);
+    }
+}
@@ -0,0 +1,28 @@
+class Plain {
+}
+
+class Always {
+    public boolean equals(Object other) {
+        return true;
+    }
+}
+
+public class Equality {
+    static int check(boolean condition) {
+        return condition ? 1 : 0;
+    }
+
+    public static void main(String[] args) {
+        Plain first = new Plain();
+        Plain second = new Plain();
+        Object always = new Always();
+
+        System.out.println(check(first.equals(first)));
+        System.out.println(check(first.equals(second)));
+        System.out.println(check(first.hashCode() == first.hashCode()));
+        System.out.println(check(first.hashCode() == second.hashCode()));
+
+        // The override is found through the class of the object, not the type of the variable
+        System.out.println(check(always.equals(first)));
+    }
+}
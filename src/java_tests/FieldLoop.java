@@ -0,0 +1,17 @@
+public class FieldLoop {
+    public static int total = 0;
+    public int count;
+
+    public FieldLoop() {
+        count = 0;
+    }
+
+    public static void main(String[] args) {
+        FieldLoop f = new FieldLoop();
+        for (int i = 0; i < 100000; i++) {
+            total = total + 1;
+            f.count = f.count + 2;
+        }
+        System.out.println(total + f.count);
+    }
+}
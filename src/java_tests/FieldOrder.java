@@ -0,0 +1,18 @@
+public class FieldOrder {
+    int zeta;
+    int alpha;
+    String mid;
+    long beta;
+
+    FieldOrder() {
+        zeta = 26;
+        alpha = 1;
+        mid = "m";
+        beta = 2;
+    }
+
+    public static void main(String[] args) {
+        FieldOrder object = new FieldOrder();
+        object.alpha = 7;
+    }
+}
@@ -0,0 +1,19 @@
+public class Final {
+    static final int[] TABLE = new int[2];
+    final int value;
+    int mutable;
+
+    Final(int value) {
+        this.value = value;
+    }
+
+    void update(int mutable) {
+        this.mutable = mutable;
+    }
+
+    public static void main(String[] args) {
+        Final f = new Final(3);
+        f.update(4);
+        System.out.println(f.value + f.mutable + TABLE[0]);
+    }
+}
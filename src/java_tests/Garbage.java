@@ -0,0 +1,37 @@
+public class Garbage {
+    static GarbageNode first;
+
+    public static void main(String[] args) {
+        first = new GarbageNode(1000, null);
+        GarbageNode list = null;
+
+        for (int i = 0; i < 100; i++) {
+            list = new GarbageNode(i, list);
+
+            // Only the list and the first node outlive each iteration
+            for (int j = 0; j < 20; j++) {
+                int[] temporary = new int[4];
+                temporary[0] = j;
+                new GarbageNode(temporary[0], list);
+            }
+        }
+
+        int sum = 0;
+        for (GarbageNode node = list; node != null; node = node.next) {
+            sum += node.value;
+        }
+
+        System.out.println(sum);
+        System.out.println(first.value);
+    }
+}
+
+class GarbageNode {
+    int value;
+    GarbageNode next;
+
+    GarbageNode(int value, GarbageNode next) {
+        this.value = value;
+        this.next = next;
+    }
+}
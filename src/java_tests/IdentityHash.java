@@ -0,0 +1,22 @@
+public class IdentityHash {
+    static int check(boolean condition) {
+        return condition ? 1 : 0;
+    }
+
+    public static void main(String[] args) {
+        for (int i = 0; i < 150; i++) {
+            new IdentityHash();
+        }
+
+        IdentityHash kept = new IdentityHash();
+        int hash = kept.hashCode();
+
+        // Collecting the garbage moves the kept object to the front of the heap
+        for (int i = 0; i < 150; i++) {
+            new IdentityHash();
+        }
+
+        System.out.println(check(kept.hashCode() == hash));
+        System.out.println(check(new IdentityHash().hashCode() != hash));
+    }
+}
@@ -0,0 +1,9 @@
+public class InfiniteLoop {
+    public static void main(String[] args) {
+        int i = 0;
+
+        while (true) {
+            i++;
+        }
+    }
+}
@@ -0,0 +1,7 @@
+public class IntegerParse {
+    public static void main(String[] args) {
+        int x = Integer.parseInt("42");
+        System.out.println(x + 1);
+        System.out.println(Integer.toString(7));
+    }
+}
@@ -0,0 +1,6 @@
+public class IntegerParseMalformed {
+    public static void main(String[] args) {
+        int x = Integer.parseInt("4x2");
+        System.out.println(x);
+    }
+}
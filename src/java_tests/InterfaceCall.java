@@ -0,0 +1,39 @@
+interface Shape {
+    int area();
+}
+
+class Square implements Shape {
+    int side;
+
+    Square(int side) {
+        this.side = side;
+    }
+
+    public int area() {
+        return side * side;
+    }
+}
+
+class Rectangle implements Shape {
+    int width;
+    int height;
+
+    Rectangle(int width, int height) {
+        this.width = width;
+        this.height = height;
+    }
+
+    public int area() {
+        return width * height;
+    }
+}
+
+public class InterfaceCall {
+    public static void main(String[] args) {
+        Shape shape = new Square(4);
+        System.out.println(shape.area());
+
+        shape = new Rectangle(2, 3);
+        System.out.println(shape.area());
+    }
+}
@@ -0,0 +1,15 @@
+interface IntOp {
+    int apply(int value);
+}
+
+public class Lambda {
+    public static void main(String[] args) {
+        int offset = 10;
+
+        IntOp square = x -> x * x;
+        IntOp shift = x -> x + offset;
+
+        System.out.println(square.apply(4));
+        System.out.println(shift.apply(5));
+    }
+}
@@ -0,0 +1,13 @@
+public class Ldc {
+    public static void main(String[] args) {
+        System.out.println("hi");
+        System.out.println(String.class.getName());
+        System.out.println(Ldc.class.getName());
+
+        Class<?> first = Ldc.class;
+        Class<?> second = Ldc.class;
+        if (first == second) {
+            System.out.println(1);
+        }
+    }
+}
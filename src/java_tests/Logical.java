@@ -0,0 +1,13 @@
+public class Logical {
+    public static void main(String[] args) {
+        int x = 3;
+        int y = 0 - 2;
+        boolean both = x > 0 && y > 0;
+        boolean either = x > 0 || y > 0;
+        boolean nested = (x > 0 || y > 0) && x != y;
+        System.out.println(both);
+        System.out.println(either);
+        System.out.println(nested);
+        System.out.println(x == 3);
+    }
+}
@@ -0,0 +1,11 @@
+public class LongCompare {
+    public static void main(String[] args) {
+        int shift = 63;
+        long zero = 0;
+        long min = 1L << shift;
+        long max = zero - (min + 1);
+
+        System.out.println(max > min);
+        System.out.println(min > max);
+    }
+}
@@ -0,0 +1,8 @@
+public class MathBuiltins {
+    public static void main(String[] args) {
+        int n = 16;
+        System.out.println(Math.max(3, 7));
+        System.out.println(Math.abs(-5));
+        System.out.println(Math.sqrt(n));
+    }
+}
@@ -0,0 +1,15 @@
+public class MathSignedZero {
+    public static void main(String[] args) {
+        double negative = -0.0;
+        float negativeFloat = -0.0f;
+
+        // Negative zero is below positive zero, in either order
+        System.out.println(Math.max(negative, 0.0));
+        System.out.println(Math.min(0.0, negative));
+        System.out.println(Math.max(0.0f, negativeFloat));
+        System.out.println(Math.min(negativeFloat, 0.0f));
+
+        System.out.println(Math.max(1.5f, -2.0f));
+        System.out.println(Math.min(Float.NaN, 1.0f));
+    }
+}
@@ -0,0 +1,13 @@
+public class MultiArray {
+    public static void main(String[] args) {
+        int[][] grid = new int[2][3];
+        grid[1][2] = 7;
+
+        System.out.println(grid[1][2]);
+        System.out.println(grid[0][1]);
+        System.out.println(grid[1].length);
+
+        int[][][] cube = new int[2][3][];
+        System.out.println(cube[1][2] == null);
+    }
+}
@@ -0,0 +1,7 @@
+public class NegativeMultiArray {
+    public static void main(String[] args) {
+        int inner = -1;
+        int[][] grid = new int[100000][inner];
+        System.out.println(grid.length);
+    }
+}
@@ -0,0 +1,18 @@
+public class ObjectArray {
+    public static void main(String[] args) {
+        String[] names = new String[3];
+        names[0] = "a";
+        names[2] = "c";
+        System.out.println(names[0]);
+        if (names[1] == null) {
+            System.out.println(names.length);
+        }
+        System.out.println(names[2]);
+
+        int[][] rows = new int[2][];
+        rows[1] = new int[4];
+        if (rows[0] == null) {
+            System.out.println(rows[1].length);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+public class OutOfMemory {
+    OutOfMemory next;
+    int[] data;
+
+    public static void main(String[] args) {
+        OutOfMemory list = null;
+
+        // Every node stays reachable through the next one, so the heap can only grow
+        while (true) {
+            OutOfMemory node = new OutOfMemory();
+            node.next = list;
+            node.data = new int[16];
+            list = node;
+        }
+    }
+}
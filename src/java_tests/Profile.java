@@ -0,0 +1,13 @@
+public class Profile {
+    public static void main(String[] args) {
+        int evens = 0;
+        int odds = 0;
+
+        for (int i = 0; i < 100; i++) {
+            evens += 2;
+            odds += 3;
+        }
+
+        System.out.println(evens + odds);
+    }
+}
@@ -0,0 +1,32 @@
+public class StackMap {
+    static int collatz(int n) {
+        int steps = 0;
+        while (n != 1) {
+            if (n % 2 == 0) {
+                n = n / 2;
+            } else {
+                n = 3 * n + 1;
+            }
+            steps++;
+        }
+        return steps;
+    }
+
+    static int max(int a, int b) {
+        return a > b ? a : b;
+    }
+
+    static long sum(int n) {
+        long total = 0;
+        for (int i = 0; i < n; i++) {
+            total += i;
+        }
+        return total;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(collatz(27));
+        System.out.println(max(3, 7));
+        System.out.println(sum(10));
+    }
+}
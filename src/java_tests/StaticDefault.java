@@ -0,0 +1,15 @@
+public class StaticDefault {
+    static int count;
+    static boolean flag;
+    static String name;
+
+    public static void main(String[] args) {
+        System.out.println(count);
+        count++;
+        System.out.println(count);
+        System.out.println(flag);
+        if (name == null) {
+            System.out.println(7);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+class StaticBase {
+    static int count = 5;
+}
+
+public class StaticInherit extends StaticBase {
+    public static void main(String[] args) {
+        // Both accesses name this class, but the field is declared by its superclass
+        StaticInherit.count += 2;
+        System.out.println(StaticInherit.count);
+        System.out.println(StaticBase.count);
+    }
+}
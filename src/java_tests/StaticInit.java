@@ -0,0 +1,19 @@
+class Config {
+    static int base;
+
+    static {
+        System.out.println(1);
+        base = 10;
+    }
+}
+
+class Derived {
+    static int value = Config.base * 2;
+}
+
+public class StaticInit {
+    public static void main(String[] args) {
+        System.out.println(0);
+        System.out.println(Derived.value);
+    }
+}
@@ -0,0 +1,9 @@
+public class StringConcat {
+    public static void main(String[] args) {
+        String name = "world";
+        int count = 3;
+        char mark = '!';
+
+        System.out.println("hello " + name + " x" + count + mark);
+    }
+}
@@ -0,0 +1,10 @@
+public class StringEquality {
+    public static void main(String[] args) {
+        String hello = "he".concat("llo");
+        System.out.println(hello);
+        System.out.println(hello.equals("hello"));
+        System.out.println(hello.equals("world"));
+        System.out.println(hello.equals(args));
+        System.out.println(hello.concat(", world").length());
+    }
+}
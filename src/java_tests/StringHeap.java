@@ -0,0 +1,22 @@
+public class StringHeap {
+    static void keep(int count) {
+        String[] kept = new String[count];
+        for (int i = 0; i < count; i++) {
+            kept[i] = "s" + i;
+        }
+        System.out.println(kept[count - 1]);
+    }
+
+    static void discard(int count) {
+        String last = "";
+        for (int i = 0; i < count; i++) {
+            last = "s" + i;
+        }
+        System.out.println(last);
+    }
+
+    public static void main(String[] args) {
+        discard(5000);
+        keep(500);
+    }
+}
@@ -0,0 +1,6 @@
+public class StringIndexOutOfBounds {
+    public static void main(String[] args) {
+        String s = "hello";
+        System.out.println(s.charAt(10));
+    }
+}
@@ -0,0 +1,5 @@
+public class StringLiteral {
+    public static void main(String[] args) {
+        System.out.println("hello");
+    }
+}
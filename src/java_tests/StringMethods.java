@@ -0,0 +1,7 @@
+public class StringMethods {
+    public static void main(String[] args) {
+        String s = "hello";
+        System.out.println(s.length());
+        System.out.println(s.charAt(1));
+    }
+}
@@ -0,0 +1,39 @@
+public class Switch {
+    public static void main(String[] args) {
+        int i = 0;
+        do {
+            System.out.println(name(i));
+            i = i + 1;
+        } while (i < 5);
+
+        System.out.println(sparse(1000));
+        System.out.println(sparse(7));
+    }
+
+    static int name(int value) {
+        int result = 0;
+        switch (value) {
+            case 1:
+                result = 10;
+                break;
+            case 2:
+                result = 20;
+            case 3:
+                result = result + 30;
+                break;
+            default:
+                result = 0 - 1;
+        }
+        return result;
+    }
+
+    static int sparse(int value) {
+        switch (value) {
+            case 10:
+                return 1;
+            case 1000:
+                return 2;
+        }
+        return 0;
+    }
+}
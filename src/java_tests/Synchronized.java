@@ -0,0 +1,14 @@
+public class Synchronized {
+    static int counter;
+
+    public static void main(String[] args) {
+        Object lock = new Object();
+        synchronized (lock) {
+            counter = counter + 1;
+            synchronized (lock) {
+                counter = counter + 2;
+            }
+        }
+        System.out.println(counter);
+    }
+}
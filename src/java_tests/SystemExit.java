@@ -0,0 +1,7 @@
+public class SystemExit {
+    public static void main(String[] args) {
+        System.out.println(1);
+        System.exit(42);
+        System.out.println(2);
+    }
+}
@@ -0,0 +1,30 @@
+public class ThreadSubclass {
+    public static void main(String[] args) throws InterruptedException {
+        Worker worker = new Worker(3);
+        worker.start();
+        worker.join();
+        System.out.println(worker.id);
+        System.out.println(worker.target);
+
+        try {
+            worker.start();
+        } catch (IllegalThreadStateException e) {
+            System.out.println(7);
+        }
+    }
+}
+
+// The fields share their names with the bookkeeping of a thread
+class Worker extends Thread {
+    int id;
+    String target;
+
+    Worker(int id) {
+        this.id = id;
+    }
+
+    public void run() {
+        id = id * 10;
+        target = "done";
+    }
+}
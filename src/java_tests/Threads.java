@@ -0,0 +1,26 @@
+class Incrementer implements Runnable {
+    public void run() {
+        for (int i = 0; i < 50; i++) {
+            synchronized (Threads.LOCK) {
+                Threads.counter = Threads.counter + 1;
+            }
+        }
+    }
+}
+
+public class Threads {
+    static final Object LOCK = new Object();
+    static int counter;
+
+    public static void main(String[] args) throws InterruptedException {
+        Thread first = new Thread(new Incrementer());
+        Thread second = new Thread(new Incrementer());
+
+        first.start();
+        second.start();
+        first.join();
+        second.join();
+
+        System.out.println(counter);
+    }
+}
@@ -0,0 +1,24 @@
+public class TryCatch {
+    static int check(int value) {
+        try {
+            if (value < 0) {
+                throw new Failure();
+            }
+            System.out.println(value);
+        } catch (Failure e) {
+            System.out.println(-1);
+            return 0;
+        } finally {
+            System.out.println(99);
+        }
+        return 1;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(check(5));
+        System.out.println(check(-5));
+    }
+}
+
+class Failure extends RuntimeException {
+}
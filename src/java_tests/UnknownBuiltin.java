@@ -0,0 +1,5 @@
+public class UnknownBuiltin {
+    public static void main(String[] args) {
+        System.out.println(Math.floor(2.5));
+    }
+}
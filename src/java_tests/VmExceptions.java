@@ -0,0 +1,35 @@
+public class VmExceptions {
+    static int divide(int a, int b) {
+        return a / b;
+    }
+
+    public static void main(String[] args) {
+        try {
+            System.out.println(divide(1, 0));
+        } catch (ArithmeticException e) {
+            System.out.println(e.getMessage());
+        }
+
+        int[] numbers = new int[2];
+        try {
+            numbers[5] = 1;
+        } catch (IndexOutOfBoundsException e) {
+            System.out.println(e.getMessage());
+        }
+
+        String missing = null;
+        try {
+            System.out.println(missing.length());
+        } catch (NullPointerException e) {
+            System.out.println("npe");
+        }
+
+        try {
+            throw new IllegalStateException("state");
+        } catch (RuntimeException e) {
+            System.out.println(e.getMessage());
+        }
+
+        System.out.println(divide(2, 0));
+    }
+}
@@ -0,0 +1,12 @@
+public class Wide {
+    static final long BIG = 1234567890123L;
+
+    public static void main(String[] args) {
+        long big = 9876543210L;
+        double ratio = 2.5;
+        int small = 70000;
+        System.out.println(big + 1);
+        System.out.println(ratio * 2);
+        System.out.println(small);
+    }
+}
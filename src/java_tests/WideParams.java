@@ -0,0 +1,12 @@
+public class WideParams {
+    static long scale(long value, int factor) {
+        long result = value * (long) factor;
+        int offset = factor + 1;
+        return result + (long) offset;
+    }
+
+    public static void main(String[] args) {
+        int base = 7;
+        System.out.println(scale((long) base, 3));
+    }
+}
@@ -1,22 +1,27 @@
-use std::any::Any;
-use crate::java_class::{ConstantPoolEntry, ConstantPoolExt};
+use crate::java_class::{
+    ClassFlags, ConstantPoolEntry, ConstantPoolExt, ExceptionTableEntry, MethodFlags,
+};
 use crate::jvm::{Class, Method};
-use crate::{Comparison, Instruction, Primitive, PrimitiveType};
-use std::collections::HashMap;
+use crate::{optimizer, stack_map};
+use crate::{Comparison, Instruction, Operator, Primitive, PrimitiveType};
+use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use tree_sitter::{Node, Parser};
 
 trait NodeExt {
-    fn child_by_kind(&self, kind: &str) -> Result<Node, String>;
-    fn children_by_kind(&self, kind: &str) -> Vec<Node>;
-    fn get_children(&self) -> Vec<Node>;
+    fn child_by_kind(&self, kind: &str) -> Result<Node<'_>, String>;
+    fn children_by_kind(&self, kind: &str) -> Vec<Node<'_>>;
+    fn get_children(&self) -> Vec<Node<'_>>;
     fn name_from_identifier(&self, source: &[u8]) -> Result<String, String>;
+    #[allow(dead_code)]
     fn count_node_kind_recursive(&self, kind: &str) -> usize;
     fn depth(&self) -> usize;
     fn print_tree(&self);
 }
 
 impl NodeExt for Node<'_> {
-    fn child_by_kind(&self, kind: &str) -> Result<Node, String> {
+    fn child_by_kind(&self, kind: &str) -> Result<Node<'_>, String> {
         match self
             .children(&mut self.walk())
             .find(|child| child.kind() == kind)
@@ -30,13 +35,13 @@ impl NodeExt for Node<'_> {
         }
     }
 
-    fn children_by_kind(&self, kind: &str) -> Vec<Node> {
+    fn children_by_kind(&self, kind: &str) -> Vec<Node<'_>> {
         self.children(&mut self.walk())
             .filter(|child| child.kind() == kind)
             .collect()
     }
 
-    fn get_children(&self) -> Vec<Node> {
+    fn get_children(&self) -> Vec<Node<'_>> {
         self.children(&mut self.walk()).collect()
     }
 
@@ -92,6 +97,11 @@ struct SuperLocals {
     pub local_types: Vec<PrimitiveType>,
     // TODO: add support for arrays
     pub reference_classes: HashMap<usize, usize>, // index of local, class name
+    /// The loop depth of each labeled loop around the code being compiled, keyed by label.
+    pub labels: HashMap<String, usize>,
+    /// The declared return type of the method being compiled, which return statements convert
+    /// their value to.
+    pub return_type: PrimitiveType,
 }
 
 impl SuperLocals {
@@ -108,16 +118,31 @@ impl SuperLocals {
         }
     }
 
-    pub fn add_local(&mut self, name: &str, local_type: PrimitiveType) {
+    /// Declare a local, returning its index. This is the first of its slots, as longs and doubles
+    /// take up two.
+    pub fn add_local(&mut self, name: &str, local_type: PrimitiveType) -> usize {
+        let index = self.local_names.len();
+        let slot_count = local_type.slot_count();
+
         self.local_names.push(name.to_string());
         self.local_types.push(local_type);
+
+        // Longs and doubles take up two slots, and the second has no name so it is never found
+        for _ in 1..slot_count {
+            self.local_names.push(String::new());
+            self.local_types.push(PrimitiveType::Null);
+        }
+
+        index
     }
 }
 
 #[derive(Debug)]
 struct FieldInfo {
     pub name: String,
-    // TODO: add flags
+    // TODO: add the remaining flags
+    pub is_static: bool,
+    pub is_final: bool,
     pub signature: String,
     pub descriptor: PrimitiveType,
     // TODO: add support for arrays and objects
@@ -126,17 +151,92 @@ struct FieldInfo {
 #[derive(Debug)]
 struct MethodInfo {
     pub name: String,
-    // TODO: add flags
+    pub access_flags: u16,
     pub signature: String,
+    /// The descriptor of each parameter, where a varargs parameter is an array such as `[I`.
+    pub parameter_descriptors: Vec<String>,
     pub variables: SuperLocals,
     pub return_type: PrimitiveType,
 }
 
+impl MethodInfo {
+    pub fn is_static(&self) -> bool {
+        self.access_flags & MethodFlags::Static as u16 != 0
+    }
+
+    pub fn is_varargs(&self) -> bool {
+        self.access_flags & MethodFlags::Varargs as u16 != 0
+    }
+
+    /// The signature without the method's name, e.g. `(I)V`.
+    pub fn descriptor(&self) -> &str {
+        &self.signature[self.name.len()..]
+    }
+
+    /// The types the parameters are held as, with classes and arrays being references.
+    pub fn parameter_types(&self) -> Vec<PrimitiveType> {
+        self.parameter_descriptors
+            .iter()
+            .map(|descriptor| PrimitiveType::from_descriptor(descriptor))
+            .collect()
+    }
+
+    /// Whether a call with arguments of the passed types can be made to this method by widening
+    /// each argument to the type of its parameter, e.g. passing an int as a long.
+    pub fn accepts_widened(&self, argument_types: &[PrimitiveType]) -> bool {
+        let parameter_types = self.parameter_types();
+
+        parameter_types.len() == argument_types.len()
+            && argument_types
+                .iter()
+                .zip(&parameter_types)
+                .all(|(argument, parameter)| widens_to(argument, parameter))
+    }
+
+    /// Whether a call with arguments of the passed types can be made to this varargs method, by
+    /// passing the arguments after the fixed parameters in an array.
+    pub fn accepts_varargs(&self, argument_types: &[PrimitiveType]) -> bool {
+        let fixed = match self.parameter_descriptors.split_last() {
+            Some((_, fixed)) if self.is_varargs() => fixed,
+            _ => return false,
+        };
+        let element_descriptor = &self.parameter_descriptors[fixed.len()][1..];
+
+        let element_type = PrimitiveType::from_descriptor(element_descriptor);
+
+        argument_types.len() >= fixed.len()
+            && fixed
+                .iter()
+                .zip(argument_types)
+                .all(|(parameter, argument)| {
+                    widens_to(argument, &PrimitiveType::from_descriptor(parameter))
+                })
+            && (self.passes_varargs_array(argument_types)
+                || argument_types[fixed.len()..]
+                    .iter()
+                    .all(|argument| widens_to(argument, &element_type)))
+    }
+
+    /// Whether the last argument of a call to this varargs method is the array of the varargs
+    /// themselves. Only arrays of primitives can be told apart from a single reference argument.
+    pub fn passes_varargs_array(&self, argument_types: &[PrimitiveType]) -> bool {
+        let element_type = match self.parameter_descriptors.last() {
+            Some(descriptor) => PrimitiveType::from_descriptor(&descriptor[1..]),
+            None => return false,
+        };
+
+        argument_types.len() == self.parameter_descriptors.len()
+            && !matches!(element_type, PrimitiveType::Reference)
+            && matches!(argument_types.last(), Some(PrimitiveType::Reference))
+    }
+}
+
 #[derive(Debug)]
 struct ClassInfo {
     pub name: String,
     pub super_class: String,
-    // TODO: add flags
+    pub access_flags: u16,
+    pub interfaces: Vec<String>,
     pub fields: Vec<FieldInfo>,
     pub methods: Vec<MethodInfo>,
 }
@@ -144,9 +244,62 @@ struct ClassInfo {
 #[derive(Debug)]
 struct ParserContext {
     pub classes: Vec<ClassInfo>,
+    /// Whether compiled methods are passed through the peephole optimizer.
+    pub optimize: bool,
+}
+
+/// The classes of `java.lang` that may be referred to by their simple names.
+const JAVA_LANG_CLASSES: [&str; 18] = [
+    "Object",
+    "String",
+    "Throwable",
+    "Exception",
+    "Error",
+    "RuntimeException",
+    "ArithmeticException",
+    "ArrayIndexOutOfBoundsException",
+    "ArrayStoreException",
+    "ClassCastException",
+    "IllegalArgumentException",
+    "IllegalStateException",
+    "IndexOutOfBoundsException",
+    "NegativeArraySizeException",
+    "NullPointerException",
+    "NumberFormatException",
+    "StringIndexOutOfBoundsException",
+    "UnsupportedOperationException",
+];
+
+/// The full name of a class referred to in source. Classes in `java.lang` are imported implicitly,
+/// unless a class of the same name is being compiled.
+fn qualified_class_name(name: &str, classes: &[ClassInfo]) -> String {
+    if JAVA_LANG_CLASSES.contains(&name) && !classes.iter().any(|class| class.name == name) {
+        format!("java/lang/{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Whether a class is one of the throwables of `java.lang` which the JVM provides itself, such as
+/// `java/lang/RuntimeException`.
+fn is_builtin_throwable(class_name: &str) -> bool {
+    match class_name.strip_prefix("java/lang/") {
+        Some(name) => name == "Throwable" || name.ends_with("Exception") || name.ends_with("Error"),
+        None => false,
+    }
+}
+
+impl ClassInfo {
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ClassFlags::Interface as u16 != 0
+    }
 }
 
 impl ParserContext {
+    pub fn qualified_class_name(&self, name: &str) -> String {
+        qualified_class_name(name, &self.classes)
+    }
+
     pub fn find_class(&self, class_name: &str) -> Result<&ClassInfo, String> {
         match self.classes.iter().find(|class| class.name.eq(class_name)) {
             Some(class) => Ok(class),
@@ -165,22 +318,52 @@ impl ParserContext {
         }
     }
 
-    pub fn find_method(
+    /// Find the method which a call with arguments of the passed types runs. Calls which don't
+    /// match the parameters of any method exactly may still match a varargs method.
+    pub fn find_method_for_call(
         &self,
         class_name: &str,
-        method_signature: &String,
+        method_name: &str,
+        argument_types: &[PrimitiveType],
     ) -> Result<&MethodInfo, String> {
-        match self
-            .find_class(class_name)?
-            .methods
+        let method_parameters = format!(
+            "{}({})",
+            method_name,
+            argument_types
+                .iter()
+                .map(|a| a.as_descriptor())
+                .collect::<String>()
+        );
+
+        let exact = self.find_method_by_params(class_name, &method_parameters);
+        if exact.is_ok() {
+            return exact;
+        }
+
+        let methods = &self.find_class(class_name)?.methods;
+
+        // Of the methods the arguments can be widened for, the most specific is the one whose
+        // parameters can all be widened to those of the others, so f(long) is picked over
+        // f(double) for an int argument
+        let widened = methods
             .iter()
-            .find(|method| method.signature.eq(method_signature))
+            .filter(|method| method.name == method_name && method.accepts_widened(argument_types))
+            .collect::<Vec<_>>();
+        let most_specific = widened.iter().find(|method| {
+            widened
+                .iter()
+                .all(|other| other.accepts_widened(&method.parameter_types()))
+        });
+        if let Some(method) = most_specific.or(widened.first()) {
+            return Ok(method);
+        }
+
+        match methods
+            .iter()
+            .find(|method| method.name == method_name && method.accepts_varargs(argument_types))
         {
             Some(method) => Ok(method),
-            None => Err(format!(
-                "Method {} not found in class {}",
-                method_signature, class_name
-            )),
+            None => exact,
         }
     }
 
@@ -243,6 +426,40 @@ fn type_node_to_primitive_type(node: Node) -> Result<PrimitiveType, String> {
     }
 }
 
+/// The descriptor of an array type node, e.g. `[[I` for `int[][]`.
+fn array_type_descriptor(
+    node: &Node,
+    source: &[u8],
+    parser_context: &ParserContext,
+) -> Result<String, String> {
+    let element_node = match node.child(0) {
+        Some(node) => node,
+        None => return Err(String::from("Array type is missing element type")),
+    };
+    let dimensions = node
+        .child_by_kind("dimensions")?
+        .children_by_kind("[")
+        .len();
+
+    let element = match element_node.kind() {
+        "type_identifier" => match element_node.utf8_text(source) {
+            Ok(text) => format!("L{};", parser_context.qualified_class_name(text)),
+            Err(err) => return Err(format!("Failed to parse array element type: {}", err)),
+        },
+        _ => type_node_to_primitive_type(element_node)?
+            .as_letter()
+            .to_string(),
+    };
+
+    Ok(format!("{}{}", "[".repeat(dimensions), element))
+}
+
+/// The type an element of an array with the passed descriptor is held as, e.g. a reference for
+/// each row of an `int[][]`.
+fn array_element_type(array_descriptor: &str) -> PrimitiveType {
+    PrimitiveType::from_descriptor(array_descriptor.get(1..).unwrap_or(""))
+}
+
 fn parse_method_info(
     method_node: &Node,
     class_name: &String,
@@ -252,20 +469,41 @@ fn parse_method_info(
 
     let mut param_names = vec![];
     let mut param_types = vec![];
+    let mut param_descriptors = vec![];
+    let mut is_varargs = false;
 
-    for param in formal_params.children_by_kind("formal_parameter") {
-        let param_name = param.name_from_identifier(source)?;
-
-        let param_type = match param.child(0) {
-            Some(node) => type_node_to_primitive_type(node)?,
-            None => return Err(String::from("Formal parameter is missing type")),
+    for param in formal_params.get_children() {
+        let param_type = match (param.kind(), param.child(0)) {
+            ("formal_parameter" | "spread_parameter", Some(node)) => {
+                type_node_to_primitive_type(node)?
+            }
+            ("formal_parameter" | "spread_parameter", None) => {
+                return Err(String::from("Formal parameter is missing type"))
+            }
+            _ => continue,
         };
 
-        param_names.push(param_name);
-        param_types.push(param_type);
+        // A varargs parameter is an array of its declared type, and can only come last
+        if param.kind() == "spread_parameter" {
+            let declarator = param.child_by_kind("variable_declarator")?;
+            param_names.push(declarator.name_from_identifier(source)?);
+            param_descriptors.push(format!("[{}", param_type.as_descriptor()));
+            param_types.push(PrimitiveType::Reference);
+            is_varargs = true;
+        } else {
+            param_names.push(param.name_from_identifier(source)?);
+            param_descriptors.push(param_type.as_descriptor());
+            param_types.push(param_type);
+        }
     }
 
-    let method_return_type = match method_node.child(1) {
+    // Constructors don't declare a return type, and return nothing
+    let method_return_type = match method_node
+        .get_children()
+        .into_iter()
+        .find(|child| child.kind() != "modifiers")
+    {
+        _ if method_node.kind() == "constructor_declaration" => PrimitiveType::Null,
         Some(method_return_type_node) => type_node_to_primitive_type(method_return_type_node)?,
         None => return Err(String::from("Method missing return type")),
     };
@@ -281,32 +519,107 @@ fn parse_method_info(
     let mut signature = format!(
         "{}({}){}",
         method_name,
-        param_types
-            .iter()
-            .map(|t| t.as_letter())
-            .collect::<String>(),
-        method_return_type.as_letter()
+        param_descriptors.concat(),
+        method_return_type.as_descriptor()
     );
 
     // TODO: remove this when the standard library is implemented
-    if signature == "main(R)V" {
+    if signature == "main(Ljava/lang/Object;)V" {
         signature = "main([Ljava/lang/String;)V".to_string();
     }
 
-    let variables = SuperLocals {
-        local_names: param_names,
-        local_types: param_types,
+    let mut access_flags = match method_node.child_by_kind("modifiers") {
+        Ok(modifiers) => modifiers
+            .get_children()
+            .iter()
+            .map(|modifier| match modifier.kind() {
+                "public" => MethodFlags::Public as u16,
+                "private" => MethodFlags::Private as u16,
+                "protected" => MethodFlags::Protected as u16,
+                "static" => MethodFlags::Static as u16,
+                "final" => MethodFlags::Final as u16,
+                "synchronized" => MethodFlags::Synchronized as u16,
+                "native" => MethodFlags::Native as u16,
+                "abstract" => MethodFlags::Abstract as u16,
+                _ => 0,
+            })
+            .fold(0, |flags, flag| flags | flag),
+        Err(_) => 0,
+    };
+
+    if is_varargs {
+        access_flags |= MethodFlags::Varargs as u16;
+    }
+
+    let mut variables = SuperLocals {
+        local_names: Vec::new(),
+        local_types: Vec::new(),
         reference_classes: HashMap::new(), // TODO: Implement this
+        labels: HashMap::new(),
+        return_type: method_return_type.clone(),
     };
 
+    // Instance methods receive the object they were called on in local 0
+    if access_flags & MethodFlags::Static as u16 == 0 {
+        variables.add_local("this", PrimitiveType::Reference);
+    }
+
+    for (param_name, param_type) in param_names.iter().zip(param_types.iter()) {
+        variables.add_local(param_name, param_type.clone());
+    }
+
     Ok(MethodInfo {
         name: method_name,
+        access_flags,
         signature,
+        parameter_descriptors: param_descriptors,
         variables,
         return_type: method_return_type,
     })
 }
 
+fn generate_field_list(class_node: &Node, source: &[u8]) -> Result<Vec<FieldInfo>, String> {
+    let mut fields = vec![];
+
+    for field_node in class_node.children_by_kind("field_declaration") {
+        let has_modifier = |kind: &str| match field_node.child_by_kind("modifiers") {
+            Ok(modifiers) => modifiers
+                .get_children()
+                .iter()
+                .any(|modifier| modifier.kind() == kind),
+            Err(_) => false,
+        };
+        let is_static = has_modifier("static");
+        let is_final = has_modifier("final");
+
+        let field_type = match field_node
+            .get_children()
+            .into_iter()
+            .find(|child| child.kind() != "modifiers")
+        {
+            Some(node) => type_node_to_primitive_type(node)?,
+            None => return Err(String::from("Field declaration is missing type")),
+        };
+
+        for declarator in field_node.children_by_kind("variable_declarator") {
+            // TODO: compile field initializers into <clinit> and <init>
+            if declarator.child_count() > 1 {
+                return Err(String::from("Field initializers are not supported"));
+            }
+
+            fields.push(FieldInfo {
+                name: declarator.name_from_identifier(source)?,
+                is_static,
+                is_final,
+                signature: field_type.as_descriptor(),
+                descriptor: field_type.clone(),
+            });
+        }
+    }
+
+    Ok(fields)
+}
+
 fn generate_method_list(class_node: &Node, source: &[u8]) -> Result<Vec<MethodInfo>, String> {
     let mut methods = vec![];
 
@@ -316,131 +629,282 @@ fn generate_method_list(class_node: &Node, source: &[u8]) -> Result<Vec<MethodIn
     };
 
     let class_name = class_declaration_node.name_from_identifier(source)?;
+    let is_interface = class_declaration_node.kind() == "interface_declaration";
 
     for method_node in class_node.children_by_kind("method_declaration") {
-        methods.push(parse_method_info(&method_node, &class_name, source)?);
+        let mut method = parse_method_info(&method_node, &class_name, source)?;
+
+        // Interface methods are implicitly public, and abstract unless they have a body
+        if is_interface {
+            method.access_flags |= MethodFlags::Public as u16;
+            if method_node.child_by_kind("block").is_err() {
+                method.access_flags |= MethodFlags::Abstract as u16;
+            }
+        }
+
+        methods.push(method);
+    }
+
+    // Constructors follow the methods, in the order they are declared
+    for constructor_node in class_node.children_by_kind("constructor_declaration") {
+        methods.push(parse_method_info(&constructor_node, &class_name, source)?);
     }
 
-    // TODO: Add constructor_declaration
+    // Classes which don't declare a constructor get a default one taking no arguments, while
+    // interfaces have no constructors
+    if !is_interface && !methods.iter().any(|method| method.name == "<init>") {
+        let mut variables = SuperLocals {
+            local_names: Vec::new(),
+            local_types: Vec::new(),
+            reference_classes: HashMap::new(),
+            labels: HashMap::new(),
+            return_type: PrimitiveType::Null,
+        };
+        variables.add_local("this", PrimitiveType::Reference);
+
+        methods.push(MethodInfo {
+            name: String::from("<init>"),
+            access_flags: MethodFlags::Public as u16,
+            signature: String::from("<init>()V"),
+            parameter_descriptors: Vec::new(),
+            variables,
+            return_type: PrimitiveType::Null,
+        });
+    }
 
     Ok(methods)
 }
 
-fn parse_expression(
+/// The default constructor, which only calls the constructor of the superclass.
+fn default_constructor(super_class: &str, constant_pool: &mut Vec<ConstantPoolEntry>) -> Method {
+    let super_constructor = constant_pool.find_or_add_method_ref(super_class, "<init>", "()V");
+
+    Method {
+        access_flags: MethodFlags::Public as u16,
+        instructions: vec![
+            Instruction::Load(0, PrimitiveType::Reference),
+            Instruction::InvokeSpecial(super_constructor),
+            Instruction::Return(PrimitiveType::Null),
+        ],
+        stack_map: Vec::new(),
+        exception_table: Vec::new(),
+    }
+}
+
+/// Whether a value of one type can be used where another is expected without a cast, which for
+/// primitives is a widening conversion such as int to double.
+fn widens_to(from: &PrimitiveType, to: &PrimitiveType) -> bool {
+    from.matches(to)
+        || matches!(
+            (from, to),
+            (
+                PrimitiveType::Byte,
+                PrimitiveType::Short
+                    | PrimitiveType::Int
+                    | PrimitiveType::Long
+                    | PrimitiveType::Float
+                    | PrimitiveType::Double
+            ) | (
+                PrimitiveType::Short | PrimitiveType::Char,
+                PrimitiveType::Int
+                    | PrimitiveType::Long
+                    | PrimitiveType::Float
+                    | PrimitiveType::Double
+            ) | (
+                PrimitiveType::Int,
+                PrimitiveType::Long | PrimitiveType::Float | PrimitiveType::Double
+            ) | (
+                PrimitiveType::Long,
+                PrimitiveType::Float | PrimitiveType::Double
+            ) | (PrimitiveType::Float, PrimitiveType::Double)
+        )
+}
+
+/// Generate the instructions that convert a value of one type to another for a cast expression.
+fn cast_instructions(from: &PrimitiveType, to: &PrimitiveType) -> Result<Vec<Instruction>, String> {
+    // Bytes, shorts and chars are stored as ints on the operand stack
+    let stack_type = |primitive_type: &PrimitiveType| match primitive_type {
+        PrimitiveType::Byte | PrimitiveType::Short | PrimitiveType::Char => PrimitiveType::Int,
+        other => other.clone(),
+    };
+
+    let is_numeric = |primitive_type: &PrimitiveType| {
+        !matches!(
+            primitive_type,
+            PrimitiveType::Null | PrimitiveType::Reference | PrimitiveType::Boolean
+        )
+    };
+
+    if from.matches(to) {
+        return Ok(vec![]);
+    }
+
+    if !is_numeric(from) || !is_numeric(to) {
+        return Err(format!("Cannot cast {:?} to {:?}", from, to));
+    }
+
+    let mut instructions = vec![];
+    let from_stack_type = stack_type(from);
+
+    match to {
+        // Widening a byte to a short doesn't change its value
+        PrimitiveType::Short if from.matches(&PrimitiveType::Byte) => {}
+        PrimitiveType::Byte | PrimitiveType::Short | PrimitiveType::Char => {
+            if !from_stack_type.matches(&PrimitiveType::Int) {
+                instructions.push(Instruction::Convert(from_stack_type, PrimitiveType::Int));
+            }
+            instructions.push(Instruction::Convert(PrimitiveType::Int, to.clone()));
+        }
+        _ => {
+            if !from_stack_type.matches(to) {
+                instructions.push(Instruction::Convert(from_stack_type, to.clone()));
+            }
+        }
+    }
+
+    Ok(instructions)
+}
+
+/// The grammar predates pattern matching, so in `x instanceof Foo f` the binding is parsed as an
+/// error node holding the identifier which directly follows the instanceof expression.
+fn pattern_binding(instanceof_node: &Node, source: &[u8]) -> Option<String> {
+    let binding = instanceof_node.next_sibling()?;
+
+    if binding.kind() != "ERROR" || binding.named_child_count() != 1 {
+        return None;
+    }
+
+    binding.name_from_identifier(source).ok()
+}
+
+/// Declare the variables bound by `instanceof` patterns within an if condition, which are in scope
+/// for the condition and the body of the if.
+fn add_pattern_bindings(
     node: &Node,
     source: &[u8],
-    current_class: &String,
     parser_context: &ParserContext,
-    super_locals: &SuperLocals,
+    locals: &mut SuperLocals,
     constant_pool: &mut Vec<ConstantPoolEntry>,
-) -> Result<(Vec<Instruction>, PrimitiveType), String> {
-    let mut instructions = vec![];
-    let mut expression_type = PrimitiveType::Null;
-
-    match node.kind() {
-        "(" | "," | ")" => {}
-        "decimal_integer_literal" => {
-            let value = match node.utf8_text(source) {
-                Ok(text) => match text.parse::<i32>() {
-                    Ok(value) => value,
-                    Err(err) => return Err(format!("Failed to parse integer literal: {}", err)),
-                },
-                Err(err) => {
-                    return Err(format!("Failed to parse decimal integer literal: {}", err))
+) -> Result<(), String> {
+    for child in node.get_children() {
+        if child.kind() == "instanceof_expression" {
+            if let Some(name) = pattern_binding(&child, source) {
+                if locals.find_local(&name).is_some() {
+                    return Err(format!("Variable {} is already defined", name));
                 }
-            };
 
-            expression_type = PrimitiveType::Int;
-            instructions.push(Instruction::Const(Primitive::Int(value)));
+                let class_name = match child.child(2).map(|node| node.utf8_text(source)) {
+                    Some(Ok(text)) => parser_context.qualified_class_name(text),
+                    _ => return Err(String::from("Instanceof expression is missing type")),
+                };
+
+                let index = locals.add_local(&name, PrimitiveType::Reference);
+                locals
+                    .reference_classes
+                    .insert(index, constant_pool.find_or_add_class(&class_name));
+            }
         }
-        "decimal_floating_point_literal" => {
-            let text = match node.utf8_text(source) {
-                Ok(text) => text,
-                Err(err) => {
-                    return Err(format!(
-                        "Failed to parse decimal floating point literal: {}",
-                        err
-                    ))
-                }
-            };
 
-            let text = if text.ends_with('f') || text.ends_with('F') {
-                &text[..text.len() - 1]
-            } else {
-                text
-            };
+        add_pattern_bindings(&child, source, parser_context, locals, constant_pool)?;
+    }
 
-            let value = match text.parse::<f32>() {
-                Ok(value) => value,
-                Err(err) => return Err(format!("Failed to parse floating point literal: {}", err)),
-            };
+    Ok(())
+}
 
-            expression_type = PrimitiveType::Float;
-            instructions.push(Instruction::Const(Primitive::Float(value)));
-        }
+/// The descriptor of the array an expression evaluates to. Only local variables and elements of
+/// arrays of arrays are known to be arrays.
+fn array_expression_descriptor(
+    node: &Node,
+    source: &[u8],
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<String, String> {
+    let descriptor = match node.kind() {
         "identifier" => {
             let name = match node.utf8_text(source) {
-                Ok(text) => text.to_string(),
+                Ok(text) => text,
                 Err(err) => return Err(format!("Failed to parse identifier: {}", err)),
             };
 
-            match super_locals.find_local(&name) {
-                Some(index) => {
-                    let local_type = super_locals.get_local_type(&index)?;
-                    instructions.push(Instruction::Load(index, local_type.clone()));
-                    expression_type = local_type;
-                }
-                None => return Err(format!("Local variable {} not found", name)),
+            match super_locals
+                .find_local(name)
+                .and_then(|index| super_locals.reference_classes.get(&index))
+            {
+                Some(class_index) => constant_pool.class_parser(class_index)?,
+                None => return Err(format!("Local variable {} is not an array", name)),
             }
         }
-        "array_initializer" => {
-            instructions.push(Instruction::NewArray(PrimitiveType::Int)); // TODO: Support other types
-
-            let mut i = 0;
-            for child in node.get_children() {
-                if child.kind() == "," || child.kind() == "{" || child.kind() == "}" {
-                    continue;
-                }
+        "array_access" => match node.child(0) {
+            Some(array) => {
+                array_expression_descriptor(&array, source, super_locals, constant_pool)?[1..]
+                    .to_string()
+            }
+            None => return Err(String::from("Array access is missing array")),
+        },
+        "parenthesized_expression" => match node.named_child(0) {
+            Some(inner) => {
+                array_expression_descriptor(&inner, source, super_locals, constant_pool)?
+            }
+            None => return Err(String::from("Parenthesized expression is empty")),
+        },
+        kind => return Err(format!("Unsupported array expression: {}", kind)),
+    };
 
-                instructions.push(Instruction::Dup);
-                instructions.push(Instruction::Const(Primitive::Int(i)));
+    if !descriptor.starts_with('[') {
+        return Err(format!("{} is not an array type", descriptor));
+    }
 
-                let (child_instructions, child_type) = parse_expression(
-                    &child,
-                    source,
-                    current_class,
-                    parser_context,
-                    super_locals,
-                    constant_pool,
-                )?;
+    Ok(descriptor)
+}
 
-                instructions.extend(child_instructions);
-                instructions.push(Instruction::AStore(child_type));
-                i += 1;
+/// Compile an array initializer such as `{1, 2, 3}` for an array of the passed type, leaving the
+/// new array on the stack. Nested initializers build the inner arrays of multidimensional arrays.
+fn parse_array_initializer(
+    node: &Node,
+    array_descriptor: &str,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<Instruction>, String> {
+    let elements = node
+        .get_children()
+        .into_iter()
+        .filter(|child| !matches!(child.kind(), "{" | "," | "}"))
+        .collect::<Vec<_>>();
+    let element_type = array_element_type(array_descriptor);
+    let element_descriptor = &array_descriptor[1..];
+
+    let mut instructions = vec![
+        Instruction::Const(Primitive::Int(elements.len() as i32)),
+        new_array_instruction(element_descriptor, constant_pool),
+    ];
+
+    for (index, element) in elements.iter().enumerate() {
+        instructions.push(Instruction::Dup);
+        instructions.push(Instruction::Const(Primitive::Int(index as i32)));
+
+        if element.kind() == "array_initializer" {
+            if !element_descriptor.starts_with('[') {
+                return Err(format!(
+                    "Array initializer used for an element of type {}",
+                    element_descriptor
+                ));
             }
 
-            expression_type = PrimitiveType::Reference;
-        }
-        "assignment_expression" | "variable_declarator" => {
-            let variable_index =
-                match super_locals.find_local(node.name_from_identifier(source)?.as_str()) {
-                    Some(index) => index,
-                    None => {
-                        return Err(format!(
-                            "Local variable {} not found",
-                            node.name_from_identifier(source)?
-                        ))
-                    }
-                };
-            let variable_type = super_locals.get_local_type(&variable_index)?;
-
-            let expression_node = match node.child(2) {
-                Some(node) => node,
-                None => return Err(String::from("Assignment expression is missing expression")),
-            };
-
-            let (expression_instructions, expr_type) = parse_expression(
-                &expression_node,
+            instructions.extend(parse_array_initializer(
+                element,
+                element_descriptor,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?);
+        } else {
+            let (element_instructions, value_type) = parse_expression(
+                element,
                 source,
                 current_class,
                 parser_context,
@@ -448,910 +912,2724 @@ fn parse_expression(
                 constant_pool,
             )?;
 
-            instructions.extend(expression_instructions);
-            if !variable_type.matches(&expr_type) {
+            // Int constants can initialize the elements of the narrower integral types
+            let narrows = value_type.matches(&PrimitiveType::Int)
+                && matches!(
+                    element_type,
+                    PrimitiveType::Byte | PrimitiveType::Short | PrimitiveType::Char
+                );
+
+            if !element_type.matches(&value_type) && !narrows {
                 return Err(format!(
-                    "Assignment expression type mismatch: {:?} != {:?}",
-                    variable_type, expr_type
+                    "Array element type {} does not match expression type {}",
+                    element_type.as_letter(),
+                    value_type.as_letter()
                 ));
             }
-            expression_type = variable_type.clone();
-
-            let operator = match node.child(1) {
-                Some(node) => match node.utf8_text(source) {
-                    Ok(text) => text,
-                    Err(err) => {
-                        return Err(format!("Failed to parse assignment operator: {}", err))
-                    }
-                },
-                None => return Err(String::from("Assignment expression is missing operator")),
-            };
 
-            if operator.len() == 2 {
-                instructions.push(Instruction::Load(variable_index, variable_type.clone()));
-                let variable_type_clone = variable_type.clone();
+            instructions.extend(element_instructions);
+        }
 
-                instructions.push(match operator {
-                    "+=" => Instruction::Add(variable_type_clone),
-                    "-=" => Instruction::Sub(variable_type_clone),
-                    "*=" => Instruction::Mul(variable_type_clone),
-                    "/=" => Instruction::Div(variable_type_clone),
-                    "%=" => Instruction::Rem(variable_type_clone),
-                    _ => return Err(format!("Unknown assignment operator: {}", operator)),
-                });
-            }
+        instructions.push(Instruction::AStore(element_type.clone()));
+    }
 
-            instructions.push(Instruction::Store(variable_index, variable_type));
+    Ok(instructions)
+}
+
+/// The instructions passing the arguments of a call to a method. The arguments after the fixed
+/// parameters of a varargs method are packed into a new array, unless an array is passed directly.
+/// The instruction creating an array with elements of the passed descriptor from the length on
+/// the stack. Arrays of references are given the class of their elements, or the descriptor of
+/// the elements when they are arrays themselves.
+fn new_array_instruction(
+    element_descriptor: &str,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Instruction {
+    match PrimitiveType::from_descriptor(element_descriptor) {
+        PrimitiveType::Reference => {
+            let element_class = element_descriptor
+                .strip_prefix('L')
+                .and_then(|class_name| class_name.strip_suffix(';'))
+                .unwrap_or(element_descriptor);
+            Instruction::ANewArray(constant_pool.find_or_add_class(element_class))
         }
-        "binary_expression" => {
-            let left = match node.child(0) {
-                Some(node) => node,
-                None => return Err(String::from("Binary expression is missing left operand")),
-            };
+        element_type => Instruction::NewArray(element_type),
+    }
+}
 
-            let operator = match node.child(1) {
-                Some(node) => match node.utf8_text(source) {
-                    Ok(text) => text.to_string(),
-                    Err(err) => return Err(format!("Failed to parse binary operator: {}", err)),
-                },
-                None => return Err(String::from("Binary expression is missing operator")),
-            };
+fn call_arguments(
+    method: &MethodInfo,
+    arguments: Vec<(Vec<Instruction>, PrimitiveType)>,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<Instruction>, String> {
+    let argument_types = arguments
+        .iter()
+        .map(|(_, argument_type)| argument_type.clone())
+        .collect::<Vec<_>>();
+
+    // Arguments passed to a parameter of a wider type are converted to it, e.g. with i2d. The
+    // parameters before the varargs are always passed as themselves
+    let parameter_types = method.parameter_types();
+    let fixed = method.parameter_descriptors.len().saturating_sub(1);
+    let is_widened = method.accepts_widened(&argument_types);
+    let mut arguments = arguments
+        .into_iter()
+        .enumerate()
+        .map(|(index, (mut instructions, argument_type))| {
+            if let Some(parameter_type) = parameter_types.get(index) {
+                if is_widened || (method.is_varargs() && index < fixed) {
+                    instructions.extend(cast_instructions(&argument_type, parameter_type)?);
+                }
+            }
+            Ok((instructions, argument_type))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
 
-            let right = match node.child(2) {
-                Some(node) => node,
-                None => return Err(String::from("Binary expression is missing right operand")),
-            };
+    let element_descriptor = match method.parameter_descriptors.last() {
+        Some(descriptor) if method.is_varargs() => &descriptor[1..],
+        _ => return Ok(arguments.into_iter().flat_map(|(a, _)| a).collect()),
+    };
+    let element_type = PrimitiveType::from_descriptor(element_descriptor);
 
-            let (left_instructions, left_type) = parse_expression(
-                &left,
-                source,
-                current_class,
-                parser_context,
-                super_locals,
-                constant_pool,
-            )?;
+    if method.passes_varargs_array(&argument_types) {
+        return Ok(arguments.into_iter().flat_map(|(a, _)| a).collect());
+    }
 
-            let (right_instructions, right_type) = parse_expression(
-                &right,
-                source,
-                current_class,
-                parser_context,
-                super_locals,
-                constant_pool,
-            )?;
+    let packed = arguments.split_off(fixed.min(arguments.len()));
+    let mut instructions = arguments
+        .into_iter()
+        .flat_map(|(a, _)| a)
+        .collect::<Vec<_>>();
+
+    instructions.push(Instruction::Const(Primitive::Int(packed.len() as i32)));
+    instructions.push(new_array_instruction(element_descriptor, constant_pool));
+
+    for (index, (argument_instructions, argument_type)) in packed.into_iter().enumerate() {
+        instructions.push(Instruction::Dup);
+        instructions.push(Instruction::Const(Primitive::Int(index as i32)));
+        instructions.extend(argument_instructions);
+        instructions.extend(cast_instructions(&argument_type, &element_type)?);
+        instructions.push(Instruction::AStore(element_type.clone()));
+    }
 
-            if !left_type.matches(&right_type) {
-                // TODO: implement automatic type widening
-                return Err(format!(
-                    "Binary expression has mismatched types: {:?} and {:?}",
-                    left_type, right_type
-                ));
-            }
+    Ok(instructions)
+}
 
-            instructions.extend(left_instructions);
-            instructions.extend(right_instructions);
-            expression_type = left_type;
+/// Parse the text of an integer literal, e.g. `10`, `0xFF`, `0b1010`, `010` or `1_000L`. Literals
+/// which aren't decimal may set the sign bit, so `0xFFFFFFFF` is -1.
+fn parse_integer_literal(text: &str) -> Result<Primitive, String> {
+    let digits = text.replace('_', "");
+    let (digits, is_long) = match digits.strip_suffix(['l', 'L']) {
+        Some(digits) => (digits, true),
+        None => (digits.as_str(), false),
+    };
 
-            instructions.push(match operator.as_str() {
-                "+" => Instruction::Add(expression_type.clone()),
-                "-" => Instruction::Sub(expression_type.clone()),
-                "*" => Instruction::Mul(expression_type.clone()),
-                "/" => Instruction::Div(expression_type.clone()),
-                "%" => Instruction::Rem(expression_type.clone()),
-                _ => return Err(format!("Unknown binary operator {}", operator)),
-            })
-        }
-        "parenthesized_expression" => {
-            let expression = match node.child(1) {
-                Some(node) => node,
-                None => {
-                    return Err(String::from(
-                        "Parenthesized expression is missing expression",
-                    ))
-                }
-            };
+    let (digits, radix) = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        (binary, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8)
+    } else {
+        (digits, 10)
+    };
 
-            return parse_expression(
-                &expression,
-                source,
-                current_class,
-                parser_context,
-                super_locals,
-                constant_pool,
-            );
-        }
-        "object_creation_expression" => {
-            let class_name = match node.child_by_kind("type_identifier")?.utf8_text(source) {
-                Ok(text) => text.to_string(),
-                Err(err) => return Err(format!("Failed to parse class name: {}", err)),
-            };
+    let value = match u64::from_str_radix(digits, radix) {
+        Ok(value) => value,
+        Err(err) => return Err(format!("Failed to parse integer literal {}: {}", text, err)),
+    };
 
-            parser_context.find_class(&class_name)?;
-            let class_index = constant_pool.find_or_add_class(&class_name);
+    let max = match (is_long, radix) {
+        (true, 10) => i64::MAX as u64,
+        (true, _) => u64::MAX,
+        (false, 10) => i32::MAX as u64,
+        (false, _) => u32::MAX as u64,
+    };
+    if value > max {
+        return Err(format!("Integer literal {} is too large", text));
+    }
 
-            instructions.push(Instruction::New(class_index as usize));
-            instructions.push(Instruction::Dup);
+    Ok(if is_long {
+        Primitive::Long(value as i64)
+    } else {
+        Primitive::Int(value as u32 as i32)
+    })
+}
 
-            let arguments_node = node.child_by_kind("argument_list")?;
-            let mut argument_types = vec![];
+/// The text of a string literal, with its quotes removed and escape sequences replaced.
+fn string_literal_text(node: &Node, source: &[u8]) -> Result<String, String> {
+    let literal = match node.utf8_text(source) {
+        Ok(text) => text,
+        Err(err) => return Err(format!("Failed to parse string literal: {}", err)),
+    };
 
-            for i in 1..(arguments_node.child_count() - 1) {
-                let argument = match arguments_node.child(i) {
-                    Some(node) => node,
-                    None => return Err(format!("Could not find argument_list child {}", i)),
-                };
+    unescape_java_string(&literal[1..literal.len() - 1])
+}
 
-                let (argument_instructions, argument_type) = parse_expression(
-                    &argument,
-                    source,
-                    current_class,
-                    parser_context,
-                    super_locals,
-                    constant_pool,
-                )?;
+/// Replace the escape sequences in the source text of a string with the characters they stand
+/// for, e.g. `\t` with a tab, `\u0041` with `A` and the octal escape `\101` with `A`.
+pub fn unescape_java_string(literal: &str) -> Result<String, String> {
+    let mut text = String::new();
+    let mut chars = literal.chars().peekable();
 
-                if argument_type.matches(&PrimitiveType::Null) {
-                    continue;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+
+        text.push(match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('s') => ' ',
+            Some(c @ ('"' | '\'' | '\\')) => c,
+            Some('u') => {
+                // Any number of u's may follow the backslash
+                while chars.peek() == Some(&'u') {
+                    chars.next();
                 }
 
-                instructions.extend(argument_instructions);
-                argument_types.push(argument_type);
+                let code = chars.by_ref().take(4).collect::<String>();
+                match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    Some(c) if code.len() == 4 => c,
+                    _ => return Err(format!("Invalid unicode escape \\u{}", code)),
+                }
             }
+            Some(first @ '0'..='7') => {
+                // Octal escapes go up to \377, so only ones starting with 0 to 3 take three digits
+                let max_digits = if first <= '3' { 3 } else { 2 };
+                let mut code = first.to_digit(8).unwrap();
+
+                for _ in 1..max_digits {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
 
-            let constructor_descriptor = format!(
-                "({})V",
-                argument_types
-                    .iter()
-                    .map(|a| a.as_letter())
-                    .collect::<String>()
-            );
+                char::from_u32(code).unwrap()
+            }
+            c => {
+                return Err(format!(
+                    "Unsupported escape sequence \\{}",
+                    c.unwrap_or(' ')
+                ))
+            }
+        });
+    }
 
-            let constructor_signature = format!("<init>{}", constructor_descriptor);
-            parser_context.find_method(&class_name, &constructor_signature)?;
+    Ok(text)
+}
 
-            let method_index = constant_pool.find_or_add_method_ref(
-                &class_name,
-                "<init>",
-                &constructor_descriptor,
-            );
+/// Whether an expression is known to evaluate to a string, which makes `+` concatenate.
+fn is_string_expression(
+    node: &Node,
+    source: &[u8],
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> bool {
+    match node.kind() {
+        "string_literal" => true,
+        "parenthesized_expression" => node
+            .named_child(0)
+            .is_some_and(|inner| is_string_expression(&inner, source, super_locals, constant_pool)),
+        "binary_expression" => {
+            node.child(1).map(|operator| operator.kind()) == Some("+")
+                && [node.child(0), node.child(2)]
+                    .iter()
+                    .flatten()
+                    .any(|operand| {
+                        is_string_expression(operand, source, super_locals, constant_pool)
+                    })
+        }
+        "identifier" => node
+            .utf8_text(source)
+            .ok()
+            .and_then(|name| super_locals.find_local(name))
+            .and_then(|index| super_locals.reference_classes.get(&index))
+            .and_then(|class_index| constant_pool.class_parser(class_index).ok())
+            .is_some_and(|class_name| class_name == "java/lang/String"),
+        _ => false,
+    }
+}
 
-            expression_type = PrimitiveType::Null;
-            instructions.push(Instruction::InvokeSpecial(method_index));
+/// Resolve a name which isn't a local to a field of the current class, which is how unqualified
+/// field names are looked up. Instance fields are accessed through `this`, so the instruction
+/// loading it is returned along with the field and its constant pool reference.
+fn unqualified_field<'a>(
+    name: &String,
+    current_class: &str,
+    parser_context: &'a ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<(&'a FieldInfo, Option<Instruction>, usize), String> {
+    let field = match parser_context.find_field(current_class, name) {
+        Ok(field) => field,
+        Err(_) => return Err(format!("Local variable {} not found", name)),
+    };
+
+    let object = if field.is_static {
+        None
+    } else {
+        match super_locals.find_local("this") {
+            Some(index) => Some(Instruction::Load(index, PrimitiveType::Reference)),
+            None => {
+                return Err(format!(
+                    "Instance field {} cannot be used in a static method",
+                    name
+                ))
+            }
         }
-        "method_invocation" => {
-            let arguments_node = node.child_by_kind("argument_list")?;
-            let mut argument_types = vec![];
+    };
 
-            for i in 1..(arguments_node.child_count() - 1) {
-                let argument = match arguments_node.child(i) {
-                    Some(node) => node,
-                    None => return Err(format!("Could not find argument_list child {}", i)),
-                };
+    let field_ref = constant_pool.find_or_add_field_ref(current_class, name, &field.signature);
 
-                let (argument_instructions, argument_type) = parse_expression(
-                    &argument,
-                    source,
-                    current_class,
-                    parser_context,
-                    super_locals,
-                    constant_pool,
-                )?;
+    Ok((field, object, field_ref))
+}
 
-                if argument_type.matches(&PrimitiveType::Null) {
-                    continue;
-                }
+/// The arithmetic instruction performed by a compound assignment operator such as `+=`.
+fn compound_assignment_instruction(
+    operator: &str,
+    operand_type: &PrimitiveType,
+) -> Result<Instruction, String> {
+    let operand_type = operand_type.clone();
+
+    Ok(match operator {
+        "+=" => Instruction::Add(operand_type),
+        "-=" => Instruction::Sub(operand_type),
+        "*=" => Instruction::Mul(operand_type),
+        "/=" => Instruction::Div(operand_type),
+        "%=" => Instruction::Rem(operand_type),
+        _ => return Err(format!("Unknown assignment operator: {}", operator)),
+    })
+}
 
-                instructions.extend(argument_instructions);
-                argument_types.push(argument_type);
+/// Compile a string concatenation into appending each operand to a new StringBuilder. Operands
+/// are evaluated left to right, so `1 + 2 + "a"` adds the numbers before concatenating, while the
+/// operands of `"a" + 1 + 2` are each appended.
+fn parse_string_concatenation(
+    node: &Node,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<Instruction>, String> {
+    let mut operands = Vec::new();
+    let mut concatenation = *node;
+
+    // Concatenations are left-associative, so the chain continues down the left operands
+    loop {
+        match (concatenation.child(0), concatenation.child(2)) {
+            (Some(left), Some(right)) => {
+                operands.push(right);
+
+                if left.kind() == "binary_expression"
+                    && is_string_expression(&left, source, super_locals, constant_pool)
+                {
+                    concatenation = left;
+                } else {
+                    operands.push(left);
+                    break;
+                }
             }
+            _ => return Err(String::from("Binary expression is missing an operand")),
+        }
+    }
+    operands.reverse();
+
+    let builder_class = constant_pool.find_or_add_class("java/lang/StringBuilder");
+    let constructor =
+        constant_pool.find_or_add_method_ref("java/lang/StringBuilder", "<init>", "()V");
+
+    let mut instructions = vec![
+        Instruction::New(builder_class),
+        Instruction::Dup,
+        Instruction::InvokeSpecial(constructor),
+    ];
+
+    for operand in operands {
+        let is_string = is_string_expression(&operand, source, super_locals, constant_pool);
+        let (operand_instructions, operand_type) = parse_expression(
+            &operand,
+            source,
+            current_class,
+            parser_context,
+            super_locals,
+            constant_pool,
+        )?;
 
-            let method_params = format!(
-                "({})",
-                argument_types
-                    .iter()
-                    .map(|a| a.as_letter())
-                    .collect::<String>()
-            );
+        let parameter = match operand_type {
+            PrimitiveType::Reference if is_string => "Ljava/lang/String;",
+            PrimitiveType::Reference => "Ljava/lang/Object;",
+            PrimitiveType::Byte | PrimitiveType::Short | PrimitiveType::Int => "I",
+            PrimitiveType::Boolean => "Z",
+            PrimitiveType::Char => "C",
+            PrimitiveType::Long => "J",
+            PrimitiveType::Float => "F",
+            PrimitiveType::Double => "D",
+            PrimitiveType::Null => return Err(String::from("Cannot concatenate a void value")),
+        };
+        let append = constant_pool.find_or_add_method_ref(
+            "java/lang/StringBuilder",
+            "append",
+            &format!("({})Ljava/lang/StringBuilder;", parameter),
+        );
+
+        instructions.extend(operand_instructions);
+        instructions.push(Instruction::InvokeVirtual(append));
+    }
 
-            // This is the case where the method is inside the same class
-            if node.child_count() < 3 {
-                let method_name = match node.child_by_kind("identifier")?.utf8_text(source) {
-                    Ok(text) => text.to_string(),
-                    Err(err) => return Err(format!("Failed to parse method name: {}", err)),
-                };
+    let to_string = constant_pool.find_or_add_method_ref(
+        "java/lang/StringBuilder",
+        "toString",
+        "()Ljava/lang/String;",
+    );
+    instructions.push(Instruction::InvokeVirtual(to_string));
 
-                let method_partial_signature = format!("{}{}", method_name, method_params);
-                let method = parser_context
-                    .find_method_by_params(current_class, &method_partial_signature)?;
+    Ok(instructions)
+}
 
-                let method_descriptor =
-                    format!("{}{}", method_params, method.return_type.as_letter());
+fn parse_expression(
+    node: &Node,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<(Vec<Instruction>, PrimitiveType), String> {
+    let mut instructions = vec![];
+    let mut expression_type = PrimitiveType::Null;
 
-                let method_index = constant_pool.find_or_add_method_ref(
-                    current_class,
-                    &method_name,
-                    &method_descriptor,
-                );
+    match node.kind() {
+        "(" | "," | ")" => {}
+        "decimal_integer_literal"
+        | "hex_integer_literal"
+        | "octal_integer_literal"
+        | "binary_integer_literal" => {
+            let value = match node.utf8_text(source) {
+                Ok(text) => parse_integer_literal(text)?,
+                Err(err) => return Err(format!("Failed to parse integer literal: {}", err)),
+            };
 
-                expression_type = method.return_type.clone();
-                // TODO: handle non-static methods for methods inside the same class
-                instructions.push(Instruction::InvokeStatic(method_index));
+            expression_type = if value.is_wide() {
+                PrimitiveType::Long
             } else {
-                // TODO: these two are the same as for field access and should be abstracted
-                let class_or_object_name = match node.child(0) {
-                    Some(node) => match node.utf8_text(source) {
-                        Ok(text) => text.to_string(),
-                        Err(err) => {
-                            return Err(format!("Failed to parse class or object name: {}", err));
-                        }
-                    },
-                    None => {
-                        return Err(String::from(
-                            "Method invocation is missing class or object name",
-                        ));
-                    }
-                };
-
-                let method_name = match node.child(2) {
-                    Some(node) => match node.utf8_text(source) {
-                        Ok(text) => text.to_string(),
-                        Err(err) => return Err(format!("Failed to parse method name: {}", err)),
-                    },
-                    None => return Err(String::from("Method invocation is missing method name")),
-                };
+                PrimitiveType::Int
+            };
+            instructions.push(Instruction::Const(value));
+        }
+        "decimal_floating_point_literal" => {
+            let text = match node.utf8_text(source) {
+                Ok(text) => text,
+                Err(err) => {
+                    return Err(format!(
+                        "Failed to parse decimal floating point literal: {}",
+                        err
+                    ))
+                }
+            };
 
-                if method_name.eq("println") {
-                    let method_index = constant_pool.find_or_add_method_ref(
-                        "java/io/PrintStream",
-                        "println",
-                        "(I)V",
-                    );
+            let text = if text.ends_with('f') || text.ends_with('F') {
+                &text[..text.len() - 1]
+            } else {
+                text
+            };
 
-                    instructions.push(Instruction::InvokeVirtual(method_index));
-                    expression_type = PrimitiveType::Null;
+            let value = match text.parse::<f32>() {
+                Ok(value) => value,
+                Err(err) => return Err(format!("Failed to parse floating point literal: {}", err)),
+            };
 
-                    return Ok((instructions, expression_type));
-                }
+            expression_type = PrimitiveType::Float;
+            instructions.push(Instruction::Const(Primitive::Float(value)));
+        }
+        "string_literal" => {
+            let text = string_literal_text(node, source)?;
 
-                let method_partial_signature = format!("{}{}", method_name, method_params);
+            expression_type = PrimitiveType::Reference;
+            instructions.push(Instruction::LoadConst(
+                constant_pool.find_or_add_string(&text),
+            ));
+        }
+        "identifier" => {
+            let name = match node.utf8_text(source) {
+                Ok(text) => text.to_string(),
+                Err(err) => return Err(format!("Failed to parse identifier: {}", err)),
+            };
 
-                if let Some(index) = super_locals.find_local(&class_or_object_name) {
-                    // Dynamic method invocation
-                    let class_name = match super_locals.reference_classes.get(&index) {
-                        Some(class_name) => match constant_pool.class_parser(class_name) {
-                            Some(name) => name,
-                            None => {
-                                return Err(format!(
-                                    "Invoked dynamic method on class not in constant pool: {}",
-                                    class_or_object_name
-                                ))
-                            }
-                        },
-                        None => {
-                            return Err(format!(
-                                "Dynamic method invocation on non-object: {}",
-                                class_or_object_name
-                            ));
-                        }
-                    };
+            match super_locals.find_local(&name) {
+                Some(index) => {
+                    let local_type = super_locals.get_local_type(&index)?;
+                    instructions.push(Instruction::Load(index, local_type.clone()));
+                    expression_type = local_type;
+                }
+                None => {
+                    let (field, object, field_ref) = unqualified_field(
+                        &name,
+                        current_class,
+                        parser_context,
+                        super_locals,
+                        constant_pool,
+                    )?;
 
-                    let method = parser_context
-                        .find_method_by_params(&class_name, &method_partial_signature)?;
+                    expression_type = field.descriptor.clone();
+                    match object {
+                        Some(object) => {
+                            instructions.extend([object, Instruction::GetField(field_ref)])
+                        }
+                        None => instructions.push(Instruction::GetStatic(field_ref)),
+                    }
+                }
+            }
+        }
+        "array_access" => {
+            let (array, index) = match (node.child(0), node.child(2)) {
+                (Some(array), Some(index)) => (array, index),
+                _ => return Err(String::from("Array access is missing array or index")),
+            };
+            let descriptor =
+                array_expression_descriptor(&array, source, super_locals, constant_pool)?;
 
-                    let method_descriptor =
-                        format!("{}{}", method_params, method.return_type.as_letter());
+            for operand in [array, index] {
+                let (operand_instructions, _) = parse_expression(
+                    &operand,
+                    source,
+                    current_class,
+                    parser_context,
+                    super_locals,
+                    constant_pool,
+                )?;
+                instructions.extend(operand_instructions);
+            }
 
-                    let method_index = constant_pool.find_or_add_method_ref(
-                        &class_or_object_name,
-                        &method_name,
-                        &method_descriptor,
-                    );
+            expression_type = array_element_type(&descriptor);
+            instructions.push(Instruction::ALoad(expression_type.clone()));
+        }
+        "assignment_expression"
+            if node.child(0).map(|left| left.kind()) == Some("array_access") =>
+        {
+            let left = node.child(0).unwrap();
+            let (array, index) = match (left.child(0), left.child(2)) {
+                (Some(array), Some(index)) => (array, index),
+                _ => return Err(String::from("Array access is missing array or index")),
+            };
+            let descriptor =
+                array_expression_descriptor(&array, source, super_locals, constant_pool)?;
+            let element_type = array_element_type(&descriptor);
 
-                    expression_type = method.return_type.clone();
-                    instructions.push(Instruction::Load(index, PrimitiveType::Reference));
-                    instructions.push(Instruction::InvokeVirtual(method_index));
-                } else {
-                    // Static method invocation
-                    let method = parser_context
-                        .find_method_by_params(&class_or_object_name, &method_partial_signature)?;
+            match node.child(1).map(|operator| operator.kind()) {
+                Some("=") => {}
+                _ => {
+                    return Err(String::from(
+                        "Compound assignments to array elements are not supported yet",
+                    ))
+                }
+            }
 
-                    let method_descriptor =
-                        format!("{}{}", method_params, method.return_type.as_letter());
+            let value = match node.child(2) {
+                Some(node) => node,
+                None => return Err(String::from("Assignment expression is missing expression")),
+            };
 
-                    let method_index = constant_pool.find_or_add_method_ref(
-                        &class_or_object_name,
-                        &method_name,
-                        &method_descriptor,
-                    );
+            let mut value_type = PrimitiveType::Null;
+            for operand in [array, index, value] {
+                let (operand_instructions, operand_type) = parse_expression(
+                    &operand,
+                    source,
+                    current_class,
+                    parser_context,
+                    super_locals,
+                    constant_pool,
+                )?;
+                instructions.extend(operand_instructions);
+                value_type = operand_type;
+            }
 
-                    expression_type = method.return_type.clone();
-                    instructions.push(Instruction::InvokeStatic(method_index));
-                }
+            if !element_type.matches(&value_type) {
+                return Err(format!(
+                    "Assignment expression type mismatch: {:?} != {:?}",
+                    element_type, value_type
+                ));
             }
+
+            instructions.push(Instruction::AStore(element_type.clone()));
+            expression_type = element_type;
         }
-        "field_access" => {
-            let class_or_object_name = match node.child(0) {
+        "assignment_expression"
+            if node.child(0).is_some_and(|left| {
+                left.kind() == "identifier"
+                    && left
+                        .utf8_text(source)
+                        .is_ok_and(|name| super_locals.find_local(name).is_none())
+            }) =>
+        {
+            let name = node.name_from_identifier(source)?;
+            let (field, object, field_ref) = unqualified_field(
+                &name,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
+            let field_type = field.descriptor.clone();
+
+            let operator = match node.child(1) {
                 Some(node) => match node.utf8_text(source) {
-                    Ok(text) => text.to_string(),
+                    Ok(text) => text,
                     Err(err) => {
-                        return Err(format!("Failed to parse class or object name: {}", err))
+                        return Err(format!("Failed to parse assignment operator: {}", err))
                     }
                 },
-                None => return Err(String::from("Field access is missing class or object name")),
+                None => return Err(String::from("Assignment expression is missing operator")),
             };
+            let is_compound = operator.len() == 2;
 
-            let field_name = match node.child(2) {
-                Some(node) => match node.utf8_text(source) {
-                    Ok(text) => text.to_string(),
-                    Err(err) => return Err(format!("Failed to parse field name: {}", err)),
-                },
-                None => return Err(String::from("Field access is missing field name")),
+            let value = match node.child(2) {
+                Some(node) => node,
+                None => return Err(String::from("Assignment expression is missing expression")),
             };
 
-            if let Some(index) = super_locals.find_local(&class_or_object_name) {
-                let class_name = match super_locals.reference_classes.get(&index) {
-                    Some(class_name) => match constant_pool.class_parser(class_name) {
-                        Some(name) => name,
-                        None => {
-                            return Err(format!("{} is missing from the constant pool", class_name))
-                        }
-                    },
+            let (value_instructions, value_type) = parse_expression(
+                &value,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
+
+            if !field_type.matches(&value_type) {
+                return Err(format!(
+                    "Assignment expression type mismatch: {:?} != {:?}",
+                    field_type, value_type
+                ));
+            }
+
+            // Compound assignments read the field first, which for instance fields needs a
+            // second copy of the object for the write
+            let (get, put) = match object {
+                Some(object) => {
+                    instructions.push(object);
+                    if is_compound {
+                        instructions.push(Instruction::Dup);
+                    }
+                    (
+                        Instruction::GetField(field_ref),
+                        Instruction::PutField(field_ref),
+                    )
+                }
+                None => (
+                    Instruction::GetStatic(field_ref),
+                    Instruction::PutStatic(field_ref),
+                ),
+            };
+
+            if is_compound {
+                instructions.push(get);
+                instructions.extend(value_instructions);
+                instructions.push(compound_assignment_instruction(operator, &field_type)?);
+            } else {
+                instructions.extend(value_instructions);
+            }
+
+            instructions.push(put);
+            expression_type = field_type;
+        }
+        "assignment_expression" | "variable_declarator" => {
+            let variable_index =
+                match super_locals.find_local(node.name_from_identifier(source)?.as_str()) {
+                    Some(index) => index,
                     None => {
                         return Err(format!(
-                            "Local variable {} is not a valid class reference",
-                            class_or_object_name
+                            "Local variable {} not found",
+                            node.name_from_identifier(source)?
                         ))
                     }
                 };
+            let variable_type = super_locals.get_local_type(&variable_index)?;
 
-                let field = parser_context.find_field(&class_name, &field_name)?;
-                let field_index = constant_pool.find_or_add_field_ref(
-                    &class_name,
-                    &field_name,
-                    field.signature.as_str(),
-                );
+            let expression_node = match node.child(2) {
+                Some(node) => node,
+                None => return Err(String::from("Assignment expression is missing expression")),
+            };
 
-                expression_type = field.descriptor.clone();
-                instructions.push(Instruction::Load(index, PrimitiveType::Reference));
-                instructions.push(Instruction::GetField(field_index));
-            } else {
-                let field = parser_context.find_field(&class_or_object_name, &field_name)?;
+            let (expression_instructions, expr_type) = parse_expression(
+                &expression_node,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
 
-                let field_index = constant_pool.find_or_add_field_ref(
-                    &class_or_object_name,
-                    &field_name,
-                    field.signature.as_str(),
-                );
+            instructions.extend(expression_instructions);
+            if !variable_type.matches(&expr_type) {
+                return Err(format!(
+                    "Assignment expression type mismatch: {:?} != {:?}",
+                    variable_type, expr_type
+                ));
+            }
+            expression_type = variable_type.clone();
 
-                expression_type = field.descriptor.clone();
-                instructions.push(Instruction::GetStatic(field_index));
+            let operator = match node.child(1) {
+                Some(node) => match node.utf8_text(source) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        return Err(format!("Failed to parse assignment operator: {}", err))
+                    }
+                },
+                None => return Err(String::from("Assignment expression is missing operator")),
+            };
+
+            if operator.len() == 2 {
+                instructions.push(Instruction::Load(variable_index, variable_type.clone()));
+                instructions.push(compound_assignment_instruction(operator, &variable_type)?);
             }
+
+            instructions.push(Instruction::Store(variable_index, variable_type));
         }
-        _ => return Err(format!("Unknown expression type {}", node.kind())),
-    }
+        "binary_expression" if is_string_expression(node, source, super_locals, constant_pool) => {
+            instructions = parse_string_concatenation(
+                node,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
+            expression_type = PrimitiveType::Reference;
+        }
+        "binary_expression" => {
+            let left = match node.child(0) {
+                Some(node) => node,
+                None => return Err(String::from("Binary expression is missing left operand")),
+            };
 
-    Ok((instructions, expression_type))
-}
+            let operator = match node.child(1) {
+                Some(node) => match node.utf8_text(source) {
+                    Ok(text) => text.to_string(),
+                    Err(err) => return Err(format!("Failed to parse binary operator: {}", err)),
+                },
+                None => return Err(String::from("Binary expression is missing operator")),
+            };
 
-#[derive(Debug)]
-struct ExpressionInfo {
-    pub comparison: Comparison,
-    pub instructions: Vec<Instruction>,
-    pub start_index: usize,
-    pub end_index: usize,
-    // TODO: add is_int to this struct or otherwise handle non-int comparisons
-}
+            // Logical operators outside of if conditions produce a boolean value
+            if operator == "&&" || operator == "||" {
+                instructions.extend(parse_condition_value(
+                    node,
+                    source,
+                    current_class,
+                    parser_context,
+                    super_locals,
+                    constant_pool,
+                )?);
+                return Ok((instructions, PrimitiveType::Boolean));
+            }
 
-#[derive(Debug)]
-struct ConnectiveInfo {
-    pub comparisons: Vec<BlockType>,
-    pub start_index: usize,
-    pub end_index: usize,
-}
+            let right = match node.child(2) {
+                Some(node) => node,
+                None => return Err(String::from("Binary expression is missing right operand")),
+            };
 
-#[derive(Debug)]
-enum BlockType {
-    And(ConnectiveInfo),
-    Or(ConnectiveInfo),
-    Parenthesis(ConnectiveInfo),
-    Expression(ExpressionInfo),
-}
+            let (left_instructions, left_type) = parse_expression(
+                &left,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
 
-impl BlockType {
-    /// Get the start_index of the block
-    pub fn start_index(&self) -> usize {
-        match self {
-            BlockType::And(connective) => connective.start_index,
-            BlockType::Or(connective) => connective.start_index,
-            BlockType::Parenthesis(connective) => connective.start_index,
-            BlockType::Expression(expression) => expression.start_index,
-        }
-    }
+            let (right_instructions, right_type) = parse_expression(
+                &right,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
 
-    /// Get the end_index of the block
-    pub fn end_index(&self) -> usize {
-        match self {
-            BlockType::And(connective) => connective.end_index,
-            BlockType::Or(connective) => connective.end_index,
-            BlockType::Parenthesis(connective) => connective.end_index,
-            BlockType::Expression(expression) => expression.end_index,
-        }
-    }
+            if !left_type.matches(&right_type) {
+                // TODO: implement automatic type widening
+                return Err(format!(
+                    "Binary expression has mismatched types: {:?} and {:?}",
+                    left_type, right_type
+                ));
+            }
 
-    /// Pretty print the block type and its children
-    pub fn pretty_print_tree(&self, depth: usize) {
-        let indent = "  ".repeat(depth);
+            // Comparisons push 1 when they hold and 0 otherwise
+            if let Some(comparison) = Comparison::from_operator(&operator) {
+                // Less-than checks use the G variant so that NaN makes them false, like javac
+                let nan_greater = matches!(
+                    comparison,
+                    Comparison::LessThan | Comparison::LessThanOrEqual
+                );
+                let compare = match (&left_type, nan_greater) {
+                    (PrimitiveType::Long, _) => Some(Instruction::LCmp),
+                    (PrimitiveType::Float, false) => Some(Instruction::FCmpL),
+                    (PrimitiveType::Float, true) => Some(Instruction::FCmpG),
+                    (PrimitiveType::Double, false) => Some(Instruction::DCmpL),
+                    (PrimitiveType::Double, true) => Some(Instruction::DCmpG),
+                    (PrimitiveType::Reference | PrimitiveType::Null, _)
+                        if !matches!(comparison, Comparison::Equal | Comparison::NotEqual) =>
+                    {
+                        return Err(format!("Cannot compare references with {}", operator));
+                    }
+                    _ => None,
+                };
 
-        match self {
-            BlockType::And(info) => {
-                println!("{}AND [{}..{}]", indent, info.start_index, info.end_index);
-                for comparison in &info.comparisons {
-                    comparison.pretty_print_tree(depth + 1);
-                }
+                instructions.extend(left_instructions);
+                instructions.extend(right_instructions);
+                let branch = match compare {
+                    Some(compare) => {
+                        instructions.push(compare);
+                        Instruction::If(3, comparison.negate())
+                    }
+                    None => Instruction::IfICmp(3, comparison.negate()),
+                };
+                instructions.extend([
+                    branch,
+                    Instruction::Const(Primitive::Int(1)),
+                    Instruction::Goto(2),
+                    Instruction::Const(Primitive::Int(0)),
+                ]);
+                return Ok((instructions, PrimitiveType::Boolean));
             }
-            BlockType::Or(info) => {
-                println!("{}OR [{}..{}]", indent, info.start_index, info.end_index);
-                for comparison in &info.comparisons {
-                    comparison.pretty_print_tree(depth + 1);
+
+            expression_type = left_type;
+
+            let (instruction, operation) = match operator.as_str() {
+                "+" => (Instruction::Add(expression_type.clone()), Operator::Add),
+                "-" => (Instruction::Sub(expression_type.clone()), Operator::Sub),
+                "*" => (Instruction::Mul(expression_type.clone()), Operator::Mul),
+                "/" => (Instruction::Div(expression_type.clone()), Operator::Div),
+                "%" => (Instruction::Rem(expression_type.clone()), Operator::Rem),
+                _ => return Err(format!("Unknown binary operator {}", operator)),
+            };
+
+            // Operations on two constants are evaluated at compile time, unless they would throw,
+            // e.g. on division by zero, in which case that is left to happen at runtime
+            if let ([Instruction::Const(left)], [Instruction::Const(right)]) =
+                (&left_instructions[..], &right_instructions[..])
+            {
+                if let Ok(value) = Primitive::eval2(left.clone(), right.clone(), operation) {
+                    instructions.push(Instruction::Const(value));
+                    return Ok((instructions, expression_type));
                 }
             }
-            BlockType::Parenthesis(info) => {
-                println!(
-                    "{}PARENTHESIS [{}..{}]",
-                    indent, info.start_index, info.end_index
-                );
-                for comparison in &info.comparisons {
-                    comparison.pretty_print_tree(depth + 1);
+
+            instructions.extend(left_instructions);
+            instructions.extend(right_instructions);
+            instructions.push(instruction);
+        }
+        "parenthesized_expression" => {
+            let expression = match node.child(1) {
+                Some(node) => node,
+                None => {
+                    return Err(String::from(
+                        "Parenthesized expression is missing expression",
+                    ))
                 }
-            }
-            BlockType::Expression(info) => {
-                println!(
-                    "{}COMPARISON [{}..{}]",
-                    indent, info.start_index, info.end_index
-                );
-                for instruction in &info.instructions {
-                    println!("{}  {:?}", indent, instruction);
+            };
+
+            return parse_expression(
+                &expression,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            );
+        }
+        "unary_expression" => {
+            let operator = match node.child(0) {
+                Some(node) => match node.utf8_text(source) {
+                    Ok(text) => text.to_string(),
+                    Err(err) => return Err(format!("Failed to parse unary operator: {}", err)),
+                },
+                None => return Err(String::from("Unary expression is missing operator")),
+            };
+
+            let operand = match node.child(1) {
+                Some(node) => node,
+                None => return Err(String::from("Unary expression is missing operand")),
+            };
+
+            let (operand_instructions, operand_type) = parse_expression(
+                &operand,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
+
+            // The complement of a value flips all of its bits, which is xor with all bits set
+            let all_bits = match (operator.as_str(), &operand_type) {
+                (
+                    "~",
+                    PrimitiveType::Byte
+                    | PrimitiveType::Short
+                    | PrimitiveType::Char
+                    | PrimitiveType::Int,
+                ) => Primitive::Int(-1),
+                ("~", PrimitiveType::Long) => Primitive::Long(-1),
+                ("~", _) => {
+                    return Err(format!(
+                        "Bitwise complement of {:?} is not supported",
+                        operand_type
+                    ))
+                }
+                _ => return Err(format!("Unknown unary operator {}", operator)),
+            };
+
+            expression_type = match operand_type {
+                PrimitiveType::Long => PrimitiveType::Long,
+                _ => PrimitiveType::Int,
+            };
+
+            if let [Instruction::Const(value)] = &operand_instructions[..] {
+                if let Ok(value) = Primitive::eval2(value.clone(), all_bits.clone(), Operator::Xor)
+                {
+                    instructions.push(Instruction::Const(value));
+                    return Ok((instructions, expression_type));
                 }
-                println!("{}  {:?}", indent, info.comparison);
             }
+
+            instructions.extend(operand_instructions);
+            instructions.push(Instruction::Const(all_bits));
+            instructions.push(Instruction::Xor(expression_type.clone()));
         }
-    }
+        "cast_expression" => {
+            let type_node = match node.child(1) {
+                Some(node) => node,
+                None => return Err(String::from("Cast expression is missing type")),
+            };
+            let target_type = type_node_to_primitive_type(type_node)?;
 
-    /// Flatten the connective block into a single connective
-    /// i.e. And(And(Expr, Expr), Expr) -> And(Expr, Expr, Expr)
-    /// or Or(Or(Expr, Expr), Expr) -> Or(Expr, Expr, Expr)
-    /// This should also strip unnecessary parenthesis.
-    pub fn flatten(&self) -> BlockType {
-        let mut comparisons = Vec::new();
+            let operand = match node.child(3) {
+                Some(node) => node,
+                None => return Err(String::from("Cast expression is missing operand")),
+            };
 
-        match self {
-            BlockType::And(info) => {
-                for comparison in &info.comparisons {
-                    match comparison.flatten() {
-                        BlockType::And(info) => comparisons.extend(info.comparisons),
-                        comparison => comparisons.push(comparison),
-                    }
-                }
-                BlockType::And(ConnectiveInfo {
-                    comparisons,
-                    start_index: info.start_index,
-                    end_index: info.end_index,
-                })
+            let (operand_instructions, operand_type) = parse_expression(
+                &operand,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
+
+            instructions.extend(operand_instructions);
+
+            // Casts between classes are checked when they run
+            if target_type.matches(&PrimitiveType::Reference)
+                && (operand_type.matches(&PrimitiveType::Reference)
+                    || operand_type.matches(&PrimitiveType::Null))
+            {
+                let class_name = match type_node.kind() {
+                    "array_type" => array_type_descriptor(&type_node, source, parser_context)?,
+                    _ => match type_node.utf8_text(source) {
+                        Ok(text) => parser_context.qualified_class_name(text),
+                        Err(err) => return Err(format!("Failed to parse cast type: {}", err)),
+                    },
+                };
+                instructions.push(Instruction::CheckCast(
+                    constant_pool.find_or_add_class(&class_name),
+                ));
+            } else {
+                instructions.extend(cast_instructions(&operand_type, &target_type)?);
             }
-            BlockType::Or(info) => {
-                for comparison in &info.comparisons {
-                    match comparison.flatten() {
-                        BlockType::Or(info) => comparisons.extend(info.comparisons),
-                        comparison => comparisons.push(comparison),
-                    }
-                }
-                BlockType::Or(ConnectiveInfo {
-                    comparisons,
-                    start_index: info.start_index,
-                    end_index: info.end_index,
-                })
+            expression_type = target_type;
+        }
+        "instanceof_expression" => {
+            let operand = match node.child(0) {
+                Some(node) => node,
+                None => return Err(String::from("Instanceof expression is missing operand")),
+            };
+
+            let class_name = match node.child(2) {
+                Some(node) => match node.utf8_text(source) {
+                    Ok(text) => parser_context.qualified_class_name(text),
+                    Err(err) => return Err(format!("Failed to parse instanceof type: {}", err)),
+                },
+                None => return Err(String::from("Instanceof expression is missing type")),
+            };
+
+            let (operand_instructions, operand_type) = parse_expression(
+                &operand,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+            )?;
+
+            if !operand_type.matches(&PrimitiveType::Reference) {
+                return Err(format!(
+                    "Instanceof operand must be an object, not {:?}",
+                    operand_type
+                ));
             }
-            BlockType::Parenthesis(info) => {
-                for comparison in &info.comparisons {
-                    match comparison.flatten() {
-                        BlockType::Parenthesis(info) => comparisons.extend(info.comparisons),
-                        comparison => comparisons.push(comparison),
+
+            instructions.extend(operand_instructions);
+
+            let class_index = constant_pool.find_or_add_class(&class_name);
+
+            // The pattern variable is only given the operand once the check has passed, and is
+            // null otherwise. The result of the check is left on the stack either way
+            if let Some(name) = pattern_binding(node, source) {
+                let index = match super_locals.find_local(&name) {
+                    Some(index) => index,
+                    None => {
+                        return Err(format!(
+                            "Pattern variable {} is only supported in if conditions",
+                            name
+                        ))
                     }
-                }
-                if comparisons.len() == 1 {
-                    comparisons.remove(0)
-                } else {
-                    BlockType::Parenthesis(ConnectiveInfo {
-                        comparisons,
-                        start_index: info.start_index,
-                        end_index: info.end_index,
-                    })
-                }
+                };
+
+                instructions.extend([
+                    Instruction::Dup,
+                    Instruction::InstanceOf(class_index),
+                    Instruction::Dup,
+                    Instruction::If(5, Comparison::Equal),
+                    Instruction::Swap,
+                    Instruction::CheckCast(class_index),
+                    Instruction::Store(index, PrimitiveType::Reference),
+                    Instruction::Goto(5),
+                    Instruction::Swap,
+                    Instruction::Pop,
+                    Instruction::AConstNull,
+                    Instruction::Store(index, PrimitiveType::Reference),
+                ]);
+            } else {
+                instructions.push(Instruction::InstanceOf(class_index));
             }
-            BlockType::Expression(info) => BlockType::Expression(ExpressionInfo {
-                comparison: info.comparison.clone(),
-                instructions: info.instructions.clone(),
-                start_index: info.start_index,
-                end_index: info.end_index,
-            }),
+            expression_type = PrimitiveType::Boolean;
         }
-    }
+        "object_creation_expression" => {
+            let class_name = match node.child_by_kind("type_identifier")?.utf8_text(source) {
+                Ok(text) => text.to_string(),
+                Err(err) => return Err(format!("Failed to parse class name: {}", err)),
+            };
 
-    /// Convert the block type into a list of instructions including correctly indexed jumps
-    /// for the if statements as a result of the connectives.
-    pub fn fully_flatten(
-        &self,
-        on_true_jump: usize,
-        on_false_jump: usize,
-        negate: bool,
-        must_be_true: bool,
-    ) -> Result<Vec<Instruction>, String> {
-        let mut instructions = Vec::new();
+            // Built-in exceptions aren't compiled, so there are no constructors to look up
+            let class_name = parser_context.qualified_class_name(&class_name);
+            let is_builtin = is_builtin_throwable(&class_name);
+            if !is_builtin {
+                parser_context.find_class(&class_name)?;
+            }
+            let class_index = constant_pool.find_or_add_class(&class_name);
 
-        match self {
-            BlockType::And(info) => {
-                // There are n total comparisons in the and block
-                // The first n - 1 comparisons will jump to on_false_jump - their instruction_index if false
-                // The last comparison will jump to on_true_jump - its instruction_index if true
-                let n = info.comparisons.len();
-                for (i, comparison) in info.comparisons.iter().enumerate() {
-                    instructions.extend(if i == (n - 1) {
-                        comparison.fully_flatten(
-                            on_true_jump,
-                            on_false_jump,
-                            false,
-                            must_be_true,
-                        )?
-                    } else {
-                        comparison.fully_flatten(
-                            comparison.end_index() + 1,
-                            on_false_jump,
-                            true,
-                            must_be_true,
-                        )?
-                    });
+            instructions.push(Instruction::New(class_index as usize));
+            instructions.push(Instruction::Dup);
+
+            let arguments_node = node.child_by_kind("argument_list")?;
+            let mut arguments = vec![];
+
+            for i in 1..(arguments_node.child_count() - 1) {
+                let argument = match arguments_node.child(i) {
+                    Some(node) => node,
+                    None => return Err(format!("Could not find argument_list child {}", i)),
+                };
+
+                let (argument_instructions, argument_type) = parse_expression(
+                    &argument,
+                    source,
+                    current_class,
+                    parser_context,
+                    super_locals,
+                    constant_pool,
+                )?;
+
+                if argument_type.matches(&PrimitiveType::Null) {
+                    continue;
                 }
+
+                arguments.push((argument_instructions, argument_type));
             }
-            BlockType::Or(info) => {
-                // There are n total comparisons in the or block
-                // The first n - 1 comparisons will jump to on_true_jump - their instruction_index if true
-                // The last comparison will jump to on_false_jump - its instruction_index if false
-                let n = info.comparisons.len();
-                for (i, comparison) in info.comparisons.iter().enumerate() {
-                    instructions.extend(if i == (n - 1) {
-                        comparison.fully_flatten(on_true_jump, on_false_jump, true, must_be_true)?
+
+            let argument_types = arguments
+                .iter()
+                .map(|(_, argument_type)| argument_type.clone())
+                .collect::<Vec<_>>();
+
+            let constructor_descriptor = if is_builtin {
+                // Built-in exceptions are created with a message or without one
+                let descriptor = match argument_types.as_slice() {
+                    [] => String::from("()V"),
+                    [PrimitiveType::Reference] => String::from("(Ljava/lang/String;)V"),
+                    _ => {
+                        return Err(format!(
+                            "{} can only be created with a message or without one",
+                            class_name
+                        ))
+                    }
+                };
+                instructions.extend(arguments.into_iter().flat_map(|(a, _)| a));
+                descriptor
+            } else {
+                // Arguments are widened to the parameters of the constructor like those of methods
+                let constructor =
+                    parser_context.find_method_for_call(&class_name, "<init>", &argument_types)?;
+                instructions.extend(call_arguments(constructor, arguments, constant_pool)?);
+                constructor.descriptor().to_string()
+            };
+
+            let method_index = constant_pool.find_or_add_method_ref(
+                &class_name,
+                "<init>",
+                &constructor_descriptor,
+            );
+
+            expression_type = PrimitiveType::Reference;
+            instructions.push(Instruction::InvokeSpecial(method_index));
+        }
+        "method_invocation" => {
+            let arguments_node = node.child_by_kind("argument_list")?;
+            let mut arguments = vec![];
+
+            for i in 1..(arguments_node.child_count() - 1) {
+                let argument = match arguments_node.child(i) {
+                    Some(node) => node,
+                    None => return Err(format!("Could not find argument_list child {}", i)),
+                };
+
+                let (argument_instructions, argument_type) = parse_expression(
+                    &argument,
+                    source,
+                    current_class,
+                    parser_context,
+                    super_locals,
+                    constant_pool,
+                )?;
+
+                if argument_type.matches(&PrimitiveType::Null) {
+                    continue;
+                }
+
+                arguments.push((argument_instructions, argument_type));
+            }
+
+            let argument_types = arguments
+                .iter()
+                .map(|(_, argument_type)| argument_type.clone())
+                .collect::<Vec<_>>();
+
+            // This is the case where the method is inside the same class
+            if node.child_count() < 3 {
+                let method_name = match node.child_by_kind("identifier")?.utf8_text(source) {
+                    Ok(text) => text.to_string(),
+                    Err(err) => return Err(format!("Failed to parse method name: {}", err)),
+                };
+
+                let method = parser_context.find_method_for_call(
+                    current_class,
+                    &method_name,
+                    &argument_types,
+                )?;
+                instructions.extend(call_arguments(method, arguments, constant_pool)?);
+
+                let method_descriptor = method.descriptor();
+
+                let method_index = constant_pool.find_or_add_method_ref(
+                    current_class,
+                    &method_name,
+                    method_descriptor,
+                );
+
+                expression_type = method.return_type.clone();
+
+                if method.is_static() {
+                    instructions.push(Instruction::InvokeStatic(method_index));
+                } else {
+                    // Instance methods are called on this, which only static methods lack
+                    match super_locals.find_local("this") {
+                        Some(index) => {
+                            instructions
+                                .insert(0, Instruction::Load(index, PrimitiveType::Reference));
+                            instructions.push(Instruction::InvokeVirtual(method_index));
+                        }
+                        None => {
+                            return Err(format!(
+                                "Non-static method {} cannot be referenced from a static context",
+                                method_name
+                            ))
+                        }
+                    }
+                }
+            } else {
+                // TODO: these two are the same as for field access and should be abstracted
+                let class_or_object_name = match node.child(0) {
+                    Some(node) => match node.utf8_text(source) {
+                        Ok(text) => text.to_string(),
+                        Err(err) => {
+                            return Err(format!("Failed to parse class or object name: {}", err));
+                        }
+                    },
+                    None => {
+                        return Err(String::from(
+                            "Method invocation is missing class or object name",
+                        ));
+                    }
+                };
+
+                let method_name = match node.child(2) {
+                    Some(node) => match node.utf8_text(source) {
+                        Ok(text) => text.to_string(),
+                        Err(err) => return Err(format!("Failed to parse method name: {}", err)),
+                    },
+                    None => return Err(String::from("Method invocation is missing method name")),
+                };
+
+                if method_name.eq("println") {
+                    let parameter = match argument_types.first() {
+                        Some(PrimitiveType::Boolean) => "Z",
+                        Some(PrimitiveType::Char) => "C",
+                        Some(PrimitiveType::Long) => "J",
+                        Some(PrimitiveType::Float) => "F",
+                        Some(PrimitiveType::Double) => "D",
+                        Some(PrimitiveType::Reference) => "Ljava/lang/Object;",
+                        _ => "I",
+                    };
+                    let method_index = constant_pool.find_or_add_method_ref(
+                        "java/io/PrintStream",
+                        "println",
+                        &format!("({})V", parameter),
+                    );
+
+                    instructions.extend(
+                        arguments
+                            .into_iter()
+                            .flat_map(|(argument_instructions, _)| argument_instructions),
+                    );
+
+                    // The stream is the receiver, so it goes below the argument
+                    let field_index = constant_pool.find_or_add_field_ref(
+                        "java/lang/System",
+                        "out",
+                        "Ljava/io/PrintStream;",
+                    );
+                    instructions.insert(0, Instruction::GetStatic(field_index));
+
+                    instructions.push(Instruction::InvokeVirtual(method_index));
+                    expression_type = PrimitiveType::Null;
+
+                    return Ok((instructions, expression_type));
+                }
+
+                if let Some(index) = super_locals.find_local(&class_or_object_name) {
+                    // Dynamic method invocation
+                    let class_name = match super_locals.reference_classes.get(&index) {
+                        Some(class_name) => match constant_pool.class_parser(class_name) {
+                            Ok(name) => name,
+                            Err(_) => {
+                                return Err(format!(
+                                    "Invoked dynamic method on class not in constant pool: {}",
+                                    class_or_object_name
+                                ))
+                            }
+                        },
+                        None => {
+                            return Err(format!(
+                                "Dynamic method invocation on non-object: {}",
+                                class_or_object_name
+                            ));
+                        }
+                    };
+
+                    let method = parser_context.find_method_for_call(
+                        &class_name,
+                        &method_name,
+                        &argument_types,
+                    )?;
+                    instructions.extend(call_arguments(method, arguments, constant_pool)?);
+
+                    let method_descriptor = method.descriptor();
+
+                    let is_interface = parser_context.find_class(&class_name)?.is_interface();
+                    let method_index = if is_interface {
+                        constant_pool.find_or_add_interface_method_ref(
+                            &class_name,
+                            &method_name,
+                            method_descriptor,
+                        )
                     } else {
-                        comparison.fully_flatten(
-                            on_true_jump,
-                            comparison.end_index() + 1,
-                            false,
-                            false,
-                        )?
-                    });
+                        constant_pool.find_or_add_method_ref(
+                            &class_name,
+                            &method_name,
+                            method_descriptor,
+                        )
+                    };
+
+                    expression_type = method.return_type.clone();
+
+                    // Static methods may be called through an object, which is then ignored
+                    if method.is_static() {
+                        instructions.push(Instruction::InvokeStatic(method_index));
+                    } else {
+                        // The receiver goes below the arguments
+                        instructions.insert(0, Instruction::Load(index, PrimitiveType::Reference));
+                        instructions.push(if is_interface {
+                            Instruction::InvokeInterface(method_index)
+                        } else {
+                            Instruction::InvokeVirtual(method_index)
+                        });
+                    }
+                } else {
+                    // Static method invocation
+                    let method = parser_context.find_method_for_call(
+                        &class_or_object_name,
+                        &method_name,
+                        &argument_types,
+                    )?;
+                    instructions.extend(call_arguments(method, arguments, constant_pool)?);
+
+                    if !method.is_static() {
+                        return Err(format!(
+                            "Non-static method {} cannot be referenced from a static context",
+                            method_name
+                        ));
+                    }
+
+                    let method_descriptor = method.descriptor();
+
+                    let method_index = constant_pool.find_or_add_method_ref(
+                        &class_or_object_name,
+                        &method_name,
+                        method_descriptor,
+                    );
+
+                    expression_type = method.return_type.clone();
+                    instructions.push(Instruction::InvokeStatic(method_index));
                 }
             }
-            BlockType::Expression(info) => {
-                instructions.extend(info.instructions.clone());
+        }
+        "field_access" => {
+            let class_or_object_name = match node.child(0) {
+                Some(node) => match node.utf8_text(source) {
+                    Ok(text) => text.to_string(),
+                    Err(err) => {
+                        return Err(format!("Failed to parse class or object name: {}", err))
+                    }
+                },
+                None => return Err(String::from("Field access is missing class or object name")),
+            };
+
+            let field_name = match node.child(2) {
+                Some(node) => match node.utf8_text(source) {
+                    Ok(text) => text.to_string(),
+                    Err(err) => return Err(format!("Failed to parse field name: {}", err)),
+                },
+                None => return Err(String::from("Field access is missing field name")),
+            };
+
+            if let Some(index) = super_locals.find_local(&class_or_object_name) {
+                let class_name = match super_locals.reference_classes.get(&index) {
+                    Some(class_name) => match constant_pool.class_parser(class_name) {
+                        Ok(name) => name,
+                        Err(_) => {
+                            return Err(format!("{} is missing from the constant pool", class_name))
+                        }
+                    },
+                    None => {
+                        return Err(format!(
+                            "Local variable {} is not a valid class reference",
+                            class_or_object_name
+                        ))
+                    }
+                };
+
+                let field = parser_context.find_field(&class_name, &field_name)?;
+                let field_index = constant_pool.find_or_add_field_ref(
+                    &class_name,
+                    &field_name,
+                    field.signature.as_str(),
+                );
+
+                expression_type = field.descriptor.clone();
+                instructions.push(Instruction::Load(index, PrimitiveType::Reference));
+                instructions.push(Instruction::GetField(field_index));
+            } else {
+                let field = parser_context.find_field(&class_or_object_name, &field_name)?;
+
+                let field_index = constant_pool.find_or_add_field_ref(
+                    &class_or_object_name,
+                    &field_name,
+                    field.signature.as_str(),
+                );
+
+                expression_type = field.descriptor.clone();
+                instructions.push(Instruction::GetStatic(field_index));
+            }
+        }
+        _ => return Err(format!("Unknown expression type {}", node.kind())),
+    }
+
+    Ok((instructions, expression_type))
+}
+
+#[derive(Debug)]
+struct ExpressionInfo {
+    pub comparison: Comparison,
+    pub instructions: Vec<Instruction>,
+    pub start_index: usize,
+    pub end_index: usize,
+    // TODO: add is_int to this struct or otherwise handle non-int comparisons
+}
+
+#[derive(Debug)]
+struct ConnectiveInfo {
+    pub comparisons: Vec<BlockType>,
+    pub start_index: usize,
+    pub end_index: usize,
+}
+
+#[derive(Debug)]
+enum BlockType {
+    And(ConnectiveInfo),
+    Or(ConnectiveInfo),
+    Parenthesis(ConnectiveInfo),
+    Expression(ExpressionInfo),
+}
+
+impl BlockType {
+    /// Get the start_index of the block
+    #[allow(dead_code)]
+    pub fn start_index(&self) -> usize {
+        match self {
+            BlockType::And(connective) => connective.start_index,
+            BlockType::Or(connective) => connective.start_index,
+            BlockType::Parenthesis(connective) => connective.start_index,
+            BlockType::Expression(expression) => expression.start_index,
+        }
+    }
+
+    /// Get the end_index of the block
+    pub fn end_index(&self) -> usize {
+        match self {
+            BlockType::And(connective) => connective.end_index,
+            BlockType::Or(connective) => connective.end_index,
+            BlockType::Parenthesis(connective) => connective.end_index,
+            BlockType::Expression(expression) => expression.end_index,
+        }
+    }
+
+    /// Pretty print the block type and its children
+    #[allow(dead_code)]
+    pub fn pretty_print_tree(&self, depth: usize) {
+        let indent = "  ".repeat(depth);
+
+        match self {
+            BlockType::And(info) => {
+                println!("{}AND [{}..{}]", indent, info.start_index, info.end_index);
+                for comparison in &info.comparisons {
+                    comparison.pretty_print_tree(depth + 1);
+                }
+            }
+            BlockType::Or(info) => {
+                println!("{}OR [{}..{}]", indent, info.start_index, info.end_index);
+                for comparison in &info.comparisons {
+                    comparison.pretty_print_tree(depth + 1);
+                }
+            }
+            BlockType::Parenthesis(info) => {
+                println!(
+                    "{}PARENTHESIS [{}..{}]",
+                    indent, info.start_index, info.end_index
+                );
+                for comparison in &info.comparisons {
+                    comparison.pretty_print_tree(depth + 1);
+                }
+            }
+            BlockType::Expression(info) => {
+                println!(
+                    "{}COMPARISON [{}..{}]",
+                    indent, info.start_index, info.end_index
+                );
+                for instruction in &info.instructions {
+                    println!("{}  {:?}", indent, instruction);
+                }
+                println!("{}  {:?}", indent, info.comparison);
+            }
+        }
+    }
+
+    /// Flatten the connective block into a single connective
+    /// i.e. And(And(Expr, Expr), Expr) -> And(Expr, Expr, Expr)
+    /// or Or(Or(Expr, Expr), Expr) -> Or(Expr, Expr, Expr)
+    /// This should also strip unnecessary parenthesis.
+    pub fn flatten(&self) -> BlockType {
+        let mut comparisons = Vec::new();
+
+        match self {
+            BlockType::And(info) => {
+                for comparison in &info.comparisons {
+                    match comparison.flatten() {
+                        BlockType::And(info) => comparisons.extend(info.comparisons),
+                        comparison => comparisons.push(comparison),
+                    }
+                }
+                BlockType::And(ConnectiveInfo {
+                    comparisons,
+                    start_index: info.start_index,
+                    end_index: info.end_index,
+                })
+            }
+            BlockType::Or(info) => {
+                for comparison in &info.comparisons {
+                    match comparison.flatten() {
+                        BlockType::Or(info) => comparisons.extend(info.comparisons),
+                        comparison => comparisons.push(comparison),
+                    }
+                }
+                BlockType::Or(ConnectiveInfo {
+                    comparisons,
+                    start_index: info.start_index,
+                    end_index: info.end_index,
+                })
+            }
+            BlockType::Parenthesis(info) => {
+                for comparison in &info.comparisons {
+                    match comparison.flatten() {
+                        BlockType::Parenthesis(info) => comparisons.extend(info.comparisons),
+                        comparison => comparisons.push(comparison),
+                    }
+                }
+                if comparisons.len() == 1 {
+                    comparisons.remove(0)
+                } else {
+                    BlockType::Parenthesis(ConnectiveInfo {
+                        comparisons,
+                        start_index: info.start_index,
+                        end_index: info.end_index,
+                    })
+                }
+            }
+            BlockType::Expression(info) => BlockType::Expression(ExpressionInfo {
+                comparison: info.comparison.clone(),
+                instructions: info.instructions.clone(),
+                start_index: info.start_index,
+                end_index: info.end_index,
+            }),
+        }
+    }
+
+    /// Convert the block type into a list of instructions including correctly indexed jumps
+    /// for the if statements as a result of the connectives.
+    pub fn fully_flatten(
+        &self,
+        on_true_jump: usize,
+        on_false_jump: usize,
+        negate: bool,
+        must_be_true: bool,
+    ) -> Result<Vec<Instruction>, String> {
+        let mut instructions = Vec::new();
+
+        match self {
+            BlockType::And(info) => {
+                // There are n total comparisons in the and block
+                // The first n - 1 comparisons will jump to on_false_jump - their instruction_index if false
+                // The last comparison will jump to on_true_jump - its instruction_index if true
+                let n = info.comparisons.len();
+                for (i, comparison) in info.comparisons.iter().enumerate() {
+                    instructions.extend(if i == (n - 1) {
+                        comparison.fully_flatten(
+                            on_true_jump,
+                            on_false_jump,
+                            false,
+                            must_be_true,
+                        )?
+                    } else {
+                        comparison.fully_flatten(
+                            comparison.end_index() + 1,
+                            on_false_jump,
+                            true,
+                            must_be_true,
+                        )?
+                    });
+                }
+            }
+            BlockType::Or(info) => {
+                // There are n total comparisons in the or block
+                // The first n - 1 comparisons will jump to on_true_jump - their instruction_index if true
+                // The last comparison will jump to on_false_jump - its instruction_index if false
+                let n = info.comparisons.len();
+                for (i, comparison) in info.comparisons.iter().enumerate() {
+                    instructions.extend(if i == (n - 1) {
+                        comparison.fully_flatten(on_true_jump, on_false_jump, true, must_be_true)?
+                    } else {
+                        comparison.fully_flatten(
+                            on_true_jump,
+                            comparison.end_index() + 1,
+                            false,
+                            false,
+                        )?
+                    });
+                }
+            }
+            BlockType::Expression(info) => {
+                instructions.extend(info.instructions.clone());
+
+                let (comp, abs_jmp_pos) = if negate || must_be_true {
+                    (info.comparison.negate(), on_false_jump)
+                } else {
+                    (info.comparison.clone(), on_true_jump)
+                };
+
+                instructions.push(Instruction::IfICmp(abs_jmp_pos - info.end_index, comp))
+            }
+            BlockType::Parenthesis(_) => {
+                return Err("fully_flatten input should not include parenthesis".to_string())
+            }
+        }
+
+        Ok(instructions)
+    }
+}
+
+fn partial_parse_if(
+    node: &Node,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+    instructions_count: &mut usize,
+) -> Result<BlockType, String> {
+    let mut instructions = Vec::new();
+
+    if node.kind() == "parenthesized_expression" {
+        let start_index = *instructions_count;
+
+        let child = match node.child(1) {
+            Some(node) => node,
+            None => return Err(String::from("Parenthesized expression is missing child")),
+        };
+
+        let block = partial_parse_if(
+            &child,
+            source,
+            current_class,
+            parser_context,
+            super_locals,
+            constant_pool,
+            instructions_count,
+        )?;
+
+        return Ok(BlockType::Parenthesis(ConnectiveInfo {
+            comparisons: vec![block],
+            start_index,
+            end_index: *instructions_count - 1,
+        }));
+    }
+
+    if node.kind() == "binary_expression" {
+        let left = match node.child(0) {
+            Some(node) => node,
+            None => return Err(String::from("Binary expression is missing left side")),
+        };
+
+        let right = match node.child(2) {
+            Some(node) => node,
+            None => return Err(String::from("Binary expression is missing right side")),
+        };
+
+        let operator = match node.child(1) {
+            Some(node) => match node.utf8_text(source) {
+                Ok(text) => text,
+                Err(err) => return Err(format!("Failed to parse binary operator: {}", err)),
+            },
+            None => return Err(String::from("Binary expression is missing operator")),
+        };
+
+        if operator.eq("&&") || operator.eq("||") {
+            let start_index = *instructions_count;
+
+            let left_block = partial_parse_if(
+                &left,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+                instructions_count,
+            )?;
+
+            let right_block = partial_parse_if(
+                &right,
+                source,
+                current_class,
+                parser_context,
+                super_locals,
+                constant_pool,
+                instructions_count,
+            )?;
+
+            return Ok(match operator {
+                "&&" => BlockType::And(ConnectiveInfo {
+                    comparisons: vec![left_block, right_block],
+                    start_index,
+                    end_index: *instructions_count - 1,
+                }),
+                "||" => BlockType::Or(ConnectiveInfo {
+                    comparisons: vec![left_block, right_block],
+                    start_index,
+                    end_index: *instructions_count - 1,
+                }),
+                _ => return Err(format!("Unknown operator {}", operator)),
+            });
+        }
+
+        // TODO: Handle expressions with non-integer operands
+        // Probably just need to add a subtract instruction and use if instead of if_icmp
+
+        let (left_instructions, _left_type) = parse_expression(
+            &left,
+            source,
+            current_class,
+            parser_context,
+            super_locals,
+            constant_pool,
+        )?;
+
+        let (right_instructions, _right_type) = parse_expression(
+            &right,
+            source,
+            current_class,
+            parser_context,
+            super_locals,
+            constant_pool,
+        )?;
+
+        instructions.extend(left_instructions);
+        instructions.extend(right_instructions);
+
+        let comparison = match Comparison::from_operator(operator) {
+            Some(comparison) => comparison,
+            None => return Err(format!("Unknown comparison operator {}", operator)),
+        };
+
+        let comparison_length = instructions.len() + 1;
+
+        *instructions_count += comparison_length;
+
+        return Ok(BlockType::Expression(ExpressionInfo {
+            comparison,
+            instructions,
+            start_index: *instructions_count - comparison_length,
+            end_index: *instructions_count - 1,
+        }));
+    }
+
+    // Any other boolean expression, such as an instanceof check, holds when it isn't zero
+    let (expression_instructions, expression_type) = parse_expression(
+        node,
+        source,
+        current_class,
+        parser_context,
+        super_locals,
+        constant_pool,
+    )?;
+
+    if !expression_type.matches(&PrimitiveType::Boolean) {
+        return Err(format!(
+            "Unable to parse {} as part of if condition",
+            node.kind()
+        ));
+    }
+
+    instructions.extend(expression_instructions);
+    instructions.push(Instruction::Const(Primitive::Int(0)));
+
+    let comparison_length = instructions.len() + 1;
+
+    *instructions_count += comparison_length;
+
+    Ok(BlockType::Expression(ExpressionInfo {
+        comparison: Comparison::NotEqual,
+        instructions,
+        start_index: *instructions_count - comparison_length,
+        end_index: *instructions_count - 1,
+    }))
+}
+
+// Notes on parsing if statements:
+// a && b && c
+// not(a) -> end; not(b) -> end; not(c) -> end;
+
+// a || b || c
+// a -> start; b -> start; not(c) -> end;
+
+// (a || b || c) && (d || e || f)
+// a -> next block; b -> next block; not(c) -> end;   &&   d -> start; e -> start; not(f) -> end;
+
+// (a && b && c) || (d && e && f)
+// not(a) -> next block; not(b) -> next block; c -> start;   ||   not(d) -> start; not(e) -> start; not(f) -> end;
+
+// And statements are parsed first, then or statements
+
+fn parse_if(
+    node: &Node,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+    code_block_length: usize,
+) -> Result<Vec<Instruction>, String> {
+    let child = match node.child_by_kind("parenthesized_expression")?.child(1) {
+        Some(node) => node,
+        None => return Err(String::from("If statement doesn't have a condition")),
+    };
+
+    let mut tree_instruction_count = 0;
+
+    let expression_tree = partial_parse_if(
+        &child,
+        source,
+        current_class,
+        parser_context,
+        super_locals,
+        constant_pool,
+        &mut tree_instruction_count,
+    )?
+    .flatten();
+
+    let instructions = expression_tree.fully_flatten(
+        tree_instruction_count,
+        tree_instruction_count + code_block_length,
+        false,
+        true,
+    )?;
+
+    Ok(instructions)
+}
+
+/// Compile a condition into pushing 1 when it holds and 0 otherwise, using the same short-circuit
+/// jumps as an if statement whose blocks push the constants.
+fn parse_condition_value(
+    node: &Node,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    super_locals: &SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<Instruction>, String> {
+    let mut tree_instruction_count = 0;
+
+    let expression_tree = partial_parse_if(
+        node,
+        source,
+        current_class,
+        parser_context,
+        super_locals,
+        constant_pool,
+        &mut tree_instruction_count,
+    )?
+    .flatten();
+
+    // The true block pushes 1 and jumps over the false block, which pushes 0
+    let mut instructions = expression_tree.fully_flatten(
+        tree_instruction_count,
+        tree_instruction_count + 2,
+        false,
+        true,
+    )?;
+    instructions.push(Instruction::Const(Primitive::Int(1)));
+    instructions.push(Instruction::Goto(2));
+    instructions.push(Instruction::Const(Primitive::Int(0)));
+
+    Ok(instructions)
+}
+
+/// `break` and `continue` are compiled before the extents of their loop are known, so they are
+/// emitted as gotos to marker targets which the loop replaces once it is complete. Each level of
+/// loop nesting has its own pair of markers.
+const LOOP_MARKER_BASE: usize = usize::MAX / 2;
+
+fn break_marker(loop_depth: usize) -> usize {
+    LOOP_MARKER_BASE + loop_depth * 2
+}
+
+fn continue_marker(loop_depth: usize) -> usize {
+    LOOP_MARKER_BASE + loop_depth * 2 + 1
+}
+
+/// Point the `break` and `continue` jumps of the loop at `loop_depth` at the passed indices within
+/// the loop's instructions.
+fn patch_loop_jumps(
+    instructions: &mut [Instruction],
+    loop_depth: usize,
+    break_index: usize,
+    continue_index: usize,
+) {
+    for (index, instruction) in instructions.iter_mut().enumerate() {
+        if let Instruction::Goto(target) = instruction {
+            if *target == break_marker(loop_depth) {
+                *target = break_index.wrapping_sub(index);
+            } else if *target == continue_marker(loop_depth) {
+                *target = continue_index.wrapping_sub(index);
+            }
+        }
+    }
+}
+
+/// Try statements are also compiled before their position in the method is known, so they mark
+/// the start and end of the region they protect and the start of each handler with gotos to marker
+/// targets. Once the method is complete, the markers are turned into its exception table. Each try
+/// statement is identified by the position of its node in the source.
+const TRY_MARKER_BASE: usize = usize::MAX / 4;
+
+#[derive(Debug, Clone, Copy)]
+enum TryMarker {
+    Start(usize),
+    End(usize),
+    /// A handler for the class at the passed constant pool index.
+    Handler(usize, u16),
+}
+
+impl TryMarker {
+    fn target(self) -> usize {
+        let (id, kind, catch_type) = match self {
+            TryMarker::Start(id) => (id, 0, 0),
+            TryMarker::End(id) => (id, 1, 0),
+            TryMarker::Handler(id, catch_type) => (id, 2, catch_type),
+        };
+
+        TRY_MARKER_BASE + (id << 18) + ((catch_type as usize) << 2) + kind
+    }
+
+    fn from_target(target: usize) -> Option<TryMarker> {
+        if !(TRY_MARKER_BASE..LOOP_MARKER_BASE).contains(&target) {
+            return None;
+        }
+
+        let offset = target - TRY_MARKER_BASE;
+        let id = offset >> 18;
+
+        Some(match offset & 3 {
+            0 => TryMarker::Start(id),
+            1 => TryMarker::End(id),
+            _ => TryMarker::Handler(id, (offset >> 2) as u16),
+        })
+    }
+}
 
-                let (comp, abs_jmp_pos) = if negate || must_be_true {
-                    (info.comparison.negate(), on_false_jump)
-                } else {
-                    (info.comparison.clone(), on_true_jump)
-                };
+/// Replace the try markers of a complete method with nops, building its exception table from their
+/// positions. Nested try statements are inside the regions of the ones around them, so their
+/// handlers are found and tried first.
+fn extract_exception_table(
+    instructions: &mut [Instruction],
+) -> Result<Vec<ExceptionTableEntry>, String> {
+    let mut regions: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut handlers = Vec::new();
+
+    for (index, instruction) in instructions.iter_mut().enumerate() {
+        let marker = match instruction {
+            Instruction::Goto(target) => TryMarker::from_target(*target),
+            _ => None,
+        };
 
-                instructions.push(Instruction::IfICmp(abs_jmp_pos - info.end_index, comp))
-            }
-            BlockType::Parenthesis(_) => {
-                return Err("fully_flatten input should not include parenthesis".to_string())
+        if let Some(marker) = marker {
+            *instruction = Instruction::Nop;
+
+            match marker {
+                TryMarker::Start(id) => regions.entry(id).or_default().0 = index,
+                TryMarker::End(id) => regions.entry(id).or_default().1 = index,
+                TryMarker::Handler(id, catch_type) => handlers.push((id, index, catch_type)),
             }
         }
-
-        Ok(instructions)
     }
+
+    handlers
+        .into_iter()
+        .map(|(id, handler_pc, catch_type)| match regions.get(&id) {
+            Some((start_pc, end_pc)) => Ok(ExceptionTableEntry {
+                start_pc: *start_pc,
+                end_pc: *end_pc,
+                handler_pc,
+                catch_type,
+            }),
+            None => Err(format!(
+                "Exception handler at {} has no try block",
+                handler_pc
+            )),
+        })
+        .collect()
 }
 
-fn partial_parse_if(
+fn parse_code_block(
     node: &Node,
     source: &[u8],
     current_class: &String,
     parser_context: &ParserContext,
     super_locals: &SuperLocals,
     constant_pool: &mut Vec<ConstantPoolEntry>,
-    instructions_count: &mut usize,
-) -> Result<BlockType, String> {
+    loop_depth: usize,
+) -> Result<Vec<Instruction>, String> {
     let mut instructions = Vec::new();
+    let mut locals = (*super_locals).clone();
 
-    if node.kind() == "parenthesized_expression" {
-        let start_index = *instructions_count;
-
-        let child = match node.child(1) {
-            Some(node) => node,
-            None => return Err(String::from("Parenthesized expression is missing child")),
-        };
-
-        let block = partial_parse_if(
+    for child in node.get_children() {
+        instructions.extend(parse_statement(
             &child,
             source,
             current_class,
             parser_context,
-            super_locals,
+            &mut locals,
             constant_pool,
-            instructions_count,
-        )?;
-
-        return Ok(BlockType::Parenthesis(ConnectiveInfo {
-            comparisons: vec![block],
-            start_index,
-            end_index: *instructions_count - 1,
-        }));
+            loop_depth,
+        )?);
     }
 
-    if node.kind() == "binary_expression" {
-        let left = match node.child(0) {
-            Some(node) => node,
-            None => return Err(String::from("Binary expression is missing left side")),
-        };
+    Ok(instructions)
+}
 
-        let right = match node.child(2) {
-            Some(node) => node,
-            None => return Err(String::from("Binary expression is missing right side")),
-        };
+/// Compile a single statement, declaring any variables it introduces in the passed locals.
+fn parse_statement(
+    statement: &Node,
+    source: &[u8],
+    current_class: &String,
+    parser_context: &ParserContext,
+    locals: &mut SuperLocals,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+    loop_depth: usize,
+) -> Result<Vec<Instruction>, String> {
+    let mut instructions = Vec::new();
 
-        let operator = match node.child(1) {
-            Some(node) => match node.utf8_text(source) {
-                Ok(text) => text,
-                Err(err) => return Err(format!("Failed to parse binary operator: {}", err)),
-            },
-            None => return Err(String::from("Binary expression is missing operator")),
-        };
+    match statement.kind() {
+        "local_variable_declaration" => {
+            let type_node = match statement.child(0) {
+                Some(node) => node,
+                None => return Err(String::from("Local variable declaration is missing type")),
+            };
+            let variable_type = type_node_to_primitive_type(type_node)?;
 
-        if operator.eq("&&") || operator.eq("||") {
-            let start_index = *instructions_count;
+            // A declaration such as `int a = 1, b = 2;` declares each variable in turn, so later
+            // initializers can use the earlier variables
+            for variable_declarator in statement.children_by_kind("variable_declarator") {
+                let variable_name = variable_declarator.name_from_identifier(source)?;
 
-            let left_block = partial_parse_if(
-                &left,
+                if locals.find_local(&variable_name).is_some() {
+                    return Err(format!("Variable {} is already defined", variable_name));
+                }
+
+                let variable_index = locals.add_local(&variable_name, variable_type.clone());
+
+                // References remember their class, so that indexing an array knows the element
+                // type and concatenation knows which operands are strings
+                let array_descriptor = match type_node.kind() {
+                    "array_type" => {
+                        let descriptor = array_type_descriptor(&type_node, source, parser_context)?;
+                        locals
+                            .reference_classes
+                            .insert(variable_index, constant_pool.find_or_add_class(&descriptor));
+                        Some(descriptor)
+                    }
+                    "type_identifier" => {
+                        if let Ok(text) = type_node.utf8_text(source) {
+                            let class_name = parser_context.qualified_class_name(text);
+                            locals.reference_classes.insert(
+                                variable_index,
+                                constant_pool.find_or_add_class(&class_name),
+                            );
+                        }
+                        None
+                    }
+                    _ => None,
+                };
+
+                let initializer = variable_declarator.child(2);
+
+                if let Some(initializer) =
+                    initializer.filter(|node| node.kind() == "array_initializer")
+                {
+                    let array_descriptor = match array_descriptor {
+                        Some(descriptor) => descriptor,
+                        None => {
+                            return Err(format!(
+                                "Array initializer assigned to {}, which is not an array",
+                                variable_name
+                            ))
+                        }
+                    };
+
+                    instructions.extend(parse_array_initializer(
+                        &initializer,
+                        &array_descriptor,
+                        source,
+                        current_class,
+                        parser_context,
+                        locals,
+                        constant_pool,
+                    )?);
+                    instructions.push(Instruction::Store(variable_index, PrimitiveType::Reference));
+                } else if initializer.is_some() {
+                    let (expression_instructions, expression_type) = parse_expression(
+                        &variable_declarator,
+                        source,
+                        current_class,
+                        parser_context,
+                        locals,
+                        constant_pool,
+                    )?;
+
+                    instructions.extend(expression_instructions);
+
+                    if !variable_type.matches(&expression_type) {
+                        return Err(format!(
+                            "Variable type {} does not match expression type {}",
+                            variable_type.as_letter(),
+                            expression_type.as_letter()
+                        ));
+                    }
+                }
+            }
+        }
+        "expression_statement" => {
+            let expression = match statement.child(0) {
+                Some(node) => node,
+                None => return Err(String::from("Expression statement is missing expression")),
+            };
+
+            let (expression_instructions, expression_type) = parse_expression(
+                &expression,
                 source,
                 current_class,
                 parser_context,
-                super_locals,
+                locals,
                 constant_pool,
-                instructions_count,
             )?;
 
-            let right_block = partial_parse_if(
-                &right,
+            instructions.extend(expression_instructions);
+
+            // Assignments store their value without leaving a copy on the stack, but any other
+            // value the statement produces is unused and has to be discarded
+            if expression.kind() != "assignment_expression" {
+                match expression_type {
+                    PrimitiveType::Null => {}
+                    _ if expression_type.is_category_two() => instructions.push(Instruction::Pop2),
+                    _ => instructions.push(Instruction::Pop),
+                }
+            }
+        }
+        "block" => {
+            // Locals declared inside the block go out of scope at its end, so their slots
+            // can be reused by later declarations
+            instructions.extend(parse_code_block(
+                statement,
                 source,
                 current_class,
                 parser_context,
-                super_locals,
+                locals,
+                constant_pool,
+                loop_depth,
+            )?);
+        }
+        "if_statement" => {
+            let mut if_locals = locals.clone();
+            add_pattern_bindings(
+                &statement.child_by_kind("parenthesized_expression")?,
+                source,
+                parser_context,
+                &mut if_locals,
                 constant_pool,
-                instructions_count,
             )?;
 
-            return Ok(match operator {
-                "&&" => BlockType::And(ConnectiveInfo {
-                    comparisons: vec![left_block, right_block],
-                    start_index,
-                    end_index: *instructions_count - 1,
-                }),
-                "||" => BlockType::Or(ConnectiveInfo {
-                    comparisons: vec![left_block, right_block],
-                    start_index,
-                    end_index: *instructions_count - 1,
-                }),
-                _ => return Err(format!("Unknown operator {}", operator)),
-            });
+            let if_code_block = parse_code_block(
+                &statement.child_by_kind("block")?,
+                source,
+                current_class,
+                parser_context,
+                &if_locals,
+                constant_pool,
+                loop_depth,
+            )?;
+
+            instructions.extend(parse_if(
+                statement,
+                source,
+                current_class,
+                parser_context,
+                &if_locals,
+                constant_pool,
+                if_code_block.len(),
+            )?);
+
+            instructions.extend(if_code_block);
         }
+        "do_statement" => {
+            let body = parse_code_block(
+                &statement.child_by_kind("block")?,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+                loop_depth + 1,
+            )?;
 
-        // TODO: Handle expressions with non-integer operands
-        // Probably just need to add a subtract instruction and use if instead of if_icmp
+            // The condition falls through to a jump back to the start of the body while it
+            // holds, and skips over that jump once it doesn't
+            let condition = parse_if(
+                statement,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+                1,
+            )?;
 
-        let (left_instructions, left_type) = parse_expression(
-            &left,
-            source,
-            current_class,
-            parser_context,
-            super_locals,
-            constant_pool,
-        )?;
+            let condition_index = body.len();
+            let loop_length = body.len() + condition.len();
 
-        let (right_instructions, right_type) = parse_expression(
-            &right,
-            source,
-            current_class,
-            parser_context,
-            super_locals,
-            constant_pool,
-        )?;
+            let mut loop_instructions = body;
+            loop_instructions.extend(condition);
+            loop_instructions.push(Instruction::Goto(0usize.wrapping_sub(loop_length)));
 
-        instructions.extend(left_instructions);
-        instructions.extend(right_instructions);
+            // Continuing a do-while loop re-evaluates its condition
+            patch_loop_jumps(
+                &mut loop_instructions,
+                loop_depth + 1,
+                loop_length + 1,
+                condition_index,
+            );
 
-        let comparison = match operator {
-            "==" => Comparison::Equal,
-            "!=" => Comparison::NotEqual,
-            ">" => Comparison::GreaterThan,
-            ">=" => Comparison::GreaterThanOrEqual,
-            "<" => Comparison::LessThan,
-            "<=" => Comparison::LessThanOrEqual,
-            _ => return Err(format!("Unknown comparison operator {}", operator)),
-        };
+            instructions.extend(loop_instructions);
+        }
+        "enhanced_for_statement" => {
+            let field = |name: &str| match statement.child_by_field_name(name) {
+                Some(node) => Ok(node),
+                None => Err(format!("For-each loop is missing {}", name)),
+            };
+            let (type_node, name_node, value, body) = (
+                field("type")?,
+                field("name")?,
+                field("value")?,
+                field("body")?,
+            );
 
-        let comparison_length = instructions.len() + 1;
+            let array_descriptor =
+                array_expression_descriptor(&value, source, locals, constant_pool)?;
+            let element_type = array_element_type(&array_descriptor);
+            let variable_type = type_node_to_primitive_type(type_node)?;
 
-        *instructions_count += comparison_length;
+            if !variable_type.matches(&element_type) {
+                return Err(format!(
+                    "Variable type {} does not match array element type {}",
+                    variable_type.as_letter(),
+                    element_type.as_letter()
+                ));
+            }
+
+            let variable_name = match name_node.utf8_text(source) {
+                Ok(text) => text.to_string(),
+                Err(err) => return Err(format!("Failed to parse identifier: {}", err)),
+            };
+            if locals.find_local(&variable_name).is_some() {
+                return Err(format!("Variable {} is already defined", variable_name));
+            }
+
+            let (value_instructions, _) = parse_expression(
+                &value,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+            )?;
+
+            // The array and the index into it are kept in locals without names, so that the body
+            // can't refer to them
+            let mut loop_locals = locals.clone();
+            let array_index = loop_locals.add_local("", PrimitiveType::Reference);
+            let index_index = loop_locals.add_local("", PrimitiveType::Int);
+            let variable_index = loop_locals.add_local(&variable_name, variable_type.clone());
+
+            if element_type.matches(&PrimitiveType::Reference) {
+                let element_class = constant_pool.find_or_add_class(&array_descriptor[1..]);
+                loop_locals
+                    .reference_classes
+                    .insert(variable_index, element_class);
+            }
+
+            let body = parse_statement(
+                &body,
+                source,
+                current_class,
+                parser_context,
+                &mut loop_locals,
+                constant_pool,
+                loop_depth + 1,
+            )?;
+
+            instructions.extend(value_instructions);
+            instructions.push(Instruction::Store(array_index, PrimitiveType::Reference));
+            instructions.push(Instruction::Const(Primitive::Int(0)));
+            instructions.push(Instruction::Store(index_index, PrimitiveType::Int));
+
+            // The loop exits once the index reaches the length of the array, which may be
+            // straight away
+            let continue_index = body.len() + 8;
+            let loop_length = continue_index + 2;
+
+            let mut loop_instructions = vec![
+                Instruction::Load(index_index, PrimitiveType::Int),
+                Instruction::Load(array_index, PrimitiveType::Reference),
+                Instruction::ArrayLength,
+                Instruction::IfICmp(loop_length - 3, Comparison::GreaterThanOrEqual),
+                Instruction::Load(array_index, PrimitiveType::Reference),
+                Instruction::Load(index_index, PrimitiveType::Int),
+                Instruction::ALoad(element_type),
+                Instruction::Store(variable_index, variable_type),
+            ];
+            loop_instructions.extend(body);
+            loop_instructions.push(Instruction::IInc(index_index, 1));
+            loop_instructions.push(Instruction::Goto(0usize.wrapping_sub(loop_length - 1)));
+
+            patch_loop_jumps(
+                &mut loop_instructions,
+                loop_depth + 1,
+                loop_length,
+                continue_index,
+            );
+
+            instructions.extend(loop_instructions);
+        }
+        "switch_statement" => {
+            let condition = match statement
+                .child_by_kind("parenthesized_expression")?
+                .child(1)
+            {
+                Some(node) => node,
+                None => return Err(String::from("Switch statement is missing condition")),
+            };
+
+            let (condition_instructions, condition_type) = parse_expression(
+                &condition,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+            )?;
+
+            if !matches!(
+                condition_type,
+                PrimitiveType::Int
+                    | PrimitiveType::Short
+                    | PrimitiveType::Char
+                    | PrimitiveType::Byte
+            ) {
+                return Err(format!(
+                    "Cannot switch on a value of type {:?}",
+                    condition_type
+                ));
+            }
+
+            // The cases share one scope, and break out of the switch like they would a loop
+            let mut switch_locals = locals.clone();
+            let mut body = Vec::new();
+            // The value of each case with the offset of its code within the body
+            let mut cases: Vec<(i32, usize)> = Vec::new();
+            let mut default = None;
+
+            for child in statement.child_by_kind("switch_block")?.get_children() {
+                if child.kind() != "switch_label" {
+                    body.extend(parse_statement(
+                        &child,
+                        source,
+                        current_class,
+                        parser_context,
+                        &mut switch_locals,
+                        constant_pool,
+                        loop_depth + 1,
+                    )?);
+                    continue;
+                }
+
+                let value = match child.child(1) {
+                    Some(value) if value.kind() != ":" => value,
+                    _ => {
+                        default = Some(body.len());
+                        continue;
+                    }
+                };
+
+                let (value_instructions, _) = parse_expression(
+                    &value,
+                    source,
+                    current_class,
+                    parser_context,
+                    &switch_locals,
+                    constant_pool,
+                )?;
+
+                match value_instructions[..] {
+                    [Instruction::Const(Primitive::Int(value))] => cases.push((value, body.len())),
+                    _ => return Err(String::from("Case labels must be constant integers")),
+                }
+            }
+
+            cases.sort_by_key(|(value, _)| *value);
+
+            if let Some(duplicate) = cases.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+                return Err(format!("Duplicate case label {}", duplicate[0].0));
+            }
+
+            // Targets are relative to the switch, which directly precedes the body
+            let end = body.len() + 1;
+            let default_target = default.map_or(end, |offset| offset + 1);
+
+            let switch = match (cases.first(), cases.last()) {
+                // Use a table when at least half of the values in the range have a case
+                (Some((low, _)), Some((high, _)))
+                    if (*high as i64 - *low as i64) < 2 * cases.len() as i64 =>
+                {
+                    let targets = (*low..=*high)
+                        .map(|value| {
+                            match cases.binary_search_by_key(&value, |(value, _)| *value) {
+                                Ok(index) => cases[index].1 + 1,
+                                Err(_) => default_target,
+                            }
+                        })
+                        .collect();
+
+                    Instruction::TableSwitch(default_target, *low, targets)
+                }
+                _ => Instruction::LookupSwitch(
+                    default_target,
+                    cases
+                        .iter()
+                        .map(|(value, offset)| (*value, offset + 1))
+                        .collect(),
+                ),
+            };
+
+            let mut switch_instructions = vec![switch];
+            switch_instructions.extend(body);
+
+            // Continuing inside a switch continues the loop around it
+            for instruction in switch_instructions.iter_mut() {
+                if let Instruction::Goto(target) = instruction {
+                    if *target == continue_marker(loop_depth + 1) {
+                        if loop_depth == 0 {
+                            return Err(String::from("continue_statement outside of a loop"));
+                        }
+                        *target = continue_marker(loop_depth);
+                    }
+                }
+            }
 
-        return Ok(BlockType::Expression(ExpressionInfo {
-            comparison,
-            instructions,
-            start_index: *instructions_count - comparison_length,
-            end_index: *instructions_count - 1,
-        }));
-    }
+            patch_loop_jumps(&mut switch_instructions, loop_depth + 1, end, end);
 
-    return Err(format!(
-        "Unable to parse {} as part of if condition",
-        node.kind()
-    ));
-}
+            instructions.extend(condition_instructions);
+            instructions.extend(switch_instructions);
+        }
+        "break_statement" | "continue_statement" => {
+            if loop_depth == 0 {
+                return Err(format!("{} outside of a loop", statement.kind()));
+            }
 
-/// Notes on parsing if statements:
-// a && b && c
-// not(a) -> end; not(b) -> end; not(c) -> end;
+            // A label jumps out of or continues the loop it names instead of the innermost one
+            let target_depth = match statement.child_by_kind("identifier") {
+                Ok(label) => {
+                    let label = match label.utf8_text(source) {
+                        Ok(text) => text,
+                        Err(err) => return Err(format!("Failed to parse label: {}", err)),
+                    };
 
-// a || b || c
-// a -> start; b -> start; not(c) -> end;
+                    match locals.labels.get(label) {
+                        Some(depth) => *depth,
+                        None => return Err(format!("Undefined label {}", label)),
+                    }
+                }
+                Err(_) => loop_depth,
+            };
 
-// (a || b || c) && (d || e || f)
-// a -> next block; b -> next block; not(c) -> end;   &&   d -> start; e -> start; not(f) -> end;
+            instructions.push(Instruction::Goto(
+                if statement.kind() == "break_statement" {
+                    break_marker(target_depth)
+                } else {
+                    continue_marker(target_depth)
+                },
+            ));
+        }
+        "labeled_statement" => {
+            let label = match statement.child_by_kind("identifier")?.utf8_text(source) {
+                Ok(text) => text.to_string(),
+                Err(err) => return Err(format!("Failed to parse label: {}", err)),
+            };
+            let labeled = match statement.named_child(1) {
+                Some(node) => node,
+                None => return Err(format!("Label {} is missing its statement", label)),
+            };
 
-// (a && b && c) || (d && e && f)
-// not(a) -> next block; not(b) -> next block; c -> start;   ||   not(d) -> start; not(e) -> start; not(f) -> end;
+            if !matches!(labeled.kind(), "do_statement" | "enhanced_for_statement") {
+                return Err(format!("Label {} must be on a loop", label));
+            }
 
-// And statements are parsed first, then or statements
+            if locals.labels.contains_key(&label) {
+                return Err(format!("Label {} is already defined", label));
+            }
 
-fn parse_if(
-    node: &Node,
-    source: &[u8],
-    current_class: &String,
-    parser_context: &ParserContext,
-    super_locals: &SuperLocals,
-    constant_pool: &mut Vec<ConstantPoolEntry>,
-    code_block_length: usize,
-) -> Result<Vec<Instruction>, String> {
-    let child = match node.child_by_kind("parenthesized_expression")?.child(1) {
-        Some(node) => node,
-        None => return Err(String::from("If statement doesn't have a condition")),
-    };
+            // The label can only be used inside the loop, which is one level deeper
+            let mut labeled_locals = locals.clone();
+            labeled_locals.labels.insert(label, loop_depth + 1);
 
-    let mut tree_instruction_count = 0;
+            instructions.extend(parse_statement(
+                &labeled,
+                source,
+                current_class,
+                parser_context,
+                &mut labeled_locals,
+                constant_pool,
+                loop_depth,
+            )?);
+        }
+        "throw_statement" => {
+            let exception = match statement.child(1) {
+                Some(node) => node,
+                None => return Err(String::from("Throw statement is missing exception")),
+            };
 
-    let expression_tree = partial_parse_if(
-        &child,
-        source,
-        current_class,
-        parser_context,
-        super_locals,
-        constant_pool,
-        &mut tree_instruction_count,
-    )?
-    .flatten();
+            let (exception_instructions, exception_type) = parse_expression(
+                &exception,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+            )?;
 
-    let instructions = expression_tree.fully_flatten(
-        tree_instruction_count,
-        tree_instruction_count + code_block_length,
-        false,
-        true,
-    )?;
+            if !exception_type.matches(&PrimitiveType::Reference) {
+                return Err(format!(
+                    "Only objects can be thrown, not {:?}",
+                    exception_type
+                ));
+            }
 
-    Ok(instructions)
-}
+            instructions.extend(exception_instructions);
+            instructions.push(Instruction::AThrow);
+        }
+        "assert_statement" => {
+            let condition = match statement.child(1) {
+                Some(node) => node,
+                None => return Err(String::from("Assert statement is missing condition")),
+            };
 
-fn parse_code_block(
-    node: &Node,
-    source: &[u8],
-    current_class: &String,
-    parser_context: &ParserContext,
-    super_locals: &SuperLocals,
-    constant_pool: &mut Vec<ConstantPoolEntry>,
-) -> Result<Vec<Instruction>, String> {
-    let mut instructions = Vec::new();
-    let mut locals = (*super_locals).clone();
+            let (condition_instructions, condition_type) = parse_expression(
+                &condition,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+            )?;
 
-    for child in node.get_children() {
-        match child.kind() {
-            "local_variable_declaration" => {
-                let variable_declarator = child.child_by_kind("variable_declarator")?;
-                let variable_name = variable_declarator.name_from_identifier(source)?;
-                let type_node = match child.child(0) {
-                    Some(node) => node,
-                    None => return Err(String::from("Local variable declaration is missing type")),
-                };
-                let variable_type = type_node_to_primitive_type(type_node)?;
-                locals.add_local(&variable_name, variable_type.clone());
+            if !condition_type.matches(&PrimitiveType::Boolean) {
+                return Err(format!(
+                    "Assert condition must be a boolean, not {:?}",
+                    condition_type
+                ));
+            }
 
-                if variable_declarator.child_count() == 3 {
-                    let (expression_instructions, expression_type) = parse_expression(
-                        &variable_declarator,
+            // The message after the colon is passed to the error, using the constructor for its
+            // type like String.valueOf would
+            let error_class = constant_pool.find_or_add_class("java/lang/AssertionError");
+            let mut error_instructions = vec![Instruction::New(error_class), Instruction::Dup];
+            let constructor_descriptor = match statement.child(3) {
+                Some(message) if statement.child(2).map(|colon| colon.kind()) == Some(":") => {
+                    let (message_instructions, message_type) = parse_expression(
+                        &message,
                         source,
                         current_class,
                         parser_context,
-                        &locals,
+                        locals,
                         constant_pool,
                     )?;
+                    error_instructions.extend(message_instructions);
 
-                    instructions.extend(expression_instructions);
-
-                    if !variable_type.matches(&expression_type) {
-                        return Err(format!(
-                            "Variable type {} does not match expression type {}",
-                            variable_type.as_letter(),
-                            expression_type.as_letter()
-                        ));
+                    match message_type {
+                        PrimitiveType::Reference | PrimitiveType::Null => {
+                            String::from("(Ljava/lang/Object;)V")
+                        }
+                        PrimitiveType::Byte | PrimitiveType::Short => String::from("(I)V"),
+                        message_type => format!("({})V", message_type.as_descriptor()),
                     }
                 }
+                _ => String::from("()V"),
+            };
+            let constructor = constant_pool.find_or_add_method_ref(
+                "java/lang/AssertionError",
+                "<init>",
+                &constructor_descriptor,
+            );
+            error_instructions.push(Instruction::InvokeSpecial(constructor));
+            error_instructions.push(Instruction::AThrow);
+
+            // Assertions are skipped unless they are enabled for the class, and otherwise throw
+            // when the condition doesn't hold
+            let class_index = constant_pool.find_or_add_class(current_class);
+            let assertion_status = constant_pool.find_or_add_method_ref(
+                "java/lang/Class",
+                "desiredAssertionStatus",
+                "()Z",
+            );
+            let check_length = condition_instructions.len() + 2 + error_instructions.len();
+
+            instructions.push(Instruction::LoadConst(class_index));
+            instructions.push(Instruction::InvokeVirtual(assertion_status));
+            instructions.push(Instruction::Const(Primitive::Int(0)));
+            instructions.push(Instruction::IfICmp(check_length + 1, Comparison::Equal));
+            instructions.extend(condition_instructions);
+            instructions.push(Instruction::Const(Primitive::Int(0)));
+            instructions.push(Instruction::IfICmp(
+                error_instructions.len() + 1,
+                Comparison::NotEqual,
+            ));
+            instructions.extend(error_instructions);
+        }
+        "try_statement" => {
+            if statement.child_by_kind("finally_clause").is_ok() {
+                return Err(String::from("Finally clauses are not supported yet"));
             }
-            "expression_statement" => {
-                let expression = match child.child(0) {
-                    Some(node) => node,
-                    None => return Err(String::from("Expression statement is missing expression")),
-                };
 
-                let (expression_instructions, _) = parse_expression(
-                    &expression,
-                    source,
-                    current_class,
-                    parser_context,
-                    &locals,
-                    constant_pool,
-                )?;
+            let id = statement.start_byte();
 
-                instructions.extend(expression_instructions);
-            }
-            "if_statement" => {
-                let if_code_block = parse_code_block(
-                    &child.child_by_kind("block")?,
-                    source,
-                    current_class,
-                    parser_context,
-                    &locals,
-                    constant_pool,
-                )?;
+            instructions.push(Instruction::Goto(TryMarker::Start(id).target()));
+            instructions.extend(parse_code_block(
+                &statement.child_by_kind("block")?,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+                loop_depth,
+            )?);
+            instructions.push(Instruction::Goto(TryMarker::End(id).target()));
 
-                instructions.extend(parse_if(
-                    &child,
-                    source,
-                    current_class,
-                    parser_context,
-                    &locals,
-                    constant_pool,
-                    if_code_block.len(),
-                )?);
+            let mut handlers = Vec::new();
 
-                instructions.extend(if_code_block);
-            }
-            "return_statement" => {
-                let return_expression = match child.child(1) {
-                    Some(node) => node,
-                    None => return Err(String::from("Return statement is missing expression")),
+            for catch_clause in statement.children_by_kind("catch_clause") {
+                let parameter = catch_clause.child_by_kind("catch_formal_parameter")?;
+                let catch_type = parameter.child_by_kind("catch_type")?;
+
+                if catch_type.named_child_count() != 1 {
+                    return Err(String::from("Multi-catch clauses are not supported yet"));
+                }
+
+                let class_name = match catch_type.named_child(0).map(|node| node.utf8_text(source))
+                {
+                    Some(Ok(text)) => parser_context.qualified_class_name(text),
+                    _ => return Err(String::from("Catch clause is missing type")),
                 };
+                let class_index = constant_pool.find_or_add_class(&class_name) as u16;
+
+                let name = parameter.name_from_identifier(source)?;
+                if locals.find_local(&name).is_some() {
+                    return Err(format!("Variable {} is already defined", name));
+                }
 
-                let (expression_instructions, expression_type) = parse_expression(
-                    &return_expression,
+                let mut catch_locals = locals.clone();
+                let exception_index = catch_locals.add_local(&name, PrimitiveType::Reference);
+                catch_locals
+                    .reference_classes
+                    .insert(exception_index, class_index as usize);
+
+                // The handler is entered with the exception on the stack
+                let mut handler = vec![
+                    Instruction::Goto(TryMarker::Handler(id, class_index).target()),
+                    Instruction::Store(exception_index, PrimitiveType::Reference),
+                ];
+                handler.extend(parse_code_block(
+                    &catch_clause.child_by_kind("block")?,
                     source,
                     current_class,
                     parser_context,
-                    &locals,
+                    &catch_locals,
                     constant_pool,
-                )?;
+                    loop_depth,
+                )?);
+                handlers.push(handler);
+            }
 
-                // TODO: Check that the return type matches the method return type
+            // The try block and every handler jump past the remaining handlers when they finish
+            let mut remaining_length: usize =
+                handlers.iter().map(|handler| handler.len() + 1).sum();
+            instructions.push(Instruction::Goto(remaining_length + 1));
 
-                instructions.extend(expression_instructions);
-                instructions.push(Instruction::Return(expression_type));
+            for handler in handlers {
+                remaining_length -= handler.len() + 1;
+                instructions.extend(handler);
+                instructions.push(Instruction::Goto(remaining_length + 1));
             }
-            _ => {}
         }
+        "return_statement" => {
+            // Only void methods have a bare return, and only they can't return a value
+            let is_void = locals.return_type.matches(&PrimitiveType::Null);
+            let return_expression = match statement.named_child(0) {
+                Some(_) if is_void => {
+                    return Err(String::from("Cannot return a value from a void method"))
+                }
+                Some(node) => node,
+                None if is_void => {
+                    instructions.push(Instruction::Return(PrimitiveType::Null));
+                    return Ok(instructions);
+                }
+                None => {
+                    return Err(format!(
+                        "Missing return value in a method returning {:?}",
+                        locals.return_type
+                    ))
+                }
+            };
+
+            let (expression_instructions, expression_type) = parse_expression(
+                &return_expression,
+                source,
+                current_class,
+                parser_context,
+                locals,
+                constant_pool,
+            )?;
+
+            instructions.extend(expression_instructions);
+
+            // Values are widened to the declared return type, e.g. an int returned from a method
+            // returning double, and can't be returned as any other type
+            let return_type = &locals.return_type;
+            let value_type = if expression_type.matches(return_type) {
+                expression_type
+            } else if widens_to(&expression_type, return_type) {
+                instructions.extend(cast_instructions(&expression_type, return_type)?);
+                return_type.clone()
+            } else {
+                return Err(format!(
+                    "Cannot return {:?} from a method returning {:?}",
+                    expression_type, return_type
+                ));
+            };
+
+            // Booleans are held as ints
+            instructions.push(Instruction::Return(match value_type {
+                PrimitiveType::Boolean => PrimitiveType::Int,
+                value_type => value_type,
+            }));
+        }
+        _ => {}
     }
 
     Ok(instructions)
@@ -1365,8 +3643,23 @@ fn parse_method(
     constant_pool: &mut Vec<ConstantPoolEntry>,
     method_info: &MethodInfo,
 ) -> Result<Method, String> {
-    let super_locals = method_info.variables.clone();
-    let code_block = match node.child_by_kind("block") {
+    let mut super_locals = method_info.variables.clone();
+
+    // The varargs parameter is the last local, and holds an array of its declared type
+    if method_info.is_varargs() {
+        if let Some(descriptor) = method_info.parameter_descriptors.last() {
+            let index = super_locals.local_names.len() - 1;
+            let class_index = constant_pool.find_or_add_class(descriptor);
+            super_locals.reference_classes.insert(index, class_index);
+        }
+    }
+
+    let is_constructor = node.kind() == "constructor_declaration";
+    let code_block = match node.child_by_kind(if is_constructor {
+        "constructor_body"
+    } else {
+        "block"
+    }) {
         Ok(node) => node,
         Err(err) => return Err(format!("Failed to parse code block: {}", err)),
     };
@@ -1378,8 +3671,31 @@ fn parse_method(
         parser_context,
         &super_locals,
         constant_pool,
+        0,
     )?;
 
+    // Constructors start by calling the constructor of the superclass which takes no arguments
+    if is_constructor {
+        if code_block
+            .child_by_kind("explicit_constructor_invocation")
+            .is_ok()
+        {
+            return Err(String::from(
+                "Explicit constructor invocations are not supported",
+            ));
+        }
+
+        let super_class = &parser_context.find_class(current_class)?.super_class;
+        let super_constructor = constant_pool.find_or_add_method_ref(super_class, "<init>", "()V");
+        instructions.splice(
+            0..0,
+            [
+                Instruction::Load(0, PrimitiveType::Reference),
+                Instruction::InvokeSpecial(super_constructor),
+            ],
+        );
+    }
+
     if method_info.return_type.matches(&PrimitiveType::Null) {
         let last_instruction = match instructions.last() {
             Some(instruction) => instruction,
@@ -1391,7 +3707,58 @@ fn parse_method(
         }
     }
 
-    Ok(Method { instructions })
+    if parser_context.optimize {
+        optimizer::optimize(&mut instructions);
+    }
+
+    let exception_table = extract_exception_table(&mut instructions)?;
+    resolve_branch_targets(&mut instructions);
+
+    let descriptor = &method_info.signature[method_info.name.len()..];
+    let initial_locals = stack_map::initial_locals(
+        current_class,
+        &method_info.name,
+        descriptor,
+        method_info.is_static(),
+        constant_pool,
+    )?;
+    let stack_map = match stack_map::analyze(
+        &instructions,
+        &exception_table,
+        initial_locals.clone(),
+        current_class,
+        constant_pool,
+    ) {
+        Ok(stack_map) => stack_map::stack_map_frames(&stack_map, &initial_locals),
+        Err(err) => return Err(format!("Failed to compute stack map: {}", err)),
+    };
+
+    Ok(Method {
+        access_flags: method_info.access_flags,
+        instructions,
+        stack_map,
+        exception_table,
+    })
+}
+
+/// Branches are generated with offsets relative to their own instruction, since the code blocks
+/// containing them are built independently. Once the method is complete, replace these offsets
+/// with the absolute instruction indices that the JVM expects.
+fn resolve_branch_targets(instructions: &mut [Instruction]) {
+    for (index, instruction) in instructions.iter_mut().enumerate() {
+        for target in instruction.branch_targets_mut() {
+            *target = index.wrapping_add(*target);
+        }
+    }
+}
+
+/// The kind of node holding the members of a class or interface declaration.
+fn body_kind(class_node: &Node) -> &'static str {
+    if class_node.kind() == "interface_declaration" {
+        "interface_body"
+    } else {
+        "class_body"
+    }
 }
 
 fn parse_class(
@@ -1400,14 +3767,43 @@ fn parse_class(
     parser_context: &ParserContext,
 ) -> Result<Class, String> {
     let class_name = node.name_from_identifier(source)?;
-    let class_body = match node.child_by_kind("class_body") {
+    let class_body = match node.child_by_kind(body_kind(node)) {
         Ok(node) => node,
         Err(err) => return Err(format!("Failed to parse class body: {}", err)),
     };
     let class_info = parser_context.find_class(&class_name)?;
     let mut constant_pool = Vec::new();
+    let mut static_fields = HashMap::new();
+
+    for field in class_info.fields.iter().filter(|field| field.is_static) {
+        static_fields.insert(
+            field.name.clone(),
+            Primitive::default_for_descriptor(&field.signature),
+        );
+    }
+
+    let instance_fields: IndexMap<String, Primitive> = class_info
+        .fields
+        .iter()
+        .filter(|field| !field.is_static)
+        .map(|field| {
+            (
+                field.name.clone(),
+                Primitive::default_for_descriptor(&field.signature),
+            )
+        })
+        .collect();
+
+    let final_fields: HashSet<String> = class_info
+        .fields
+        .iter()
+        .filter(|field| field.is_final)
+        .map(|field| field.name.clone())
+        .collect();
+
     let mut methods = HashMap::new();
-    let method_nodes = class_body.children_by_kind("method_declaration");
+    let mut method_nodes = class_body.children_by_kind("method_declaration");
+    method_nodes.extend(class_body.children_by_kind("constructor_declaration"));
 
     for (i, method) in method_nodes.iter().enumerate() {
         let method_info = match class_info.methods.get(i) {
@@ -1416,58 +3812,174 @@ fn parse_class(
         };
         let method_signature = method_info.signature.clone();
 
-        let parsed_method = parse_method(
-            method,
-            source,
-            &class_name,
-            parser_context,
-            &mut constant_pool,
-            method_info,
-        )?;
+        // Abstract methods are stubs without any code
+        let parsed_method = if method_info.access_flags & MethodFlags::Abstract as u16 != 0 {
+            Method {
+                access_flags: method_info.access_flags,
+                instructions: Vec::new(),
+                stack_map: Vec::new(),
+                exception_table: Vec::new(),
+            }
+        } else {
+            parse_method(
+                method,
+                source,
+                &class_name,
+                parser_context,
+                &mut constant_pool,
+                method_info,
+            )?
+        };
+
+        methods.insert(method_signature, Rc::new(parsed_method));
+    }
 
-        methods.insert(method_signature, parsed_method);
+    // Any methods after the declared ones are implicit, which is only the default constructor
+    for method_info in class_info.methods.iter().skip(method_nodes.len()) {
+        methods.insert(
+            method_info.signature.clone(),
+            Rc::new(default_constructor(
+                &class_info.super_class,
+                &mut constant_pool,
+            )),
+        );
     }
 
     Ok(Class {
         name: class_name,
+        access_flags: class_info.access_flags,
+        super_class: Some(class_info.super_class.clone()),
+        interfaces: class_info.interfaces.clone(),
+        bootstrap_methods: Vec::new(),
+        initialized: false,
         constant_pool,
-        static_fields: Default::default(),
+        static_fields,
+        instance_fields,
+        string_constants: HashMap::new(),
+        final_fields,
         methods,
+        resolved_refs: HashMap::new(),
     })
 }
 
 pub fn parse_to_class(code: String) -> Result<Vec<Class>, String> {
+    parse_sources_to_classes(vec![code], false)
+}
+
+/// Compile several source files together, so that classes can refer to classes declared in any of
+/// the other files. Redundant instructions are removed from the compiled methods if `optimize` is
+/// set.
+pub fn parse_sources_to_classes(codes: Vec<String>, optimize: bool) -> Result<Vec<Class>, String> {
     let mut parser = Parser::new();
     parser
         .set_language(tree_sitter_java::language())
         .expect("Error loading Java grammar");
-    let tree = parser.parse(&code, None).expect("Error parsing Java code");
 
-    let root_node = tree.root_node();
-    let source = code.as_bytes();
+    let mut trees = vec![];
 
-    root_node.print_tree();
-    println!();
+    for code in &codes {
+        let tree = parser.parse(code, None).expect("Error parsing Java code");
 
-    let class = root_node.child_by_kind("class_declaration").unwrap();
-    let class_body = class.child_by_kind("class_body").unwrap();
-    let class_name = class.name_from_identifier(source)?;
+        tree.root_node().print_tree();
+        println!();
 
-    println!("Methods: {:?}", generate_method_list(&class_body, source));
+        trees.push(tree);
+    }
 
-    let class_info = ClassInfo {
-        name: class_name,
-        super_class: "java/lang/Object".to_string(),
-        fields: vec![],
-        methods: generate_method_list(&class_body, source)?,
-    };
+    let root_nodes = trees
+        .iter()
+        .map(|tree| tree.root_node())
+        .collect::<Vec<_>>();
+    let mut class_nodes = vec![];
+
+    for (root_node, code) in root_nodes.iter().zip(codes.iter()) {
+        for class in root_node.get_children() {
+            if matches!(class.kind(), "class_declaration" | "interface_declaration") {
+                class_nodes.push((class, code.as_bytes()));
+            }
+        }
+    }
+
+    // Every class is described up front so that references between them resolve at compile time
+    let mut class_infos = vec![];
+
+    for (class, source) in &class_nodes {
+        let class_body = class.child_by_kind(body_kind(class))?;
+
+        let super_class = match class.child_by_kind("superclass") {
+            Ok(superclass) => match superclass
+                .child_by_kind("type_identifier")?
+                .utf8_text(source)
+            {
+                Ok(text) => text.to_string(),
+                Err(err) => return Err(format!("Failed to parse superclass name: {}", err)),
+            },
+            Err(_) => String::from("java/lang/Object"),
+        };
+
+        let mut access_flags = if class.kind() == "interface_declaration" {
+            ClassFlags::Interface as u16 | ClassFlags::Abstract as u16
+        } else {
+            ClassFlags::Super as u16
+        };
+        if let Ok(modifiers) = class.child_by_kind("modifiers") {
+            for modifier in modifiers.get_children() {
+                access_flags |= match modifier.kind() {
+                    "public" => ClassFlags::Public as u16,
+                    "final" => ClassFlags::Final as u16,
+                    "abstract" => ClassFlags::Abstract as u16,
+                    _ => 0,
+                };
+            }
+        }
+
+        // Classes list the interfaces they implement, and interfaces the ones they extend
+        let mut interfaces = vec![];
+        for list_kind in ["super_interfaces", "extends_interfaces"] {
+            if let Ok(list) = class.child_by_kind(list_kind) {
+                for interface in list.child_by_kind("interface_type_list")?.get_children() {
+                    if interface.kind() == "type_identifier" {
+                        match interface.utf8_text(source) {
+                            Ok(text) => interfaces.push(text.to_string()),
+                            Err(err) => {
+                                return Err(format!("Failed to parse interface name: {}", err))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        class_infos.push(ClassInfo {
+            name: class.name_from_identifier(source)?,
+            super_class,
+            access_flags,
+            interfaces,
+            fields: generate_field_list(&class_body, source)?,
+            methods: generate_method_list(&class_body, source)?,
+        });
+    }
+
+    for index in 0..class_infos.len() {
+        class_infos[index].super_class =
+            qualified_class_name(&class_infos[index].super_class, &class_infos);
+        class_infos[index].interfaces = class_infos[index]
+            .interfaces
+            .iter()
+            .map(|interface| qualified_class_name(interface, &class_infos))
+            .collect();
+    }
 
-    // TODO: generate method list for every class in project
     let parser_context = ParserContext {
-        classes: vec![class_info],
+        classes: class_infos,
+        optimize,
     };
 
-    let parsed_class = parse_class(&class, source, &parser_context)?;
+    let mut classes = vec![];
+
+    for (class, source) in &class_nodes {
+        classes.push(parse_class(class, source, &parser_context)?);
+    }
 
-    Ok(vec![parsed_class])
+    Ok(classes)
 }
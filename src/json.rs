@@ -0,0 +1,796 @@
+//! Conversion of methods to and from JSON, so that decoded bytecode can be analysed by external
+//! tools. A method is written as an object of the form
+//!
+//! ```text
+//! {"access_flags":9,"instructions":[["Load",0,"Int"],["Const",{"Int":1}],["Add","Int"],
+//! ["Return","Int"]],"exception_table":[]}
+//! ```
+//!
+//! Each instruction is an array of its name followed by its operands, in the order of the fields
+//! of its `Instruction` variant. Indices and offsets are numbers, types and comparisons are the
+//! names of their variants, and constants are objects with the name of their type as the only key,
+//! apart from `"Null"`. Float and double constants which aren't finite are written as the strings
+//! `"NaN"`, `"Infinity"` and `"-Infinity"`. Branch targets are absolute instruction indices, as in
+//! a loaded `Method`.
+use crate::java_class::ExceptionTableEntry;
+use crate::jvm::Method;
+use crate::{Comparison, Instruction, Primitive, PrimitiveType};
+use std::fmt::{Display, Formatter};
+
+/// A parsed JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Numbers keep their text, so that large integers aren't rounded by going through a float.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /// The entries of an object, in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            chars: text.chars().collect(),
+            position: 0,
+        };
+
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+
+        match parser.chars.get(parser.position) {
+            Some(c) => Err(format!(
+                "Unexpected {} after the JSON value at position {}",
+                c, parser.position
+            )),
+            None => Ok(value),
+        }
+    }
+
+    fn number(value: impl ToString) -> Json {
+        Json::Number(value.to_string())
+    }
+
+    /// The value of an entry of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Json], String> {
+        match self {
+            Json::Array(values) => Ok(values),
+            value => Err(format!("Expected an array but found {}", value)),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, String> {
+        match self {
+            Json::String(text) => Ok(text),
+            value => Err(format!("Expected a string but found {}", value)),
+        }
+    }
+
+    /// Parse a number as any numeric type, failing if it doesn't fit.
+    pub fn as_number<T: std::str::FromStr>(&self) -> Result<T, String> {
+        match self {
+            Json::Number(text) => match text.parse() {
+                Ok(number) => Ok(number),
+                Err(_) => Err(format!(
+                    "{} is out of range for {}",
+                    text,
+                    std::any::type_name::<T>()
+                )),
+            },
+            value => Err(format!("Expected a number but found {}", value)),
+        }
+    }
+}
+
+impl Display for Json {
+    /// Write the value as compact JSON.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(text) => write!(f, "{}", text),
+            Json::String(text) => write_string(f, text),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, text: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl JsonParser {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\n' | '\r' | '\t')) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!(
+                "Expected {} but found {} at position {}",
+                expected, c, self.position
+            )),
+            None => Err(format!("Expected {} but the JSON ended", expected)),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let mut entries = Vec::new();
+
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(Json::Object(entries));
+                }
+
+                loop {
+                    self.skip_whitespace();
+                    let key = self.parse_string()?;
+                    self.expect(':')?;
+                    entries.push((key, self.parse_value()?));
+
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        _ => break,
+                    }
+                }
+
+                self.expect('}')?;
+                Ok(Json::Object(entries))
+            }
+            Some('[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+
+                loop {
+                    values.push(self.parse_value()?);
+
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        _ => break,
+                    }
+                }
+
+                self.expect(']')?;
+                Ok(Json::Array(values))
+            }
+            Some('"') => Ok(Json::String(self.parse_string()?)),
+            Some('-' | '0'..='9') => {
+                let start = self.position;
+
+                while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+                    self.position += 1;
+                }
+
+                Ok(Json::Number(
+                    self.chars[start..self.position].iter().collect(),
+                ))
+            }
+            Some(_) => {
+                for (word, value) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    let end = self.position + word.len();
+
+                    if end <= self.chars.len()
+                        && self.chars[self.position..end]
+                            .iter()
+                            .copied()
+                            .eq(word.chars())
+                    {
+                        self.position = end;
+                        return Ok(value);
+                    }
+                }
+
+                Err(format!(
+                    "Unexpected character at position {}",
+                    self.position
+                ))
+            }
+            None => Err(String::from("Expected a value but the JSON ended")),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(String::from("Unterminated string in JSON")),
+            };
+            self.position += 1;
+
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = self.peek();
+                    self.position += 1;
+
+                    text.push(match escaped {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let end = self.position + 4;
+                            let code = match self.chars.get(self.position..end) {
+                                Some(digits) => digits.iter().collect::<String>(),
+                                None => return Err(String::from("Unterminated string in JSON")),
+                            };
+                            self.position = end;
+
+                            match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                                Some(c) => c,
+                                None => return Err(format!("Invalid escape \\u{} in JSON", code)),
+                            }
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Invalid escape in JSON string at position {}",
+                                self.position - 1
+                            ))
+                        }
+                    });
+                }
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+impl PrimitiveType {
+    pub fn to_json(&self) -> Json {
+        Json::String(format!("{:?}", self))
+    }
+
+    pub fn from_json(json: &Json) -> Result<PrimitiveType, String> {
+        Ok(match json.as_str()? {
+            "Null" => PrimitiveType::Null,
+            "Byte" => PrimitiveType::Byte,
+            "Short" => PrimitiveType::Short,
+            "Char" => PrimitiveType::Char,
+            "Int" => PrimitiveType::Int,
+            "Long" => PrimitiveType::Long,
+            "Float" => PrimitiveType::Float,
+            "Double" => PrimitiveType::Double,
+            "Reference" => PrimitiveType::Reference,
+            "Boolean" => PrimitiveType::Boolean,
+            name => return Err(format!("Unknown type {}", name)),
+        })
+    }
+}
+
+impl Comparison {
+    pub fn to_json(&self) -> Json {
+        Json::String(format!("{:?}", self))
+    }
+
+    pub fn from_json(json: &Json) -> Result<Comparison, String> {
+        Ok(match json.as_str()? {
+            "Equal" => Comparison::Equal,
+            "NotEqual" => Comparison::NotEqual,
+            "LessThan" => Comparison::LessThan,
+            "GreaterThan" => Comparison::GreaterThan,
+            "LessThanOrEqual" => Comparison::LessThanOrEqual,
+            "GreaterThanOrEqual" => Comparison::GreaterThanOrEqual,
+            name => return Err(format!("Unknown comparison {}", name)),
+        })
+    }
+}
+
+/// Floats which aren't finite have no JSON number, so they are written as strings.
+fn float_to_json(value: f64, text: String) -> Json {
+    if value.is_nan() {
+        Json::String(String::from("NaN"))
+    } else if value.is_infinite() {
+        Json::String(String::from(if value > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }))
+    } else {
+        Json::Number(text)
+    }
+}
+
+fn float_from_json(json: &Json) -> Result<f64, String> {
+    match json {
+        Json::String(text) => match &text[..] {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => Err(format!("Expected a float but found {}", json)),
+        },
+        json => json.as_number(),
+    }
+}
+
+impl Primitive {
+    pub fn to_json(&self) -> Json {
+        let (name, value) = match self {
+            Primitive::Null => return Json::String(String::from("Null")),
+            Primitive::Byte(value) => ("Byte", Json::number(value)),
+            Primitive::Short(value) => ("Short", Json::number(value)),
+            Primitive::Char(value) => ("Char", Json::number(value)),
+            Primitive::Int(value) => ("Int", Json::number(value)),
+            Primitive::Long(value) => ("Long", Json::number(value)),
+            Primitive::Float(value) => ("Float", float_to_json(*value as f64, value.to_string())),
+            Primitive::Double(value) => ("Double", float_to_json(*value, value.to_string())),
+            Primitive::Reference(value) => ("Reference", Json::number(value)),
+            Primitive::ReturnAddress(value) => ("ReturnAddress", Json::number(value)),
+        };
+
+        Json::Object(vec![(String::from(name), value)])
+    }
+
+    pub fn from_json(json: &Json) -> Result<Primitive, String> {
+        let (name, value) = match json {
+            Json::String(name) if name == "Null" => return Ok(Primitive::Null),
+            Json::Object(entries) if entries.len() == 1 => (&entries[0].0, &entries[0].1),
+            json => return Err(format!("Expected a constant but found {}", json)),
+        };
+
+        Ok(match &name[..] {
+            "Byte" => Primitive::Byte(value.as_number()?),
+            "Short" => Primitive::Short(value.as_number()?),
+            "Char" => Primitive::Char(value.as_number()?),
+            "Int" => Primitive::Int(value.as_number()?),
+            "Long" => Primitive::Long(value.as_number()?),
+            "Float" => Primitive::Float(float_from_json(value)? as f32),
+            "Double" => Primitive::Double(float_from_json(value)?),
+            "Reference" => Primitive::Reference(value.as_number()?),
+            "ReturnAddress" => Primitive::ReturnAddress(value.as_number()?),
+            name => return Err(format!("Unknown constant type {}", name)),
+        })
+    }
+}
+
+impl Instruction {
+    pub fn to_json(&self) -> Json {
+        let index = |index: &usize| Json::number(index);
+
+        let operands = match self {
+            Instruction::Nop => vec![],
+            Instruction::AConstNull => vec![],
+            Instruction::Const(value) => vec![value.to_json()],
+            Instruction::LoadConst(pool_index) => vec![index(pool_index)],
+            Instruction::Load(local, local_type) => {
+                vec![index(local), local_type.to_json()]
+            }
+            Instruction::ALoad(element_type) => vec![element_type.to_json()],
+            Instruction::Store(local, local_type) => {
+                vec![index(local), local_type.to_json()]
+            }
+            Instruction::AStore(element_type) => vec![element_type.to_json()],
+            Instruction::Pop => vec![],
+            Instruction::Pop2 => vec![],
+            Instruction::Dup => vec![],
+            Instruction::DupX1 => vec![],
+            Instruction::DupX2 => vec![],
+            Instruction::Dup2 => vec![],
+            Instruction::Dup2X1 => vec![],
+            Instruction::Dup2X2 => vec![],
+            Instruction::Swap => vec![],
+            Instruction::Add(operand_type) => vec![operand_type.to_json()],
+            Instruction::Sub(operand_type) => vec![operand_type.to_json()],
+            Instruction::Mul(operand_type) => vec![operand_type.to_json()],
+            Instruction::Div(operand_type) => vec![operand_type.to_json()],
+            Instruction::Rem(operand_type) => vec![operand_type.to_json()],
+            Instruction::Neg(operand_type) => vec![operand_type.to_json()],
+            Instruction::Shl(operand_type) => vec![operand_type.to_json()],
+            Instruction::Shr(operand_type) => vec![operand_type.to_json()],
+            Instruction::UShr(operand_type) => vec![operand_type.to_json()],
+            Instruction::And(operand_type) => vec![operand_type.to_json()],
+            Instruction::Or(operand_type) => vec![operand_type.to_json()],
+            Instruction::Xor(operand_type) => vec![operand_type.to_json()],
+            Instruction::IInc(local, constant) => {
+                vec![index(local), Json::number(constant)]
+            }
+            Instruction::Convert(from, to) => vec![from.to_json(), to.to_json()],
+            Instruction::LCmp => vec![],
+            Instruction::FCmpL => vec![],
+            Instruction::FCmpG => vec![],
+            Instruction::DCmpL => vec![],
+            Instruction::DCmpG => vec![],
+            Instruction::If(target, comparison) => {
+                vec![index(target), comparison.to_json()]
+            }
+            Instruction::IfICmp(target, comparison) => {
+                vec![index(target), comparison.to_json()]
+            }
+            Instruction::Goto(target) => vec![index(target)],
+            Instruction::Jsr(target) => vec![index(target)],
+            Instruction::Ret(local) => vec![index(local)],
+            Instruction::TableSwitch(default, low, targets) => vec![
+                index(default),
+                Json::number(low),
+                Json::Array(targets.iter().map(index).collect()),
+            ],
+            Instruction::LookupSwitch(default, pairs) => vec![
+                index(default),
+                Json::Array(
+                    pairs
+                        .iter()
+                        .map(|(value, target)| {
+                            Json::Array(vec![Json::number(value), index(target)])
+                        })
+                        .collect(),
+                ),
+            ],
+            Instruction::Return(return_type) => vec![return_type.to_json()],
+            Instruction::GetStatic(pool_index) => vec![index(pool_index)],
+            Instruction::PutStatic(pool_index) => vec![index(pool_index)],
+            Instruction::GetField(pool_index) => vec![index(pool_index)],
+            Instruction::PutField(pool_index) => vec![index(pool_index)],
+            Instruction::InvokeVirtual(pool_index) => vec![index(pool_index)],
+            Instruction::InvokeSpecial(pool_index) => vec![index(pool_index)],
+            Instruction::InvokeStatic(pool_index) => vec![index(pool_index)],
+            Instruction::InvokeInterface(pool_index) => {
+                vec![index(pool_index)]
+            }
+            Instruction::InvokeDynamic(pool_index) => vec![index(pool_index)],
+            Instruction::New(pool_index) => vec![index(pool_index)],
+            Instruction::NewArray(element_type) => vec![element_type.to_json()],
+            Instruction::ANewArray(pool_index) => vec![index(pool_index)],
+            Instruction::ArrayLength => vec![],
+            Instruction::AThrow => vec![],
+            Instruction::CheckCast(pool_index) => vec![index(pool_index)],
+            Instruction::InstanceOf(pool_index) => vec![index(pool_index)],
+            Instruction::MonitorEnter => vec![],
+            Instruction::MonitorExit => vec![],
+            Instruction::MultiANewArray(pool_index, dimensions) => {
+                vec![index(pool_index), index(dimensions)]
+            }
+            Instruction::IfNull(target) => vec![index(target)],
+            Instruction::IfNonNull(target) => vec![index(target)],
+            Instruction::Breakpoint => vec![],
+        };
+
+        let mut values = vec![Json::String(String::from(self.name()))];
+        values.extend(operands);
+        Json::Array(values)
+    }
+
+    pub fn from_json(json: &Json) -> Result<Instruction, String> {
+        let (name, operands) = match json.as_array()? {
+            [name, operands @ ..] => (name.as_str()?, operands),
+            [] => return Err(String::from("Instruction is missing its name")),
+        };
+
+        let index = |position: usize| operands[position].as_number::<usize>();
+        let primitive_type = |position: usize| PrimitiveType::from_json(&operands[position]);
+
+        Ok(match (name, operands.len()) {
+            ("Nop", 0) => Instruction::Nop,
+            ("AConstNull", 0) => Instruction::AConstNull,
+            ("Const", 1) => Instruction::Const(Primitive::from_json(&operands[0])?),
+            ("LoadConst", 1) => Instruction::LoadConst(index(0)?),
+            ("Load", 2) => Instruction::Load(index(0)?, primitive_type(1)?),
+            ("ALoad", 1) => Instruction::ALoad(primitive_type(0)?),
+            ("Store", 2) => Instruction::Store(index(0)?, primitive_type(1)?),
+            ("AStore", 1) => Instruction::AStore(primitive_type(0)?),
+            ("Pop", 0) => Instruction::Pop,
+            ("Pop2", 0) => Instruction::Pop2,
+            ("Dup", 0) => Instruction::Dup,
+            ("DupX1", 0) => Instruction::DupX1,
+            ("DupX2", 0) => Instruction::DupX2,
+            ("Dup2", 0) => Instruction::Dup2,
+            ("Dup2X1", 0) => Instruction::Dup2X1,
+            ("Dup2X2", 0) => Instruction::Dup2X2,
+            ("Swap", 0) => Instruction::Swap,
+            ("Add", 1) => Instruction::Add(primitive_type(0)?),
+            ("Sub", 1) => Instruction::Sub(primitive_type(0)?),
+            ("Mul", 1) => Instruction::Mul(primitive_type(0)?),
+            ("Div", 1) => Instruction::Div(primitive_type(0)?),
+            ("Rem", 1) => Instruction::Rem(primitive_type(0)?),
+            ("Neg", 1) => Instruction::Neg(primitive_type(0)?),
+            ("Shl", 1) => Instruction::Shl(primitive_type(0)?),
+            ("Shr", 1) => Instruction::Shr(primitive_type(0)?),
+            ("UShr", 1) => Instruction::UShr(primitive_type(0)?),
+            ("And", 1) => Instruction::And(primitive_type(0)?),
+            ("Or", 1) => Instruction::Or(primitive_type(0)?),
+            ("Xor", 1) => Instruction::Xor(primitive_type(0)?),
+            ("IInc", 2) => Instruction::IInc(index(0)?, operands[1].as_number()?),
+            ("Convert", 2) => Instruction::Convert(primitive_type(0)?, primitive_type(1)?),
+            ("LCmp", 0) => Instruction::LCmp,
+            ("FCmpL", 0) => Instruction::FCmpL,
+            ("FCmpG", 0) => Instruction::FCmpG,
+            ("DCmpL", 0) => Instruction::DCmpL,
+            ("DCmpG", 0) => Instruction::DCmpG,
+            ("If", 2) => Instruction::If(index(0)?, Comparison::from_json(&operands[1])?),
+            ("IfICmp", 2) => Instruction::IfICmp(index(0)?, Comparison::from_json(&operands[1])?),
+            ("Goto", 1) => Instruction::Goto(index(0)?),
+            ("Jsr", 1) => Instruction::Jsr(index(0)?),
+            ("Ret", 1) => Instruction::Ret(index(0)?),
+            ("TableSwitch", 3) => Instruction::TableSwitch(
+                index(0)?,
+                operands[1].as_number()?,
+                operands[2]
+                    .as_array()?
+                    .iter()
+                    .map(|target| target.as_number())
+                    .collect::<Result<_, _>>()?,
+            ),
+            ("LookupSwitch", 2) => Instruction::LookupSwitch(
+                index(0)?,
+                operands[1]
+                    .as_array()?
+                    .iter()
+                    .map(|pair| match pair.as_array()? {
+                        [value, target] => Ok((value.as_number()?, target.as_number()?)),
+                        _ => Err(format!("Expected a value and target but found {}", pair)),
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            ("Return", 1) => Instruction::Return(primitive_type(0)?),
+            ("GetStatic", 1) => Instruction::GetStatic(index(0)?),
+            ("PutStatic", 1) => Instruction::PutStatic(index(0)?),
+            ("GetField", 1) => Instruction::GetField(index(0)?),
+            ("PutField", 1) => Instruction::PutField(index(0)?),
+            ("InvokeVirtual", 1) => Instruction::InvokeVirtual(index(0)?),
+            ("InvokeSpecial", 1) => Instruction::InvokeSpecial(index(0)?),
+            ("InvokeStatic", 1) => Instruction::InvokeStatic(index(0)?),
+            ("InvokeInterface", 1) => Instruction::InvokeInterface(index(0)?),
+            ("InvokeDynamic", 1) => Instruction::InvokeDynamic(index(0)?),
+            ("New", 1) => Instruction::New(index(0)?),
+            ("NewArray", 1) => Instruction::NewArray(primitive_type(0)?),
+            ("ANewArray", 1) => Instruction::ANewArray(index(0)?),
+            ("ArrayLength", 0) => Instruction::ArrayLength,
+            ("AThrow", 0) => Instruction::AThrow,
+            ("CheckCast", 1) => Instruction::CheckCast(index(0)?),
+            ("InstanceOf", 1) => Instruction::InstanceOf(index(0)?),
+            ("MonitorEnter", 0) => Instruction::MonitorEnter,
+            ("MonitorExit", 0) => Instruction::MonitorExit,
+            ("MultiANewArray", 2) => Instruction::MultiANewArray(index(0)?, index(1)?),
+            ("IfNull", 1) => Instruction::IfNull(index(0)?),
+            ("IfNonNull", 1) => Instruction::IfNonNull(index(0)?),
+            ("Breakpoint", 0) => Instruction::Breakpoint,
+            (name, count) => {
+                return Err(format!(
+                    "Unknown instruction {} with {} operands",
+                    name, count
+                ))
+            }
+        })
+    }
+}
+
+impl Method {
+    /// Describe the method as JSON, in the format documented at the top of this module.
+    pub fn to_json(&self) -> String {
+        let exception_table = self
+            .exception_table
+            .iter()
+            .map(|entry| {
+                Json::Object(vec![
+                    (String::from("start_pc"), Json::number(entry.start_pc)),
+                    (String::from("end_pc"), Json::number(entry.end_pc)),
+                    (String::from("handler_pc"), Json::number(entry.handler_pc)),
+                    (String::from("catch_type"), Json::number(entry.catch_type)),
+                ])
+            })
+            .collect();
+
+        Json::Object(vec![
+            (
+                String::from("access_flags"),
+                Json::number(self.access_flags),
+            ),
+            (
+                String::from("instructions"),
+                Json::Array(self.instructions.iter().map(Instruction::to_json).collect()),
+            ),
+            (
+                String::from("exception_table"),
+                Json::Array(exception_table),
+            ),
+        ])
+        .to_string()
+    }
+
+    /// Load a method described in the format documented at the top of this module. The operands of
+    /// every instruction are checked against the ranges a class file could hold, and every branch
+    /// and handler must stay within the method. The method has no stack map, which is only needed
+    /// to write it back out to a class file.
+    pub fn from_json(text: &str) -> Result<Method, String> {
+        let json = Json::parse(text)?;
+        let field = |name: &str| match json.get(name) {
+            Some(value) => Ok(value),
+            None => Err(format!("Method is missing {}", name)),
+        };
+
+        let instructions = field("instructions")?
+            .as_array()?
+            .iter()
+            .enumerate()
+            .map(
+                |(index, instruction)| match Instruction::from_json(instruction) {
+                    Ok(instruction) => Ok(instruction),
+                    Err(err) => Err(format!("Invalid instruction {}: {}", index, err)),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            check_operands(instruction, instructions.len())
+                .map_err(|err| format!("Invalid instruction {}: {}", index, err))?;
+        }
+
+        let exception_table = match json.get("exception_table") {
+            Some(exception_table) => exception_table
+                .as_array()?
+                .iter()
+                .map(|entry| exception_table_entry_from_json(entry, instructions.len()))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Method {
+            access_flags: field("access_flags")?.as_number()?,
+            instructions,
+            stack_map: Vec::new(),
+            exception_table,
+        })
+    }
+}
+
+/// Check that the operands of an instruction could be encoded in a class file, and that its
+/// branches land inside a method of the passed length.
+fn check_operands(instruction: &Instruction, length: usize) -> Result<(), String> {
+    for target in instruction.branch_targets() {
+        if target >= length {
+            return Err(format!("Branch to {} is outside of the method", target));
+        }
+    }
+
+    let (name, value, max) = match instruction {
+        Instruction::Load(local, _)
+        | Instruction::Store(local, _)
+        | Instruction::IInc(local, _)
+        | Instruction::Ret(local) => ("Local", *local, u16::MAX as usize),
+        Instruction::LoadConst(index)
+        | Instruction::GetStatic(index)
+        | Instruction::PutStatic(index)
+        | Instruction::GetField(index)
+        | Instruction::PutField(index)
+        | Instruction::InvokeVirtual(index)
+        | Instruction::InvokeSpecial(index)
+        | Instruction::InvokeStatic(index)
+        | Instruction::InvokeInterface(index)
+        | Instruction::InvokeDynamic(index)
+        | Instruction::New(index)
+        | Instruction::CheckCast(index)
+        | Instruction::InstanceOf(index) => ("Constant pool index", *index, u16::MAX as usize),
+        Instruction::MultiANewArray(index, dimensions) => {
+            if !(1..=u8::MAX as usize).contains(dimensions) {
+                return Err(format!(
+                    "Dimension count {} is not between 1 and {}",
+                    dimensions,
+                    u8::MAX
+                ));
+            }
+            ("Constant pool index", *index, u16::MAX as usize)
+        }
+        _ => return Ok(()),
+    };
+
+    if value > max {
+        return Err(format!("{} {} is larger than {}", name, value, max));
+    }
+
+    Ok(())
+}
+
+fn exception_table_entry_from_json(
+    json: &Json,
+    length: usize,
+) -> Result<ExceptionTableEntry, String> {
+    let field = |name: &str| match json.get(name) {
+        Some(value) => value.as_number::<usize>(),
+        None => Err(format!("Exception table entry is missing {}", name)),
+    };
+
+    let entry = ExceptionTableEntry {
+        start_pc: field("start_pc")?,
+        end_pc: field("end_pc")?,
+        handler_pc: field("handler_pc")?,
+        catch_type: match json.get("catch_type") {
+            Some(catch_type) => catch_type.as_number()?,
+            None => 0,
+        },
+    };
+
+    if entry.start_pc >= entry.end_pc || entry.end_pc > length || entry.handler_pc >= length {
+        return Err(format!(
+            "Exception table entry {} is outside of the method",
+            json
+        ));
+    }
+
+    Ok(entry)
+}
@@ -1,19 +1,57 @@
-use crate::java_class::{ConstantPoolEntry, ConstantPoolExt};
+use crate::descriptor::parse_descriptor;
+use crate::java_class::{
+    BootstrapMethod, ClassFlags, ConstantPoolEntry, ConstantPoolExt, ExceptionTableEntry,
+    MethodFlags, StackMapFrame,
+};
+use crate::threads::Thread;
 use crate::{Instruction, Operator, Primitive, PrimitiveType};
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Method {
+    pub access_flags: u16,
     pub instructions: Vec<Instruction>,
+    /// The stack map frames of the method, whose offsets count instructions rather than bytes.
+    #[allow(dead_code)]
+    pub stack_map: Vec<StackMapFrame>,
+    /// The exception handlers of the method, whose pcs count instructions rather than bytes.
+    pub exception_table: Vec<ExceptionTableEntry>,
 }
 
-#[derive(Debug)]
+impl Method {
+    /// Static methods have no receiver, so their parameters start at local 0.
+    pub fn is_static(&self) -> bool {
+        self.access_flags & MethodFlags::Static as u16 != 0
+    }
+
+    /// Abstract methods have no code, and have to be implemented by a subclass to be called.
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags & MethodFlags::Abstract as u16 != 0
+    }
+
+    /// Native methods are implemented outside of Java, so they have no code to run either.
+    pub fn is_native(&self) -> bool {
+        self.access_flags & MethodFlags::Native as u16 != 0
+    }
+
+    /// Private methods can only be invoked from the class declaring them, and are never
+    /// overridden.
+    pub fn is_private(&self) -> bool {
+        self.access_flags & MethodFlags::Private as u16 != 0
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct StackFrame {
     pub pc: usize,
     pub locals: Vec<Primitive>,
-    pub arrays: Vec<Vec<Primitive>>,
     pub stack: Vec<Primitive>,
-    pub method: Method,
+    pub method: Rc<Method>,
     pub class_name: String,
 }
 
@@ -40,6 +78,49 @@ impl StackFrame {
         }
     }
 
+    /// Compare the two floats or doubles on top of the stack, pushing -1, 0 or 1. The passed
+    /// result is pushed instead if either value is NaN, which is -1 for fcmpl/dcmpl and 1 for
+    /// fcmpg/dcmpg.
+    pub fn float_compare(&mut self, nan_result: i32) -> Result<(), String> {
+        let value2 = self.pop_primitive()?;
+        let value1 = self.pop_primitive()?;
+
+        let ordering = match (value1, value2) {
+            (Primitive::Float(a), Primitive::Float(b)) => a.partial_cmp(&b),
+            (Primitive::Double(a), Primitive::Double(b)) => a.partial_cmp(&b),
+            _ => return Err(String::from("Expected floats or doubles to compare")),
+        };
+
+        self.stack.push(Primitive::Int(match ordering {
+            Some(Ordering::Less) => -1,
+            Some(Ordering::Equal) => 0,
+            Some(Ordering::Greater) => 1,
+            None => nan_result,
+        }));
+
+        Ok(())
+    }
+
+    /// Pop values taking up the passed number of stack slots, where longs and doubles take two.
+    /// The values are returned in the order they were on the stack.
+    pub fn pop_slots(&mut self, slots: usize) -> Result<Vec<Primitive>, String> {
+        let mut values = Vec::new();
+        let mut popped = 0;
+
+        while popped < slots {
+            let value = self.pop_primitive()?;
+            popped += if value.is_wide() { 2 } else { 1 };
+            values.push(value);
+        }
+
+        if popped != slots {
+            return Err(String::from("Cannot split a wide value on the stack"));
+        }
+
+        values.reverse();
+        Ok(values)
+    }
+
     pub fn pop_int(&mut self) -> Result<i32, String> {
         match self.pop_primitive()? {
             Primitive::Int(i) => Ok(i),
@@ -54,9 +135,18 @@ impl StackFrame {
         }
     }
 
+    pub fn pop_double(&mut self) -> Result<f64, String> {
+        match self.pop_primitive()? {
+            Primitive::Double(d) => Ok(d),
+            _ => Err("Expected double when popping from stack".to_string()),
+        }
+    }
+
     pub fn pop_ref(&mut self) -> Result<usize, String> {
         match self.pop_primitive()? {
             Primitive::Reference(r) => Ok(r),
+            // Using null where an object is needed, e.g. as the receiver of a call
+            Primitive::Null => Err(String::from("java/lang/NullPointerException")),
             _ => Err("Expected reference when popping from stack".to_string()),
         }
     }
@@ -65,15 +155,404 @@ impl StackFrame {
 #[derive(Debug)]
 pub struct Class {
     pub name: String,
+    /// The `ClassFlags` of the class, such as whether it is an interface.
+    pub access_flags: u16,
+    /// The direct superclass, which is `None` for `java/lang/Object`.
+    pub super_class: Option<String>,
+    pub interfaces: Vec<String>,
+    pub bootstrap_methods: Vec<BootstrapMethod>,
+    /// Whether the static initializer has been started, which happens on the class's first use.
+    pub initialized: bool,
     pub constant_pool: Vec<ConstantPoolEntry>,
     pub static_fields: HashMap<String, Primitive>,
-    pub methods: HashMap<String, Method>,
+    /// The default values of the instance fields the class declares, which new objects start out
+    /// with alongside those of its superclasses.
+    pub instance_fields: IndexMap<String, Primitive>,
+    /// The text of the static fields whose constant value is a string. They hold null until the
+    /// class is initialized, which puts their strings on the heap.
+    pub string_constants: HashMap<String, String>,
+    /// The names of the fields declared final, which only the class's initializers may assign.
+    pub final_fields: HashSet<String>,
+    pub methods: HashMap<String, Rc<Method>>,
+    pub resolved_refs: HashMap<usize, Rc<ResolvedRef>>,
 }
 
+/// A field or method reference from the constant pool, resolved to the names it refers to.
 #[derive(Debug)]
+pub struct ResolvedRef {
+    pub class_name: String,
+    pub name: String,
+    pub descriptor: String,
+    /// The name followed by the descriptor, which is how methods are keyed in `Class.methods`.
+    pub signature: String,
+}
+
+impl Class {
+    #[allow(dead_code)]
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ClassFlags::Interface as u16 != 0
+    }
+
+    /// The root of every class hierarchy. Only its constructor, which does nothing, is loaded, so
+    /// that constructors can chain up to it. Its other methods are built-ins.
+    pub fn object() -> Class {
+        let constructor = Method {
+            access_flags: MethodFlags::Public as u16,
+            instructions: vec![Instruction::Return(PrimitiveType::Null)],
+            stack_map: Vec::new(),
+            exception_table: Vec::new(),
+        };
+
+        Class {
+            name: String::from("java/lang/Object"),
+            access_flags: ClassFlags::Public as u16,
+            super_class: None,
+            interfaces: Vec::new(),
+            bootstrap_methods: Vec::new(),
+            initialized: false,
+            constant_pool: Vec::new(),
+            static_fields: HashMap::new(),
+            instance_fields: IndexMap::new(),
+            string_constants: HashMap::new(),
+            final_fields: HashSet::new(),
+            methods: HashMap::from([(String::from("<init>()V"), Rc::new(constructor))]),
+            resolved_refs: HashMap::new(),
+        }
+    }
+
+    /// Resolve the field reference at the passed constant pool index.
+    pub fn resolve_field_ref(&mut self, index: usize) -> Result<Rc<ResolvedRef>, String> {
+        self.resolve_ref(index, false)
+    }
+
+    /// Resolve the method reference at the passed constant pool index.
+    pub fn resolve_method_ref(&mut self, index: usize) -> Result<Rc<ResolvedRef>, String> {
+        self.resolve_ref(index, true)
+    }
+
+    /// Resolve a method or field reference, caching the result so that repeated executions of
+    /// the same instruction don't have to walk the constant pool again.
+    fn resolve_ref(&mut self, index: usize, is_method: bool) -> Result<Rc<ResolvedRef>, String> {
+        if let Some(resolved) = self.resolved_refs.get(&index) {
+            return Ok(Rc::clone(resolved));
+        }
+
+        let (class_name, name, descriptor) = if is_method {
+            self.constant_pool.method_ref_parser(&index)?
+        } else {
+            self.constant_pool.field_ref_parser(&index)?
+        };
+
+        let resolved = Rc::new(ResolvedRef {
+            signature: format!("{}{}", name, descriptor),
+            class_name,
+            name,
+            descriptor,
+        });
+
+        self.resolved_refs.insert(index, Rc::clone(&resolved));
+        Ok(resolved)
+    }
+}
+
+/// State held natively by the JVM for objects of built-in classes.
+#[derive(Debug, Clone)]
+pub enum ObjectData {
+    None,
+    String(String),
+    /// The type the elements are held as, followed by the elements.
+    Array(PrimitiveType, Vec<Primitive>),
+    Lambda(Lambda),
+    /// The runnable a `java/lang/Thread` calls, which is null for subclasses overriding `run`, and
+    /// the id of the thread once it has been started. This is kept apart from the fields, so that
+    /// the fields of a subclass can have any name.
+    Thread {
+        target: Primitive,
+        id: Option<usize>,
+    },
+}
+
+/// The method a lambda object forwards calls of its interface method to, along with the values
+/// captured when the lambda was created, which are passed before the call's own arguments.
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    pub class_name: String,
+    pub name: String,
+    pub descriptor: String,
+    /// The kind of method handle the lambda was created from, e.g. 6 for `REF_invokeStatic`.
+    pub reference_kind: u8,
+    pub captured: Vec<Primitive>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Object {
     pub class_name: String,
-    pub fields: HashMap<String, Primitive>,
+    /// The instance fields which have been assigned, in the order they were first assigned.
+    pub fields: IndexMap<String, Primitive>,
+    pub data: ObjectData,
+    /// How many more times the object's monitor has been entered than exited.
+    pub monitor_entries: usize,
+    /// The id of the thread which entered the monitor, while it has any entries.
+    pub monitor_owner: usize,
+    /// The value `Object.hashCode` returns, given when the object is allocated so that it stays
+    /// the same when the garbage collector moves the object.
+    pub identity_hash: i32,
+}
+
+impl Object {
+    /// Create a `java/lang/String` object backed by the passed text.
+    pub fn new_string(text: String) -> Object {
+        Object {
+            class_name: String::from("java/lang/String"),
+            fields: IndexMap::new(),
+            data: ObjectData::String(text),
+            monitor_entries: 0,
+            monitor_owner: 0,
+            identity_hash: 0,
+        }
+    }
+
+    /// Create an array object with the passed elements, e.g. `[I` for an int array.
+    pub fn new_array(class_name: String, elements: Vec<Primitive>) -> Object {
+        Object {
+            data: ObjectData::Array(
+                PrimitiveType::from_descriptor(class_name.get(1..).unwrap_or("")),
+                elements,
+            ),
+            class_name,
+            fields: IndexMap::new(),
+            monitor_entries: 0,
+            monitor_owner: 0,
+            identity_hash: 0,
+        }
+    }
+
+    /// Get the backing text if this object is a string.
+    pub fn as_string(&self) -> Option<&str> {
+        match &self.data {
+            ObjectData::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Get the elements if this object is an array.
+    pub fn as_array(&self) -> Option<&Vec<Primitive>> {
+        match &self.data {
+            ObjectData::Array(_, elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<Primitive>> {
+        match &mut self.data {
+            ObjectData::Array(_, elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// Describe the object's class and fields, e.g. `Point{x=3, y=4}`. Fields are listed in the
+    /// order they are declared, superclass fields first, so the output is the same on every run.
+    #[allow(dead_code)]
+    pub fn dump(&self, heap: &[Object]) -> String {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, pretty_print(value, heap)))
+            .collect::<Vec<_>>();
+
+        format!("{}{{{}}}", self.class_name, fields.join(", "))
+    }
+
+    /// The number of heap cells the object takes up, one for the object itself and one for each
+    /// element of an array.
+    pub fn size(&self) -> usize {
+        match &self.data {
+            ObjectData::Array(_, elements) => 1 + elements.len(),
+            _ => 1,
+        }
+    }
+}
+
+/// Lay out values as locals, where longs and doubles take up two slots. The second slot of a wide
+/// value is never read.
+fn to_local_slots(values: Vec<Primitive>) -> Vec<Primitive> {
+    let mut locals = Vec::new();
+
+    for value in values {
+        let is_wide = value.is_wide();
+        locals.push(value);
+
+        if is_wide {
+            locals.push(Primitive::Null);
+        }
+    }
+
+    locals
+}
+
+/// The number of values a method with the passed descriptor takes as parameters.
+fn parameter_count(descriptor: &str) -> Result<usize, String> {
+    Ok(parse_descriptor(descriptor)?.0.len())
+}
+
+/// The superclass of a built-in throwable class. These classes are never loaded, so this lets
+/// handlers catch them by their superclasses.
+fn builtin_super_class(class_name: &str) -> Option<&'static str> {
+    match class_name {
+        "java/lang/Throwable" => Some("java/lang/Object"),
+        "java/lang/Exception" | "java/lang/Error" => Some("java/lang/Throwable"),
+        "java/lang/RuntimeException" => Some("java/lang/Exception"),
+        "java/lang/VirtualMachineError" | "java/lang/AssertionError" | "java/lang/LinkageError" => {
+            Some("java/lang/Error")
+        }
+        "java/lang/IncompatibleClassChangeError" | "java/lang/UnsatisfiedLinkError" => {
+            Some("java/lang/LinkageError")
+        }
+        "java/lang/AbstractMethodError"
+        | "java/lang/IllegalAccessError"
+        | "java/lang/NoSuchFieldError"
+        | "java/lang/NoSuchMethodError" => Some("java/lang/IncompatibleClassChangeError"),
+        "java/lang/OutOfMemoryError" | "java/lang/StackOverflowError" => {
+            Some("java/lang/VirtualMachineError")
+        }
+        "java/lang/ArithmeticException"
+        | "java/lang/ArrayStoreException"
+        | "java/lang/ClassCastException"
+        | "java/lang/IllegalArgumentException"
+        | "java/lang/IllegalMonitorStateException"
+        | "java/lang/IllegalStateException"
+        | "java/lang/IndexOutOfBoundsException"
+        | "java/lang/NegativeArraySizeException"
+        | "java/lang/NullPointerException"
+        | "java/lang/UnsupportedOperationException" => Some("java/lang/RuntimeException"),
+        "java/lang/ArrayIndexOutOfBoundsException"
+        | "java/lang/StringIndexOutOfBoundsException" => {
+            Some("java/lang/IndexOutOfBoundsException")
+        }
+        "java/lang/NumberFormatException" | "java/lang/IllegalThreadStateException" => {
+            Some("java/lang/IllegalArgumentException")
+        }
+        _ => None,
+    }
+}
+
+/// Check the values passed to a method against the parameter types in its descriptor.
+fn check_arguments(descriptor: &str, arguments: &[Primitive]) -> Result<(), String> {
+    let (parameters, _) = parse_descriptor(descriptor)?;
+
+    for (index, (parameter, argument)) in parameters.iter().zip(arguments).enumerate() {
+        if !parameter.accepts(argument) {
+            return Err(format!(
+                "Argument {} of {} should be {} but was {:?}",
+                index, descriptor, parameter, argument
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the class of the calling frame may invoke a method declared by the passed class.
+fn check_method_access(
+    caller: &StackFrame,
+    class_name: &str,
+    signature: &str,
+    method: &Method,
+) -> Result<(), String> {
+    if method.is_private() && caller.class_name != class_name {
+        return Err(format!(
+            "java/lang/IllegalAccessError: {} cannot access private method {}.{}",
+            caller.class_name, class_name, signature
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check that a method has code to run. Abstract methods have to be implemented by a subclass,
+/// and native methods can't be run as only bytecode is interpreted.
+fn check_method_code(class_name: &str, signature: &str, method: &Method) -> Result<(), String> {
+    if method.is_abstract() {
+        return Err(format!(
+            "java/lang/AbstractMethodError: {}.{}",
+            class_name, signature
+        ));
+    }
+
+    if method.is_native() {
+        return Err(format!(
+            "java/lang/UnsatisfiedLinkError: {}.{}",
+            class_name, signature
+        ));
+    }
+
+    Ok(())
+}
+
+/// Build an array of the given class, e.g. `[[I`, with an element count for each of the outermost
+/// dimensions, adding it and its nested arrays to the passed objects. The objects will be
+/// allocated from the passed reference onwards. Dimensions beyond the passed counts are left as
+/// null references. The counts must not be negative.
+fn build_multi_array(
+    class_name: &str,
+    counts: &[usize],
+    objects: &mut Vec<Object>,
+    first: usize,
+) -> Primitive {
+    let component = &class_name[1..];
+    let elements = if counts.len() == 1 {
+        vec![Primitive::default_for_descriptor(component); counts[0]]
+    } else {
+        (0..counts[0])
+            .map(|_| build_multi_array(component, &counts[1..], objects, first))
+            .collect()
+    };
+
+    objects.push(Object::new_array(class_name.to_string(), elements));
+    Primitive::Reference(first + objects.len() - 1)
+}
+
+/// The name of the class of arrays whose elements are of the passed class, which is itself an
+/// array descriptor for nested arrays.
+pub fn array_class_name(element_class: &str) -> String {
+    if element_class.starts_with('[') {
+        format!("[{}", element_class)
+    } else {
+        format!("[L{};", element_class)
+    }
+}
+
+/// Render a value the way `println` would, resolving references to strings on the heap.
+pub fn pretty_print(value: &Primitive, heap: &[Object]) -> String {
+    if let Primitive::Reference(reference) = value {
+        if let Some(text) = heap.get(*reference).and_then(|object| object.as_string()) {
+            return text.to_string();
+        }
+    }
+
+    value.pretty_print()
+}
+
+/// A function which is called with the current stack frame before each instruction is executed.
+pub type TraceFn = dyn FnMut(&StackFrame, &Instruction);
+
+pub struct TraceHook(pub Box<TraceFn>);
+
+impl fmt::Debug for TraceHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TraceHook")
+    }
+}
+
+/// The state of the JVM after executing an instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutcome {
+    Running,
+    /// The program has finished, with the exit code passed to `System.exit` or 0.
+    Finished(i32),
+    /// Execution is paused before the instruction at a breakpoint.
+    Breakpoint,
 }
 
 #[derive(Debug)]
@@ -82,20 +561,114 @@ pub struct Jvm {
     pub heap: Vec<Object>,
     pub stack_frames: Vec<StackFrame>,
     pub stdout: String,
+    /// The number of instructions executed so far.
+    pub instructions_executed: usize,
+    /// Methods and instruction indices at which execution should pause.
+    pub breakpoints: Vec<(Rc<Method>, usize)>,
+    /// The command-line arguments passed to main.
+    pub args: Vec<String>,
+    /// The status passed to `System.exit`, if it has been called.
+    pub exit_code: Option<i32>,
+    /// The number of objects allocated after which the garbage collector runs again.
+    pub gc_threshold: usize,
+    /// The size of the heap after the last garbage collection.
+    pub heap_after_collection: usize,
+    /// The number of cells allocated on the heap, as counted by `Object::size`.
+    pub heap_size: usize,
+    /// The number of cells the heap may hold before allocations fail with an OutOfMemoryError.
+    pub max_heap_size: usize,
+    /// The number of objects allocated so far, which numbers the identity hash of each new one.
+    pub objects_allocated: usize,
+    /// The id of the running thread, which is 0 for the main thread.
+    pub thread_id: usize,
+    /// The threads waiting for their turn, in the order they will run in.
+    pub threads: Vec<Thread>,
+    /// The number of threads started so far, which is also the id of the latest one.
+    pub threads_started: usize,
+    /// The number of instructions a thread may execute before the next thread gets its turn.
+    pub time_slice: usize,
+    /// The value of `instructions_executed` when the running thread's turn started.
+    pub turn_started_at: usize,
+    /// Whether the running thread is blocked and should hand over to the next thread.
+    pub yield_requested: bool,
+    /// The time `System.currentTimeMillis` returns instead of the wall-clock time, so that runs
+    /// can be reproduced.
+    pub fixed_clock: Option<i64>,
+    /// Called before each instruction is executed, for tracing execution.
+    pub trace: Option<TraceHook>,
+    /// How many times each kind of instruction has been executed, once profiling is enabled.
+    pub instruction_counts: Option<HashMap<&'static str, u64>>,
+    /// Whether loads and stores of locals check that the value has the type the instruction
+    /// declares, to catch mistyped bytecode.
+    pub strict: bool,
+    /// Whether `assert` statements are checked, as they are when java is run with `-ea`.
+    pub assertions: bool,
+}
+
+/// A copy of the mutable state of a JVM, which can be restored to return execution to the point
+/// the snapshot was taken at. Classes and their methods never change, so only their static state
+/// is kept.
+#[derive(Debug, Clone)]
+pub struct JvmSnapshot {
+    pub heap: Vec<Object>,
+    pub stack_frames: Vec<StackFrame>,
+    pub thread_id: usize,
+    pub threads: Vec<Thread>,
+    pub threads_started: usize,
+    pub objects_allocated: usize,
+    /// The static fields of each class, keyed by class name.
+    pub static_fields: HashMap<String, HashMap<String, Primitive>>,
+    /// The classes whose static initializers have been started.
+    pub initialized_classes: Vec<String>,
+    pub stdout: String,
+    pub instructions_executed: usize,
+    pub exit_code: Option<i32>,
 }
 
 impl Jvm {
     pub fn new(classes: Vec<Class>) -> Jvm {
-        let class_area = classes
+        let mut class_area = classes
             .into_iter()
             .map(|c| (c.name.clone(), c))
             .collect::<HashMap<String, Class>>();
 
+        class_area
+            .entry(String::from("java/lang/Object"))
+            .or_insert_with(Class::object);
+
         Jvm {
             class_area,
             heap: Vec::new(),
             stack_frames: Vec::new(),
             stdout: String::new(),
+            instructions_executed: 0,
+            breakpoints: Vec::new(),
+            args: Vec::new(),
+            exit_code: None,
+            gc_threshold: 10_000,
+            heap_after_collection: 0,
+            heap_size: 0,
+            max_heap_size: 1 << 24,
+            objects_allocated: 0,
+            thread_id: 0,
+            threads: Vec::new(),
+            threads_started: 0,
+            time_slice: 100,
+            turn_started_at: 0,
+            yield_requested: false,
+            fixed_clock: None,
+            trace: None,
+            instruction_counts: None,
+            strict: false,
+            assertions: false,
+        }
+    }
+
+    /// Create a JVM which passes the given command-line arguments to main.
+    pub fn with_args(classes: Vec<Class>, args: Vec<String>) -> Jvm {
+        Jvm {
+            args,
+            ..Jvm::new(classes)
         }
     }
 
@@ -114,52 +687,846 @@ impl Jvm {
         trace
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
-        // Find the main method and push it onto the stack for execution
-        for class in self.class_area.values() {
-            if class.methods.contains_key("main([Ljava/lang/String;)V") {
-                let main_method = match class.methods.get("main([Ljava/lang/String;)V") {
-                    Some(m) => m,
-                    None => return Err("Could not find main method".to_string()),
-                };
+    pub fn run(&mut self) -> Result<StepOutcome, String> {
+        self.start()?;
+        self.execute(usize::MAX, false)
+    }
 
-                let stack_frame = StackFrame {
-                    pc: 0,
-                    locals: Vec::new(),
-                    arrays: Vec::new(),
-                    stack: Vec::new(),
-                    method: main_method.clone(),
-                    class_name: class.name.clone(),
+    /// Run the main method of the given class, for programs where several classes declare one.
+    #[allow(dead_code)]
+    pub fn run_class(&mut self, main_class: &str) -> Result<StepOutcome, String> {
+        self.start_class(main_class)?;
+        self.execute(usize::MAX, false)
+    }
+
+    /// Run the program like `run`, but give up once `max_steps` instructions have been executed.
+    /// The stack frames are left untouched when the limit is hit, so they can still be inspected.
+    #[allow(dead_code)]
+    pub fn run_with_limit(&mut self, max_steps: usize) -> Result<StepOutcome, String> {
+        self.start()?;
+        self.execute(max_steps, false)
+    }
+
+    /// Continue execution after stopping at a breakpoint.
+    #[allow(dead_code)]
+    pub fn resume(&mut self) -> Result<StepOutcome, String> {
+        self.execute(usize::MAX, true)
+    }
+
+    /// Call a static method with the given arguments and run it to completion, returning the
+    /// value it returns, if any.
+    #[allow(dead_code)]
+    pub fn invoke_static(
+        &mut self,
+        class_name: &str,
+        method_signature: &str,
+        args: Vec<Primitive>,
+    ) -> Result<Option<Primitive>, String> {
+        let method = match self
+            .class_area
+            .get(class_name)
+            .and_then(|class| class.methods.get(method_signature))
+        {
+            Some(method) => Rc::clone(method),
+            None => {
+                return Err(format!(
+                    "Could not find method {}.{}",
+                    class_name, method_signature
+                ))
+            }
+        };
+
+        if !method.is_static() {
+            return Err(format!(
+                "Method {}.{} is not static",
+                class_name, method_signature
+            ));
+        }
+
+        check_method_code(class_name, method_signature, &method)?;
+
+        if let Some(start) = method_signature.find('(') {
+            check_arguments(&method_signature[start..], &args)?;
+        }
+
+        // The method returns into an empty frame, which then holds the return value
+        let start_depth = self.stack_frames.len();
+        let caller_depth = start_depth + 1;
+
+        self.stack_frames.push(StackFrame {
+            pc: 0,
+            locals: Vec::new(),
+            stack: Vec::new(),
+            method: Rc::new(Method {
+                access_flags: MethodFlags::Static as u16,
+                instructions: Vec::new(),
+                stack_map: Vec::new(),
+                exception_table: Vec::new(),
+            }),
+            class_name: class_name.to_string(),
+        });
+
+        self.stack_frames.push(StackFrame {
+            pc: 0,
+            locals: to_local_slots(args),
+            stack: Vec::new(),
+            method,
+            class_name: class_name.to_string(),
+        });
+
+        let thread_id = self.thread_id;
+        let result = self
+            .initialize_class(class_name)
+            .and_then(|_| self.run_until_returned(thread_id, caller_depth));
+
+        // A failed invocation leaves the invoking thread's stack frames as they were before it
+        if let Err(err) = result {
+            if self.thread_id == thread_id {
+                self.stack_frames.truncate(start_depth);
+            } else if let Some(thread) = self.threads.iter_mut().find(|t| t.id == thread_id) {
+                thread.stack_frames.truncate(start_depth);
+            }
+
+            return Err(err);
+        }
+
+        Ok(self
+            .stack_frames
+            .pop()
+            .and_then(|mut caller| caller.stack.pop()))
+    }
+
+    /// Run until the passed thread has returned to the frame at the passed depth. Other threads
+    /// may take turns in the meantime.
+    fn run_until_returned(&mut self, thread_id: usize, caller_depth: usize) -> Result<(), String> {
+        while self.thread_id != thread_id || self.stack_frames.len() > caller_depth {
+            self.schedule();
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Call the passed function with the current stack frame before each instruction is executed.
+    #[allow(dead_code)]
+    pub fn set_trace(&mut self, hook: impl FnMut(&StackFrame, &Instruction) + 'static) {
+        self.trace = Some(TraceHook(Box::new(hook)));
+    }
+
+    /// Start counting how many times each kind of instruction is executed.
+    #[allow(dead_code)]
+    pub fn enable_profiling(&mut self) {
+        self.instruction_counts.get_or_insert_with(HashMap::new);
+    }
+
+    /// The number of times each kind of instruction has been executed since profiling was
+    /// enabled, most frequent first.
+    #[allow(dead_code)]
+    pub fn profile(&self) -> Vec<(&'static str, u64)> {
+        let mut profile = self
+            .instruction_counts
+            .iter()
+            .flatten()
+            .map(|(name, count)| (*name, *count))
+            .collect::<Vec<_>>();
+
+        profile
+            .sort_by(|(name1, count1), (name2, count2)| count2.cmp(count1).then(name1.cmp(name2)));
+        profile
+    }
+
+    /// Pause execution whenever the instruction at `pc` of the given method is reached.
+    #[allow(dead_code)]
+    pub fn set_breakpoint(
+        &mut self,
+        class_name: &str,
+        method_signature: &str,
+        pc: usize,
+    ) -> Result<(), String> {
+        let method = match self
+            .class_area
+            .get(class_name)
+            .and_then(|class| class.methods.get(method_signature))
+        {
+            Some(method) => Rc::clone(method),
+            None => {
+                return Err(format!(
+                    "Could not find method {}.{}",
+                    class_name, method_signature
+                ))
+            }
+        };
+
+        if pc >= method.instructions.len() {
+            return Err(format!(
+                "No instruction at pc {} of {}.{}",
+                pc, class_name, method_signature
+            ));
+        }
+
+        self.breakpoints.push((method, pc));
+        Ok(())
+    }
+
+    /// Whether the next instruction to be executed is a breakpoint, either set with
+    /// `set_breakpoint` or a breakpoint instruction in the bytecode.
+    fn at_breakpoint(&self) -> bool {
+        let sf = match self.current_frame() {
+            Some(sf) => sf,
+            None => return false,
+        };
+
+        matches!(
+            sf.method.instructions.get(sf.pc),
+            Some(Instruction::Breakpoint)
+        ) || self
+            .breakpoints
+            .iter()
+            .any(|(method, pc)| *pc == sf.pc && Rc::ptr_eq(method, &sf.method))
+    }
+
+    /// Step until every thread finishes, a breakpoint is reached, or `max_steps` instructions
+    /// have been executed. When resuming, the breakpoint execution is currently paused at is
+    /// skipped.
+    fn execute(&mut self, max_steps: usize, resuming: bool) -> Result<StepOutcome, String> {
+        let limit = self.instructions_executed.saturating_add(max_steps);
+        let mut skip_breakpoint = resuming;
+
+        while self.schedule() {
+            if !skip_breakpoint && self.at_breakpoint() {
+                return Ok(StepOutcome::Breakpoint);
+            }
+            skip_breakpoint = false;
+
+            if self.instructions_executed >= limit {
+                return Err(format!(
+                    "Step limit exceeded after {} instructions",
+                    max_steps
+                ));
+            }
+
+            self.step()?;
+        }
+
+        Ok(StepOutcome::Finished(self.exit_code.unwrap_or(0)))
+    }
+
+    /// Capture the current state of execution.
+    #[allow(dead_code)]
+    pub fn snapshot(&self) -> JvmSnapshot {
+        JvmSnapshot {
+            heap: self.heap.clone(),
+            stack_frames: self.stack_frames.clone(),
+            thread_id: self.thread_id,
+            threads: self.threads.clone(),
+            threads_started: self.threads_started,
+            objects_allocated: self.objects_allocated,
+            static_fields: self
+                .class_area
+                .iter()
+                .map(|(name, class)| (name.clone(), class.static_fields.clone()))
+                .collect(),
+            initialized_classes: self
+                .class_area
+                .values()
+                .filter(|class| class.initialized)
+                .map(|class| class.name.clone())
+                .collect(),
+            stdout: self.stdout.clone(),
+            instructions_executed: self.instructions_executed,
+            exit_code: self.exit_code,
+        }
+    }
+
+    /// Return execution to the point at which the snapshot was taken.
+    #[allow(dead_code)]
+    pub fn restore(&mut self, snapshot: JvmSnapshot) {
+        let mut static_fields = snapshot.static_fields;
+
+        for (name, class) in self.class_area.iter_mut() {
+            class.static_fields = static_fields.remove(name).unwrap_or_default();
+            class.initialized = snapshot.initialized_classes.contains(name);
+        }
+
+        self.heap = snapshot.heap;
+        self.heap_size = self.heap.iter().map(Object::size).sum();
+        self.stack_frames = snapshot.stack_frames;
+        self.thread_id = snapshot.thread_id;
+        self.threads = snapshot.threads;
+        self.threads_started = snapshot.threads_started;
+        self.objects_allocated = snapshot.objects_allocated;
+        self.turn_started_at = snapshot.instructions_executed;
+        self.yield_requested = false;
+        self.stdout = snapshot.stdout;
+        self.instructions_executed = snapshot.instructions_executed;
+        self.exit_code = snapshot.exit_code;
+    }
+
+    /// Push the main method and static initializers onto the stack without executing anything.
+    /// `run` does this itself, but it must be called before driving the JVM with `step_once`.
+    /// Exactly one class must declare a main method, otherwise use `start_class`.
+    pub fn start(&mut self) -> Result<(), String> {
+        let mut main_classes = self
+            .class_area
+            .values()
+            .filter(|class| class.methods.contains_key("main([Ljava/lang/String;)V"))
+            .map(|class| class.name.clone())
+            .collect::<Vec<_>>();
+
+        match main_classes.len() {
+            0 => Err(String::from("Could not find main method")),
+            1 => self.start_class(&main_classes[0]),
+            _ => {
+                main_classes.sort();
+                Err(format!(
+                    "Multiple classes declare a main method, choose one of: {}",
+                    main_classes.join(", ")
+                ))
+            }
+        }
+    }
+
+    /// Like `start`, but for the main method of the given class.
+    pub fn start_class(&mut self, main_class: &str) -> Result<(), String> {
+        let main_method = match self
+            .class_area
+            .get(main_class)
+            .and_then(|class| class.methods.get("main([Ljava/lang/String;)V"))
+        {
+            Some(method) => Rc::clone(method),
+            None => return Err(format!("Could not find main method in {}", main_class)),
+        };
+
+        // Store the command-line arguments as a string array on the heap, after the strings
+        let args = self.args.clone();
+        let arg_count = args.len();
+        let first = self.allocate_with(2 * arg_count + 1, |first| {
+            let mut objects = args.into_iter().map(Object::new_string).collect::<Vec<_>>();
+            objects.push(Object::new_array(
+                String::from("[Ljava/lang/String;"),
+                (first..first + arg_count)
+                    .map(Primitive::Reference)
+                    .collect(),
+            ));
+            objects
+        })?;
+        let args_ref = Primitive::Reference(first + arg_count);
+
+        self.stack_frames.push(StackFrame {
+            pc: 0,
+            locals: vec![args_ref],
+            stack: Vec::new(),
+            method: main_method,
+            class_name: main_class.to_string(),
+        });
+
+        // The class declaring main is initialized before it runs, and every other class on its
+        // first use
+        self.initialize_class(main_class)?;
+
+        Ok(())
+    }
+
+    /// Find the class declaring a static field, which is the passed class, one of its interfaces
+    /// or one of its superclasses, searched in that order.
+    fn find_static_field_class<'a>(
+        class_area: &'a HashMap<String, Class>,
+        class_name: &str,
+        field_name: &str,
+    ) -> Option<&'a Class> {
+        let class = class_area.get(class_name)?;
+
+        if class.static_fields.contains_key(field_name) {
+            return Some(class);
+        }
+
+        class
+            .interfaces
+            .iter()
+            .chain(class.super_class.iter())
+            .find_map(|parent| Jvm::find_static_field_class(class_area, parent, field_name))
+    }
+
+    /// Check that a write to a field is allowed. A final field can only be assigned by the passed
+    /// initializer, `<init>` or `<clinit>`, of the class declaring it.
+    fn check_field_write(
+        class_area: &HashMap<String, Class>,
+        curr_sf: &StackFrame,
+        class_name: &str,
+        field_name: &str,
+        initializer: &str,
+    ) -> Result<(), String> {
+        let mut declaring_class = class_area.get(class_name);
+
+        while let Some(class) = declaring_class {
+            if class.final_fields.contains(field_name) {
+                break;
+            }
+
+            declaring_class = class
+                .super_class
+                .as_ref()
+                .and_then(|super_class| class_area.get(super_class));
+        }
+
+        let declaring_class = match declaring_class {
+            Some(class) => class,
+            None => return Ok(()),
+        };
+
+        let in_initializer = curr_sf.class_name == declaring_class.name
+            && declaring_class.methods.iter().any(|(signature, method)| {
+                signature.starts_with(initializer) && Rc::ptr_eq(method, &curr_sf.method)
+            });
+
+        if in_initializer {
+            Ok(())
+        } else {
+            Err(format!(
+                "java/lang/IllegalAccessError: Update to final field {}.{} attempted outside of {}",
+                declaring_class.name, field_name, initializer
+            ))
+        }
+    }
+
+    /// Run the static initializers of a class and its superclasses if this is the first time the
+    /// class is used. Returns whether any initializers were pushed, in which case the current
+    /// instruction has to be executed again once they have returned.
+    fn initialize_class(&mut self, class_name: &str) -> Result<bool, String> {
+        let class = match self.class_area.get_mut(class_name) {
+            Some(class) if !class.initialized => class,
+            _ => return Ok(false),
+        };
+
+        // Mark the class first so that uses from within its own initializer don't recurse
+        class.initialized = true;
+
+        let static_initializer = class.methods.get("<clinit>()V").cloned();
+        let super_class = class.super_class.clone();
+        let string_constants = class.string_constants.clone();
+        let mut pushed = false;
+
+        // Each string is held by its field straight away, so that collecting garbage to allocate
+        // the next one keeps it
+        for (field_name, text) in string_constants {
+            let reference = self.allocate(Object::new_string(text))?;
+            if let Some(class) = self.class_area.get_mut(class_name) {
+                class
+                    .static_fields
+                    .insert(field_name, Primitive::Reference(reference));
+            }
+        }
+
+        if let Some(method) = static_initializer {
+            self.stack_frames.push(StackFrame {
+                pc: 0,
+                locals: Vec::new(),
+                stack: Vec::new(),
+                method,
+                class_name: class_name.to_string(),
+            });
+            pushed = true;
+        }
+
+        // Superclasses are pushed afterwards so that their initializers run first
+        if let Some(super_class) = super_class {
+            pushed |= self.initialize_class(&super_class)?;
+        }
+
+        Ok(pushed)
+    }
+
+    /// Execute a single instruction and report whether the program has finished.
+    #[allow(dead_code)]
+    pub fn step_once(&mut self) -> Result<StepOutcome, String> {
+        if self.schedule() {
+            self.step()?;
+        }
+
+        Ok(if !self.schedule() {
+            StepOutcome::Finished(self.exit_code.unwrap_or(0))
+        } else {
+            StepOutcome::Running
+        })
+    }
+
+    /// The stack frame of the method currently being executed.
+    pub fn current_frame(&self) -> Option<&StackFrame> {
+        self.stack_frames.last()
+    }
+
+    /// The index of the next instruction to be executed in the current method.
+    #[allow(dead_code)]
+    pub fn pc(&self) -> Option<usize> {
+        self.current_frame().map(|sf| sf.pc)
+    }
+
+    /// The value on top of the current frame's operand stack.
+    #[allow(dead_code)]
+    pub fn peek_stack(&self) -> Option<&Primitive> {
+        self.current_frame().and_then(|sf| sf.stack.last())
+    }
+
+    /// The value of a static field, if the class is loaded and the field has been set.
+    #[allow(dead_code)]
+    pub fn get_static_field(&self, class_name: &str, name: &str) -> Option<Primitive> {
+        self.class_area
+            .get(class_name)
+            .and_then(|class| class.static_fields.get(name))
+            .cloned()
+    }
+
+    /// The value of a field of the object at the passed reference, if it has been set.
+    #[allow(dead_code)]
+    pub fn get_object_field(&self, reference: usize, name: &str) -> Option<Primitive> {
+        self.heap
+            .get(reference)
+            .and_then(|object| object.fields.get(name))
+            .cloned()
+    }
+
+    /// Make room on the heap for the passed number of cells, collecting garbage first if they
+    /// wouldn't fit. Every reference must be held by a stack frame or static field when this is
+    /// called, so that the collector doesn't free objects which are still in use.
+    fn reserve_heap(&mut self, cells: usize) -> Result<(), String> {
+        if self.heap_size.saturating_add(cells) > self.max_heap_size {
+            self.collect_garbage();
+        }
+
+        if self.heap_size.saturating_add(cells) > self.max_heap_size {
+            return Err(String::from("java/lang/OutOfMemoryError: Java heap space"));
+        }
+
+        self.heap_size += cells;
+        Ok(())
+    }
+
+    /// Allocate objects taking up the passed number of heap cells, collecting garbage first if
+    /// they wouldn't fit. The objects are only built once room has been made, and are passed the
+    /// reference the first of them gets so that they can refer to each other. Returns that
+    /// reference. Every reference must be held by a stack frame or static field when this is
+    /// called, like for `reserve_heap`.
+    pub fn allocate_with(
+        &mut self,
+        cells: usize,
+        build: impl FnOnce(usize) -> Vec<Object>,
+    ) -> Result<usize, String> {
+        self.reserve_heap(cells)?;
+
+        let first = self.heap.len();
+        for mut object in build(first) {
+            object.identity_hash = self.objects_allocated as i32;
+            self.objects_allocated += 1;
+            self.heap.push(object);
+        }
+        Ok(first)
+    }
+
+    /// Allocate a single object on the heap, returning its reference.
+    pub fn allocate(&mut self, object: Object) -> Result<usize, String> {
+        let cells = object.size();
+        self.allocate_with(cells, |_| vec![object])
+    }
+
+    /// The `java/lang/Class` object for the class with the passed internal name, which holds the
+    /// class's name in dotted form as its "name" field. Objects which are still reachable are
+    /// reused, so that loading the same class constant twice gives the same object.
+    pub fn class_object(&mut self, class_name: &str) -> Result<usize, String> {
+        let name = class_name.replace('/', ".");
+
+        let existing = self.heap.iter().position(|object| {
+            object.class_name == "java/lang/Class"
+                && match object.fields.get("name") {
+                    Some(Primitive::Reference(name_reference)) => {
+                        self.heap
+                            .get(*name_reference)
+                            .and_then(|name_object| name_object.as_string())
+                            == Some(name.as_str())
+                    }
+                    _ => false,
+                }
+        });
+        if let Some(reference) = existing {
+            return Ok(reference);
+        }
+
+        // The name is allocated first, followed by the class object referring to it
+        let name_reference = self.allocate_with(2, |name_reference| {
+            vec![
+                Object::new_string(name),
+                Object {
+                    class_name: String::from("java/lang/Class"),
+                    fields: IndexMap::from([(
+                        String::from("name"),
+                        Primitive::Reference(name_reference),
+                    )]),
+                    data: ObjectData::None,
+                    monitor_entries: 0,
+                    monitor_owner: 0,
+                    identity_hash: 0,
+                },
+            ]
+        })?;
+        Ok(name_reference + 1)
+    }
+
+    /// Find the implementation of a method for objects of a class, searching its superclasses and
+    /// then the default methods of its interfaces. Returns the declaring class with the method.
+    pub fn find_method(&self, class_name: &str, signature: &str) -> Option<(String, Rc<Method>)> {
+        let class = self.class_area.get(class_name)?;
+
+        // Abstract methods are only stubs for the implementations in subclasses
+        if let Some(method) = class.methods.get(signature).filter(|m| !m.is_abstract()) {
+            return Some((class.name.clone(), Rc::clone(method)));
+        }
+
+        class
+            .super_class
+            .iter()
+            .chain(class.interfaces.iter())
+            .find_map(|parent| self.find_method(parent, signature))
+    }
+
+    /// The built-in class whose methods a class inherits, which is the class itself if it isn't
+    /// loaded, or otherwise its closest superclass which isn't. Only the constructor of
+    /// `java/lang/Object` is loaded, so its other methods are built-ins too.
+    fn builtin_ancestor(&self, class_name: &str) -> String {
+        match self.class_area.get(class_name) {
+            Some(class) if class_name != "java/lang/Object" => match &class.super_class {
+                Some(super_class) => self.builtin_ancestor(super_class),
+                None => String::from("java/lang/Object"),
+            },
+            _ => class_name.to_string(),
+        }
+    }
+
+    /// The fields of a new object of the class, holding the default values of those declared by it
+    /// and its superclasses. Built-in classes declare no fields.
+    fn default_fields(&self, class_name: &str) -> IndexMap<String, Primitive> {
+        let class = match self.class_area.get(class_name) {
+            Some(class) => class,
+            None => return IndexMap::new(),
+        };
+
+        let mut fields = match &class.super_class {
+            Some(super_class) => self.default_fields(super_class),
+            None => IndexMap::new(),
+        };
+        fields.extend(class.instance_fields.clone());
+
+        fields
+    }
+
+    /// Whether objects of the first class are instances of the second, which is the case when it
+    /// is the same class, one of its superclasses or one of the interfaces it implements.
+    pub fn is_subclass_of(&self, class_name: &str, target: &str) -> bool {
+        if class_name == target || target == "java/lang/Object" {
+            return true;
+        }
+
+        match self.class_area.get(class_name) {
+            Some(class) => class
+                .super_class
+                .iter()
+                .chain(class.interfaces.iter())
+                .any(|parent| self.is_subclass_of(parent, target)),
+            None => match builtin_super_class(class_name) {
+                Some(parent) => self.is_subclass_of(parent, target),
+                None => false,
+            },
+        }
+    }
+
+    /// Unwind the stack frames to the innermost exception handler which catches the passed
+    /// exception, clearing its frame's stack and pushing the exception for it. Uncaught exceptions
+    /// fail with the name of their class and their message if they have one, leaving the stack
+    /// frames as they were.
+    fn throw_exception(&mut self, reference: usize) -> Result<(), String> {
+        let class_name = match self.heap.get(reference) {
+            Some(exception) => exception.class_name.clone(),
+            None => return Err(format!("Reference {} not found on heap", reference)),
+        };
+
+        for depth in (0..self.stack_frames.len()).rev() {
+            let sf = &self.stack_frames[depth];
+
+            // The placeholder frame of a nested invocation can't catch anything, and the frames
+            // below it have to wait for that invocation to return
+            if sf.method.instructions.is_empty() {
+                break;
+            }
+
+            // Callers have already moved past the invoke instruction that threw
+            let pc = if depth + 1 == self.stack_frames.len() {
+                sf.pc
+            } else {
+                sf.pc - 1
+            };
+
+            let mut handler_pc = None;
+
+            for entry in &sf.method.exception_table {
+                if !(entry.start_pc..entry.end_pc).contains(&pc) {
+                    continue;
+                }
+
+                let catches = match entry.catch_type {
+                    0 => true,
+                    catch_type => {
+                        let catch_class = self
+                            .class_area
+                            .get(&sf.class_name)
+                            .unwrap()
+                            .constant_pool
+                            .class_parser(&(catch_type as usize))?;
+                        self.is_subclass_of(&class_name, &catch_class)
+                    }
                 };
 
-                self.stack_frames.push(stack_frame);
+                if catches {
+                    handler_pc = Some(entry.handler_pc);
+                    break;
+                }
             }
+
+            if let Some(handler_pc) = handler_pc {
+                self.stack_frames.truncate(depth + 1);
+
+                let sf = &mut self.stack_frames[depth];
+                sf.stack.clear();
+                sf.stack.push(Primitive::Reference(reference));
+                sf.pc = handler_pc;
+                return Ok(());
+            }
+        }
+
+        match self.heap[reference].fields.get("message") {
+            Some(message) => Err(format!(
+                "{}: {}",
+                class_name,
+                pretty_print(message, &self.heap)
+            )),
+            None => Err(class_name),
         }
+    }
 
-        // Perform static initialization for all classes
-        for class in self.class_area.values() {
-            if class.methods.contains_key("<clinit>()V") {
-                let method = class.methods.get("<clinit>()V").unwrap().clone();
+    /// Call the method behind a lambda, passing its captured values followed by the arguments.
+    pub fn invoke_lambda(&mut self, lambda: Lambda, args: Vec<Primitive>) -> Result<(), String> {
+        let mut locals = lambda.captured;
+        locals.extend(args);
+
+        // Virtual (5) and interface (9) method handles dispatch on the class of the receiver
+        let class_name = match (lambda.reference_kind, locals.first()) {
+            (5 | 9, Some(Primitive::Reference(receiver))) => match self.heap.get(*receiver) {
+                Some(object) => object.class_name.clone(),
+                None => return Err(format!("Reference {} not found on heap", receiver)),
+            },
+            (5 | 6 | 7 | 9, _) => lambda.class_name.clone(),
+            (reference_kind, _) => {
+                return Err(format!(
+                    "Unsupported lambda method handle kind {}",
+                    reference_kind
+                ))
+            }
+        };
 
+        let signature = format!("{}{}", lambda.name, lambda.descriptor);
+
+        match self.find_method(&class_name, &signature) {
+            Some((class_name, method)) => {
                 self.stack_frames.push(StackFrame {
                     pc: 0,
-                    locals: Vec::new(),
-                    arrays: Vec::new(),
-                    stack: Vec::new(),
+                    locals: to_local_slots(locals),
+                    stack: vec![],
                     method,
-                    class_name: class.name.clone(),
+                    class_name,
                 });
+                Ok(())
+            }
+            None => {
+                // Method references to classes that aren't loaded, such as Math::abs, are
+                // built-ins which take their arguments from the stack
+                match self.stack_frames.last_mut() {
+                    Some(sf) => sf.stack.extend(locals),
+                    None => return Err(String::from("No stack frames")),
+                }
+                self.invoke_builtin(&lambda.class_name, &lambda.name, &lambda.descriptor)
             }
         }
+    }
 
-        while !self.stack_frames.is_empty() {
-            self.step()?;
+    /// Execute the next instruction. Exceptions the JVM raises while executing it, such as
+    /// `java/lang/ArithmeticException: / by zero`, are thrown from the instruction so that
+    /// handlers can catch them.
+    pub fn step(&mut self) -> Result<(), String> {
+        let depth = self.stack_frames.len();
+        let pc = match self.stack_frames.last() {
+            Some(sf) => sf.pc,
+            None => return Err(String::from("No stack frames")),
+        };
+
+        match self.step_instruction() {
+            Err(err) => self.throw_raised(err, depth, pc),
+            result => result,
         }
+    }
 
-        Ok(())
+    /// Throw an exception described by an error such as `java/lang/NullPointerException` or
+    /// `java/lang/ArithmeticException: / by zero` from the instruction at the passed pc of the
+    /// frame at the passed depth. Errors which don't name a built-in throwable class are returned
+    /// as they are, as is the error when there is no room left for the exception.
+    fn throw_raised(&mut self, err: String, depth: usize, pc: usize) -> Result<(), String> {
+        let (class_name, message) = match err.split_once(": ") {
+            Some((class_name, message)) => (class_name.to_string(), Some(message.to_string())),
+            None => (err.clone(), None),
+        };
+
+        if builtin_super_class(&class_name).is_none()
+            || !self.is_subclass_of(&class_name, "java/lang/Throwable")
+            || self.stack_frames.len() < depth
+        {
+            return Err(err);
+        }
+
+        // Frames the instruction pushed before failing are dropped, and the instruction is
+        // thrown from as if it had not been executed yet
+        self.stack_frames.truncate(depth);
+        self.stack_frames[depth - 1].pc = pc;
+
+        // The message is allocated first, followed by the exception referring to it
+        let cells = if message.is_some() { 2 } else { 1 };
+        let allocated = self.allocate_with(cells, |first| {
+            let mut objects = Vec::new();
+            let mut fields = IndexMap::new();
+
+            if let Some(message) = message {
+                objects.push(Object::new_string(message));
+                fields.insert(String::from("message"), Primitive::Reference(first));
+            }
+
+            objects.push(Object {
+                class_name,
+                fields,
+                data: ObjectData::None,
+                monitor_entries: 0,
+                monitor_owner: 0,
+                identity_hash: 0,
+            });
+            objects
+        });
+
+        match allocated {
+            Ok(first) => self.throw_exception(first + cells - 1),
+            Err(_) => Err(err),
+        }
     }
 
-    pub fn step(&mut self) -> Result<(), String> {
+    fn step_instruction(&mut self) -> Result<(), String> {
+        // Between instructions every live reference is held by a stack frame or static field
+        if self.needs_collection() {
+            self.collect_garbage();
+        }
+
         let curr_sf = match self.stack_frames.last_mut() {
             Some(sf) => sf,
             None => return Err(String::from("No stack frames")),
@@ -169,68 +1536,178 @@ impl Jvm {
             None => return Err(String::from("No instruction at current pc")),
         };
 
-        // let indent = " ".repeat(current_stack_frame_index * 2);
-        // println!("{}stack: {:?}", indent, curr_sf.stack);
-        // println!("{}arrays: {:?}", indent, curr_sf.arrays);
-        // println!("{}locals: {:?}", indent, curr_sf.locals);
-        // println!("{}heap: {:?}", indent, self.heap);
-        // println!("{}{} | {:?}\n", indent, curr_sf.pc, instruction);
+        // Accessing a static member or instantiating a class first runs its static initializers,
+        // after which the instruction is executed again
+        let used_class = match self.class_area.get_mut(&curr_sf.class_name) {
+            Some(class) => match instruction {
+                Instruction::GetStatic(index) | Instruction::PutStatic(index) => {
+                    Some(class.resolve_field_ref(index)?.class_name.clone())
+                }
+                Instruction::InvokeStatic(index) => {
+                    Some(class.resolve_method_ref(index)?.class_name.clone())
+                }
+                Instruction::New(index) => Some(class.constant_pool.class_parser(&index)?),
+                _ => None,
+            },
+            None => None,
+        };
+
+        if let Some(class_name) = used_class {
+            if self.initialize_class(&class_name)? {
+                return Ok(());
+            }
+        }
+
+        let curr_sf = self.stack_frames.last_mut().unwrap();
+        self.instructions_executed += 1;
+
+        if let Some(trace) = &mut self.trace {
+            (trace.0)(curr_sf, &instruction);
+        }
+
+        if let Some(counts) = &mut self.instruction_counts {
+            *counts.entry(instruction.name()).or_insert(0) += 1;
+        }
+
+        match instruction {
+            Instruction::Nop => {}
+            Instruction::AConstNull => curr_sf.stack.push(Primitive::Null),
+            Instruction::Const(value) => curr_sf.stack.push(value),
+            Instruction::LoadConst(index) => {
+                let constant_pool = &self
+                    .class_area
+                    .get(&curr_sf.class_name)
+                    .unwrap()
+                    .constant_pool;
+
+                let value = match constant_pool.get(index - 1) {
+                    Some(ConstantPoolEntry::String(string_index)) => {
+                        let text = match constant_pool.utf8_parser(string_index) {
+                            Ok(text) => text,
+                            Err(err) => {
+                                return Err(format!(
+                                    "Invalid string constant for LoadConst: {}",
+                                    err
+                                ))
+                            }
+                        };
+
+                        let string = self.allocate(Object::new_string(text))?;
+
+                        let curr_sf = self.stack_frames.last_mut().unwrap();
+                        curr_sf.stack.push(Primitive::Reference(string));
+                        curr_sf.pc += 1;
+
+                        return Ok(());
+                    }
+                    Some(ConstantPoolEntry::Class(name_index)) => {
+                        let class_name = constant_pool.utf8_parser(name_index)?;
+                        let class_object = self.class_object(&class_name)?;
+
+                        let curr_sf = self.stack_frames.last_mut().unwrap();
+                        curr_sf.stack.push(Primitive::Reference(class_object));
+                        curr_sf.pc += 1;
+
+                        return Ok(());
+                    }
+                    Some(ConstantPoolEntry::Dynamic(..)) => {
+                        return Err(String::from(
+                            "Dynamically-computed constants are not supported by LoadConst",
+                        ))
+                    }
+                    Some(entry) => entry.get_primitive()?,
+                    None => return Err(String::from("Invalid constant pool index for LoadConst")),
+                };
+
+                curr_sf.stack.push(value);
+            }
+            Instruction::Load(index, type_to_load) => {
+                let value = match curr_sf.locals.get(index) {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("Local variable {} not found", index)),
+                };
 
-        match instruction {
-            Instruction::Nop => {}
-            Instruction::AConstNull => curr_sf.stack.push(Primitive::Null),
-            Instruction::Const(value) => curr_sf.stack.push(value),
-            Instruction::LoadConst(index) => {
-                curr_sf.stack.push(
-                    self.class_area
-                        .get(&curr_sf.class_name)
-                        .unwrap()
-                        .constant_pool
-                        .get(index - 1)
-                        .unwrap()
-                        .get_primitive()?,
-                );
-            }
-            // TODO: Check that the stored or loaded type matches the expected type
-            Instruction::Load(index, _type_to_load) => curr_sf
-                .stack
-                .push(curr_sf.locals.get(index).unwrap().clone()),
+                if self.strict && !type_to_load.accepts(&value) {
+                    return Err(format!(
+                        "Loaded {:?} from local {} where {:?} was expected",
+                        value, index, type_to_load
+                    ));
+                }
+
+                curr_sf.stack.push(value);
+            }
             Instruction::ALoad(_stored_type) => {
                 let index = curr_sf.pop_int()?;
                 let array_ref = curr_sf.pop_ref()?;
 
-                let array = curr_sf.arrays.get(array_ref).expect("array not found");
-                let value = array.get(index as usize).unwrap().clone();
+                let array = match self.heap.get(array_ref).and_then(|o| o.as_array()) {
+                    Some(array) => array,
+                    None => return Err(format!("Reference {} is not an array", array_ref)),
+                };
+                let value = match array.get(index as usize) {
+                    Some(value) if index >= 0 => value.clone(),
+                    _ => {
+                        return Err(format!(
+                            "java/lang/ArrayIndexOutOfBoundsException: Index {} out of bounds for length {}",
+                            index,
+                            array.len()
+                        ))
+                    }
+                };
                 curr_sf.stack.push(value);
             }
-            Instruction::Store(index, _type_to_store) => {
-                if curr_sf.locals.len() <= index {
-                    curr_sf.locals.resize(index + 1, Primitive::Null)
+            Instruction::Store(index, type_to_store) => {
+                let value = curr_sf.pop_primitive()?;
+                if self.strict && !type_to_store.accepts(&value) {
+                    return Err(format!(
+                        "Stored {:?} into local {} where {:?} was expected",
+                        value, index, type_to_store
+                    ));
+                }
+
+                // Longs and doubles also take up the slot after their index
+                let slots = if value.is_wide() { 2 } else { 1 };
+
+                if curr_sf.locals.len() < index + slots {
+                    curr_sf.locals.resize(index + slots, Primitive::Null)
                 };
-                curr_sf.locals[index] = curr_sf.pop_primitive()?;
+                curr_sf.locals[index] = value;
             }
             Instruction::AStore(_stored_type) => {
                 let value = curr_sf.pop_primitive()?;
                 let index = curr_sf.pop_int()?;
                 let array_ref = curr_sf.pop_ref()?;
 
-                let array = curr_sf.arrays.get_mut(array_ref).expect("array not found");
-
-                if array.len() <= index as usize {
-                    array.resize(index as usize + 1, Primitive::Null)
+                let (element_type, array) = match self.heap.get_mut(array_ref).map(|o| &mut o.data)
+                {
+                    Some(ObjectData::Array(element_type, array)) => (element_type, array),
+                    _ => return Err(format!("Reference {} is not an array", array_ref)),
                 };
 
-                array[index as usize] = value;
+                if !element_type.accepts(&value) {
+                    return Err(format!(
+                        "Stored {:?} into an array of {:?}",
+                        value, element_type
+                    ));
+                }
+
+                if index < 0 || array.len() <= index as usize {
+                    return Err(format!(
+                        "java/lang/ArrayIndexOutOfBoundsException: Index {} out of bounds for length {}",
+                        index,
+                        array.len()
+                    ));
+                }
+
+                array[index as usize] = element_type.narrow(value);
             }
             Instruction::Pop => {
                 curr_sf.stack.pop();
             }
             Instruction::Pop2 => {
-                if !curr_sf.pop_primitive()?.is_wide() {
-                    curr_sf.stack.pop();
-                }
+                curr_sf.pop_slots(2)?;
             }
-            // TODO: Dup instructions interact with wide types differently
+            // The dup instructions work on stack slots, so a long or double counts as two values
             Instruction::Dup => {
                 let value = curr_sf.pop_primitive()?;
                 curr_sf.stack.push(value.clone());
@@ -245,43 +1722,30 @@ impl Jvm {
                 curr_sf.stack.push(value2);
             }
             Instruction::DupX2 => {
-                let value3 = curr_sf.pop_primitive()?;
-                let value2 = curr_sf.pop_primitive()?;
-                let value1 = curr_sf.pop_primitive()?;
-                curr_sf.stack.push(value3.clone());
-                curr_sf.stack.push(value1);
-                curr_sf.stack.push(value2);
-                curr_sf.stack.push(value3);
+                let top = curr_sf.pop_slots(1)?;
+                let below = curr_sf.pop_slots(2)?;
+                curr_sf.stack.extend(top.iter().cloned());
+                curr_sf.stack.extend(below);
+                curr_sf.stack.extend(top);
             }
             Instruction::Dup2 => {
-                let value2 = curr_sf.pop_primitive()?;
-                let value1 = curr_sf.pop_primitive()?;
-                curr_sf.stack.push(value1.clone());
-                curr_sf.stack.push(value2.clone());
-                curr_sf.stack.push(value1);
-                curr_sf.stack.push(value2);
+                let top = curr_sf.pop_slots(2)?;
+                curr_sf.stack.extend(top.iter().cloned());
+                curr_sf.stack.extend(top);
             }
             Instruction::Dup2X1 => {
-                let value3 = curr_sf.pop_primitive()?;
-                let value2 = curr_sf.pop_primitive()?;
-                let value1 = curr_sf.pop_primitive()?;
-                curr_sf.stack.push(value2.clone());
-                curr_sf.stack.push(value3.clone());
-                curr_sf.stack.push(value1);
-                curr_sf.stack.push(value2);
-                curr_sf.stack.push(value3);
+                let top = curr_sf.pop_slots(2)?;
+                let below = curr_sf.pop_slots(1)?;
+                curr_sf.stack.extend(top.iter().cloned());
+                curr_sf.stack.extend(below);
+                curr_sf.stack.extend(top);
             }
             Instruction::Dup2X2 => {
-                let value4 = curr_sf.pop_primitive()?;
-                let value3 = curr_sf.pop_primitive()?;
-                let value2 = curr_sf.pop_primitive()?;
-                let value1 = curr_sf.pop_primitive()?;
-                curr_sf.stack.push(value3.clone());
-                curr_sf.stack.push(value4.clone());
-                curr_sf.stack.push(value1);
-                curr_sf.stack.push(value2);
-                curr_sf.stack.push(value3);
-                curr_sf.stack.push(value4);
+                let top = curr_sf.pop_slots(2)?;
+                let below = curr_sf.pop_slots(2)?;
+                curr_sf.stack.extend(top.iter().cloned());
+                curr_sf.stack.extend(below);
+                curr_sf.stack.extend(top);
             }
             Instruction::Swap => {
                 let top = curr_sf.pop_primitive()?;
@@ -302,8 +1766,12 @@ impl Jvm {
             Instruction::Or(operand_type) => curr_sf.math(operand_type, Operator::Or)?,
             Instruction::Xor(operand_type) => curr_sf.math(operand_type, Operator::Xor)?,
             Instruction::IInc(index, constant) => {
-                curr_sf.locals[index] = Primitive::eval2(
-                    curr_sf.locals.get(index).unwrap().clone(),
+                let local = curr_sf
+                    .locals
+                    .get_mut(index)
+                    .ok_or(format!("Local variable {} not found", index))?;
+                *local = Primitive::eval2(
+                    local.clone(),
                     Primitive::Int(constant as i32),
                     Operator::Add,
                 )?;
@@ -318,51 +1786,65 @@ impl Jvm {
                 let second = curr_sf.pop_long()?;
                 let first = curr_sf.pop_long()?;
 
-                let result = match first - second {
-                    0 => 0,
-                    x if x > 0 => 1,
-                    _ => -1,
+                let result = match first.cmp(&second) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
                 };
 
                 curr_sf.stack.push(Primitive::Int(result));
             }
-            // Instruction::FCmpL => {}
-            // Instruction::FCmpG => {}
-            // Instruction::DCmpL => {}
-            // Instruction::DCmpG => {}
-            Instruction::If(branch_offset, comparator) => {
+            Instruction::FCmpL | Instruction::DCmpL => curr_sf.float_compare(-1)?,
+            Instruction::FCmpG | Instruction::DCmpG => curr_sf.float_compare(1)?,
+            Instruction::If(target, comparator) => {
                 if curr_sf.pop_primitive()?.compare_to_zero(comparator)? {
-                    curr_sf.pc += branch_offset;
+                    curr_sf.pc = target;
                     return Ok(());
                 }
             }
-            Instruction::IfICmp(branch_offset, comparator) => {
+            Instruction::IfICmp(target, comparator) => {
                 let value2 = curr_sf.pop_primitive()?;
                 let value1 = curr_sf.pop_primitive()?;
 
                 if value1.integer_compare(value2, comparator)? {
-                    curr_sf.pc += branch_offset;
+                    curr_sf.pc = target;
                     return Ok(());
                 }
             }
-            Instruction::Goto(branch_offset) => {
-                curr_sf.pc += branch_offset;
+            Instruction::Goto(target) => {
+                curr_sf.pc = target;
                 return Ok(());
             }
-            Instruction::Jsr(branch_offset) => {
-                curr_sf.stack.push(Primitive::Reference(curr_sf.pc + 1));
-                curr_sf.pc += branch_offset;
+            Instruction::Jsr(target) => {
+                curr_sf.stack.push(Primitive::ReturnAddress(curr_sf.pc + 1));
+                curr_sf.pc = target;
                 return Ok(());
             }
             Instruction::Ret(index) => {
-                curr_sf.pc = match curr_sf.locals.get(index).unwrap() {
-                    Primitive::Reference(x) => *x,
+                curr_sf.pc = match curr_sf.locals.get(index) {
+                    Some(Primitive::ReturnAddress(x)) => *x,
                     _ => return Err(String::from("Invalid return address")),
                 };
                 return Ok(());
             }
-            // Instruction::TableSwitch(usize, usize, usize) => {}, // TODO: Implement table switch and lookup switch
-            // Instruction::LookupSwitch(usize, usize, usize) => {},
+            Instruction::TableSwitch(default, low, targets) => {
+                let value = curr_sf.pop_int()?;
+
+                curr_sf.pc = match usize::try_from(value as i64 - low as i64) {
+                    Ok(offset) => targets.get(offset).copied().unwrap_or(default),
+                    Err(_) => default,
+                };
+                return Ok(());
+            }
+            Instruction::LookupSwitch(default, pairs) => {
+                let value = curr_sf.pop_int()?;
+
+                curr_sf.pc = match pairs.binary_search_by_key(&value, |(key, _)| *key) {
+                    Ok(index) => pairs[index].1,
+                    Err(_) => default,
+                };
+                return Ok(());
+            }
             Instruction::Return(expected_return_type) => {
                 if matches!(expected_return_type, PrimitiveType::Null) {
                     self.stack_frames.pop();
@@ -389,37 +1871,47 @@ impl Jvm {
                 return Ok(());
             }
             Instruction::GetStatic(index) => {
-                let (class_name, field_name, _field_type) = match self
+                let field_ref = match self
                     .class_area
-                    .get(&curr_sf.class_name)
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
-                    .constant_pool
-                    .field_ref_parser(&index)
+                    .resolve_field_ref(index)
                 {
-                    Some(x) => x,
-                    None => {
-                        return Err(String::from("Invalid static field reference for GetStatic"))
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!(
+                            "Invalid static field reference for GetStatic: {}",
+                            err
+                        ))
                     }
                 };
 
-                if self.class_area.contains_key(&class_name) {
-                    let value = self
-                        .class_area
-                        .get(&class_name)
-                        .unwrap()
-                        .static_fields
-                        .get(&field_name)
-                        .unwrap()
-                        .clone();
-                    curr_sf.stack.push(value);
+                if self.class_area.contains_key(&field_ref.class_name) {
+                    let value = Jvm::find_static_field_class(
+                        &self.class_area,
+                        &field_ref.class_name,
+                        &field_ref.name,
+                    )
+                    .and_then(|class| class.static_fields.get(&field_ref.name));
+
+                    match value {
+                        Some(value) => curr_sf.stack.push(value.clone()),
+                        None => {
+                            return Err(format!(
+                                "java/lang/NoSuchFieldError: {}.{}",
+                                field_ref.class_name, field_ref.name
+                            ))
+                        }
+                    }
                 } else {
                     // TODO: Remove
-                    if class_name == "java/lang/System" {
-                        // Do nothing
+                    if field_ref.class_name == "java/lang/System" {
+                        // The standard streams aren't real objects, so push a placeholder
+                        curr_sf.stack.push(Primitive::Null);
                     } else {
                         return Err(format!(
                             "Unable to find static field {}.{}",
-                            class_name, field_name
+                            field_ref.class_name, field_ref.name
                         ));
                     }
                 }
@@ -427,45 +1919,78 @@ impl Jvm {
             Instruction::PutStatic(index) => {
                 let value = curr_sf.pop_primitive()?;
 
-                let (class_name, field_name, _field_type) = match self
+                let field_ref = match self
                     .class_area
-                    .get(&curr_sf.class_name)
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
-                    .constant_pool
-                    .field_ref_parser(&index)
+                    .resolve_field_ref(index)
                 {
-                    Some(x) => x,
-                    None => {
-                        return Err(String::from("Invalid static field reference for PutStatic"))
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!(
+                            "Invalid static field reference for PutStatic: {}",
+                            err
+                        ))
                     }
                 };
 
-                match self.class_area.get_mut(&class_name) {
-                    Some(ca) => ca.static_fields.insert(field_name, value),
-                    None => return Err(String::from("Unable to find class")),
+                Jvm::check_field_write(
+                    &self.class_area,
+                    curr_sf,
+                    &field_ref.class_name,
+                    &field_ref.name,
+                    "<clinit>",
+                )?;
+
+                if !self.class_area.contains_key(&field_ref.class_name) {
+                    return Err(String::from("Unable to find class"));
+                }
+
+                let declaring_class = match Jvm::find_static_field_class(
+                    &self.class_area,
+                    &field_ref.class_name,
+                    &field_ref.name,
+                ) {
+                    Some(class) => class.name.clone(),
+                    None => {
+                        return Err(format!(
+                            "java/lang/NoSuchFieldError: {}.{}",
+                            field_ref.class_name, field_ref.name
+                        ))
+                    }
                 };
+
+                if let Some(class) = self.class_area.get_mut(&declaring_class) {
+                    class.static_fields.insert(field_ref.name.clone(), value);
+                }
             }
             Instruction::GetField(index) => {
                 let object = curr_sf.pop_ref()?;
 
-                let (_class_name, field_name, _field_type) = match self
+                let field_ref = match self
                     .class_area
-                    .get(&curr_sf.class_name)
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
-                    .constant_pool
-                    .field_ref_parser(&index)
+                    .resolve_field_ref(index)
                 {
-                    Some(x) => x,
-                    None => return Err(String::from("Invalid field reference for GetField")),
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!("Invalid field reference for GetField: {}", err))
+                    }
                 };
 
-                let field = self
-                    .heap
-                    .get(object)
-                    .unwrap()
-                    .fields
-                    .get(&field_name)
-                    .unwrap();
+                let field = match self.heap.get(object) {
+                    Some(object) => match object.fields.get(&field_ref.name) {
+                        Some(field) => field,
+                        None => {
+                            return Err(format!(
+                                "java/lang/NoSuchFieldError: {}.{}",
+                                object.class_name, field_ref.name
+                            ))
+                        }
+                    },
+                    None => return Err(format!("Reference {} not found on heap", object)),
+                };
 
                 curr_sf.stack.push(field.clone());
             }
@@ -473,85 +1998,136 @@ impl Jvm {
                 let value = curr_sf.pop_primitive()?;
                 let reference = curr_sf.pop_ref()?;
 
-                let (_class_name, field_name, _field_type) = match self
+                let field_ref = match self
                     .class_area
-                    .get(&curr_sf.class_name)
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
-                    .constant_pool
-                    .field_ref_parser(&index)
+                    .resolve_field_ref(index)
                 {
-                    Some(x) => x,
-                    None => return Err(String::from("Invalid field reference for PutField")),
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!("Invalid field reference for PutField: {}", err))
+                    }
                 };
 
-                self.heap
-                    .get_mut(reference)
-                    .unwrap()
-                    .fields
-                    .insert(field_name, value);
+                Jvm::check_field_write(
+                    &self.class_area,
+                    curr_sf,
+                    &field_ref.class_name,
+                    &field_ref.name,
+                    "<init>",
+                )?;
+
+                match self.heap.get_mut(reference) {
+                    Some(object) => object.fields.insert(field_ref.name.clone(), value),
+                    None => return Err(format!("Reference {} not found on heap", reference)),
+                };
             }
             Instruction::InvokeVirtual(index) | Instruction::InvokeSpecial(index) => {
                 // TODO: May need to split into separate InvokeVirtual and InvokeSpecial implementations.
-                let (class_name, method_name, method_descriptor) = match self
+                let method_ref = match self
                     .class_area
-                    .get(&curr_sf.class_name)
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
-                    .constant_pool
-                    .method_ref_parser(&index)
+                    .resolve_method_ref(index)
                 {
-                    Some(x) => x,
-                    None => {
-                        return Err(String::from("Method reference not found for InvokeVirtual"))
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!(
+                            "Method reference not found for InvokeVirtual: {}",
+                            err
+                        ))
                     }
                 };
 
-                if !self.class_area.contains_key(&class_name) {
-                    // println!("Unable to find method {}/{} : {}", class_name, method_name, method_descriptor);
-                    // TODO: Move this to standard library
-                    if method_name == "println" {
-                        let value_string = curr_sf.pop_primitive()?.pretty_print();
-                        println!("{}", value_string);
-                        self.stdout.push_str(value_string.as_str());
+                // Virtual calls run the implementation of the method in the receiver's class or its
+                // closest superclass, which may override the method of the referenced class
+                let receiver_class = match instruction {
+                    Instruction::InvokeVirtual(_) => {
+                        let parameters = parameter_count(&method_ref.descriptor)?;
+
+                        match curr_sf
+                            .stack
+                            .len()
+                            .checked_sub(parameters + 1)
+                            .and_then(|i| curr_sf.stack.get(i))
+                        {
+                            Some(Primitive::Reference(receiver)) => {
+                                self.heap.get(*receiver).map(|o| o.class_name.clone())
+                            }
+                            _ => None,
+                        }
                     }
+                    _ => None,
+                };
 
-                    curr_sf.stack.pop();
-                    curr_sf.pc += 1;
-                    return Ok(());
-                }
+                let found = receiver_class
+                    .and_then(|class_name| self.find_method(&class_name, &method_ref.signature))
+                    .filter(|(class_name, method)| {
+                        !method.is_private() || *class_name == method_ref.class_name
+                    });
+                let curr_sf = self.stack_frames.last_mut().unwrap();
 
-                let method = self
-                    .class_area
-                    .get(&class_name)
-                    .unwrap()
-                    .methods
-                    .get(&format!("{}{}", method_name, method_descriptor))
-                    .unwrap()
-                    .clone();
+                // Classes which aren't loaded, and the methods of java/lang/Object other than its
+                // constructor, are built-ins
+                let (class_name, method) = match found.or_else(|| {
+                    self.class_area
+                        .get(&method_ref.class_name)
+                        .and_then(|class| class.methods.get(&method_ref.signature))
+                        .map(|method| (method_ref.class_name.clone(), Rc::clone(method)))
+                }) {
+                    Some(found) => found,
+                    None => {
+                        let builtin_class = self.builtin_ancestor(&method_ref.class_name);
+                        self.stack_frames.last_mut().unwrap().pc += 1;
+                        return self.invoke_builtin(
+                            &builtin_class,
+                            &method_ref.name,
+                            &method_ref.descriptor,
+                        );
+                    }
+                };
 
-                let mut method_parameters = Vec::new();
+                if method.is_static() {
+                    return Err(format!(
+                        "Static method {}.{} cannot be invoked on an instance",
+                        method_ref.class_name, method_ref.signature
+                    ));
+                }
 
-                let param_string_len = method_descriptor
-                    .split(')')
-                    .collect::<Vec<&str>>()
-                    .get(0)
-                    .unwrap()
+                // Built-in objects such as System.out are held as null, but loaded classes need
+                // a receiver
+                let parameters = parameter_count(&method_ref.descriptor)?;
+                if let Some(Primitive::Null) = curr_sf
+                    .stack
                     .len()
-                    - 1;
+                    .checked_sub(parameters + 1)
+                    .and_then(|i| curr_sf.stack.get(i))
+                {
+                    return Err(String::from("java/lang/NullPointerException"));
+                }
+
+                check_method_access(curr_sf, &class_name, &method_ref.signature, &method)?;
 
-                for _i in 0..param_string_len {
+                // An abstract method means only its declaration was found, so the receiver's
+                // class doesn't implement it
+                check_method_code(&class_name, &method_ref.signature, &method)?;
+
+                let mut method_parameters = Vec::new();
+
+                // The receiver is passed before the parameters
+                for _i in 0..parameter_count(&method_ref.descriptor)? + 1 {
                     method_parameters.push(curr_sf.pop_primitive()?);
                 }
 
-                method_parameters.push(curr_sf.pop_primitive()?);
-
                 method_parameters.reverse();
+                check_arguments(&method_ref.descriptor, &method_parameters[1..])?;
 
                 curr_sf.pc += 1;
 
                 self.stack_frames.push(StackFrame {
                     pc: 0,
-                    locals: method_parameters,
-                    arrays: Vec::new(),
+                    locals: to_local_slots(method_parameters),
                     stack: vec![],
                     method,
                     class_name,
@@ -560,53 +2136,176 @@ impl Jvm {
                 return Ok(());
             }
             Instruction::InvokeStatic(index) => {
-                let (class_name, method_name, method_descriptor) = match self
+                let method_ref = match self
                     .class_area
-                    .get(&curr_sf.class_name)
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
-                    .constant_pool
-                    .method_ref_parser(&index)
+                    .resolve_method_ref(index)
                 {
-                    Some(x) => x,
-                    None => {
-                        return Err(String::from(
-                            "Could not find method reference for InvokeStatic",
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!(
+                            "Could not find method reference for InvokeStatic: {}",
+                            err
                         ))
                     }
                 };
 
-                let method = self
+                if !self.class_area.contains_key(&method_ref.class_name) {
+                    curr_sf.pc += 1;
+                    return self.invoke_builtin(
+                        &method_ref.class_name,
+                        &method_ref.name,
+                        &method_ref.descriptor,
+                    );
+                }
+
+                let method = match self
                     .class_area
-                    .get(&class_name)
-                    .unwrap()
-                    .methods
-                    .get(&format!("{}{}", method_name, method_descriptor))
-                    .unwrap()
-                    .clone();
+                    .get(&method_ref.class_name)
+                    .and_then(|class| class.methods.get(&method_ref.signature))
+                {
+                    Some(method) => Rc::clone(method),
+                    None => {
+                        return Err(format!(
+                            "java/lang/NoSuchMethodError: {}.{}",
+                            method_ref.class_name, method_ref.signature
+                        ))
+                    }
+                };
+
+                if !method.is_static() {
+                    return Err(format!(
+                        "Instance method {}.{} cannot be invoked statically",
+                        method_ref.class_name, method_ref.signature
+                    ));
+                }
+
+                check_method_access(
+                    curr_sf,
+                    &method_ref.class_name,
+                    &method_ref.signature,
+                    &method,
+                )?;
+                check_method_code(&method_ref.class_name, &method_ref.signature, &method)?;
 
                 let mut method_parameters = Vec::new();
 
-                let param_string_len = method_descriptor
-                    .split(')')
-                    .collect::<Vec<&str>>()
-                    .get(0)
+                for _i in 0..parameter_count(&method_ref.descriptor)? {
+                    method_parameters.push(curr_sf.pop_primitive()?);
+                }
+
+                method_parameters.reverse();
+                check_arguments(&method_ref.descriptor, &method_parameters)?;
+
+                curr_sf.pc += 1;
+
+                self.stack_frames.push(StackFrame {
+                    pc: 0,
+                    locals: to_local_slots(method_parameters),
+                    stack: vec![],
+                    method,
+                    class_name: method_ref.class_name.clone(),
+                });
+
+                return Ok(());
+            }
+            Instruction::InvokeInterface(index) => {
+                let method_ref = match self
+                    .class_area
+                    .get_mut(&curr_sf.class_name)
                     .unwrap()
+                    .resolve_method_ref(index)
+                {
+                    Ok(x) => x,
+                    Err(err) => {
+                        return Err(format!(
+                            "Method reference not found for InvokeInterface: {}",
+                            err
+                        ))
+                    }
+                };
+
+                let parameters = parameter_count(&method_ref.descriptor)?;
+
+                // The receiver sits below the parameters, and its class decides which method runs
+                let receiver = match curr_sf
+                    .stack
                     .len()
-                    - 1;
+                    .checked_sub(parameters + 1)
+                    .and_then(|i| curr_sf.stack.get(i))
+                {
+                    Some(Primitive::Reference(r)) => *r,
+                    Some(Primitive::Null) => {
+                        return Err(String::from("java/lang/NullPointerException"))
+                    }
+                    _ => return Err(String::from("Expected receiver for InvokeInterface")),
+                };
+                let receiver_class = match self.heap.get(receiver) {
+                    Some(object) => object.class_name.clone(),
+                    None => return Err(format!("Reference {} not found on heap", receiver)),
+                };
 
-                // TODO: Check that the parameters passed to the method are the correct types
-                for _i in 0..param_string_len {
-                    method_parameters.push(curr_sf.pop_primitive()?);
+                if let Some(ObjectData::Lambda(lambda)) =
+                    self.heap.get(receiver).map(|object| &object.data)
+                {
+                    let lambda = lambda.clone();
+                    let args = curr_sf.stack.split_off(curr_sf.stack.len() - parameters);
+                    curr_sf.stack.pop();
+                    curr_sf.pc += 1;
+                    return self.invoke_lambda(lambda, args);
                 }
 
-                method_parameters.reverse();
+                let found = self.find_method(&receiver_class, &method_ref.signature);
+                let curr_sf = self.stack_frames.last_mut().unwrap();
+
+                let (class_name, method) = match found {
+                    Some(found) => found,
+                    None if !self.class_area.contains_key(&receiver_class) => {
+                        curr_sf.pc += 1;
+                        return self.invoke_builtin(
+                            &method_ref.class_name,
+                            &method_ref.name,
+                            &method_ref.descriptor,
+                        );
+                    }
+                    None if self
+                        .class_area
+                        .get(&method_ref.class_name)
+                        .is_some_and(|class| class.methods.contains_key(&method_ref.signature)) =>
+                    {
+                        return Err(format!(
+                            "java/lang/AbstractMethodError: {}.{}",
+                            receiver_class, method_ref.signature
+                        ))
+                    }
+                    None => {
+                        return Err(format!(
+                            "Could not find method {} for class {}",
+                            method_ref.signature, receiver_class
+                        ))
+                    }
+                };
+
+                if method.is_static() {
+                    return Err(format!(
+                        "Static method {}.{} cannot be invoked on an instance",
+                        class_name, method_ref.signature
+                    ));
+                }
+
+                check_method_code(&class_name, &method_ref.signature, &method)?;
+
+                let method_parameters = curr_sf
+                    .stack
+                    .split_off(curr_sf.stack.len() - parameters - 1);
+                check_arguments(&method_ref.descriptor, &method_parameters[1..])?;
 
                 curr_sf.pc += 1;
 
                 self.stack_frames.push(StackFrame {
                     pc: 0,
-                    locals: method_parameters,
-                    arrays: Vec::new(),
+                    locals: to_local_slots(method_parameters),
                     stack: vec![],
                     method,
                     class_name,
@@ -614,62 +2313,243 @@ impl Jvm {
 
                 return Ok(());
             }
-            // Instruction::InvokeInterface(index) => {}
-            // Instruction::InvokeDynamic(index) => {}
+            Instruction::InvokeDynamic(index) => {
+                let class_name = curr_sf.class_name.clone();
+                curr_sf.pc += 1;
+                return self.invoke_dynamic(&class_name, index);
+            }
             Instruction::New(index) => {
                 let class_name = self
                     .class_area
                     .get(&curr_sf.class_name)
                     .unwrap()
                     .constant_pool
-                    .class_parser(&index)
-                    .unwrap();
+                    .class_parser(&index)?;
 
-                self.heap.push(Object {
+                let fields = self.default_fields(&class_name);
+                let object = self.allocate(Object {
                     class_name,
-                    fields: HashMap::new(),
-                });
+                    fields,
+                    data: ObjectData::None,
+                    monitor_entries: 0,
+                    monitor_owner: 0,
+                    identity_hash: 0,
+                })?;
+
+                let curr_sf = self.stack_frames.last_mut().unwrap();
+                curr_sf.stack.push(Primitive::Reference(object));
+                curr_sf.pc += 1;
 
-                curr_sf
-                    .stack
-                    .push(Primitive::Reference(self.heap.len() - 1));
+                return Ok(());
+            }
+            Instruction::NewArray(a_type) => {
+                let count = curr_sf.pop_int()?;
+                if count < 0 {
+                    return Err(format!("java/lang/NegativeArraySizeException: {}", count));
+                }
+
+                let array = self.allocate_with(1 + count as usize, |_| {
+                    vec![Object::new_array(
+                        format!("[{}", a_type.as_letter()),
+                        vec![
+                            Primitive::default_for_descriptor(&a_type.as_letter().to_string());
+                            count as usize
+                        ],
+                    )]
+                })?;
+
+                let curr_sf = self.stack_frames.last_mut().unwrap();
+                curr_sf.stack.push(Primitive::Reference(array));
+                curr_sf.pc += 1;
+
+                return Ok(());
             }
-            Instruction::NewArray(_a_type) | Instruction::ANewArray(_a_type) => {
-                // TODO: Actually implement ANewArray correctly
+            Instruction::ANewArray(index) => {
+                let element_class = self
+                    .class_area
+                    .get(&curr_sf.class_name)
+                    .unwrap()
+                    .constant_pool
+                    .class_parser(&index)?;
+
                 let count = curr_sf.pop_int()?;
+                if count < 0 {
+                    return Err(format!("java/lang/NegativeArraySizeException: {}", count));
+                }
+
+                let array = self.allocate_with(1 + count as usize, |_| {
+                    vec![Object::new_array(
+                        array_class_name(&element_class),
+                        vec![Primitive::Null; count as usize],
+                    )]
+                })?;
 
-                let new_array_ref = curr_sf.arrays.len();
-                curr_sf
-                    .arrays
-                    .insert(new_array_ref, Vec::with_capacity(count as usize));
-                curr_sf.stack.push(Primitive::Reference(new_array_ref));
+                let curr_sf = self.stack_frames.last_mut().unwrap();
+                curr_sf.stack.push(Primitive::Reference(array));
+                curr_sf.pc += 1;
+
+                return Ok(());
             }
             Instruction::ArrayLength => {
                 let array_ref = curr_sf.pop_ref()?;
-                let array_length = curr_sf.arrays.get(array_ref).unwrap().len();
+                let array_length = match self.heap.get(array_ref).and_then(|o| o.as_array()) {
+                    Some(array) => array.len(),
+                    None => return Err(format!("Reference {} is not an array", array_ref)),
+                };
                 curr_sf.stack.push(Primitive::Int(array_length as i32));
             }
-            // Instruction::AThrow => {}
-            // Instruction::CheckCast(index) => {}
-            // Instruction::InstanceOf(index) => {}
-            // Instruction::MonitorEnter => {}
-            // Instruction::MonitorExit => {}
-            // Instruction::Wide(usize) => {}
-            // Instruction::MultiANewArray(index, dimensions) => {}
-            Instruction::IfNull(branch_offset) => {
+            Instruction::AThrow => {
+                return match curr_sf.pop_primitive()? {
+                    Primitive::Reference(reference) => self.throw_exception(reference),
+                    _ => Err(String::from("java/lang/NullPointerException")),
+                };
+            }
+            // The reference is left on the stack, and null can be cast to any class
+            Instruction::CheckCast(index) => {
+                let class_name = self
+                    .class_area
+                    .get(&curr_sf.class_name)
+                    .unwrap()
+                    .constant_pool
+                    .class_parser(&index)?;
+
+                match curr_sf.stack.last().cloned() {
+                    Some(Primitive::Reference(reference)) => {
+                        let object_class = match self.heap.get(reference) {
+                            Some(object) => object.class_name.clone(),
+                            None => {
+                                return Err(format!("Reference {} not found on heap", reference))
+                            }
+                        };
+
+                        if !self.is_subclass_of(&object_class, &class_name) {
+                            return Err(format!(
+                                "java/lang/ClassCastException: class {} cannot be cast to class {}",
+                                object_class.replace('/', "."),
+                                class_name.replace('/', ".")
+                            ));
+                        }
+                    }
+                    Some(Primitive::Null) => {}
+                    _ => return Err(String::from("Expected reference for CheckCast")),
+                }
+
+                self.stack_frames.last_mut().unwrap().pc += 1;
+                return Ok(());
+            }
+            Instruction::InstanceOf(index) => {
+                let class_name = self
+                    .class_area
+                    .get(&curr_sf.class_name)
+                    .unwrap()
+                    .constant_pool
+                    .class_parser(&index)?;
+
+                // Null is not an instance of any class
+                let is_instance = match curr_sf.pop_primitive()? {
+                    Primitive::Reference(reference) => match self.heap.get(reference) {
+                        Some(object) => self.is_subclass_of(&object.class_name, &class_name),
+                        None => return Err(format!("Reference {} not found on heap", reference)),
+                    },
+                    _ => false,
+                };
+
+                let curr_sf = self.stack_frames.last_mut().unwrap();
+                curr_sf.stack.push(Primitive::Int(is_instance as i32));
+                curr_sf.pc += 1;
+
+                return Ok(());
+            }
+            // A thread can enter a monitor it already owns again, and the entries are counted so
+            // that it is only released once every entry has been matched by an exit
+            Instruction::MonitorEnter => match curr_sf.pop_primitive()? {
+                Primitive::Reference(reference) => match self.heap.get_mut(reference) {
+                    Some(object)
+                        if object.monitor_entries > 0 && object.monitor_owner != self.thread_id =>
+                    {
+                        // Blocked until the owner exits, so try again on a later turn
+                        curr_sf.stack.push(Primitive::Reference(reference));
+                        self.yield_requested = true;
+                        return Ok(());
+                    }
+                    Some(object) => {
+                        object.monitor_owner = self.thread_id;
+                        object.monitor_entries += 1;
+                    }
+                    None => return Err(format!("Reference {} not found on heap", reference)),
+                },
+                _ => return Err(String::from("java/lang/NullPointerException")),
+            },
+            Instruction::MonitorExit => match curr_sf.pop_primitive()? {
+                Primitive::Reference(reference) => match self.heap.get_mut(reference) {
+                    Some(object)
+                        if object.monitor_entries > 0 && object.monitor_owner == self.thread_id =>
+                    {
+                        object.monitor_entries -= 1
+                    }
+                    Some(_) => return Err(String::from("java/lang/IllegalMonitorStateException")),
+                    None => return Err(format!("Reference {} not found on heap", reference)),
+                },
+                _ => return Err(String::from("java/lang/NullPointerException")),
+            },
+            Instruction::MultiANewArray(index, dimensions) => {
+                let class_name = self
+                    .class_area
+                    .get(&curr_sf.class_name)
+                    .unwrap()
+                    .constant_pool
+                    .class_parser(&index)?;
+
+                let mut counts = Vec::with_capacity(dimensions);
+                for _ in 0..dimensions {
+                    let count = curr_sf.pop_int()?;
+                    match usize::try_from(count) {
+                        Ok(count) => counts.push(count),
+                        Err(_) => {
+                            return Err(format!("java/lang/NegativeArraySizeException: {}", count))
+                        }
+                    }
+                }
+                counts.reverse();
+
+                // Every array is allocated before any of them is referenced from the stack, so
+                // room is made for all of them at once
+                let mut cells: usize = 0;
+                let mut arrays: usize = 1;
+                for count in &counts {
+                    cells = cells
+                        .saturating_add(arrays)
+                        .saturating_add(arrays.saturating_mul(*count));
+                    arrays = arrays.saturating_mul(*count);
+                }
+
+                let mut array = Primitive::Null;
+                self.allocate_with(cells, |first| {
+                    let mut objects = Vec::new();
+                    array = build_multi_array(&class_name, &counts, &mut objects, first);
+                    objects
+                })?;
+
+                let curr_sf = self.stack_frames.last_mut().unwrap();
+                curr_sf.stack.push(array);
+                curr_sf.pc += 1;
+
+                return Ok(());
+            }
+            Instruction::IfNull(target) => {
                 if curr_sf.pop_primitive()?.is_type(PrimitiveType::Null) {
-                    curr_sf.pc += branch_offset;
+                    curr_sf.pc = target;
                     return Ok(());
                 }
             }
-            Instruction::IfNonNull(branch_offset) => {
+            Instruction::IfNonNull(target) => {
                 if !curr_sf.pop_primitive()?.is_type(PrimitiveType::Null) {
-                    curr_sf.pc += branch_offset;
+                    curr_sf.pc = target;
                     return Ok(());
                 }
             }
-            // Instruction::Breakpoint => {}
-            _ => return Err(String::from("Unsupported instruction")),
+            // Pausing is handled by the caller, so executing a breakpoint does nothing
+            Instruction::Breakpoint => {}
         }
 
         curr_sf.pc += 1;
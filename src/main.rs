@@ -3,13 +3,28 @@ extern crate core;
 use crate::bytecode::*;
 
 mod bytecode;
+// Class files, JSON and control flow graphs are only used by the tests, not the binary itself
+#[allow(dead_code)]
+mod cfg;
+#[allow(dead_code)]
 mod class_file_parser;
+mod descriptor;
+mod gc;
+// The structures of the class file format are kept whole, including the parts which aren't read
+#[allow(dead_code)]
 mod java_class;
 mod javac;
+#[allow(dead_code)]
+mod json;
 mod jvm;
+mod optimizer;
+#[allow(dead_code)]
 mod reader;
+mod stack_map;
+mod stdlib;
 #[cfg(test)]
 mod tests;
+mod threads;
 
 fn main() {
     let code = include_str!("java_tests/Array.java");
@@ -23,10 +38,12 @@ fn main() {
     };
 
     println!("jvm has classes: {:?}", classes);
-    let mut jvm = jvm::Jvm::new(classes);
+    let args = std::env::args().skip(1).collect();
+    let mut jvm = jvm::Jvm::with_args(classes, args);
 
     println!("\nRunning JVM:");
     match jvm.run() {
+        Ok(jvm::StepOutcome::Finished(exit_code)) => std::process::exit(exit_code),
         Ok(_) => {}
         Err(e) => println!("\n\x1b[31m{}\x1b[0m", jvm.stack_trace(e)),
     };
@@ -0,0 +1,135 @@
+//! A peephole optimizer which removes redundant instruction sequences produced by javac.
+use crate::{Instruction, Primitive, PrimitiveType};
+
+/// Remove redundant instruction sequences from a method. Branches must still hold offsets relative
+/// to their own instruction, so this has to run before the branch targets are resolved.
+pub fn optimize(instructions: &mut Vec<Instruction>) {
+    // Removing a sequence can expose another one, so repeat until nothing changes
+    while optimize_once(instructions) {}
+}
+
+/// The number of stack slots taken by the value pushed by an instruction without side effects.
+fn pushed_slots(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Const(Primitive::Long(_) | Primitive::Double(_)) => Some(2),
+        Instruction::Const(_) | Instruction::AConstNull | Instruction::Dup => Some(1),
+        Instruction::Load(_, load_type) => Some(load_type.slot_count()),
+        _ => None,
+    }
+}
+
+/// Find a redundant sequence at the start of the passed instructions, returning how many
+/// instructions it spans and what to replace them with.
+fn replacement(window: &[Instruction]) -> Option<(usize, Vec<Instruction>)> {
+    match window {
+        // A jump to the next instruction
+        [Instruction::Goto(1), ..] => Some((1, vec![])),
+        // A conversion to the same type
+        [Instruction::Convert(from, to), ..] if from.matches(to) => Some((1, vec![])),
+        // Storing a value back into the local it was just loaded from
+        [Instruction::Load(load_index, _), Instruction::Store(store_index, _), ..]
+            if load_index == store_index =>
+        {
+            Some((2, vec![]))
+        }
+        // Loading a value that was just stored keeps it on the stack instead
+        [Instruction::Store(store_index, store_type), Instruction::Load(load_index, _), ..]
+            if store_index == load_index =>
+        {
+            let dup = if store_type.is_category_two() {
+                Instruction::Dup2
+            } else {
+                Instruction::Dup
+            };
+            Some((
+                2,
+                vec![dup, Instruction::Store(*store_index, store_type.clone())],
+            ))
+        }
+        // A value that is pushed and immediately discarded
+        [pushed, Instruction::Pop, ..] if pushed_slots(pushed) == Some(1) => Some((2, vec![])),
+        [pushed, Instruction::Pop2, ..] if pushed_slots(pushed) == Some(2) => Some((2, vec![])),
+        // A widening conversion that is immediately undone
+        [Instruction::Convert(from, to), Instruction::Convert(back_from, back_to), ..]
+            if to.matches(back_from)
+                && from.matches(back_to)
+                && matches!(
+                    (from, to),
+                    (PrimitiveType::Int, PrimitiveType::Long)
+                        | (PrimitiveType::Int, PrimitiveType::Double)
+                        | (PrimitiveType::Float, PrimitiveType::Double)
+                ) =>
+        {
+            Some((2, vec![]))
+        }
+        _ => None,
+    }
+}
+
+/// Run a single pass over the instructions, returning whether anything was changed.
+fn optimize_once(instructions: &mut Vec<Instruction>) -> bool {
+    let length = instructions.len();
+
+    // Sequences can only be replaced if nothing jumps into the middle of them
+    let mut is_target = vec![false; length + 1];
+    for (index, instruction) in instructions.iter().enumerate() {
+        for offset in instruction.branch_targets() {
+            if let Some(target) = is_target.get_mut(index.wrapping_add(offset)) {
+                *target = true;
+            }
+        }
+    }
+
+    let mut optimized = Vec::with_capacity(length);
+    // The index in the original instructions of each optimized instruction
+    let mut original_indices = Vec::with_capacity(length);
+    // The index in the optimized instructions of each original instruction, where removed
+    // instructions map to the instruction following them
+    let mut new_indices = vec![0; length + 1];
+    let mut changed = false;
+    let mut index = 0;
+
+    while index < length {
+        let found = replacement(&instructions[index..]).filter(|(span, _)| {
+            !is_target[index + 1..index + span]
+                .iter()
+                .any(|is_target| *is_target)
+        });
+
+        let (span, replaced_with) = match found {
+            Some(found) => {
+                changed = true;
+                found
+            }
+            None => (1, vec![instructions[index].clone()]),
+        };
+
+        for offset in 0..span {
+            new_indices[index + offset] = optimized.len() + offset.min(replaced_with.len());
+        }
+
+        for (offset, instruction) in replaced_with.into_iter().enumerate() {
+            optimized.push(instruction);
+            original_indices.push(index + offset.min(span - 1));
+        }
+
+        index += span;
+    }
+
+    new_indices[length] = optimized.len();
+
+    // Branch offsets are relative, so they change whenever instructions between a branch and its
+    // target are removed
+    for (new_index, instruction) in optimized.iter_mut().enumerate() {
+        let original_index = original_indices[new_index];
+
+        for offset in instruction.branch_targets_mut() {
+            if let Some(target) = new_indices.get(original_index.wrapping_add(*offset)) {
+                *offset = target.wrapping_sub(new_index);
+            }
+        }
+    }
+
+    *instructions = optimized;
+    changed
+}
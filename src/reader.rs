@@ -10,62 +10,67 @@ pub struct Reader {
 }
 
 impl Reader {
-    /// Make a new reader for a passed file.
-    pub fn new(filename: String) -> Self {
-        let filename_string = filename;
-        let mut f = File::open(&filename_string).expect("no file found");
-        let metadata = fs::metadata(&filename_string).expect("unable to read metadata");
+    /// Make a new reader for a passed file, failing if it can't be read.
+    pub fn new(filename: String) -> Result<Self, String> {
+        let read_error = |err: std::io::Error| format!("Unable to read {}: {}", filename, err);
+        let mut f = File::open(&filename).map_err(read_error)?;
+        let metadata = fs::metadata(&filename).map_err(read_error)?;
         let mut buffer = vec![0; metadata.len() as usize];
-        f.read_exact(&mut buffer).expect("buffer overflow");
+        f.read_exact(&mut buffer).map_err(read_error)?;
 
-        Self {
+        Ok(Self {
             bytes: buffer,
             index: 0,
-        }
+        })
     }
 
     /// Reads and advances a single byte.
-    pub fn g1(&mut self) -> u8 {
-        self.index += 1;
-        self.bytes[self.index - 1]
+    pub fn g1(&mut self) -> Result<u8, String> {
+        Ok(self.g(1)?[0])
     }
 
     /// Reads and advances two bytes.
-    pub fn g2(&mut self) -> u16 {
-        (self.g1() as u16) << 8 | (self.g1() as u16)
+    pub fn g2(&mut self) -> Result<u16, String> {
+        Ok((self.g1()? as u16) << 8 | (self.g1()? as u16))
     }
 
     /// Reads and advances two bytes and returns a usize.
-    pub fn g2u(&mut self) -> usize {
-        ((self.g1() as u16) << 8 | (self.g1() as u16)) as usize
+    pub fn g2u(&mut self) -> Result<usize, String> {
+        Ok(self.g2()? as usize)
     }
 
     /// Reads and advances four bytes.
-    pub fn g4(&mut self) -> u32 {
-        (self.g1() as u32) << 24
-            | (self.g1() as u32) << 16
-            | (self.g1() as u32) << 8
-            | (self.g1() as u32)
+    pub fn g4(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.g4_array()?))
     }
 
-    /// Reads and advances a passed number of bytes.
-    pub fn g(&mut self, size: usize) -> Vec<u8> {
+    /// Reads and advances a passed number of bytes, failing if fewer than that are left.
+    pub fn g(&mut self, size: usize) -> Result<Vec<u8>, String> {
+        if size > self.remaining() {
+            return Err(format!(
+                "Class file ends unexpectedly at byte {}: {} more bytes were expected but only {} remain",
+                self.index,
+                size,
+                self.remaining()
+            ));
+        }
+
         self.index += size;
-        self.bytes[self.index - size..self.index].to_vec()
+        Ok(self.bytes[self.index - size..self.index].to_vec())
     }
 
     /// Read and advance 4 bytes and return a four length array of u8.
-    pub fn g4_array(&mut self) -> [u8; 4] {
+    pub fn g4_array(&mut self) -> Result<[u8; 4], String> {
         let mut array = [0; 4];
-        array.copy_from_slice(&self.g(4));
-        array
+        array.copy_from_slice(&self.g(4)?);
+        Ok(array)
     }
 
     /// Read and advance 8 bytes and return an eight length array of u8.
-    pub fn g8_array(&mut self) -> [u8; 8] {
+    pub fn g8_array(&mut self) -> Result<[u8; 8], String> {
         let mut array = [0; 8];
-        array.copy_from_slice(&self.g(8));
-        array
+        array.copy_from_slice(&self.g(8)?);
+        Ok(array)
     }
 
     /// Read the current index.
@@ -77,4 +82,14 @@ impl Reader {
     pub fn set_pos(&mut self, pos: usize) {
         self.index = pos;
     }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.index)
+    }
+
+    /// Whether every byte has been read.
+    pub fn is_eof(&self) -> bool {
+        self.remaining() == 0
+    }
 }
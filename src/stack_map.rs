@@ -0,0 +1,923 @@
+//! Computes the types of the locals and stack throughout a method, which are recorded as stack map
+//! frames so that class files can be verified without analysing every path through a method.
+use crate::descriptor::{parse_descriptor, FieldType};
+use crate::java_class::{
+    ConstantPoolEntry, ConstantPoolExt, ExceptionTableEntry, StackMapFrame, StackMapFrameType,
+    VerificationType,
+};
+use crate::jvm::array_class_name;
+use crate::{Instruction, Primitive, PrimitiveType};
+use std::collections::BTreeMap;
+
+/// The types of the locals and stack before an instruction. Unlike in stack map frames, longs and
+/// doubles take up two slots here, the second of which is `Top`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+
+/// The result of analysing a method.
+#[derive(Debug)]
+pub struct StackMap {
+    /// The frame at each instruction which is the target of a branch.
+    pub frames: BTreeMap<usize, Frame>,
+    /// The largest number of slots taken up on the stack at any point in the method.
+    #[allow(dead_code)]
+    pub max_stack: usize,
+}
+
+/// The number of slots taken up by a value of a type.
+fn width(primitive_type: &PrimitiveType) -> usize {
+    match primitive_type {
+        PrimitiveType::Null => 0,
+        _ => primitive_type.slot_count(),
+    }
+}
+
+fn object_type(class_name: &str, constant_pool: &mut Vec<ConstantPoolEntry>) -> VerificationType {
+    VerificationType::Object(constant_pool.find_or_add_class(class_name) as u16)
+}
+
+/// The slots taken up by a value of a type.
+fn type_slots(
+    primitive_type: &PrimitiveType,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Vec<VerificationType> {
+    match primitive_type {
+        PrimitiveType::Null => vec![],
+        PrimitiveType::Long => vec![VerificationType::Long, VerificationType::Top],
+        PrimitiveType::Double => vec![VerificationType::Double, VerificationType::Top],
+        PrimitiveType::Float => vec![VerificationType::Float],
+        // The class of references isn't known, so the most general one is used
+        PrimitiveType::Reference => vec![object_type("java/lang/Object", constant_pool)],
+        _ => vec![VerificationType::Integer],
+    }
+}
+
+/// The slots taken up by a value of a type from a descriptor.
+fn field_type_slots(
+    field_type: &FieldType,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Vec<VerificationType> {
+    match field_type {
+        FieldType::Boolean
+        | FieldType::Byte
+        | FieldType::Char
+        | FieldType::Short
+        | FieldType::Int => vec![VerificationType::Integer],
+        FieldType::Float => vec![VerificationType::Float],
+        FieldType::Long => vec![VerificationType::Long, VerificationType::Top],
+        FieldType::Double => vec![VerificationType::Double, VerificationType::Top],
+        FieldType::Void => vec![],
+        FieldType::Object(class_name) => vec![object_type(class_name, constant_pool)],
+        // Array classes are named by their descriptor
+        FieldType::Array(_) => vec![object_type(&field_type.to_string(), constant_pool)],
+    }
+}
+
+/// The slots taken up by a value with a field descriptor such as `I` or `Ljava/lang/String;`.
+fn descriptor_slots(
+    descriptor: &str,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<VerificationType>, String> {
+    Ok(field_type_slots(
+        &FieldType::parse(descriptor)?,
+        constant_pool,
+    ))
+}
+
+/// The number of slots taken up by the parameters of a method descriptor, and the slots of the
+/// value it returns.
+fn method_descriptor_slots(
+    descriptor: &str,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<(usize, Vec<VerificationType>), String> {
+    let (parameters, return_type) = parse_descriptor(descriptor)?;
+    let parameter_slots = parameters
+        .iter()
+        .map(|parameter| if parameter.is_wide() { 2 } else { 1 })
+        .sum();
+
+    Ok((
+        parameter_slots,
+        field_type_slots(&return_type, constant_pool),
+    ))
+}
+
+/// The locals a method starts with, which hold the instance it was called on, if any, followed by
+/// its parameters.
+pub fn initial_locals(
+    class_name: &str,
+    method_name: &str,
+    descriptor: &str,
+    is_static: bool,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<VerificationType>, String> {
+    let mut locals = Vec::new();
+
+    if !is_static {
+        locals.push(match method_name {
+            "<init>" => VerificationType::UninitializedThis,
+            _ => object_type(class_name, constant_pool),
+        });
+    }
+
+    for parameter in parse_descriptor(descriptor)?.0 {
+        locals.append(&mut field_type_slots(&parameter, constant_pool));
+    }
+
+    Ok(locals)
+}
+
+fn pop(frame: &mut Frame, slots: usize, index: usize) -> Result<Vec<VerificationType>, String> {
+    match frame.stack.len().checked_sub(slots) {
+        Some(remaining) => Ok(frame.stack.split_off(remaining)),
+        None => Err(format!("Stack underflow at instruction {}", index)),
+    }
+}
+
+/// Copy the passed number of slots from the top of the stack, inserting them below the passed
+/// number of slots underneath them, as the dup instructions do.
+fn dup(frame: &mut Frame, slots: usize, below: usize, index: usize) -> Result<(), String> {
+    let position = match frame.stack.len().checked_sub(slots + below) {
+        Some(position) => position,
+        None => return Err(format!("Stack underflow at instruction {}", index)),
+    };
+    let copied = frame.stack[frame.stack.len() - slots..].to_vec();
+
+    frame.stack.splice(position..position, copied);
+    Ok(())
+}
+
+fn is_reference(verification_type: &VerificationType) -> bool {
+    matches!(
+        verification_type,
+        VerificationType::Null | VerificationType::Object(_)
+    )
+}
+
+/// The type of the elements of an array, given the type of the array itself.
+fn element_slots(
+    array: &VerificationType,
+    element_type: &PrimitiveType,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Vec<VerificationType>, String> {
+    match (element_type, array) {
+        (PrimitiveType::Reference, VerificationType::Object(class_index)) => {
+            let class_name = constant_pool.class_parser(&(*class_index as usize))?;
+
+            // Arrays which were only known to be some object hold objects of unknown classes
+            match class_name.strip_prefix('[') {
+                Some(element_descriptor) => descriptor_slots(element_descriptor, constant_pool),
+                None => Ok(vec![object_type("java/lang/Object", constant_pool)]),
+            }
+        }
+        (PrimitiveType::Reference, _) => Ok(vec![VerificationType::Null]),
+        (element_type, _) => Ok(type_slots(element_type, constant_pool)),
+    }
+}
+
+/// Apply the effect of an instruction to the types of the locals and stack.
+fn execute(
+    index: usize,
+    instructions: &[Instruction],
+    frame: &mut Frame,
+    class_name: &str,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<(), String> {
+    match &instructions[index] {
+        Instruction::Nop
+        | Instruction::Breakpoint
+        | Instruction::IInc(_, _)
+        | Instruction::Goto(_) => {}
+        Instruction::AConstNull => frame.stack.push(VerificationType::Null),
+        Instruction::Const(constant) => {
+            let constant_type = match constant {
+                Primitive::Null => PrimitiveType::Null,
+                Primitive::Long(_) => PrimitiveType::Long,
+                Primitive::Float(_) => PrimitiveType::Float,
+                Primitive::Double(_) => PrimitiveType::Double,
+                Primitive::Reference(_) => PrimitiveType::Reference,
+                _ => PrimitiveType::Int,
+            };
+
+            match constant_type {
+                PrimitiveType::Null => frame.stack.push(VerificationType::Null),
+                constant_type => frame
+                    .stack
+                    .append(&mut type_slots(&constant_type, constant_pool)),
+            }
+        }
+        Instruction::LoadConst(constant_index) => {
+            let mut slots = match constant_pool.get(constant_index.wrapping_sub(1)) {
+                Some(ConstantPoolEntry::Integer(_)) => vec![VerificationType::Integer],
+                Some(ConstantPoolEntry::Float(_)) => vec![VerificationType::Float],
+                Some(ConstantPoolEntry::Long(_)) => {
+                    vec![VerificationType::Long, VerificationType::Top]
+                }
+                Some(ConstantPoolEntry::Double(_)) => {
+                    vec![VerificationType::Double, VerificationType::Top]
+                }
+                Some(ConstantPoolEntry::String(_)) => {
+                    vec![object_type("java/lang/String", constant_pool)]
+                }
+                Some(ConstantPoolEntry::Class(_)) => {
+                    vec![object_type("java/lang/Class", constant_pool)]
+                }
+                Some(ConstantPoolEntry::MethodType(_)) => {
+                    vec![object_type("java/lang/invoke/MethodType", constant_pool)]
+                }
+                Some(ConstantPoolEntry::MethodHandle(_, _)) => {
+                    vec![object_type("java/lang/invoke/MethodHandle", constant_pool)]
+                }
+                Some(ConstantPoolEntry::Dynamic(_, name_and_type_index)) => {
+                    let (_, descriptor) =
+                        constant_pool.name_and_type_parser(name_and_type_index)?;
+                    descriptor_slots(&descriptor, constant_pool)?
+                }
+                _ => {
+                    return Err(format!(
+                        "Constant pool entry {} loaded at instruction {} is not loadable",
+                        constant_index, index
+                    ))
+                }
+            };
+
+            frame.stack.append(&mut slots);
+        }
+        Instruction::Load(local_index, local_type) => {
+            let slots = width(local_type);
+
+            match frame.locals.get(*local_index..*local_index + slots) {
+                Some(values) if values[0] != VerificationType::Top => {
+                    frame.stack.extend_from_slice(values)
+                }
+                _ => {
+                    return Err(format!(
+                        "Local {} is read at instruction {} before it is assigned",
+                        local_index, index
+                    ))
+                }
+            }
+        }
+        Instruction::Store(local_index, local_type) => {
+            let values = pop(frame, width(local_type), index)?;
+
+            if frame.locals.len() < local_index + values.len() {
+                frame
+                    .locals
+                    .resize(local_index + values.len(), VerificationType::Top);
+            }
+
+            // Overwriting the second slot of a long or double invalidates the whole value
+            if *local_index > 0
+                && matches!(
+                    frame.locals[local_index - 1],
+                    VerificationType::Long | VerificationType::Double
+                )
+            {
+                frame.locals[local_index - 1] = VerificationType::Top;
+            }
+
+            for (offset, value) in values.into_iter().enumerate() {
+                frame.locals[local_index + offset] = value;
+            }
+        }
+        Instruction::ALoad(element_type) => {
+            pop(frame, 1, index)?;
+            let array = pop(frame, 1, index)?.remove(0);
+            let mut slots = element_slots(&array, element_type, constant_pool)?;
+            frame.stack.append(&mut slots);
+        }
+        Instruction::AStore(element_type) => {
+            pop(frame, width(element_type) + 2, index)?;
+        }
+        Instruction::Pop
+        | Instruction::AThrow
+        | Instruction::MonitorEnter
+        | Instruction::MonitorExit
+        | Instruction::If(_, _)
+        | Instruction::IfNull(_)
+        | Instruction::IfNonNull(_)
+        | Instruction::TableSwitch(_, _, _)
+        | Instruction::LookupSwitch(_, _) => {
+            pop(frame, 1, index)?;
+        }
+        Instruction::Pop2 | Instruction::IfICmp(_, _) => {
+            pop(frame, 2, index)?;
+        }
+        Instruction::Dup => dup(frame, 1, 0, index)?,
+        Instruction::DupX1 => dup(frame, 1, 1, index)?,
+        Instruction::DupX2 => dup(frame, 1, 2, index)?,
+        Instruction::Dup2 => dup(frame, 2, 0, index)?,
+        Instruction::Dup2X1 => dup(frame, 2, 1, index)?,
+        Instruction::Dup2X2 => dup(frame, 2, 2, index)?,
+        Instruction::Swap => {
+            let mut values = pop(frame, 2, index)?;
+            values.reverse();
+            frame.stack.append(&mut values);
+        }
+        Instruction::Add(operand_type)
+        | Instruction::Sub(operand_type)
+        | Instruction::Mul(operand_type)
+        | Instruction::Div(operand_type)
+        | Instruction::Rem(operand_type)
+        | Instruction::And(operand_type)
+        | Instruction::Or(operand_type)
+        | Instruction::Xor(operand_type) => {
+            pop(frame, width(operand_type) * 2, index)?;
+            let mut slots = type_slots(operand_type, constant_pool);
+            frame.stack.append(&mut slots);
+        }
+        // Shifts take an int shift distance, whatever the type of the value being shifted
+        Instruction::Shl(operand_type)
+        | Instruction::Shr(operand_type)
+        | Instruction::UShr(operand_type) => {
+            pop(frame, width(operand_type) + 1, index)?;
+            let mut slots = type_slots(operand_type, constant_pool);
+            frame.stack.append(&mut slots);
+        }
+        Instruction::Neg(operand_type) => {
+            pop(frame, width(operand_type), index)?;
+            let mut slots = type_slots(operand_type, constant_pool);
+            frame.stack.append(&mut slots);
+        }
+        Instruction::Convert(from, to) => {
+            pop(frame, width(from), index)?;
+            let mut slots = type_slots(to, constant_pool);
+            frame.stack.append(&mut slots);
+        }
+        Instruction::LCmp | Instruction::DCmpL | Instruction::DCmpG => {
+            pop(frame, 4, index)?;
+            frame.stack.push(VerificationType::Integer);
+        }
+        Instruction::FCmpL | Instruction::FCmpG => {
+            pop(frame, 2, index)?;
+            frame.stack.push(VerificationType::Integer);
+        }
+        Instruction::Jsr(_) | Instruction::Ret(_) => {
+            return Err(format!(
+                "Subroutines at instruction {} are not supported",
+                index
+            ))
+        }
+        Instruction::Return(return_type) => {
+            pop(frame, width(return_type), index)?;
+
+            // Anything else still on the stack was pushed without ever being used
+            if !frame.stack.is_empty() {
+                return Err(format!(
+                    "Stack holds {} unused slots when returning at instruction {}",
+                    frame.stack.len(),
+                    index
+                ));
+            }
+        }
+        Instruction::GetStatic(field_index) | Instruction::GetField(field_index) => {
+            let (_, _, descriptor) = constant_pool.field_ref_parser(field_index)?;
+
+            if matches!(instructions[index], Instruction::GetField(_)) {
+                pop(frame, 1, index)?;
+            }
+
+            let mut slots = descriptor_slots(&descriptor, constant_pool)?;
+            frame.stack.append(&mut slots);
+        }
+        Instruction::PutStatic(field_index) | Instruction::PutField(field_index) => {
+            let (_, _, descriptor) = constant_pool.field_ref_parser(field_index)?;
+            let object_slots = match instructions[index] {
+                Instruction::PutField(_) => 1,
+                _ => 0,
+            };
+
+            pop(
+                frame,
+                descriptor_slots(&descriptor, constant_pool)?.len() + object_slots,
+                index,
+            )?;
+        }
+        Instruction::InvokeStatic(method_index) => {
+            let (_, _, descriptor) = constant_pool.method_ref_parser(method_index)?;
+            let (parameter_slots, mut return_slots) =
+                method_descriptor_slots(&descriptor, constant_pool)?;
+
+            pop(frame, parameter_slots, index)?;
+            frame.stack.append(&mut return_slots);
+        }
+        Instruction::InvokeVirtual(method_index)
+        | Instruction::InvokeSpecial(method_index)
+        | Instruction::InvokeInterface(method_index) => {
+            let (method_class, name, descriptor) = constant_pool.method_ref_parser(method_index)?;
+            let (parameter_slots, mut return_slots) =
+                method_descriptor_slots(&descriptor, constant_pool)?;
+
+            pop(frame, parameter_slots, index)?;
+            let receiver = pop(frame, 1, index)?.remove(0);
+
+            // A constructor initializes every copy of the object it is called on
+            if name == "<init>" {
+                let initialized = match receiver {
+                    VerificationType::UninitializedThis => object_type(class_name, constant_pool),
+                    VerificationType::Uninitialized(new_index) => {
+                        match instructions.get(new_index as usize) {
+                            Some(Instruction::New(class_index)) => {
+                                VerificationType::Object(*class_index as u16)
+                            }
+                            _ => object_type(&method_class, constant_pool),
+                        }
+                    }
+                    _ => receiver.clone(),
+                };
+
+                for value in frame.locals.iter_mut().chain(frame.stack.iter_mut()) {
+                    if *value == receiver {
+                        *value = initialized.clone();
+                    }
+                }
+            }
+
+            frame.stack.append(&mut return_slots);
+        }
+        Instruction::InvokeDynamic(call_site_index) => {
+            let descriptor = match constant_pool.get(call_site_index.wrapping_sub(1)) {
+                Some(ConstantPoolEntry::InvokeDynamic(_, name_and_type_index)) => {
+                    constant_pool.name_and_type_parser(name_and_type_index)?.1
+                }
+                _ => {
+                    return Err(format!(
+                        "Constant pool entry {} is not an InvokeDynamic",
+                        call_site_index
+                    ))
+                }
+            };
+            let (parameter_slots, mut return_slots) =
+                method_descriptor_slots(&descriptor, constant_pool)?;
+
+            pop(frame, parameter_slots, index)?;
+            frame.stack.append(&mut return_slots);
+        }
+        Instruction::New(_) => frame
+            .stack
+            .push(VerificationType::Uninitialized(index as u16)),
+        Instruction::NewArray(element_type) => {
+            pop(frame, 1, index)?;
+            let array_class = match element_type {
+                PrimitiveType::Reference | PrimitiveType::Null => {
+                    String::from("[Ljava/lang/Object;")
+                }
+                element_type => format!("[{}", element_type.as_letter()),
+            };
+            frame.stack.push(object_type(&array_class, constant_pool));
+        }
+        Instruction::ANewArray(class_index) => {
+            pop(frame, 1, index)?;
+            let element_class = constant_pool.class_parser(class_index)?;
+            frame.stack.push(object_type(
+                &array_class_name(&element_class),
+                constant_pool,
+            ));
+        }
+        Instruction::MultiANewArray(class_index, dimensions) => {
+            pop(frame, *dimensions, index)?;
+            frame
+                .stack
+                .push(VerificationType::Object(*class_index as u16));
+        }
+        Instruction::ArrayLength | Instruction::InstanceOf(_) => {
+            pop(frame, 1, index)?;
+            frame.stack.push(VerificationType::Integer);
+        }
+        Instruction::CheckCast(class_index) => {
+            pop(frame, 1, index)?;
+            frame
+                .stack
+                .push(VerificationType::Object(*class_index as u16));
+        }
+    }
+
+    Ok(())
+}
+
+/// The instructions which can run after an instruction.
+pub fn successors(index: usize, instruction: &Instruction) -> Vec<usize> {
+    let mut successors = Vec::new();
+
+    if !matches!(
+        instruction,
+        Instruction::Goto(_)
+            | Instruction::Return(_)
+            | Instruction::AThrow
+            | Instruction::TableSwitch(_, _, _)
+            | Instruction::LookupSwitch(_, _)
+    ) {
+        successors.push(index + 1);
+    }
+
+    successors.extend(instruction.branch_targets());
+
+    successors
+}
+
+/// The most specific type which two references can both be treated as.
+fn merge_references(
+    a: &VerificationType,
+    b: &VerificationType,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> VerificationType {
+    match (a, b) {
+        (VerificationType::Null, other) | (other, VerificationType::Null) => other.clone(),
+        // Without the class hierarchy, Object is the only common superclass that is known
+        _ => object_type("java/lang/Object", constant_pool),
+    }
+}
+
+/// Combine the frames reaching an instruction from two different paths.
+fn merge(
+    existing: &Frame,
+    incoming: &Frame,
+    index: usize,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<Frame, String> {
+    if existing.stack.len() != incoming.stack.len() {
+        return Err(format!(
+            "Stack heights {} and {} differ at instruction {}",
+            existing.stack.len(),
+            incoming.stack.len(),
+            index
+        ));
+    }
+
+    let mut stack = Vec::new();
+
+    for (a, b) in existing.stack.iter().zip(incoming.stack.iter()) {
+        stack.push(if a == b {
+            a.clone()
+        } else if is_reference(a) && is_reference(b) {
+            merge_references(a, b, constant_pool)
+        } else {
+            return Err(format!(
+                "Incompatible types {:?} and {:?} on the stack at instruction {}",
+                a, b, index
+            ));
+        });
+    }
+
+    let mut locals = Vec::new();
+
+    for local_index in 0..existing.locals.len().max(incoming.locals.len()) {
+        let a = existing
+            .locals
+            .get(local_index)
+            .unwrap_or(&VerificationType::Top);
+        let b = incoming
+            .locals
+            .get(local_index)
+            .unwrap_or(&VerificationType::Top);
+
+        // A local holding different types on each path can't be used until it is reassigned
+        locals.push(if a == b {
+            a.clone()
+        } else if is_reference(a) && is_reference(b) {
+            merge_references(a, b, constant_pool)
+        } else {
+            VerificationType::Top
+        });
+    }
+
+    Ok(Frame { locals, stack })
+}
+
+/// Follow every path through a method to find the types of the locals and stack at each branch
+/// target and exception handler, failing if the types on different paths can't be reconciled.
+pub fn analyze(
+    instructions: &[Instruction],
+    exception_table: &[ExceptionTableEntry],
+    initial_locals: Vec<VerificationType>,
+    class_name: &str,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<StackMap, String> {
+    let mut is_target = vec![false; instructions.len()];
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        for target in instruction.branch_targets() {
+            match is_target.get_mut(target) {
+                Some(is_target) => *is_target = true,
+                None => {
+                    return Err(format!(
+                        "Branch at instruction {} jumps outside of the method",
+                        index
+                    ))
+                }
+            }
+        }
+    }
+
+    for entry in exception_table {
+        match is_target.get_mut(entry.handler_pc) {
+            Some(is_target) => *is_target = true,
+            None => {
+                return Err(format!(
+                    "Exception handler at instruction {} is outside of the method",
+                    entry.handler_pc
+                ))
+            }
+        }
+    }
+
+    let mut states: Vec<Option<Frame>> = vec![None; instructions.len()];
+    let mut pending = Vec::new();
+    let mut max_stack = 0;
+
+    if !instructions.is_empty() {
+        states[0] = Some(Frame {
+            locals: initial_locals,
+            stack: Vec::new(),
+        });
+        pending.push(0);
+    }
+
+    while let Some(index) = pending.pop() {
+        let mut frame = match &states[index] {
+            Some(frame) => frame.clone(),
+            None => continue,
+        };
+
+        // A handler is entered with the locals from before the instruction that threw, and only
+        // the exception on the stack
+        let mut handler_frames = Vec::new();
+
+        for entry in exception_table {
+            if (entry.start_pc..entry.end_pc).contains(&index) {
+                let exception = match entry.catch_type {
+                    0 => object_type("java/lang/Throwable", constant_pool),
+                    catch_type => VerificationType::Object(catch_type),
+                };
+
+                handler_frames.push((
+                    entry.handler_pc,
+                    Frame {
+                        locals: frame.locals.clone(),
+                        stack: vec![exception],
+                    },
+                ));
+            }
+        }
+
+        execute(index, instructions, &mut frame, class_name, constant_pool)?;
+        max_stack = max_stack.max(frame.stack.len());
+
+        let mut next_frames = successors(index, &instructions[index])
+            .into_iter()
+            .map(|successor| (successor, frame.clone()))
+            .collect::<Vec<_>>();
+        next_frames.extend(handler_frames);
+
+        for (successor, frame) in next_frames {
+            if successor >= instructions.len() {
+                return Err(format!(
+                    "Execution runs past the end of the method after instruction {}",
+                    index
+                ));
+            }
+
+            let merged = match &states[successor] {
+                Some(existing) => merge(existing, &frame, successor, constant_pool)?,
+                None => frame.clone(),
+            };
+
+            if states[successor].as_ref() != Some(&merged) {
+                states[successor] = Some(merged);
+                pending.push(successor);
+            }
+        }
+    }
+
+    let frames = states
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| is_target[*index])
+        .filter_map(|(index, frame)| frame.map(|frame| (index, frame)))
+        .collect();
+
+    Ok(StackMap { frames, max_stack })
+}
+
+/// Convert slots into the entries of a stack map frame, where longs and doubles only take up one.
+fn slots_to_entries(slots: &[VerificationType]) -> Vec<VerificationType> {
+    let mut entries = Vec::new();
+    let mut index = 0;
+
+    while index < slots.len() {
+        entries.push(slots[index].clone());
+
+        index += match slots[index] {
+            VerificationType::Long | VerificationType::Double => 2,
+            _ => 1,
+        };
+    }
+
+    entries
+}
+
+fn entries_to_slots(entries: &[VerificationType]) -> Vec<VerificationType> {
+    let mut slots = Vec::new();
+
+    for entry in entries {
+        slots.push(entry.clone());
+
+        if matches!(entry, VerificationType::Long | VerificationType::Double) {
+            slots.push(VerificationType::Top);
+        }
+    }
+
+    slots
+}
+
+/// The entries for the locals of a stack map frame, which leave out unusable locals at the end.
+fn local_entries(locals: &[VerificationType]) -> Vec<VerificationType> {
+    let used = locals
+        .iter()
+        .rposition(|local| *local != VerificationType::Top)
+        .map_or(0, |last| last + 1);
+
+    slots_to_entries(&locals[..used])
+}
+
+/// Encode the frames found by analysing a method as stack map frames, each described using the
+/// smallest frame type that can express its difference from the previous frame.
+pub fn stack_map_frames(
+    stack_map: &StackMap,
+    initial_locals: &[VerificationType],
+) -> Vec<StackMapFrame> {
+    let mut stack_map_frames = Vec::new();
+    let mut previous_locals = local_entries(initial_locals);
+    let mut previous_offset = None;
+
+    for (offset, frame) in &stack_map.frames {
+        let offset_delta = match previous_offset {
+            Some(previous_offset) => offset - previous_offset - 1,
+            None => *offset,
+        };
+
+        let locals = local_entries(&frame.locals);
+        let stack = slots_to_entries(&frame.stack);
+
+        let frame_type = if locals == previous_locals && stack.is_empty() {
+            StackMapFrameType::Same
+        } else if locals == previous_locals && stack.len() == 1 {
+            StackMapFrameType::SameLocals1StackItem(stack[0].clone())
+        } else if stack.is_empty()
+            && locals.len() < previous_locals.len()
+            && previous_locals.len() - locals.len() <= 3
+            && previous_locals.starts_with(&locals)
+        {
+            StackMapFrameType::Chop((previous_locals.len() - locals.len()) as u8)
+        } else if stack.is_empty()
+            && locals.len() > previous_locals.len()
+            && locals.len() - previous_locals.len() <= 3
+            && locals.starts_with(&previous_locals)
+        {
+            StackMapFrameType::Append(locals[previous_locals.len()..].to_vec())
+        } else {
+            StackMapFrameType::Full(locals.clone(), stack)
+        };
+
+        stack_map_frames.push(StackMapFrame {
+            offset_delta: offset_delta as u16,
+            frame_type,
+        });
+        previous_locals = locals;
+        previous_offset = Some(*offset);
+    }
+
+    stack_map_frames
+}
+
+/// Decode stack map frames into the full frame at each of their offsets.
+pub fn expand_frames(
+    stack_map_frames: &[StackMapFrame],
+    initial_locals: &[VerificationType],
+) -> Result<BTreeMap<usize, Frame>, String> {
+    let mut frames = BTreeMap::new();
+    let mut locals = local_entries(initial_locals);
+    let mut previous_offset = None;
+
+    for stack_map_frame in stack_map_frames {
+        let offset = match previous_offset {
+            Some(previous_offset) => previous_offset + stack_map_frame.offset_delta as usize + 1,
+            None => stack_map_frame.offset_delta as usize,
+        };
+
+        let stack = match &stack_map_frame.frame_type {
+            StackMapFrameType::Same => vec![],
+            StackMapFrameType::SameLocals1StackItem(stack) => vec![stack.clone()],
+            StackMapFrameType::Chop(chopped) => {
+                match locals.len().checked_sub(*chopped as usize) {
+                    Some(remaining) => locals.truncate(remaining),
+                    None => {
+                        return Err(format!(
+                            "Stack map frame at {} removes more locals than there are",
+                            offset
+                        ))
+                    }
+                }
+                vec![]
+            }
+            StackMapFrameType::Append(appended) => {
+                locals.extend_from_slice(appended);
+                vec![]
+            }
+            StackMapFrameType::Full(full_locals, stack) => {
+                locals = full_locals.clone();
+                stack.clone()
+            }
+        };
+
+        frames.insert(
+            offset,
+            Frame {
+                locals: entries_to_slots(&locals),
+                stack: entries_to_slots(&stack),
+            },
+        );
+        previous_offset = Some(offset);
+    }
+
+    Ok(frames)
+}
+
+/// Whether a value of one type can be used where a frame claims another. Without the class
+/// hierarchy, an object can only be used as an object of the same class or as an Object.
+fn is_assignable(
+    from: &VerificationType,
+    to: &VerificationType,
+    object_class: &VerificationType,
+) -> bool {
+    match (from, to) {
+        (_, VerificationType::Top) => true,
+        (VerificationType::Null, VerificationType::Object(_)) => true,
+        (VerificationType::Object(_), to) if to == object_class => true,
+        (from, to) => from == to,
+    }
+}
+
+/// Check a method's stack map frames against the types found by analysing it. Every branch target
+/// must have a frame, and each frame may only claim types which the locals and stack are known to
+/// hold there.
+#[allow(dead_code)]
+pub fn verify(
+    instructions: &[Instruction],
+    exception_table: &[ExceptionTableEntry],
+    stack_map_frames: &[StackMapFrame],
+    initial_locals: Vec<VerificationType>,
+    class_name: &str,
+    constant_pool: &mut Vec<ConstantPoolEntry>,
+) -> Result<(), String> {
+    let recorded = expand_frames(stack_map_frames, &initial_locals)?;
+    let analysed = analyze(
+        instructions,
+        exception_table,
+        initial_locals,
+        class_name,
+        constant_pool,
+    )?;
+    let object_class = object_type("java/lang/Object", constant_pool);
+
+    for (offset, frame) in &analysed.frames {
+        let recorded_frame = match recorded.get(offset) {
+            Some(recorded_frame) => recorded_frame,
+            None => return Err(format!("Missing stack map frame at instruction {}", offset)),
+        };
+
+        let mismatch = || {
+            format!(
+                "Stack map frame {:?} at instruction {} does not match the analysed frame {:?}",
+                recorded_frame, offset, frame
+            )
+        };
+
+        if frame.stack.len() != recorded_frame.stack.len() {
+            return Err(mismatch());
+        }
+
+        for (from, to) in frame.stack.iter().zip(recorded_frame.stack.iter()) {
+            if !is_assignable(from, to, &object_class) {
+                return Err(mismatch());
+            }
+        }
+
+        for (local_index, to) in recorded_frame.locals.iter().enumerate() {
+            let from = frame
+                .locals
+                .get(local_index)
+                .unwrap_or(&VerificationType::Top);
+
+            if !is_assignable(from, to, &object_class) {
+                return Err(mismatch());
+            }
+        }
+    }
+
+    Ok(())
+}
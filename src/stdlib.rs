@@ -0,0 +1,562 @@
+//! Built-in implementations of the parts of the java standard library supported by the JVM.
+use crate::descriptor::{parse_descriptor, FieldType};
+use crate::java_class::{ConstantPoolEntry, ConstantPoolExt};
+use crate::jvm::{pretty_print, Jvm, Lambda, Object, ObjectData};
+use crate::Primitive;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Read the backing text of a string on the heap.
+fn heap_string(heap: &[Object], reference: usize) -> Result<String, String> {
+    match heap.get(reference).and_then(|object| object.as_string()) {
+        Some(text) => Ok(text.to_string()),
+        None => Err(format!("Reference {} is not a string", reference)),
+    }
+}
+
+/// Read a loadable constant as it would appear in a concatenated string.
+fn constant_string(constant_pool: &[ConstantPoolEntry], index: usize) -> Result<String, String> {
+    match constant_pool.get(index.wrapping_sub(1)) {
+        Some(ConstantPoolEntry::String(string_index)) => {
+            match constant_pool.get(string_index.wrapping_sub(1)) {
+                Some(ConstantPoolEntry::Utf8(text)) => Ok(text.clone()),
+                _ => Err(format!("String constant {} has no text", index)),
+            }
+        }
+        Some(entry) => Ok(entry.get_primitive()?.pretty_print()),
+        None => Err(format!("Constant pool entry {} not found", index)),
+    }
+}
+
+/// Render a value of the given type the way string concatenation would.
+fn concat_string(value: &Primitive, field_type: &FieldType, heap: &[Object]) -> String {
+    match (field_type, value) {
+        // Chars and booleans are represented as ints
+        (FieldType::Char, Primitive::Int(c)) => String::from_utf16_lossy(&[*c as u16]),
+        (FieldType::Boolean, Primitive::Int(b)) => (*b != 0).to_string(),
+        (_, Primitive::Null) => String::from("null"),
+        _ => pretty_print(value, heap),
+    }
+}
+
+impl Jvm {
+    /// Link and run an invokedynamic call site, pushing the result onto the current stack frame.
+    /// Only the bootstrap methods javac uses for string concatenation and lambdas are supported.
+    pub fn invoke_dynamic(&mut self, class_name: &str, index: usize) -> Result<(), String> {
+        let class = match self.class_area.get(class_name) {
+            Some(class) => class,
+            None => return Err(format!("Class {} not found", class_name)),
+        };
+        let constant_pool = &class.constant_pool;
+
+        let (bootstrap_index, name_and_type_index) = match constant_pool.get(index - 1) {
+            Some(ConstantPoolEntry::InvokeDynamic(bootstrap_index, name_and_type_index)) => {
+                (*bootstrap_index, *name_and_type_index)
+            }
+            _ => {
+                return Err(format!(
+                    "Constant pool entry {} is not InvokeDynamic",
+                    index
+                ))
+            }
+        };
+
+        let call_site_descriptor = match constant_pool.name_and_type_parser(&name_and_type_index) {
+            Ok((_name, descriptor)) => descriptor,
+            Err(err) => return Err(format!("Invalid InvokeDynamic name and type: {}", err)),
+        };
+
+        let bootstrap = match class.bootstrap_methods.get(bootstrap_index) {
+            Some(bootstrap) => bootstrap,
+            None => return Err(format!("Bootstrap method {} not found", bootstrap_index)),
+        };
+
+        let (_, bootstrap_class, bootstrap_name, _) =
+            match constant_pool.method_handle_parser(&(bootstrap.bootstrap_method_ref as usize)) {
+                Ok(method_handle) => method_handle,
+                Err(err) => return Err(format!("Invalid bootstrap method handle: {}", err)),
+            };
+
+        let (parameters, return_type) = parse_descriptor(&call_site_descriptor)?;
+
+        let curr_sf = match self.stack_frames.last_mut() {
+            Some(sf) => sf,
+            None => return Err(String::from("No stack frames")),
+        };
+
+        // The arguments stay on the stack until the result is allocated, so that the garbage
+        // collector still sees the references among them
+        let start = match curr_sf.stack.len().checked_sub(parameters.len()) {
+            Some(start) => start,
+            None => return Err(String::from("Not enough arguments for InvokeDynamic")),
+        };
+        let args = &curr_sf.stack[start..];
+
+        let result = match (bootstrap_class.as_str(), bootstrap_name.as_str()) {
+            ("java/lang/invoke/StringConcatFactory", "makeConcatWithConstants") => {
+                // The recipe marks where each argument (\u{1}) and constant (\u{2}) is inserted
+                let mut constants = bootstrap
+                    .bootstrap_arguments
+                    .iter()
+                    .map(|index| constant_string(constant_pool, *index as usize));
+
+                let recipe = match constants.next() {
+                    Some(recipe) => recipe?,
+                    None => return Err(String::from("String concatenation has no recipe")),
+                };
+
+                let mut args = args.iter().zip(parameters.iter());
+                let mut text = String::new();
+
+                for c in recipe.chars() {
+                    match c {
+                        '\u{1}' => match args.next() {
+                            Some((value, field_type)) => {
+                                text.push_str(&concat_string(value, field_type, &self.heap))
+                            }
+                            None => return Err(String::from("Missing concatenation argument")),
+                        },
+                        '\u{2}' => match constants.next() {
+                            Some(constant) => text.push_str(&constant?),
+                            None => return Err(String::from("Missing concatenation constant")),
+                        },
+                        c => text.push(c),
+                    }
+                }
+
+                curr_sf.stack.truncate(start);
+                self.allocate(Object::new_string(text))?
+            }
+            ("java/lang/invoke/LambdaMetafactory", "metafactory") => {
+                // The second static argument is the method the lambda forwards calls to
+                let (reference_kind, class_name, name, descriptor) = match bootstrap
+                    .bootstrap_arguments
+                    .get(1)
+                    .map(|index| constant_pool.method_handle_parser(&(*index as usize)))
+                {
+                    Some(Ok(method_handle)) => method_handle,
+                    _ => return Err(String::from("Invalid lambda implementation method")),
+                };
+
+                // The call site returns an instance of the functional interface
+                let interface = match return_type {
+                    FieldType::Object(interface) => interface,
+                    _ => return Err(String::from("Lambda call site must return an object")),
+                };
+
+                let lambda = self.allocate(Object {
+                    class_name: interface,
+                    fields: IndexMap::new(),
+                    data: ObjectData::None,
+                    monitor_entries: 0,
+                    monitor_owner: 0,
+                    identity_hash: 0,
+                })?;
+
+                // Collecting garbage may have moved the captured values, so they are taken off
+                // the stack only now
+                let captured = match self.stack_frames.last_mut() {
+                    Some(sf) => sf.stack.split_off(start),
+                    None => return Err(String::from("No stack frames")),
+                };
+                self.heap[lambda].data = ObjectData::Lambda(Lambda {
+                    class_name,
+                    name,
+                    descriptor,
+                    reference_kind,
+                    captured,
+                });
+                lambda
+            }
+            _ => {
+                return Err(format!(
+                    "Unsupported bootstrap method {}.{}",
+                    bootstrap_class, bootstrap_name
+                ))
+            }
+        };
+
+        match self.stack_frames.last_mut() {
+            Some(sf) => sf.stack.push(Primitive::Reference(result)),
+            None => return Err(String::from("No stack frames")),
+        }
+        Ok(())
+    }
+
+    /// Allocate a string with the passed text and push its reference onto the current stack frame.
+    fn push_string(&mut self, text: String) -> Result<(), String> {
+        let string = self.allocate(Object::new_string(text))?;
+
+        match self.stack_frames.last_mut() {
+            Some(sf) => sf.stack.push(Primitive::Reference(string)),
+            None => return Err(String::from("No stack frames")),
+        }
+        Ok(())
+    }
+
+    /// Run a method belonging to a class that isn't in the class area. Arguments are popped from
+    /// the current stack frame and any return value is pushed back onto it.
+    pub fn invoke_builtin(
+        &mut self,
+        class_name: &str,
+        method_name: &str,
+        method_descriptor: &str,
+    ) -> Result<(), String> {
+        let is_throwable = self.is_subclass_of(class_name, "java/lang/Throwable");
+
+        let curr_sf = match self.stack_frames.last_mut() {
+            Some(sf) => sf,
+            None => return Err(String::from("No stack frames")),
+        };
+
+        match (class_name, method_name, method_descriptor) {
+            ("java/io/PrintStream", "println", _) => {
+                let value = curr_sf.pop_primitive()?;
+                // The stream the method is called on
+                curr_sf.pop_primitive()?;
+
+                let value_string = match (method_descriptor, &value) {
+                    ("(C)V", Primitive::Int(c)) => String::from_utf16_lossy(&[*c as u16]),
+                    ("(C)V", Primitive::Char(c)) => String::from_utf16_lossy(&[*c]),
+                    // Booleans are held as ints, but print as words
+                    ("(Z)V", Primitive::Int(b)) => (*b != 0).to_string(),
+                    _ => pretty_print(&value, &self.heap),
+                };
+
+                println!("{}", value_string);
+                self.stdout.push_str(value_string.as_str());
+            }
+            ("java/lang/String", "length", "()I") => {
+                let text = heap_string(&self.heap, curr_sf.pop_ref()?)?;
+                curr_sf
+                    .stack
+                    .push(Primitive::Int(text.encode_utf16().count() as i32));
+            }
+            ("java/lang/String", "charAt", "(I)C") => {
+                let index = curr_sf.pop_int()?;
+                let text = heap_string(&self.heap, curr_sf.pop_ref()?)?;
+                let length = text.encode_utf16().count();
+
+                let c = match usize::try_from(index)
+                    .ok()
+                    .and_then(|i| text.encode_utf16().nth(i))
+                {
+                    Some(c) => c,
+                    None => {
+                        return Err(format!(
+                            "java/lang/StringIndexOutOfBoundsException: index {}, length {}",
+                            index, length
+                        ))
+                    }
+                };
+
+                // Chars are represented as ints on the operand stack
+                curr_sf.stack.push(Primitive::Int(c as i32));
+            }
+            // Strings are equal to other strings with the same text, but never to a StringBuilder
+            // even though it holds its text the same way
+            ("java/lang/String", "equals", "(Ljava/lang/Object;)Z") => {
+                let other = curr_sf.pop_primitive()?;
+                let text = heap_string(&self.heap, curr_sf.pop_ref()?)?;
+
+                let is_equal = match other {
+                    Primitive::Reference(other) => self
+                        .heap
+                        .get(other)
+                        .filter(|object| object.class_name == "java/lang/String")
+                        .and_then(|object| object.as_string())
+                        .is_some_and(|other_text| other_text == text),
+                    _ => false,
+                };
+                curr_sf.stack.push(Primitive::Int(is_equal as i32));
+            }
+            ("java/lang/String", "concat", "(Ljava/lang/String;)Ljava/lang/String;") => {
+                let suffix = match curr_sf.pop_primitive()? {
+                    Primitive::Reference(suffix) => heap_string(&self.heap, suffix)?,
+                    _ => return Err(String::from("java/lang/NullPointerException")),
+                };
+                let text = heap_string(&self.heap, curr_sf.pop_ref()?)?;
+
+                self.push_string(text + &suffix)?;
+            }
+            ("java/lang/Math", "max" | "min", _) => {
+                let value2 = curr_sf.pop_primitive()?;
+                let value1 = curr_sf.pop_primitive()?;
+                let is_max = method_name == "max";
+
+                let result = match (value1, value2) {
+                    (Primitive::Int(a), Primitive::Int(b)) => {
+                        Primitive::Int(if is_max { a.max(b) } else { a.min(b) })
+                    }
+                    (Primitive::Long(a), Primitive::Long(b)) => {
+                        Primitive::Long(if is_max { a.max(b) } else { a.min(b) })
+                    }
+                    // NaN wins either way, and otherwise -0.0 is ordered below 0.0 like Java does,
+                    // which total_cmp does too
+                    (Primitive::Float(a), Primitive::Float(b)) => {
+                        Primitive::Float(if a.is_nan() || b.is_nan() {
+                            f32::NAN
+                        } else if (a.total_cmp(&b) == Ordering::Greater) == is_max {
+                            a
+                        } else {
+                            b
+                        })
+                    }
+                    (Primitive::Double(a), Primitive::Double(b)) => {
+                        Primitive::Double(if a.is_nan() || b.is_nan() {
+                            f64::NAN
+                        } else if (a.total_cmp(&b) == Ordering::Greater) == is_max {
+                            a
+                        } else {
+                            b
+                        })
+                    }
+                    _ => return Err(format!("Unsupported arguments for Math.{}", method_name)),
+                };
+
+                curr_sf.stack.push(result);
+            }
+            ("java/lang/Math", "abs", _) => {
+                let result = match curr_sf.pop_primitive()? {
+                    Primitive::Int(a) => Primitive::Int(a.wrapping_abs()),
+                    Primitive::Long(a) => Primitive::Long(a.wrapping_abs()),
+                    Primitive::Double(a) => Primitive::Double(a.abs()),
+                    _ => return Err(String::from("Unsupported argument for Math.abs")),
+                };
+
+                curr_sf.stack.push(result);
+            }
+            ("java/lang/Math", "sqrt", "(D)D") => {
+                let a = curr_sf.pop_double()?;
+                curr_sf.stack.push(Primitive::Double(a.sqrt()));
+            }
+            ("java/lang/Math", "pow", "(DD)D") => {
+                let b = curr_sf.pop_double()?;
+                let a = curr_sf.pop_double()?;
+                curr_sf.stack.push(Primitive::Double(a.powf(b)));
+            }
+            ("java/lang/Integer", "parseInt", "(Ljava/lang/String;)I") => {
+                let text = heap_string(&self.heap, curr_sf.pop_ref()?)?;
+
+                let value = match text.parse::<i32>() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        return Err(format!(
+                            "java/lang/NumberFormatException: For input string: \"{}\"",
+                            text
+                        ))
+                    }
+                };
+
+                curr_sf.stack.push(Primitive::Int(value));
+            }
+            ("java/lang/Integer", "toString", "(I)Ljava/lang/String;") => {
+                let value = curr_sf.pop_int()?;
+
+                self.push_string(value.to_string())?;
+            }
+            // The constructor of Object has nothing to initialize
+            ("java/lang/Object", "<init>", "()V") => {
+                curr_sf.pop_ref()?;
+            }
+            // Objects are only equal to themselves unless their class overrides equals
+            ("java/lang/Object", "equals", "(Ljava/lang/Object;)Z") => {
+                let other = curr_sf.pop_primitive()?;
+                let object = curr_sf.pop_ref()?;
+
+                let is_same = other == Primitive::Reference(object);
+                curr_sf.stack.push(Primitive::Int(is_same as i32));
+            }
+            ("java/lang/Class", "getName", "()Ljava/lang/String;") => {
+                let class_object = curr_sf.pop_ref()?;
+                match self
+                    .heap
+                    .get(class_object)
+                    .and_then(|o| o.fields.get("name"))
+                {
+                    Some(name) => curr_sf.stack.push(name.clone()),
+                    None => return Err(format!("Reference {} is not a class", class_object)),
+                }
+            }
+            ("java/lang/Class", "desiredAssertionStatus", "()Z") => {
+                curr_sf.pop_ref()?;
+                curr_sf.stack.push(Primitive::Int(self.assertions as i32));
+            }
+            (_, "<init>", "()V") if is_throwable => {
+                curr_sf.pop_ref()?;
+            }
+            // The detail message is kept as it was passed, and only turned into a string if the
+            // exception is never caught. Only AssertionError takes messages which aren't strings
+            ("java/lang/AssertionError", "<init>", _) | (_, "<init>", "(Ljava/lang/String;)V")
+                if is_throwable =>
+            {
+                let message = curr_sf.pop_primitive()?;
+                let error = curr_sf.pop_ref()?;
+
+                match self.heap.get_mut(error) {
+                    Some(error) => error.fields.insert(String::from("message"), message),
+                    None => return Err(format!("Reference {} not found on heap", error)),
+                };
+            }
+            (_, "getMessage", "()Ljava/lang/String;") if is_throwable => {
+                let exception = curr_sf.pop_ref()?;
+                let message = match self.heap.get(exception) {
+                    Some(exception) => exception.fields.get("message").cloned(),
+                    None => return Err(format!("Reference {} not found on heap", exception)),
+                };
+
+                // Messages which were passed as other values are turned into strings
+                match message {
+                    Some(Primitive::Reference(message))
+                        if self.heap[message].class_name == "java/lang/String" =>
+                    {
+                        curr_sf.stack.push(Primitive::Reference(message))
+                    }
+                    None | Some(Primitive::Null) => curr_sf.stack.push(Primitive::Null),
+                    Some(message) => {
+                        let text = pretty_print(&message, &self.heap);
+                        self.push_string(text)?
+                    }
+                }
+            }
+            // Unlike its heap index, an object's identity hash survives garbage collection
+            ("java/lang/Object", "hashCode", "()I") => {
+                let object = curr_sf.pop_ref()?;
+                match self.heap.get(object) {
+                    Some(object) => curr_sf.stack.push(Primitive::Int(object.identity_hash)),
+                    None => return Err(format!("Reference {} not found on heap", object)),
+                }
+            }
+            ("java/util/Arrays", "toString", _) => {
+                let element_type = match parse_descriptor(method_descriptor)?.0.first() {
+                    Some(FieldType::Array(element_type)) => (**element_type).clone(),
+                    _ => return Err(String::from("Arrays.toString takes an array")),
+                };
+
+                let text = match curr_sf.pop_primitive()? {
+                    Primitive::Reference(array) => {
+                        let elements = match self.heap.get(array).and_then(|o| o.as_array()) {
+                            Some(elements) => elements,
+                            None => return Err(format!("Reference {} is not an array", array)),
+                        };
+
+                        let elements = elements
+                            .iter()
+                            .map(|value| concat_string(value, &element_type, &self.heap))
+                            .collect::<Vec<_>>();
+                        format!("[{}]", elements.join(", "))
+                    }
+                    _ => String::from("null"),
+                };
+
+                self.push_string(text)?;
+            }
+            // A StringBuilder holds the text built so far like a string does
+            ("java/lang/StringBuilder", "<init>", "()V") => {
+                let builder = curr_sf.pop_ref()?;
+
+                match self.heap.get_mut(builder) {
+                    Some(object) => object.data = ObjectData::String(String::new()),
+                    None => return Err(format!("Reference {} not found on heap", builder)),
+                }
+            }
+            ("java/lang/StringBuilder", "append", _) => {
+                let value = curr_sf.pop_primitive()?;
+                let builder = curr_sf.pop_ref()?;
+
+                let (parameters, _) = parse_descriptor(method_descriptor)?;
+                let value_string = match parameters.first() {
+                    Some(field_type) => concat_string(&value, field_type, &self.heap),
+                    None => return Err(String::from("StringBuilder.append takes one argument")),
+                };
+
+                match self.heap.get_mut(builder).map(|object| &mut object.data) {
+                    Some(ObjectData::String(text)) => text.push_str(&value_string),
+                    _ => return Err(format!("Reference {} is not a StringBuilder", builder)),
+                }
+
+                curr_sf.stack.push(Primitive::Reference(builder));
+            }
+            ("java/lang/StringBuilder", "toString", "()Ljava/lang/String;") => {
+                let text = heap_string(&self.heap, curr_sf.pop_ref()?)?;
+
+                self.push_string(text)?;
+            }
+            ("java/lang/System", "currentTimeMillis", "()J") => {
+                let millis = match self.fixed_clock {
+                    Some(millis) => millis,
+                    None => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(duration) => duration.as_millis() as i64,
+                        Err(err) => return Err(format!("System clock is before 1970: {}", err)),
+                    },
+                };
+
+                curr_sf.stack.push(Primitive::Long(millis));
+            }
+            ("java/lang/System", "exit", "(I)V") => {
+                self.exit_code = Some(curr_sf.pop_int()?);
+                self.stack_frames.clear();
+                self.threads.clear();
+            }
+            // Subclasses of Thread call the constructor without a target, and run themselves
+            ("java/lang/Thread", "<init>", "()V" | "(Ljava/lang/Runnable;)V") => {
+                let target = match method_descriptor {
+                    "()V" => Primitive::Null,
+                    _ => curr_sf.pop_primitive()?,
+                };
+                let thread = curr_sf.pop_ref()?;
+
+                match self.heap.get_mut(thread) {
+                    Some(object) => object.data = ObjectData::Thread { target, id: None },
+                    None => return Err(format!("Reference {} not found on heap", thread)),
+                };
+            }
+            ("java/lang/Thread", "start", "()V") => {
+                let thread = curr_sf.pop_ref()?;
+
+                let runnable = match self.heap.get(thread).map(|object| &object.data) {
+                    Some(ObjectData::Thread { id: Some(_), .. }) => {
+                        return Err(String::from("java/lang/IllegalThreadStateException"))
+                    }
+                    Some(ObjectData::Thread { target, .. }) => match target {
+                        Primitive::Reference(target) => *target,
+                        _ => thread,
+                    },
+                    _ => return Err(format!("Reference {} is not a Thread", thread)),
+                };
+
+                let id = self.start_thread(runnable)?;
+                if let ObjectData::Thread { id: thread_id, .. } = &mut self.heap[thread].data {
+                    *thread_id = Some(id);
+                }
+            }
+            ("java/lang/Thread", "join", "()V") => {
+                let thread = curr_sf.pop_ref()?;
+
+                let id = match self.heap.get(thread).map(|object| &object.data) {
+                    Some(ObjectData::Thread { id: Some(id), .. }) => *id,
+                    // Threads which were never started have nothing to wait for
+                    Some(ObjectData::Thread { id: None, .. }) => return Ok(()),
+                    _ => return Err(format!("Reference {} is not a Thread", thread)),
+                };
+
+                // Call join again on a later turn while the thread is still running
+                if self.is_thread_alive(id) {
+                    let curr_sf = self.stack_frames.last_mut().unwrap();
+                    curr_sf.stack.push(Primitive::Reference(thread));
+                    curr_sf.pc -= 1;
+                    self.yield_requested = true;
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Built-in method {}.{}{} is not implemented",
+                    class_name, method_name, method_descriptor
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,15 @@
-use crate::{class_file_parser, javac, jvm};
+use crate::bytecode::{Comparison, Instruction, Operator, Primitive, PrimitiveType};
+use crate::descriptor::{parse_descriptor, FieldType};
+use crate::java_class::{
+    ClassFlags, ConstantPoolEntry, ConstantPoolExt, MethodFlags, StackMapFrame, StackMapFrameType,
+    VerificationType,
+};
+use crate::{cfg, class_file_parser, javac, json, jvm, optimizer, stack_map};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
 
 /// Javac Tests
 
@@ -32,55 +43,3713 @@ fn main_test() {
     compile_and_run_test("Main.java", "17");
 }
 
-// TODO: Test multiple classes
+#[test]
+fn cast_test() {
+    compile_and_run_test("Cast.java", "35.03");
+}
+
+#[test]
+fn reference_cast_test() {
+    let code = "
+        public class Casts {
+            public static void main(String[] args) {
+                Base base = new Derived();
+                Derived derived = (Derived) base;
+                System.out.println(1);
+                Base plain = new Base();
+                Derived bad = (Derived) plain;
+                System.out.println(2);
+            }
+        }
+
+        class Base {}
+
+        class Derived extends Base {}";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+
+    assert_eq!(
+        jvm.run(),
+        Err(String::from(
+            "java/lang/ClassCastException: class Base cannot be cast to class Derived"
+        ))
+    );
+    assert_eq!(jvm.stdout, "1");
+}
+
+#[test]
+fn do_while_test() {
+    compile_and_run_test("DoWhile.java", "103");
+}
+
+#[test]
+fn break_continue_test() {
+    compile_and_run_test("BreakContinue.java", "12453");
+}
+
+#[test]
+fn wide_params_test() {
+    compile_and_run_test("WideParams.java", "25");
+}
+
+#[test]
+fn switch_test() {
+    compile_and_run_test("Switch.java", "-1105030-120");
+
+    let code = std::fs::read_to_string(file_path("Switch.java")).unwrap();
+    let classes = javac::parse_to_class(code).unwrap();
+    let methods = &classes[0].methods;
+
+    // Dense cases are compiled to a table, with the missing values jumping to the default
+    assert!(methods["name(I)I"]
+        .instructions
+        .iter()
+        .any(|instruction| matches!(
+            instruction,
+            Instruction::TableSwitch(_, 1, targets) if targets.len() == 3
+        )));
+    assert!(methods["sparse(I)I"]
+        .instructions
+        .iter()
+        .any(|instruction| matches!(
+            instruction,
+            Instruction::LookupSwitch(_, pairs) if pairs.len() == 2
+        )));
+}
+
+#[test]
+fn multiple_classes_test() {
+    let code = "
+        public class Main {
+            public static void main(String[] args) {
+                int x = Helper.square(4);
+                System.out.println(x);
+                System.out.println(Helper.count);
+            }
+        }
+
+        class Helper {
+            static int count;
+
+            public static int square(int a) {
+                return a * a;
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    assert_eq!(classes.len(), 2);
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "160");
+}
+
+#[test]
+fn multi_variable_declaration_test() {
+    let code = "
+        public class Declarations {
+            public static void main(String[] args) {
+                int a = 1, b = a + 2, c;
+                c = 4;
+                System.out.println(a * 100 + b * 10 + c);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "134");
+}
+
+#[test]
+fn array_initializer_test() {
+    let code = "
+        public class Initializers {
+            public static void main(String[] args) {
+                int[] numbers = {1, 2, 3, 4};
+                int[] empty = {};
+                float[] halves = {0.5f, 1.5f};
+                int[][] grid = {{1, 2}, {3}};
+
+                int sum = 0;
+                int i = 0;
+                do {
+                    sum = sum + numbers[i];
+                    i = i + 1;
+                } while (i < 4);
+                System.out.println(sum);
+                System.out.println(halves[0] + halves[1]);
+                System.out.println(grid[0][1] + grid[1][0]);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let main = &classes[0].methods["main([Ljava/lang/String;)V"];
+
+    // The empty array is allocated without storing any elements
+    let empty = main
+        .instructions
+        .windows(3)
+        .filter(|window| {
+            matches!(
+                window,
+                [
+                    Instruction::Const(Primitive::Int(0)),
+                    Instruction::NewArray(PrimitiveType::Int),
+                    Instruction::Store(_, PrimitiveType::Reference),
+                ]
+            )
+        })
+        .count();
+    assert_eq!(empty, 1);
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "102.05");
+}
+
+#[test]
+fn for_each_test() {
+    let code = "
+        public class ForEach {
+            public static void main(String[] args) {
+                int[] numbers = {1, 2, 3, 4};
+                int sum = 0;
+                for (int n : numbers) {
+                    sum = sum + n;
+                }
+                System.out.println(sum);
+
+                int[] empty = {};
+                for (int n : empty) {
+                    System.out.println(n);
+                }
+
+                int[][] grid = {{1, 2, 3}, {4, 5, 6}};
+                for (int[] row : grid) {
+                    for (int cell : row) {
+                        if (cell == 2) {
+                            continue;
+                        }
+                        if (cell == 5) {
+                            break;
+                        }
+                        System.out.println(cell);
+                    }
+                }
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "10134");
+}
+
+#[test]
+fn for_each_category_two_test() {
+    let code = "
+        public class ForEachWide {
+            public static void main(String[] args) {
+                long[] numbers = {5000000000L, 2L};
+                long total = 0L;
+                for (long n : numbers) {
+                    total = total + n;
+                }
+                System.out.println(total);
+
+                System.out.println(sum(1L, 2L, 3L));
+            }
+
+            static long sum(long... values) {
+                long total = 0L;
+                for (long value : values) {
+                    total = total + value;
+                }
+                return total;
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "50000000026");
+}
+
+#[test]
+fn string_concatenation_test() {
+    let code = "
+        public class Concatenation {
+            public static void main(String[] args) {
+                System.out.println(\"x=\" + 5);
+
+                String name = \"a\";
+                int three = 3;
+                System.out.println(name + 1 + 2);
+                System.out.println(1 + 2 + name + three + 0.5f);
+                System.out.println(\"\\\"\" + (name + 7) + \"\\\"\");
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "x=5a123a30.5\"a7\"");
+}
+
+#[test]
+fn labeled_break_continue_test() {
+    let code = "
+        public class Labels {
+            public static void main(String[] args) {
+                int[] rows = {1, 2, 3};
+                int[] columns = {1, 2, 3};
+
+                outer:
+                for (int row : rows) {
+                    for (int column : columns) {
+                        if (column == 2) {
+                            continue outer;
+                        }
+                        System.out.println(row * 10 + column);
+                    }
+                }
+
+                int i = 0;
+                search:
+                do {
+                    i = i + 1;
+                    for (int column : columns) {
+                        if (i * column == 6) {
+                            break search;
+                        }
+                    }
+                } while (i < 10);
+                System.out.println(i);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "1121312");
+
+    let undefined = "
+        public class Labels {
+            public static void main(String[] args) {
+                int[] rows = {1};
+                for (int row : rows) {
+                    break missing;
+                }
+            }
+        }";
+    assert_eq!(
+        javac::parse_to_class(undefined.to_string()).err(),
+        Some(String::from("Undefined label missing"))
+    );
+}
+
+#[test]
+fn block_scope_test() {
+    let code = "
+        public class Scope {
+            public static void main(String[] args) {
+                int y = 1;
+                {
+                    int x = 10;
+                    System.out.println(x + y);
+                }
+                {
+                    int x = 20;
+                    System.out.println(x + y);
+                }
+                int z = 3;
+                System.out.println(z);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    // Both declarations of x, and z after them, reuse the slot after y
+    let stores = classes[0].methods["main([Ljava/lang/String;)V"]
+        .instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Store(index, _) => Some(*index),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stores, vec![1, 2, 2, 2]);
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "11213");
+}
+
+#[test]
+fn wide_local_declaration_test() {
+    let code = "
+        public class WideLocals {
+            public static void main(String[] args) {
+                long big = 5000000000L;
+                int small = 3;
+                {
+                    long total = big + (long) small;
+                    System.out.println(total);
+                }
+                int after = small * 2;
+                System.out.println(after);
+                System.out.println(big);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    // The long takes up slots 1 and 2, so the int after it goes in 3. The long declared in the
+    // block is dropped at its end, freeing slots 4 and 5 for the int declared after it
+    let stores = classes[0].methods["main([Ljava/lang/String;)V"]
+        .instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Store(index, store_type) => Some((*index, store_type.slot_count())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stores, vec![(1, 2), (3, 1), (4, 2), (4, 1)]);
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "500000000365000000000");
+}
+
+#[test]
+fn redeclared_local_test() {
+    let code = "
+        public class Scope {
+            public static void main(String[] args) {
+                int x = 1;
+                {
+                    int x = 2;
+                }
+            }
+        }";
+
+    assert!(javac::parse_to_class(code.to_string()).is_err());
+}
+
+#[test]
+fn instance_method_test() {
+    let code = "
+        public class Counter {
+            public static void main(String[] args) {
+                System.out.println(1);
+            }
+
+            int twice(int a) {
+                return a * 2;
+            }
+
+            int quadruple(int a) {
+                return twice(twice(a));
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let methods = &classes[0].methods;
+
+    assert!(methods["main([Ljava/lang/String;)V"].is_static());
+    assert!(!methods["twice(I)I"].is_static());
+
+    // The receiver is local 0, so the parameter is in local 1, and calls load it below the argument
+    assert!(matches!(
+        methods["quadruple(I)I"].instructions[..3],
+        [
+            Instruction::Load(0, PrimitiveType::Reference),
+            Instruction::Load(0, PrimitiveType::Reference),
+            Instruction::Load(1, PrimitiveType::Int),
+        ]
+    ));
+
+    let code = "
+        public class Counter {
+            public static void main(String[] args) {
+                System.out.println(twice(1));
+            }
+
+            int twice(int a) {
+                return a * 2;
+            }
+        }";
+
+    assert_eq!(
+        javac::parse_to_class(code.to_string()).err(),
+        Some(String::from(
+            "Non-static method twice cannot be referenced from a static context"
+        ))
+    );
+}
+
+#[test]
+fn instanceof_test() {
+    let code = "
+        public class Shapes {
+            public static void main(String[] args) {
+                System.out.println(1);
+            }
+
+            static int classify(Object obj) {
+                if (obj instanceof Point) {
+                    return 1;
+                }
+                if (obj instanceof Shape) {
+                    return 2;
+                }
+                return 0;
+            }
+
+            static int pointX(Object obj) {
+                if (obj instanceof Point p) {
+                    return p.x;
+                }
+                return 0;
+            }
+
+            static boolean isShape(Object obj) {
+                return obj instanceof Shape;
+            }
+        }
+
+        class Shape {
+            int sides;
+        }
+
+        class Point extends Shape {
+            int x;
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+
+    let mut new_object = |class_name: &str| {
+        jvm.heap.push(jvm::Object {
+            class_name: class_name.to_string(),
+            fields: IndexMap::from([(String::from("x"), Primitive::Int(7))]),
+            data: jvm::ObjectData::None,
+            monitor_entries: 0,
+            monitor_owner: 0,
+            identity_hash: 0,
+        });
+        Primitive::Reference(jvm.heap.len() - 1)
+    };
+    let point = new_object("Point");
+    let shape = new_object("Shape");
+    let string = new_object("java/lang/String");
+
+    let mut call = |signature: &str, argument: &Primitive| {
+        jvm.invoke_static("Shapes", signature, vec![argument.clone()])
+            .unwrap()
+    };
+
+    let classify = "classify(Ljava/lang/Object;)I";
+    assert_eq!(call(classify, &point), Some(Primitive::Int(1)));
+    assert_eq!(call(classify, &shape), Some(Primitive::Int(2)));
+    assert_eq!(call(classify, &string), Some(Primitive::Int(0)));
+    assert_eq!(call(classify, &Primitive::Null), Some(Primitive::Int(0)));
+
+    let point_x = "pointX(Ljava/lang/Object;)I";
+    assert_eq!(call(point_x, &point), Some(Primitive::Int(7)));
+    assert_eq!(call(point_x, &shape), Some(Primitive::Int(0)));
+
+    let is_shape = "isShape(Ljava/lang/Object;)Z";
+    assert_eq!(call(is_shape, &point), Some(Primitive::Int(1)));
+    assert_eq!(call(is_shape, &string), Some(Primitive::Int(0)));
+}
+
+#[test]
+fn instanceof_builtin_class_test() {
+    let code = "
+        public class Builtins {
+            public static void main(String[] args) {
+                Object text = \"four\";
+                if (text instanceof String s) {
+                    System.out.println(s);
+                }
+                if (text instanceof Exception) {
+                    System.out.println(0);
+                }
+                try {
+                    throw new IllegalStateException(\"bad\");
+                } catch (RuntimeException e) {
+                    if (e instanceof IllegalStateException) {
+                        System.out.println(1);
+                    }
+                    if (e instanceof IllegalArgumentException illegal) {
+                        System.out.println(illegal);
+                    }
+                    if (e instanceof RuntimeException runtime) {
+                        System.out.println(2);
+                    }
+                }
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "four12");
+}
+
+#[test]
+fn throw_test() {
+    let code = "
+        public class Thrower {
+            public static void main(String[] args) {
+                check(5);
+                check(0 - 3);
+                System.out.println(2);
+            }
+
+            static void check(int value) {
+                if (value < 0) {
+                    throw new NegativeValue();
+                }
+                System.out.println(value);
+            }
+        }
+
+        class NegativeValue extends RuntimeException {
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+
+    // Nothing catches the exception, so it ends the program
+    assert_eq!(jvm.run(), Err(String::from("NegativeValue")));
+    assert_eq!(jvm.stdout, "5");
+
+    let code = "
+        public class Thrower {
+            public static void main(String[] args) {
+                throw 1;
+            }
+        }";
+
+    assert_eq!(
+        javac::parse_to_class(code.to_string()).err(),
+        Some(String::from("Only objects can be thrown, not Int"))
+    );
+}
+
+#[test]
+fn vm_exception_test() {
+    let code = "
+        public class Divider {
+            public static void main(String[] args) {
+                try {
+                    int z = 1 / 0;
+                    System.out.println(z);
+                } catch (ArithmeticException e) {
+                    System.out.println(1);
+                }
+
+                try {
+                    throw new IllegalStateException();
+                } catch (RuntimeException e) {
+                    System.out.println(2);
+                }
+
+                throw new RuntimeException(\"boom\");
+            }
+        }";
+
+    // Exceptions raised by the JVM are caught like thrown ones, and the built-in exceptions can
+    // be created from source
+    let mut jvm = jvm::Jvm::new(javac::parse_to_class(code.to_string()).unwrap());
+    assert_eq!(
+        jvm.run(),
+        Err(String::from("java/lang/RuntimeException: boom"))
+    );
+    assert_eq!(jvm.stdout, "12");
+
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("VmExceptions.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    assert_eq!(
+        jvm.run(),
+        Err(String::from("java/lang/ArithmeticException: / by zero"))
+    );
+    assert_eq!(
+        jvm.stdout,
+        "/ by zeroIndex 5 out of bounds for length 2npestate"
+    );
+}
+
+#[test]
+fn assert_test() {
+    let source = std::fs::read_to_string(file_path("Assert.java")).unwrap();
+
+    for classes in [
+        vec![class_file_parser::parse_file_to_class(file_path("Assert.class")).unwrap()],
+        javac::parse_to_class(source).unwrap(),
+    ] {
+        // Assertions are disabled by default, so the failing one is skipped
+        let mut jvm = jvm::Jvm::new(classes);
+        let snapshot = jvm.snapshot();
+        jvm.run().unwrap();
+        assert_eq!(jvm.stdout, "34");
+
+        // Once enabled, the failing assertion throws an error with its message
+        jvm.restore(snapshot);
+        jvm.stdout.clear();
+        jvm.assertions = true;
+        assert_eq!(
+            jvm.run(),
+            Err(String::from("java/lang/AssertionError: x is 3"))
+        );
+        assert_eq!(jvm.stdout, "3");
+    }
+}
+
+#[test]
+fn try_catch_test() {
+    let code = "
+        public class Catcher {
+            public static void main(String[] args) {
+                System.out.println(classify(5));
+                System.out.println(classify(0 - 3));
+                System.out.println(classify(200));
+                check(0 - 1);
+            }
+
+            static int classify(int value) {
+                try {
+                    check(value);
+                } catch (TooSmall e) {
+                    return 1;
+                } catch (RuntimeException e) {
+                    return 2;
+                }
+                return 0;
+            }
+
+            static void check(int value) {
+                if (value < 0) {
+                    throw new TooSmall();
+                }
+                if (value > 100) {
+                    throw new TooLarge();
+                }
+            }
+        }
+
+        class TooSmall extends RuntimeException {
+        }
+
+        class TooLarge extends RuntimeException {
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    // Both handlers cover the call to check, and the first one is tried first
+    let classify = &classes[0].methods["classify(I)I"];
+    let table = &classify.exception_table;
+    assert_eq!(table.len(), 2);
+    assert_eq!(
+        (table[0].start_pc, table[0].end_pc),
+        (table[1].start_pc, table[1].end_pc)
+    );
+    assert!(table[0].handler_pc < table[1].handler_pc);
+    assert!(matches!(
+        classify.instructions[table[0].start_pc..table[0].end_pc],
+        [.., Instruction::InvokeStatic(_)]
+    ));
+
+    let mut jvm = jvm::Jvm::new(classes);
+
+    // The exception thrown outside of the try block is still uncaught
+    assert_eq!(jvm.run(), Err(String::from("TooSmall")));
+    assert_eq!(jvm.stdout, "012");
+}
+
+#[test]
+fn void_return_test() {
+    let code = "
+        public class EarlyReturn {
+            public static void main(String[] args) {
+                show(3);
+                show(0 - 2);
+                show(7);
+            }
+
+            static void show(int value) {
+                if (value < 0) {
+                    return;
+                }
+                System.out.println(value);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+
+    assert_eq!(jvm.run(), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "37");
+
+    // Only void methods return without a value
+    let code = "
+        public class Main {
+            static int missing() {
+                return;
+            }
+        }";
+    assert_eq!(
+        javac::parse_to_class(code.to_string()).err(),
+        Some(String::from(
+            "Missing return value in a method returning Int"
+        ))
+    );
+
+    let code = "
+        public class Main {
+            static void extra() {
+                return 3;
+            }
+        }";
+    assert_eq!(
+        javac::parse_to_class(code.to_string()).err(),
+        Some(String::from("Cannot return a value from a void method"))
+    );
+}
+
+#[test]
+fn discarded_result_test() {
+    let code = "
+        public class SideEffect {
+            public static void main(String[] args) {
+                show(4);
+                System.out.println(show(5));
+            }
+
+            public static int show(int a) {
+                System.out.println(a);
+                return a * 2;
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    // The result of the first call is popped straight away
+    let main = &classes[0].methods["main([Ljava/lang/String;)V"].instructions;
+    assert!(matches!(
+        main[..3],
+        [
+            Instruction::Const(_),
+            Instruction::InvokeStatic(_),
+            Instruction::Pop
+        ]
+    ));
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "4510");
+}
+
+#[test]
+fn peephole_optimizer_test() {
+    let mut instructions = vec![
+        Instruction::Const(Primitive::Int(1)),
+        Instruction::Store(0, PrimitiveType::Int),
+        Instruction::Load(0, PrimitiveType::Int),
+        Instruction::Const(Primitive::Int(2)),
+        Instruction::Pop,
+        Instruction::Load(0, PrimitiveType::Int),
+        Instruction::Store(0, PrimitiveType::Int),
+        Instruction::Convert(PrimitiveType::Int, PrimitiveType::Long),
+        Instruction::Convert(PrimitiveType::Long, PrimitiveType::Int),
+        Instruction::Return(PrimitiveType::Int),
+    ];
+    optimizer::optimize(&mut instructions);
+
+    assert!(matches!(
+        instructions[..],
+        [
+            Instruction::Const(Primitive::Int(1)),
+            Instruction::Dup,
+            Instruction::Store(0, PrimitiveType::Int),
+            Instruction::Return(PrimitiveType::Int),
+        ]
+    ));
+}
+
+#[test]
+fn peephole_optimizer_branch_test() {
+    // The load is jumped to, so it can't be merged with the store before it
+    let mut instructions = vec![
+        Instruction::Store(0, PrimitiveType::Int),
+        Instruction::Load(0, PrimitiveType::Int),
+        Instruction::Const(Primitive::Int(2)),
+        Instruction::Pop,
+        Instruction::Goto(0usize.wrapping_sub(3)),
+    ];
+    optimizer::optimize(&mut instructions);
+
+    assert!(matches!(
+        instructions[..],
+        [
+            Instruction::Store(0, PrimitiveType::Int),
+            Instruction::Load(0, PrimitiveType::Int),
+            Instruction::Goto(target),
+        ] if target == 0usize.wrapping_sub(1)
+    ));
+}
+
+#[test]
+fn optimized_output_test() {
+    for file_name in ["BreakContinue.java", "DoWhile.java", "Cast.java"] {
+        let code = std::fs::read_to_string(file_path(file_name)).unwrap();
+        let mut outputs = vec![];
+
+        for optimize in [false, true] {
+            let classes = javac::parse_sources_to_classes(vec![code.clone()], optimize).unwrap();
+
+            let mut jvm = jvm::Jvm::new(classes);
+            jvm.run().unwrap();
+            outputs.push(jvm.stdout);
+        }
+
+        assert_eq!(outputs[0], outputs[1]);
+    }
+}
+
+#[test]
+fn constant_folding_test() {
+    let code = "
+        public class Fold {
+            public static void main(String[] args) {
+                System.out.println(2 + 3);
+                System.out.println(2 + 3 * 4);
+                System.out.println(2147483647 + 1);
+                System.out.println(1 / 0);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let main = &classes[0].methods["main([Ljava/lang/String;)V"].instructions;
+
+    assert!(matches!(main[1], Instruction::Const(Primitive::Int(5))));
+    assert!(matches!(main[4], Instruction::Const(Primitive::Int(14))));
+    assert!(matches!(
+        main[7],
+        Instruction::Const(Primitive::Int(i32::MIN))
+    ));
+
+    // Division by zero isn't folded so that it throws when the program runs
+    assert!(matches!(main[12], Instruction::Div(PrimitiveType::Int)));
+
+    // Overflow wraps around the same way at runtime
+    let instructions = vec![
+        Instruction::Const(Primitive::Int(i32::MAX)),
+        Instruction::Const(Primitive::Int(1)),
+        Instruction::Add(PrimitiveType::Int),
+        Instruction::Return(PrimitiveType::Int),
+    ];
+    assert_eq!(
+        run_instructions(instructions),
+        Ok(Some(Primitive::Int(i32::MIN)))
+    );
+
+    let mut jvm = jvm::Jvm::new(classes);
+    let result = jvm.run();
+
+    assert_eq!(jvm.stdout, "514-2147483648");
+    assert!(result
+        .unwrap_err()
+        .starts_with("java/lang/ArithmeticException"));
+}
+
+#[test]
+fn compiled_stack_map_test() {
+    let code = "
+        public class Loop {
+            public static void main(String[] args) {
+                int total = 0;
+                int i = 0;
+                do {
+                    int doubled = i * 2;
+                    if (doubled > 4) {
+                        total = total + doubled;
+                    }
+                    i = i + 1;
+                } while (i < 5);
+                System.out.println(total);
+            }
+        }";
+
+    let mut classes = javac::parse_to_class(code.to_string()).unwrap();
+    let class = &mut classes[0];
+    let method = class.methods["main([Ljava/lang/String;)V"].clone();
+
+    // The loop start, the end of the if statement and the loop exit, where doubled is still known
+    // to be an int since every path assigns it
+    assert_eq!(
+        method.stack_map,
+        vec![
+            StackMapFrame {
+                offset_delta: 4,
+                frame_type: StackMapFrameType::Append(vec![
+                    VerificationType::Integer,
+                    VerificationType::Integer
+                ]),
+            },
+            StackMapFrame {
+                offset_delta: 10,
+                frame_type: StackMapFrameType::Append(vec![VerificationType::Integer]),
+            },
+            StackMapFrame {
+                offset_delta: 7,
+                frame_type: StackMapFrameType::Same,
+            },
+        ]
+    );
+
+    let initial_locals = stack_map::initial_locals(
+        "Loop",
+        "main",
+        "([Ljava/lang/String;)V",
+        true,
+        &mut class.constant_pool,
+    )
+    .unwrap();
+    let verified = stack_map::verify(
+        &method.instructions,
+        &method.exception_table,
+        &method.stack_map,
+        initial_locals,
+        "Loop",
+        &mut class.constant_pool,
+    );
+    assert_eq!(verified, Ok(()));
+}
+
+#[test]
+fn unbalanced_stack_test() {
+    let mut constant_pool = Vec::new();
+    let mut analyze = |instructions: Vec<Instruction>| {
+        stack_map::analyze(
+            &instructions,
+            &[],
+            vec![VerificationType::Integer],
+            "Test",
+            &mut constant_pool,
+        )
+        .map(|_| ())
+    };
+
+    assert_eq!(
+        analyze(vec![
+            Instruction::Load(0, PrimitiveType::Int),
+            Instruction::Return(PrimitiveType::Int),
+        ]),
+        Ok(())
+    );
+
+    // A value left behind under the return value
+    assert_eq!(
+        analyze(vec![
+            Instruction::Load(0, PrimitiveType::Int),
+            Instruction::Load(0, PrimitiveType::Int),
+            Instruction::Return(PrimitiveType::Int),
+        ]),
+        Err(String::from(
+            "Stack holds 1 unused slots when returning at instruction 2"
+        ))
+    );
+
+    // An addition with only one operand
+    assert_eq!(
+        analyze(vec![
+            Instruction::Load(0, PrimitiveType::Int),
+            Instruction::Add(PrimitiveType::Int),
+            Instruction::Return(PrimitiveType::Int),
+        ]),
+        Err(String::from("Stack underflow at instruction 1"))
+    );
+}
+
+#[test]
+fn main_class_test() {
+    let code = "
+        public class First {
+            public static void main(String[] args) {
+                System.out.println(1);
+            }
+        }
+
+        class Second {
+            public static void main(String[] args) {
+                System.out.println(2);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+
+    assert_eq!(
+        jvm.run(),
+        Err(String::from(
+            "Multiple classes declare a main method, choose one of: First, Second"
+        ))
+    );
+
+    assert_eq!(jvm.run_class("Second"), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "2");
+}
+
+#[test]
+fn snapshot_restore_test() {
+    let classes = ["StaticInit.class", "Derived.class", "Config.class"]
+        .iter()
+        .map(|name| class_file_parser::parse_file_to_class(file_path(name)).unwrap())
+        .collect();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.start().unwrap();
+
+    // Stop partway through Config's static initializer
+    for _ in 0..8 {
+        jvm.step_once().unwrap();
+    }
+
+    let snapshot = jvm.snapshot();
+    let pc = jvm.pc();
+    let stdout = jvm.stdout.clone();
+    let heap_size = jvm.heap.len();
+
+    assert_eq!(jvm.resume(), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "0120");
+    assert_eq!(
+        jvm.class_area["Derived"].static_fields["value"],
+        Primitive::Int(20)
+    );
+
+    jvm.restore(snapshot);
+
+    assert_eq!(jvm.pc(), pc);
+    assert_eq!(jvm.stdout, stdout);
+    assert_eq!(jvm.heap.len(), heap_size);
+    assert_eq!(
+        jvm.class_area["Derived"].static_fields["value"],
+        Primitive::Int(0)
+    );
+
+    // Running on from the restored state gives the same result
+    assert_eq!(jvm.resume(), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "0120");
+}
+
+#[test]
+fn multiple_files_test() {
+    let main = "
+        public class Main {
+            public static void main(String[] args) {
+                System.out.println(Helper.triple(5));
+            }
+        }";
+
+    let helper = "
+        class Helper {
+            public static int triple(int a) {
+                return a * 3;
+            }
+        }";
+
+    let classes =
+        javac::parse_sources_to_classes(vec![main.to_string(), helper.to_string()], false).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "15");
+}
+
+#[test]
+fn private_method_access_test() {
+    let helper = "
+        class Helper {
+            private static int secret() {
+                return 7;
+            }
+
+            private int hidden() {
+                return 8;
+            }
+
+            public static int reveal() {
+                Helper helper = new Helper();
+                return secret() + helper.hidden();
+            }
+        }";
+
+    // Private methods can be called from within their own class
+    let main = "
+        public class Main {
+            public static void main(String[] args) {
+                System.out.println(Helper.reveal());
+            }
+        }";
+
+    let classes =
+        javac::parse_sources_to_classes(vec![main.to_string(), helper.to_string()], false).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "15");
+
+    // But not from any other class, whether they are static or instance methods
+    for call in ["Helper.secret()", "helper.hidden()"] {
+        let main = format!(
+            "
+            public class Main {{
+                public static void main(String[] args) {{
+                    Helper helper = new Helper();
+                    System.out.println({});
+                }}
+            }}",
+            call
+        );
+
+        let classes =
+            javac::parse_sources_to_classes(vec![main, helper.to_string()], false).unwrap();
+        let mut jvm = jvm::Jvm::new(classes);
+        let err = jvm.run().unwrap_err();
+        assert!(
+            err.contains("java/lang/IllegalAccessError: Main cannot access private method Helper."),
+            "Unexpected error: {}",
+            err
+        );
+    }
+}
+
+#[test]
+fn default_instance_field_test() {
+    let code = "
+        public class Defaults {
+            public static void main(String[] args) {
+                Counter counter = new Counter();
+                System.out.println(counter.get());
+                Base base = counter;
+                System.out.println(base.total());
+                System.out.println(counter.flag);
+                counter.set(4);
+                System.out.println(counter.get());
+            }
+        }
+
+        class Base {
+            long base;
+
+            long total() {
+                return base;
+            }
+        }
+
+        class Counter extends Base {
+            int n;
+            boolean flag;
+
+            int get() {
+                return n;
+            }
+
+            void set(int value) {
+                n = value;
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    // Fields hold the default value of their type until they are first assigned, including
+    // those declared by superclasses
+    assert_eq!(jvm.stdout, "00false4");
+
+    let counter = jvm
+        .heap
+        .iter()
+        .find(|object| object.class_name == "Counter")
+        .unwrap();
+    assert_eq!(counter.dump(&jvm.heap), "Counter{base=0, n=4, flag=0}");
+}
+
+#[test]
+fn unqualified_field_test() {
+    let counter = "
+        class Counter {
+            static int created;
+            int count;
+
+            void reset() {
+                count = 5;
+                created += 1;
+            }
+
+            void add(int amount) {
+                count += amount;
+                count -= 1;
+            }
+
+            int get() {
+                return count * 10 + created;
+            }
+        }";
+
+    let main = "
+        public class Main {
+            public static void main(String[] args) {
+                Counter counter = new Counter();
+                counter.reset();
+                counter.add(3);
+                System.out.println(counter.get());
+            }
+        }";
+
+    let classes =
+        javac::parse_sources_to_classes(vec![main.to_string(), counter.to_string()], false)
+            .unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "71");
+
+    // Static methods have no object to access instance fields through
+    let code = "
+        public class Main {
+            int count;
+
+            static void reset() {
+                count = 5;
+            }
+        }";
+    assert_eq!(
+        javac::parse_to_class(code.to_string()).unwrap_err(),
+        "Instance field count cannot be used in a static method"
+    );
+}
+
+#[test]
+fn nested_call_overload_test() {
+    let code = "
+        public class Main {
+            static int describe(int value) {
+                return 1;
+            }
+
+            static int describe(long value) {
+                return 2;
+            }
+
+            static int describe(float value) {
+                return 3;
+            }
+
+            static int describe(boolean value) {
+                return 4;
+            }
+
+            static int describe(char value) {
+                return 5;
+            }
+
+            static long wide() {
+                return 7L;
+            }
+
+            static float real() {
+                return 0.5f;
+            }
+
+            static boolean flag() {
+                return 1 > 0;
+            }
+
+            static char letter() {
+                return (char) 97;
+            }
+
+            static int number() {
+                return 3;
+            }
+
+            static int twice(int value) {
+                return value * 2;
+            }
+
+            public static void main(String[] args) {
+                System.out.println(describe(wide()));
+                System.out.println(describe(real()));
+                System.out.println(describe(flag()));
+                System.out.println(describe(letter()));
+                System.out.println(describe(twice(number())));
+                System.out.println(letter());
+                System.out.println(wide());
+            }
+        }";
+
+    let mut jvm = jvm::Jvm::new(javac::parse_to_class(code.to_string()).unwrap());
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "23451a7");
+}
+
+#[test]
+fn widened_argument_test() {
+    let code = "
+        public class Main {
+            static double square(double x) {
+                return x * x;
+            }
+
+            static int pick(long value) {
+                return 1;
+            }
+
+            static int pick(double value) {
+                return 2;
+            }
+
+            static long offset(long base, int delta) {
+                return base + (long) delta;
+            }
+
+            public static void main(String[] args) {
+                System.out.println(square(3));
+                System.out.println(pick(5));
+                System.out.println(offset(4, 2));
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    // The int literal is converted with i2d before the call
+    let main = &classes[0].methods["main([Ljava/lang/String;)V"];
+    assert!(matches!(
+        main.instructions[2],
+        Instruction::Convert(PrimitiveType::Int, PrimitiveType::Double)
+    ));
+
+    // An int argument picks the long overload over the double one, as it is more specific
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "9.016");
+}
+
+#[test]
+fn widened_return_test() {
+    let code = "
+        public class Main {
+            static double half(int value) {
+                return value / 2;
+            }
+
+            static long big(int value) {
+                return value * 1000;
+            }
+
+            public static void main(String[] args) {
+                System.out.println(half(9));
+                System.out.println(big(3));
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    // The int quotient is converted before being returned as a double
+    let half = &classes[0].methods["half(I)D"].instructions;
+    assert!(matches!(
+        half[half.len() - 2..],
+        [
+            Instruction::Convert(PrimitiveType::Int, PrimitiveType::Double),
+            Instruction::Return(PrimitiveType::Double)
+        ]
+    ));
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "4.03000");
+
+    // A long can't be returned where an int is expected without a cast
+    let code = "
+        public class Main {
+            static int narrow(long value) {
+                return value;
+            }
+
+            public static void main(String[] args) {
+                System.out.println(narrow(3L));
+            }
+        }";
+    assert_eq!(
+        javac::parse_to_class(code.to_string()).err(),
+        Some(String::from(
+            "Cannot return Long from a method returning Int"
+        ))
+    );
+
+    // Nor can a value of an unrelated type
+    let returning = |return_type: &str, value: &str| {
+        let code = format!(
+            "
+            public class Main {{
+                static {} value() {{
+                    return {};
+                }}
+
+                public static void main(String[] args) {{
+                    value();
+                }}
+            }}",
+            return_type, value
+        );
+        javac::parse_to_class(code).err()
+    };
+    assert_eq!(
+        returning("int", "\"x\""),
+        Some(String::from(
+            "Cannot return Reference from a method returning Int"
+        ))
+    );
+    assert_eq!(
+        returning("boolean", "1"),
+        Some(String::from(
+            "Cannot return Int from a method returning Boolean"
+        ))
+    );
+    assert_eq!(
+        returning("String", "1.5f"),
+        Some(String::from(
+            "Cannot return Float from a method returning Reference"
+        ))
+    );
+}
+
+#[test]
+fn comparison_value_test() {
+    let code = "
+        public class Main {
+            static int test(int x, int y) {
+                boolean same = x == y;
+                boolean less = x < y;
+                if (same) {
+                    return 1;
+                }
+                if (less) {
+                    return 2;
+                }
+                return 3;
+            }
+
+            static boolean longLess(long x, long y) {
+                boolean less = x < y;
+                return less;
+            }
+
+            static boolean doubleGreater(double x, double y) {
+                boolean greater = x > y;
+                return greater;
+            }
+
+            static boolean doubleLessOrEqual(double x, double y) {
+                boolean lessOrEqual = x <= y;
+                return lessOrEqual;
+            }
+        }";
+
+    let mut jvm = jvm::Jvm::new(javac::parse_to_class(code.to_string()).unwrap());
+    let mut test = |x: i32, y: i32| {
+        jvm.invoke_static(
+            "Main",
+            "test(II)I",
+            vec![Primitive::Int(x), Primitive::Int(y)],
+        )
+    };
+
+    assert_eq!(test(4, 4), Ok(Some(Primitive::Int(1))));
+    assert_eq!(test(3, 4), Ok(Some(Primitive::Int(2))));
+    assert_eq!(test(5, 4), Ok(Some(Primitive::Int(3))));
+
+    // Values beyond the int range must not be truncated by the comparison
+    let long_args = |x: i64, y: i64| vec![Primitive::Long(x), Primitive::Long(y)];
+    let double_args = |x: f64, y: f64| vec![Primitive::Double(x), Primitive::Double(y)];
+    let mut call = |signature: &str, args| jvm.invoke_static("Main", signature, args);
+
+    assert_eq!(
+        call("longLess(JJ)Z", long_args(1 << 32, 1 << 33)),
+        Ok(Some(Primitive::Int(1)))
+    );
+    assert_eq!(
+        call("longLess(JJ)Z", long_args(1 << 33, 1 << 32)),
+        Ok(Some(Primitive::Int(0)))
+    );
+    assert_eq!(
+        call("doubleGreater(DD)Z", double_args(0.75, 0.5)),
+        Ok(Some(Primitive::Int(1)))
+    );
+    assert_eq!(
+        call("doubleGreater(DD)Z", double_args(0.5, 0.75)),
+        Ok(Some(Primitive::Int(0)))
+    );
+    assert_eq!(
+        call("doubleGreater(DD)Z", double_args(f64::NAN, 0.5)),
+        Ok(Some(Primitive::Int(0)))
+    );
+    assert_eq!(
+        call("doubleLessOrEqual(DD)Z", double_args(0.5, 0.5)),
+        Ok(Some(Primitive::Int(1)))
+    );
+    assert_eq!(
+        call("doubleLessOrEqual(DD)Z", double_args(f64::NAN, 0.5)),
+        Ok(Some(Primitive::Int(0)))
+    );
+}
+
+#[test]
+fn invoke_static_error_test() {
+    let code = "
+        public class Main {
+            static int divide(int x, int y) {
+                return x / y;
+            }
+
+            static int half(int x) {
+                return divide(x, 2) + divide(x, 0);
+            }
+        }";
+
+    let mut jvm = jvm::Jvm::new(javac::parse_to_class(code.to_string()).unwrap());
+
+    // The frames of a failed invocation are removed, so the JVM can be invoked again
+    assert_eq!(
+        jvm.invoke_static("Main", "half(I)I", vec![Primitive::Int(8)]),
+        Err(String::from("java/lang/ArithmeticException: / by zero"))
+    );
+    assert!(jvm.stack_frames.is_empty());
+
+    assert_eq!(
+        jvm.invoke_static(
+            "Main",
+            "divide(II)I",
+            vec![Primitive::Int(8), Primitive::Int(2)]
+        ),
+        Ok(Some(Primitive::Int(4)))
+    );
+    assert!(jvm.stack_frames.is_empty());
+}
+
+#[test]
+fn bitwise_complement_test() {
+    let code = "
+        public class Main {
+            static int complement(int x) {
+                return ~x;
+            }
+
+            static long wideComplement(long x) {
+                return ~x;
+            }
+
+            public static void main(String[] args) {
+                System.out.println(~5);
+            }
+        }";
+
+    let mut jvm = jvm::Jvm::new(javac::parse_to_class(code.to_string()).unwrap());
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "-6");
+
+    assert_eq!(
+        jvm.invoke_static("Main", "complement(I)I", vec![Primitive::Int(5)]),
+        Ok(Some(Primitive::Int(-6)))
+    );
+    assert_eq!(
+        jvm.invoke_static("Main", "complement(I)I", vec![Primitive::Int(-1)]),
+        Ok(Some(Primitive::Int(0)))
+    );
+    assert_eq!(
+        jvm.invoke_static("Main", "wideComplement(J)J", vec![Primitive::Long(5)]),
+        Ok(Some(Primitive::Long(-6)))
+    );
+}
+
+#[test]
+fn integer_literal_radix_test() {
+    let code = "
+        public class Literals {
+            public static void main(String[] args) {
+                System.out.println(0xFF);
+                System.out.println(0b1010);
+                System.out.println(010);
+                System.out.println(1_000);
+                System.out.println(0xFFFFFFFF);
+                long big = 0xFFFFFFFFL;
+                System.out.println(big);
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "2551081000-14294967295");
+
+    let too_large = "
+        public class TooLarge {
+            public static void main(String[] args) {
+                System.out.println(0x1FFFFFFFF);
+            }
+        }";
+    assert!(javac::parse_to_class(too_large.to_string()).is_err());
+}
+
+#[test]
+fn string_escape_test() {
+    let code = r#"
+        public class Escapes {
+            public static void main(String[] args) {
+                System.out.println("a\tb\n");
+                System.out.println("\"quoted\" \\ A\101\7!");
+            }
+        }"#;
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "a\tb\n\"quoted\" \\ AA\u{7}!");
+
+    assert_eq!(
+        javac::unescape_java_string(r"\uuu00e9\0\377\400"),
+        Ok(String::from("\u{e9}\0\u{ff}\u{20}0"))
+    );
+    assert!(javac::unescape_java_string(r"\q").is_err());
+    assert!(javac::unescape_java_string(r"\u12").is_err());
+}
+
+#[test]
+fn strict_local_types_test() {
+    let code = "
+        public class Mistyped {
+            public static int test() {
+                int x = 5;
+                return x;
+            }
+        }";
+
+    let mut classes = javac::parse_to_class(code.to_string()).unwrap();
+    let method = classes[0].methods.get_mut("test()I").unwrap();
+    let instructions = &mut Rc::get_mut(method).unwrap().instructions;
+    let load = instructions
+        .iter()
+        .position(|instruction| matches!(instruction, Instruction::Load(..)))
+        .unwrap();
+    if let Instruction::Load(index, _) = instructions[load] {
+        instructions[load] = Instruction::Load(index, PrimitiveType::Reference);
+    }
+
+    // Without strict mode the mistyped load goes unnoticed
+    let mut jvm = jvm::Jvm::new(classes);
+    assert_eq!(
+        jvm.invoke_static("Mistyped", "test()I", Vec::new()),
+        Ok(Some(Primitive::Int(5)))
+    );
+
+    jvm.strict = true;
+    assert!(jvm
+        .invoke_static("Mistyped", "test()I", Vec::new())
+        .unwrap_err()
+        .starts_with("Loaded Int(5) from local"));
+}
+
+#[test]
+fn interface_test() {
+    let code = "
+        interface Shape {
+            int sides();
+        }
+
+        public class Square implements Shape {
+            public int sides() {
+                return 4;
+            }
+
+            public static void main(String[] args) {
+                Shape shape = new Square();
+                System.out.println(shape.sides());
+                Shape triangle = new Triangle();
+                System.out.println(triangle.sides());
+            }
+        }
+
+        class Triangle implements Shape {
+            public int sides() {
+                return 3;
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let shape = classes.iter().find(|class| class.name == "Shape").unwrap();
+    assert!(shape.is_interface());
+    assert!(shape.methods["sides()I"].is_abstract());
+    assert!(!shape.methods.contains_key("<init>()V"));
+
+    let square = classes.iter().find(|class| class.name == "Square").unwrap();
+    assert!(!square.is_interface());
+    assert_eq!(square.interfaces, vec![String::from("Shape")]);
+    assert!(square.methods["main([Ljava/lang/String;)V"]
+        .instructions
+        .iter()
+        .any(|instruction| matches!(instruction, Instruction::InvokeInterface(_))));
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "43");
+}
+
+#[test]
+fn abstract_method_error_test() {
+    let code = "
+        interface Named {
+            int name();
+        }
+
+        abstract class Animal {
+            abstract int legs();
+        }
+
+        class Snake extends Animal implements Named {
+        }
+
+        public class Zoo {
+            public static void main(String[] args) {
+                Named named = new Snake();
+                System.out.println(named.name());
+            }
+
+            public static void legs() {
+                Animal animal = new Snake();
+                System.out.println(animal.legs());
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+
+    assert_eq!(
+        jvm.run(),
+        Err(String::from("java/lang/AbstractMethodError: Snake.name()I"))
+    );
+    assert_eq!(
+        jvm.invoke_static("Zoo", "legs()V", Vec::new()),
+        Err(String::from(
+            "java/lang/AbstractMethodError: Animal.legs()I"
+        ))
+    );
+}
+
+#[test]
+fn varargs_test() {
+    let code = "
+        public class Varargs {
+            static int sum(int... xs) {
+                int total = 0;
+                for (int x : xs) {
+                    total = total + x;
+                }
+                return total;
+            }
+
+            static int count(int first, String... rest) {
+                int total = first;
+                for (String s : rest) {
+                    total = total + 1;
+                }
+                return total;
+            }
+
+            static double second(double... xs) {
+                return xs[1];
+            }
+
+            static long last(long first, long... rest) {
+                return first + rest[1];
+            }
+
+            public static void main(String[] args) {
+                System.out.println(sum(1, 2, 3));
+                System.out.println(sum());
+                int[] numbers = {4, 5};
+                System.out.println(sum(numbers));
+                System.out.println(count(10, \"a\", \"b\"));
+                System.out.println(second(1, 2));
+                System.out.println(last(1, 2, 3));
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+
+    let sum = &classes[0].methods["sum([I)I"];
+    assert_ne!(sum.access_flags & MethodFlags::Varargs as u16, 0);
+
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    // Int arguments are widened to the long and double parameters and elements
+    assert_eq!(jvm.stdout, "609122.04");
+}
+
+#[test]
+fn widened_constructor_argument_test() {
+    let code = "
+        public class Main {
+            public static void main(String[] args) {
+                Total total = new Total(7, 2);
+                System.out.println(total.value);
+            }
+        }
+
+        class Total {
+            long value;
+
+            Total(long first, double second) {
+                value = first * 2L;
+            }
+        }";
+
+    let classes = javac::parse_to_class(code.to_string()).unwrap();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "14");
+}
+
+/// JVM Tests
+
+#[test]
+fn add_class_file_test() {
+    test_class("Add.class", "37");
+}
+
+#[test]
+fn array_class_file_test() {
+    test_class("Array.class", "10");
+}
+
+#[test]
+fn hello_world_class_file_test() {
+    test_class("HelloWorld.class", "1");
+}
+
+#[test]
+fn if_class_file_test() {
+    test_class("If.class", "17");
+}
+
+#[test]
+fn cfg_dot_test() {
+    let class = class_file_parser::parse_file_to_class(file_path("If.class")).unwrap();
+    let method = &class.methods["main([Ljava/lang/String;)V"];
+
+    // The code before the if, its body and the code after it, where the condition either skips
+    // the body or falls through into it
+    let blocks = cfg::basic_blocks(method);
+    assert_eq!(
+        blocks
+            .iter()
+            .map(|block| block.successors.clone())
+            .collect::<Vec<_>>(),
+        vec![vec![1, 2], vec![2], vec![]]
+    );
+
+    let dot = cfg::cfg_dot(method);
+    assert!(dot.starts_with("digraph {"));
+    assert_eq!(dot.matches("[label=").count(), 3);
+    assert_eq!(dot.matches(" -> ").count(), 3);
+    assert!(dot.contains("block0 -> block2;"));
+    // Instructions are labelled with their mnemonics
+    assert!(dot.contains("14: if_icmple 17"), "{}", dot);
+}
+
+#[test]
+fn advanced_if_class_file_test() {
+    test_class("AdvancedIf.class", "17");
+}
+
+#[test]
+fn main_class_file_test() {
+    test_class("Main.class", "17");
+}
+
+#[test]
+fn class_class_file_test() {
+    test_class_set(vec!["ClassTest.class", "Point.class"], "90");
+}
+
+#[test]
+fn check_cast_class_file_test() {
+    test_class_set(
+        vec!["CheckCast.class", "CastBase.class", "CastDerived.class"],
+        "142",
+    );
+}
+
+#[test]
+fn identity_hash_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("IdentityHash.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.gc_threshold = 100;
+
+    assert_eq!(jvm.run(), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "11");
+}
+
+#[test]
+fn garbage_collection_test() {
+    let classes = vec![
+        class_file_parser::parse_file_to_class(file_path("Garbage.class")).unwrap(),
+        class_file_parser::parse_file_to_class(file_path("GarbageNode.class")).unwrap(),
+    ];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.gc_threshold = 100;
+
+    assert_eq!(jvm.run(), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "49501000");
+
+    // Over 4000 objects were allocated, but only the list, the first node and the arguments live
+    assert!(jvm.heap.len() < 300);
+
+    // Once main has returned, only the first node is still reachable, through a static field
+    jvm.collect_garbage();
+    assert_eq!(jvm.heap.len(), 1);
+    assert_eq!(
+        jvm.class_area["Garbage"].static_fields["first"],
+        Primitive::Reference(0)
+    );
+    assert_eq!(jvm.heap[0].fields["value"], Primitive::Int(1000));
+}
+
+#[test]
+fn jsr_ret_test() {
+    // The subroutine stores its return address, adds one to the value on the stack and returns
+    let result = run_instructions(vec![
+        Instruction::Const(Primitive::Int(5)),
+        Instruction::Jsr(3),
+        Instruction::Return(PrimitiveType::Int),
+        Instruction::Store(0, PrimitiveType::Reference),
+        Instruction::Const(Primitive::Int(1)),
+        Instruction::Add(PrimitiveType::Int),
+        Instruction::Ret(0),
+    ]);
+    assert_eq!(result, Ok(Some(Primitive::Int(6))));
+
+    // Return addresses aren't references, so they neither keep objects alive nor get relocated
+    let mut jvm = jvm::Jvm::new(Vec::new());
+    for _ in 0..3 {
+        jvm.allocate(jvm::Object::new_string(String::from("garbage")))
+            .unwrap();
+    }
+    jvm.stack_frames.push(jvm::StackFrame {
+        pc: 0,
+        locals: vec![Primitive::ReturnAddress(2)],
+        stack: Vec::new(),
+        method: Rc::new(jvm::Method {
+            access_flags: MethodFlags::Static as u16,
+            instructions: Vec::new(),
+            stack_map: Vec::new(),
+            exception_table: Vec::new(),
+        }),
+        class_name: String::from("Test"),
+    });
+
+    assert_eq!(jvm.collect_garbage(), 3);
+    assert_eq!(jvm.stack_frames[0].locals[0], Primitive::ReturnAddress(2));
+}
+
+#[test]
+fn out_of_memory_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("OutOfMemory.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.max_heap_size = 10_000;
+
+    assert_eq!(
+        jvm.run(),
+        Err(String::from("java/lang/OutOfMemoryError: Java heap space"))
+    );
+    assert!(jvm.heap_size <= 10_000);
+
+    // Programs which only keep a few objects alive fit by collecting garbage
+    let classes = vec![
+        class_file_parser::parse_file_to_class(file_path("Garbage.class")).unwrap(),
+        class_file_parser::parse_file_to_class(file_path("GarbageNode.class")).unwrap(),
+    ];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.max_heap_size = 1_000;
+
+    assert_eq!(jvm.run(), Ok(jvm::StepOutcome::Finished(0)));
+    assert_eq!(jvm.stdout, "49501000");
+}
+
+#[test]
+fn array_object_size_test() {
+    // Arrays take up a cell per element, however much room their vector has reserved
+    let mut elements = Vec::with_capacity(64);
+    elements.extend([Primitive::Int(1), Primitive::Int(2), Primitive::Int(3)]);
+    let array = jvm::Object::new_array(String::from("[I"), elements);
+    assert_eq!(array.size(), 4);
+}
+
+#[test]
+fn string_allocation_heap_limit_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("StringHeap.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.max_heap_size = 1_000;
+
+    // Concatenated strings count towards the heap size, so discarded ones are collected while
+    // the ones kept alive by the array run out of room
+    assert_eq!(
+        jvm.run(),
+        Err(String::from("java/lang/OutOfMemoryError: Java heap space"))
+    );
+    assert_eq!(jvm.stdout, "s4999");
+    assert!(jvm.heap_size <= 1_000);
+}
+
+#[test]
+fn negative_multi_array_size_test() {
+    let classes =
+        vec![
+            class_file_parser::parse_file_to_class(file_path("NegativeMultiArray.class")).unwrap(),
+        ];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.max_heap_size = 1_000;
+
+    // The negative count is reported before trying to make room for the outer dimension
+    assert_eq!(
+        jvm.run(),
+        Err(String::from("java/lang/NegativeArraySizeException: -1"))
+    );
+}
+
+#[test]
+fn object_constructor_test() {
+    let classes = vec![
+        class_file_parser::parse_file_to_class(file_path("ClassTest.class")).unwrap(),
+        class_file_parser::parse_file_to_class(file_path("Point.class")).unwrap(),
+    ];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    // The constructor of Point implicitly calls super(), which runs the built-in one of Object
+    jvm.set_breakpoint("java/lang/Object", "<init>()V", 0)
+        .unwrap();
+
+    assert_eq!(jvm.run().unwrap(), jvm::StepOutcome::Breakpoint);
+    let frame = jvm.current_frame().unwrap();
+    assert_eq!(frame.class_name, "java/lang/Object");
+    match frame.locals[0] {
+        Primitive::Reference(point) => assert_eq!(jvm.heap[point].class_name, "Point"),
+        ref other => panic!("Expected the new point as the receiver, got {:?}", other),
+    }
+
+    assert_eq!(jvm.resume().unwrap(), jvm::StepOutcome::Finished(0));
+    assert_eq!(jvm.stdout, "90");
+}
+
+#[test]
+fn string_literal_class_file_test() {
+    test_class("StringLiteral.class", "hello");
+}
+
+#[test]
+fn string_methods_class_file_test() {
+    test_class("StringMethods.class", "5e");
+}
+
+#[test]
+fn string_equals_concat_class_file_test() {
+    // The string built by concat is not the literal itself
+    test_class("StringEquality.class", "hellotruefalsefalse12");
+}
+
+#[test]
+fn object_field_order_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("FieldOrder.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    let object = jvm
+        .heap
+        .iter()
+        .find(|object| object.class_name == "FieldOrder")
+        .unwrap();
+
+    // Fields are listed in the order they are declared, and assigning one keeps its place
+    assert_eq!(
+        object.dump(&jvm.heap),
+        "FieldOrder{zeta=26, alpha=7, mid=m, beta=2}"
+    );
+}
+
+#[test]
+fn string_index_out_of_bounds_class_file_test() {
+    test_class_error(
+        "StringIndexOutOfBounds.class",
+        "java/lang/StringIndexOutOfBoundsException",
+    );
+}
+
+#[test]
+fn math_builtins_class_file_test() {
+    test_class("MathBuiltins.class", "754.0");
+}
+
+#[test]
+fn math_signed_zero_class_file_test() {
+    test_class("MathSignedZero.class", "0.0-0.00.0-0.01.5NaN");
+}
+
+#[test]
+fn unknown_builtin_test() {
+    // Built-in methods which aren't implemented fail instead of being skipped
+    test_class_error(
+        "UnknownBuiltin.class",
+        "Built-in method java/lang/Math.floor(D)D is not implemented",
+    );
+}
+
+#[test]
+fn integer_parse_class_file_test() {
+    test_class("IntegerParse.class", "437");
+}
+
+#[test]
+fn integer_parse_malformed_class_file_test() {
+    test_class_error(
+        "IntegerParseMalformed.class",
+        "java/lang/NumberFormatException",
+    );
+}
+
+#[test]
+fn fibonacci_class_file_test() {
+    // Deeply recursive, so every invocation shares its method rather than copying it
+    test_class("Fibonacci.class", "6765");
+}
+
+#[test]
+fn field_loop_class_file_test() {
+    // Resolves the same field references on every iteration, which should be served by the cache
+    let start = std::time::Instant::now();
+    test_class("FieldLoop.class", "300000");
+    assert!(start.elapsed() < std::time::Duration::from_secs(30));
+}
+
+#[test]
+fn wide_params_class_file_test() {
+    // The long parameter takes up two slots, so the int parameter is in slot 2
+    test_class("WideParams.class", "25");
+}
+
+#[test]
+fn switch_class_file_test() {
+    test_class("Switch.class", "-1105030-120");
+}
+
+#[test]
+fn stack_map_class_file_test() {
+    test_class("StackMap.class", "111745");
+
+    let mut class = class_file_parser::parse_file_to_class(file_path("StackMap.class")).unwrap();
+
+    // Where the branches of the ternary meet, its result is left on the stack
+    assert_eq!(
+        class.methods["max(II)I"].stack_map,
+        vec![
+            StackMapFrame {
+                offset_delta: 5,
+                frame_type: StackMapFrameType::Same,
+            },
+            StackMapFrame {
+                offset_delta: 0,
+                frame_type: StackMapFrameType::SameLocals1StackItem(VerificationType::Integer),
+            },
+        ]
+    );
+
+    for (name, descriptor) in [("collatz", "(I)I"), ("max", "(II)I"), ("sum", "(I)J")] {
+        let method = class.methods[&format!("{}{}", name, descriptor)].clone();
+        let initial_locals =
+            stack_map::initial_locals("StackMap", name, descriptor, true, &mut class.constant_pool)
+                .unwrap();
+
+        let verified = stack_map::verify(
+            &method.instructions,
+            &method.exception_table,
+            &method.stack_map,
+            initial_locals.clone(),
+            "StackMap",
+            &mut class.constant_pool,
+        );
+        assert_eq!(verified, Ok(()));
+
+        let analysed = stack_map::analyze(
+            &method.instructions,
+            &method.exception_table,
+            initial_locals.clone(),
+            "StackMap",
+            &mut class.constant_pool,
+        )
+        .unwrap();
+        let emitted = stack_map::stack_map_frames(&analysed, &initial_locals);
+
+        // javac also drops locals once they go out of scope, so after the loop in sum its frame
+        // no longer has the loop counter
+        if name == "sum" {
+            assert_eq!(emitted[1].frame_type, StackMapFrameType::Same);
+            assert_eq!(method.stack_map[1].frame_type, StackMapFrameType::Chop(1));
+        } else {
+            assert_eq!(emitted, method.stack_map);
+        }
+    }
+}
+
+#[test]
+fn json_export_test() {
+    let method = jvm::Method {
+        access_flags: MethodFlags::Static as u16,
+        instructions: vec![
+            Instruction::Load(0, PrimitiveType::Int),
+            Instruction::Load(1, PrimitiveType::Int),
+            Instruction::Add(PrimitiveType::Int),
+            Instruction::Return(PrimitiveType::Int),
+        ],
+        stack_map: Vec::new(),
+        exception_table: Vec::new(),
+    };
+
+    assert_eq!(
+        method.to_json(),
+        "{\"access_flags\":8,\"instructions\":[[\"Load\",0,\"Int\"],[\"Load\",1,\"Int\"],\
+         [\"Add\",\"Int\"],[\"Return\",\"Int\"]],\"exception_table\":[]}"
+    );
+
+    // Every kind of operand survives being written out and read back
+    let instructions = vec![
+        Instruction::Const(Primitive::Double(f64::NAN)),
+        Instruction::Const(Primitive::Float(-1.5)),
+        Instruction::Const(Primitive::Long(i64::MIN)),
+        Instruction::Const(Primitive::Null),
+        Instruction::IInc(3, -1),
+        Instruction::If(7, Comparison::GreaterThanOrEqual),
+        Instruction::Convert(PrimitiveType::Int, PrimitiveType::Char),
+        Instruction::TableSwitch(0, -2, vec![1, 2]),
+        Instruction::LookupSwitch(3, vec![(-100, 4), (100, 5)]),
+        Instruction::MultiANewArray(12, 2),
+        Instruction::AThrow,
+    ];
+
+    for instruction in instructions {
+        let json = json::Json::parse(&instruction.to_json().to_string()).unwrap();
+        assert_eq!(
+            format!("{:?}", Instruction::from_json(&json).unwrap()),
+            format!("{:?}", instruction)
+        );
+    }
+
+    assert_eq!(
+        Instruction::from_json(&json::Json::parse("[\"IInc\", 0, 40000]").unwrap()).err(),
+        Some(String::from("40000 is out of range for i16"))
+    );
+}
+
+#[test]
+fn instruction_mnemonic_test() {
+    let instructions = [
+        (Instruction::Load(0, PrimitiveType::Int), "iload_0"),
+        (Instruction::Load(5, PrimitiveType::Reference), "aload 5"),
+        (Instruction::Store(3, PrimitiveType::Double), "dstore_3"),
+        (Instruction::Const(Primitive::Int(-1)), "iconst_m1"),
+        (Instruction::Const(Primitive::Int(100)), "bipush 100"),
+        (Instruction::Const(Primitive::Int(1000)), "sipush 1000"),
+        (Instruction::Const(Primitive::Long(1)), "lconst_1"),
+        (Instruction::Const(Primitive::Float(2.0)), "fconst_2"),
+        (Instruction::Const(Primitive::Float(-0.0)), "ldc -0.0"),
+        (Instruction::Const(Primitive::Double(0.0)), "dconst_0"),
+        (Instruction::Const(Primitive::Double(-0.0)), "ldc2_w -0.0"),
+        (Instruction::ALoad(PrimitiveType::Boolean), "baload"),
+        (Instruction::AStore(PrimitiveType::Char), "castore"),
+        (Instruction::Add(PrimitiveType::Long), "ladd"),
+        (Instruction::IInc(1, -1), "iinc 1, -1"),
+        (
+            Instruction::Convert(PrimitiveType::Int, PrimitiveType::Char),
+            "i2c",
+        ),
+        (
+            Instruction::Convert(PrimitiveType::Double, PrimitiveType::Long),
+            "d2l",
+        ),
+        (Instruction::If(7, Comparison::GreaterThanOrEqual), "ifge 7"),
+        (Instruction::IfICmp(2, Comparison::NotEqual), "if_icmpne 2"),
+        (Instruction::InvokeVirtual(12), "invokevirtual #12"),
+        (Instruction::NewArray(PrimitiveType::Int), "newarray int"),
+        (
+            Instruction::TableSwitch(0, -2, vec![1, 2]),
+            "tableswitch { -2: 1, -1: 2, default: 0 }",
+        ),
+        (Instruction::Return(PrimitiveType::Null), "return"),
+        (Instruction::Return(PrimitiveType::Reference), "areturn"),
+    ];
+
+    for (instruction, mnemonic) in instructions {
+        assert_eq!(instruction.to_string(), mnemonic);
+    }
+}
+
+#[test]
+fn json_import_test() {
+    let add = jvm::Method::from_json(
+        r#"{
+            "access_flags": 9,
+            "instructions": [
+                ["Load", 0, "Int"],
+                ["Load", 1, "Int"],
+                ["Add", "Int"],
+                ["Return", "Int"]
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let class = jvm::Class {
+        name: String::from("Test"),
+        access_flags: ClassFlags::Public as u16,
+        super_class: Some(String::from("java/lang/Object")),
+        interfaces: Vec::new(),
+        bootstrap_methods: Vec::new(),
+        initialized: false,
+        constant_pool: Vec::new(),
+        static_fields: HashMap::new(),
+        instance_fields: IndexMap::new(),
+        string_constants: HashMap::new(),
+        final_fields: HashSet::new(),
+        methods: HashMap::from([(String::from("add(II)I"), Rc::new(add))]),
+        resolved_refs: HashMap::new(),
+    };
+
+    let mut jvm = jvm::Jvm::new(vec![class]);
+    assert_eq!(
+        jvm.invoke_static(
+            "Test",
+            "add(II)I",
+            vec![Primitive::Int(3), Primitive::Int(4)]
+        ),
+        Ok(Some(Primitive::Int(7)))
+    );
+
+    let import = |instructions: &str| {
+        jvm::Method::from_json(&format!(
+            "{{\"access_flags\": 9, \"instructions\": [{}]}}",
+            instructions
+        ))
+        .err()
+    };
+
+    assert_eq!(
+        import("[\"Load\", 0, \"Int\"], [\"Teleport\", 1]"),
+        Some(String::from(
+            "Invalid instruction 1: Unknown instruction Teleport with 1 operands"
+        ))
+    );
+    assert_eq!(
+        import("[\"Goto\", 5]"),
+        Some(String::from(
+            "Invalid instruction 0: Branch to 5 is outside of the method"
+        ))
+    );
+    assert_eq!(
+        import("[\"Load\", 70000, \"Int\"]"),
+        Some(String::from(
+            "Invalid instruction 0: Local 70000 is larger than 65535"
+        ))
+    );
+    // Locals are only checked once the method runs and has its arguments
+    let increment = jvm::Method::from_json(
+        r#"{
+            "access_flags": 9,
+            "instructions": [
+                ["IInc", 5, 1],
+                ["Load", 0, "Int"],
+                ["Return", "Int"]
+            ]
+        }"#,
+    )
+    .unwrap();
+    jvm.class_area
+        .get_mut("Test")
+        .unwrap()
+        .methods
+        .insert(String::from("increment(I)I"), Rc::new(increment));
+    assert_eq!(
+        jvm.invoke_static("Test", "increment(I)I", vec![Primitive::Int(3)]),
+        Err(String::from("Local variable 5 not found"))
+    );
+}
+
+#[test]
+fn descriptor_test() {
+    assert_eq!(
+        parse_descriptor("(J[ILjava/lang/String;[[Ljava/lang/Object;D)V"),
+        Ok((
+            vec![
+                FieldType::Long,
+                FieldType::Array(Box::new(FieldType::Int)),
+                FieldType::Object(String::from("java/lang/String")),
+                FieldType::Array(Box::new(FieldType::Array(Box::new(FieldType::Object(
+                    String::from("java/lang/Object")
+                ))))),
+                FieldType::Double,
+            ],
+            FieldType::Void
+        ))
+    );
+    assert_eq!(
+        parse_descriptor("()[J"),
+        Ok((vec![], FieldType::Array(Box::new(FieldType::Long))))
+    );
+
+    // Types are written back out unchanged
+    let (parameters, _) = parse_descriptor("(ZLjava/util/List;[[D)V").unwrap();
+    let written = parameters
+        .iter()
+        .map(|parameter| parameter.to_string())
+        .collect::<String>();
+    assert_eq!(written, "ZLjava/util/List;[[D");
+
+    assert!(parse_descriptor("(Ljava/lang/String)V").is_err());
+    assert!(parse_descriptor("(V)V").is_err());
+    assert!(parse_descriptor("(I").is_err());
+    assert!(parse_descriptor("I)V").is_err());
+}
+
+#[test]
+fn invocation_type_check_test() {
+    let mut constant_pool = Vec::new();
+    let callee = constant_pool.find_or_add_method_ref("Test", "callee", "(JI)J");
+
+    // Adds its int parameter, from slot 2, to its long parameter
+    let callee_instructions = vec![
+        Instruction::Load(0, PrimitiveType::Long),
+        Instruction::Load(2, PrimitiveType::Int),
+        Instruction::Convert(PrimitiveType::Int, PrimitiveType::Long),
+        Instruction::Add(PrimitiveType::Long),
+        Instruction::Return(PrimitiveType::Long),
+    ];
+
+    let call = |first: Primitive| {
+        run_methods(
+            vec![
+                (
+                    "test",
+                    vec![
+                        Instruction::Const(first),
+                        Instruction::Const(Primitive::Int(2)),
+                        Instruction::InvokeStatic(callee),
+                        Instruction::Return(PrimitiveType::Long),
+                    ],
+                ),
+                ("callee(JI)J", callee_instructions.clone()),
+            ],
+            constant_pool.clone(),
+        )
+    };
+
+    assert_eq!(call(Primitive::Long(40)), Ok(Some(Primitive::Long(42))));
+    assert_eq!(
+        call(Primitive::Int(40)),
+        Err(String::from(
+            "Argument 0 of (JI)J should be J but was Int(40)"
+        ))
+    );
+}
+
+#[test]
+fn double_argument_to_int_parameter_test() {
+    let mut constant_pool = Vec::new();
+    let callee = constant_pool.find_or_add_method_ref("Test", "callee", "(I)I");
+
+    let result = run_methods(
+        vec![
+            (
+                "test",
+                vec![
+                    Instruction::Const(Primitive::Double(1.5)),
+                    Instruction::InvokeStatic(callee),
+                    Instruction::Return(PrimitiveType::Int),
+                ],
+            ),
+            (
+                "callee(I)I",
+                vec![
+                    Instruction::Load(0, PrimitiveType::Int),
+                    Instruction::Return(PrimitiveType::Int),
+                ],
+            ),
+        ],
+        constant_pool,
+    );
+    assert_eq!(
+        result,
+        Err(String::from(
+            "Argument 0 of (I)I should be I but was Double(1.5)"
+        ))
+    );
+
+    // Calls from outside the JVM are checked the same way
+    let code = "
+        public class Callee {
+            public static int identity(int x) {
+                return x;
+            }
+        }";
+    let mut jvm = jvm::Jvm::new(javac::parse_to_class(code.to_string()).unwrap());
+    assert!(jvm
+        .invoke_static("Callee", "identity(I)I", vec![Primitive::Double(1.5)])
+        .is_err());
+    assert_eq!(
+        jvm.invoke_static("Callee", "identity(I)I", vec![Primitive::Int(3)]),
+        Ok(Some(Primitive::Int(3)))
+    );
+}
+
+#[test]
+fn invocation_kind_check_test() {
+    let mut constant_pool = Vec::new();
+    let callee = constant_pool.find_or_add_method_ref("Test", "callee", "()I");
+
+    let callee_instructions = vec![
+        Instruction::Const(Primitive::Int(1)),
+        Instruction::Return(PrimitiveType::Int),
+    ];
+
+    let call = |invoke: Instruction, callee_flags: u16| {
+        run_methods_with_flags(
+            vec![
+                (
+                    "test",
+                    MethodFlags::Static as u16,
+                    vec![
+                        Instruction::Const(Primitive::Null),
+                        invoke,
+                        Instruction::Return(PrimitiveType::Int),
+                    ],
+                ),
+                ("callee()I", callee_flags, callee_instructions.clone()),
+            ],
+            constant_pool.clone(),
+        )
+    };
+
+    assert_eq!(
+        call(
+            Instruction::InvokeStatic(callee),
+            MethodFlags::Static as u16
+        ),
+        Ok(Some(Primitive::Int(1)))
+    );
+    assert_eq!(
+        call(
+            Instruction::InvokeStatic(callee),
+            MethodFlags::Public as u16
+        ),
+        Err(String::from(
+            "Instance method Test.callee()I cannot be invoked statically"
+        ))
+    );
+    assert_eq!(
+        call(
+            Instruction::InvokeVirtual(callee),
+            MethodFlags::Static as u16
+        ),
+        Err(String::from(
+            "Static method Test.callee()I cannot be invoked on an instance"
+        ))
+    );
+}
+
+#[test]
+fn branch_targets_test() {
+    // iconst_0, ifne +7, iconst_1, goto -5, return
+    let instructions = class_file_parser::bytes_to_bytecode(vec![
+        0x03, 0x9a, 0x00, 0x07, 0x04, 0xa7, 0xff, 0xfb, 0xb1,
+    ])
+    .unwrap();
+
+    assert_eq!(instructions.len(), 5);
+    assert!(matches!(instructions[1], Instruction::If(4, _)));
+    assert!(matches!(instructions[3], Instruction::Goto(0)));
+}
+
+#[test]
+fn step_limit_class_file_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("InfiniteLoop.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    let result = jvm.run_with_limit(10_000);
+
+    assert!(result.unwrap_err().contains("Step limit exceeded"));
+    assert_eq!(jvm.instructions_executed, 10_000);
+    assert_eq!(jvm.stack_frames.len(), 1);
+}
+
+#[test]
+fn single_step_class_file_test() {
+    let classes = vec![class_file_parser::parse_file_to_class(file_path("Add.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.start().unwrap();
+
+    // iconst_0, istore_1, iload_1, bipush 20, iadd, istore_1, iload_1, iconst_3, isub
+    let expected_stack_tops = [
+        Some(Primitive::Int(0)),
+        None,
+        Some(Primitive::Int(0)),
+        Some(Primitive::Int(20)),
+        Some(Primitive::Int(20)),
+        None,
+        Some(Primitive::Int(20)),
+        Some(Primitive::Int(3)),
+        Some(Primitive::Int(17)),
+    ];
+
+    for (i, expected) in expected_stack_tops.iter().enumerate() {
+        assert_eq!(jvm.pc(), Some(i));
+        assert_eq!(jvm.step_once().unwrap(), jvm::StepOutcome::Running);
+        assert_eq!(jvm.peek_stack(), expected.as_ref());
+    }
+
+    while jvm.step_once().unwrap() == jvm::StepOutcome::Running {}
+
+    assert!(jvm.current_frame().is_none());
+    assert_eq!(jvm.stdout, "37");
+}
+
+#[test]
+fn breakpoint_class_file_test() {
+    let classes = vec![class_file_parser::parse_file_to_class(file_path("Add.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    // Before the call to add, once x and y have been stored
+    jvm.set_breakpoint("Main", "main([Ljava/lang/String;)V", 10)
+        .unwrap();
+    // Before the addition inside add
+    jvm.set_breakpoint("Main", "add(II)I", 2).unwrap();
+
+    assert_eq!(jvm.run().unwrap(), jvm::StepOutcome::Breakpoint);
+    assert_eq!(jvm.pc(), Some(10));
+    let locals = &jvm.current_frame().unwrap().locals;
+    assert_eq!(locals[1], Primitive::Int(20));
+    assert_eq!(locals[2], Primitive::Int(17));
+
+    assert_eq!(jvm.resume().unwrap(), jvm::StepOutcome::Breakpoint);
+    assert_eq!(jvm.stack_frames.len(), 2);
+    assert_eq!(jvm.peek_stack(), Some(&Primitive::Int(17)));
+
+    assert_eq!(jvm.resume().unwrap(), jvm::StepOutcome::Finished(0));
+    assert_eq!(jvm.stdout, "37");
+}
+
+#[test]
+fn invoke_static_class_file_test() {
+    let classes = vec![class_file_parser::parse_file_to_class(file_path("Add.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    let result = jvm.invoke_static(
+        "Main",
+        "add(II)I",
+        vec![Primitive::Int(2), Primitive::Int(3)],
+    );
+
+    assert_eq!(result, Ok(Some(Primitive::Int(5))));
+    assert!(jvm.stack_frames.is_empty());
+}
+
+#[test]
+fn args_class_file_test() {
+    let classes = vec![class_file_parser::parse_file_to_class(file_path("Args.class")).unwrap()];
+    let args = vec![String::from("hello"), String::from("world")];
+    let mut jvm = jvm::Jvm::with_args(classes, args);
+
+    jvm.run().unwrap();
+
+    assert_eq!(jvm.stdout, "2hello");
+}
+
+#[test]
+fn system_exit_class_file_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("SystemExit.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    assert_eq!(jvm.run(), Ok(jvm::StepOutcome::Finished(42)));
+    assert_eq!(jvm.stdout, "1");
+}
+
+#[test]
+fn wide_locals_test() {
+    // bipush 7, wide istore 300, wide iinc 300 5, wide iload 300, ireturn
+    let instructions = class_file_parser::bytes_to_bytecode(vec![
+        0x10, 0x07, 0xc4, 0x36, 0x01, 0x2c, 0xc4, 0x84, 0x01, 0x2c, 0x00, 0x05, 0xc4, 0x15, 0x01,
+        0x2c, 0xac,
+    ])
+    .unwrap();
+
+    assert_eq!(instructions.len(), 5);
+    assert!(matches!(instructions[1], Instruction::Store(300, _)));
+    assert!(matches!(instructions[2], Instruction::IInc(300, 5)));
+
+    let result = run_instructions(instructions);
+
+    assert_eq!(result, Ok(Some(Primitive::Int(12))));
+}
+
+#[test]
+fn wide_iinc_constant_test() {
+    // bipush 100, istore_0, wide iinc 0 -200, iinc 0 -3, iload_0, ireturn
+    let instructions = class_file_parser::bytes_to_bytecode(vec![
+        0x10, 0x64, 0x3b, 0xc4, 0x84, 0x00, 0x00, 0xff, 0x38, 0x84, 0x00, 0xfd, 0x1a, 0xac,
+    ])
+    .unwrap();
+
+    // Both forms sign-extend their constant
+    assert!(matches!(instructions[2], Instruction::IInc(0, -200)));
+    assert!(matches!(instructions[3], Instruction::IInc(0, -3)));
+
+    let result = run_instructions(instructions);
+
+    assert_eq!(result, Ok(Some(Primitive::Int(-103))));
+}
+
+#[test]
+fn multi_array_class_file_test() {
+    test_class("MultiArray.class", "703true");
+}
+
+#[test]
+fn interface_call_class_file_test() {
+    test_class_set(
+        vec![
+            "InterfaceCall.class",
+            "Shape.class",
+            "Square.class",
+            "Rectangle.class",
+        ],
+        "166",
+    );
+}
+
+#[test]
+fn codeless_method_test() {
+    let classes: Vec<_> = ["Codeless.class", "Animal.class", "Dog.class"]
+        .iter()
+        .map(|name| class_file_parser::parse_file_to_class(file_path(name)).unwrap())
+        .collect();
+
+    // Methods without a Code attribute are still loaded, with their access flags
+    let legs = &classes[1].methods["legs()I"];
+    assert!(legs.is_abstract());
+    assert!(legs.instructions.is_empty());
+    assert!(classes[0].methods["nativeValue()I"].is_native());
+
+    let mut jvm = jvm::Jvm::new(classes);
+    assert_eq!(
+        jvm.run(),
+        Err(String::from(
+            "java/lang/UnsatisfiedLinkError: Codeless.nativeValue()I"
+        ))
+    );
+    assert_eq!(jvm.stdout, "4");
+}
+
+#[test]
+fn string_concat_class_file_test() {
+    test_class("StringConcat.class", "hello world x3!");
+}
+
+#[test]
+fn lambda_class_file_test() {
+    test_class_set(vec!["Lambda.class", "IntOp.class"], "1615");
+}
 
-/// JVM Tests
+#[test]
+fn long_compare_class_file_test() {
+    // Subtracting Long.MIN_VALUE from Long.MAX_VALUE overflows, so the comparison can't use it
+    test_class("LongCompare.class", "truefalse");
+}
 
 #[test]
-fn add_class_file_test() {
-    test_class("Add.class", "37");
+fn dup2_wide_test() {
+    // lconst_1, dup2, ladd, lreturn
+    let instructions = class_file_parser::bytes_to_bytecode(vec![0x0a, 0x5c, 0x61, 0xad]).unwrap();
+    assert_eq!(run_instructions(instructions), Ok(Some(Primitive::Long(2))));
+
+    // iconst_1, iconst_2, dup2, iadd, iadd, iadd, ireturn
+    let instructions =
+        class_file_parser::bytes_to_bytecode(vec![0x04, 0x05, 0x5c, 0x60, 0x60, 0x60, 0xac])
+            .unwrap();
+    assert_eq!(run_instructions(instructions), Ok(Some(Primitive::Int(6))));
+
+    // iconst_3, lconst_1, dup2_x1, pop2, pop, lreturn
+    let instructions =
+        class_file_parser::bytes_to_bytecode(vec![0x06, 0x0a, 0x5d, 0x58, 0x57, 0xad]).unwrap();
+    assert_eq!(run_instructions(instructions), Ok(Some(Primitive::Long(1))));
 }
 
 #[test]
-fn array_class_file_test() {
-    test_class("Array.class", "10");
+fn synchronized_class_file_test() {
+    test_class("Synchronized.class", "3");
+
+    let mut constant_pool = Vec::new();
+    let object_class = constant_pool.find_or_add_class("java/lang/Object");
+    let run = |instructions| run_instructions_with_pool(instructions, constant_pool.clone());
+
+    assert_eq!(
+        run(vec![
+            Instruction::New(object_class),
+            Instruction::Dup,
+            Instruction::MonitorEnter,
+            Instruction::MonitorExit,
+            Instruction::Return(PrimitiveType::Null),
+        ]),
+        Ok(None)
+    );
+    assert_eq!(
+        run(vec![
+            Instruction::AConstNull,
+            Instruction::MonitorEnter,
+            Instruction::Return(PrimitiveType::Null),
+        ]),
+        Err(String::from("java/lang/NullPointerException"))
+    );
+
+    // Exiting a monitor which was never entered
+    assert_eq!(
+        run(vec![
+            Instruction::New(object_class),
+            Instruction::MonitorExit,
+            Instruction::Return(PrimitiveType::Null),
+        ]),
+        Err(String::from("java/lang/IllegalMonitorStateException"))
+    );
 }
 
 #[test]
-fn hello_world_class_file_test() {
-    test_class("HelloWorld.class", "1");
+fn thread_subclass_class_file_test() {
+    // The subclass runs its own run method, and its fields are its own
+    test_class_set(vec!["ThreadSubclass.class", "Worker.class"], "30done7");
 }
 
 #[test]
-fn if_class_file_test() {
-    test_class("If.class", "17");
+fn threads_class_file_test() {
+    test_class_set(vec!["Threads.class", "Incrementer.class"], "100");
+
+    // Short turns switch threads in the middle of the synchronized blocks, which then have to
+    // wait for the monitor to be released
+    for time_slice in [1, 3, 7] {
+        let classes = ["Threads.class", "Incrementer.class"]
+            .iter()
+            .map(|name| class_file_parser::parse_file_to_class(file_path(name)).unwrap())
+            .collect();
+        let mut jvm = jvm::Jvm::new(classes);
+        jvm.time_slice = time_slice;
+
+        assert_eq!(jvm.run(), Ok(jvm::StepOutcome::Finished(0)));
+        assert_eq!(jvm.stdout, "100");
+        assert_eq!(jvm.threads_started, 2);
+        assert!(jvm.threads.is_empty());
+    }
 }
 
 #[test]
-fn advanced_if_class_file_test() {
-    test_class("AdvancedIf.class", "17");
+fn arrays_to_string_class_file_test() {
+    test_class("ArraysToString.class", "[1, 2, 3][1.0, 0.0][][h, i]null");
 }
 
 #[test]
-fn main_class_file_test() {
-    test_class("Main.class", "17");
+fn equality_class_file_test() {
+    test_class_set(
+        vec!["Equality.class", "Plain.class", "Always.class"],
+        "10101",
+    );
 }
 
 #[test]
-fn class_class_file_test() {
-    test_class_set(vec!["ClassTest.class", "Point.class"], "90");
+fn clock_class_file_test() {
+    let run = |fixed_clock| {
+        let classes =
+            vec![class_file_parser::parse_file_to_class(file_path("Clock.class")).unwrap()];
+        let mut jvm = jvm::Jvm::new(classes);
+        jvm.fixed_clock = fixed_clock;
+        jvm.run().unwrap();
+        jvm.stdout
+    };
+
+    assert_eq!(run(Some(1_700_000_000_000)), "17000000000000");
+
+    // The wall clock is read otherwise, and it is well past 2020
+    let stdout = run(None);
+    let start = stdout[..13].parse::<i64>().unwrap();
+    assert!(start > 1_577_836_800_000);
 }
 
-/// Test Utils
+#[test]
+fn read_fields_after_run_test() {
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("Synchronized.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
 
-#[cfg(target_os = "windows")]
-fn file_path(file_name: &str) -> String {
-    return format!(".\\src\\java_tests\\{}", file_name);
+    assert_eq!(
+        jvm.get_static_field("Synchronized", "counter"),
+        Some(Primitive::Int(3))
+    );
+    assert_eq!(jvm.get_static_field("Synchronized", "missing"), None);
+    assert_eq!(jvm.get_static_field("Missing", "counter"), None);
+
+    // Objects are looked up by their reference
+    let classes =
+        vec![class_file_parser::parse_file_to_class(file_path("FieldOrder.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    let object = jvm
+        .heap
+        .iter()
+        .position(|object| object.class_name == "FieldOrder")
+        .unwrap();
+    assert_eq!(
+        jvm.get_object_field(object, "alpha"),
+        Some(Primitive::Int(7))
+    );
+    assert_eq!(jvm.get_object_field(object, "missing"), None);
+    assert_eq!(jvm.get_object_field(jvm.heap.len(), "alpha"), None);
+
+    // Threads keep their target apart from their fields
+    let classes = ["Threads.class", "Incrementer.class"]
+        .iter()
+        .map(|name| class_file_parser::parse_file_to_class(file_path(name)).unwrap())
+        .collect();
+    let mut jvm = jvm::Jvm::new(classes);
+    jvm.run().unwrap();
+
+    let thread = jvm
+        .heap
+        .iter()
+        .find(|object| object.class_name == "java/lang/Thread")
+        .unwrap();
+    let target = match thread.data {
+        jvm::ObjectData::Thread {
+            target: Primitive::Reference(target),
+            id: Some(_),
+        } => target,
+        ref data => panic!("Unexpected thread data {:?}", data),
+    };
+    assert_eq!(jvm.heap[target].class_name, "Incrementer");
+    assert!(thread.fields.is_empty());
+}
+
+#[test]
+fn trace_test() {
+    let classes = vec![class_file_parser::parse_file_to_class(file_path("Add.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    let traced = Rc::new(RefCell::new(Vec::new()));
+    let log = Rc::clone(&traced);
+    jvm.set_trace(move |sf, instruction| {
+        log.borrow_mut()
+            .push(format!("{} {:?}", sf.pc, instruction));
+    });
+
+    jvm.run().unwrap();
+
+    let traced = traced.borrow();
+    assert_eq!(traced.len(), 22);
+    assert_eq!(traced.len(), jvm.instructions_executed);
+    assert_eq!(traced[0], "0 Const(Int(0))");
+    assert_eq!(jvm.stdout, "37");
+}
+
+#[test]
+fn profile_test() {
+    let classes = vec![class_file_parser::parse_file_to_class(file_path("Profile.class")).unwrap()];
+    let mut jvm = jvm::Jvm::new(classes);
+
+    // Nothing is counted until profiling is enabled
+    assert!(jvm.profile().is_empty());
+
+    jvm.enable_profiling();
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "500");
+
+    let profile = jvm.profile();
+    assert_eq!(profile[0], ("IInc", 300));
+
+    let counts = profile.into_iter().collect::<HashMap<_, _>>();
+    assert_eq!(counts["IfICmp"], 101);
+    assert_eq!(counts["Goto"], 100);
+    assert_eq!(counts["Add"], 1);
+    assert_eq!(
+        counts.values().sum::<u64>() as usize,
+        jvm.instructions_executed
+    );
+}
+
+#[test]
+fn pop2_split_wide_test() {
+    // iconst_1, lconst_1, pop2, pop2: the second pop2 would take half of the long
+    let instructions =
+        class_file_parser::bytes_to_bytecode(vec![0x04, 0x0a, 0x58, 0x58, 0xb1]).unwrap();
+    assert!(run_instructions(instructions).is_err());
+}
+
+#[test]
+fn nan_compare_to_zero_test() {
+    let comparisons = [
+        Comparison::Equal,
+        Comparison::NotEqual,
+        Comparison::LessThan,
+        Comparison::GreaterThan,
+        Comparison::LessThanOrEqual,
+        Comparison::GreaterThanOrEqual,
+    ];
+
+    for comparison in comparisons {
+        let expected = matches!(comparison, Comparison::NotEqual);
+
+        assert_eq!(
+            Primitive::Float(f32::NAN).compare_to_zero(comparison.clone()),
+            Ok(expected)
+        );
+        assert_eq!(
+            Primitive::Double(f64::NAN).compare_to_zero(comparison),
+            Ok(expected)
+        );
+    }
+}
+
+#[test]
+fn nan_float_compare_test() {
+    // fconst_0, fconst_0, fdiv, fconst_0, fcmpl, ireturn
+    let instructions =
+        class_file_parser::bytes_to_bytecode(vec![0x0b, 0x0b, 0x6e, 0x0b, 0x95, 0xac]).unwrap();
+    assert_eq!(run_instructions(instructions), Ok(Some(Primitive::Int(-1))));
+
+    // dconst_0, dconst_0, ddiv, dconst_1, dcmpg, ireturn
+    let instructions =
+        class_file_parser::bytes_to_bytecode(vec![0x0e, 0x0e, 0x6f, 0x0f, 0x98, 0xac]).unwrap();
+    assert_eq!(run_instructions(instructions), Ok(Some(Primitive::Int(1))));
+
+    // fconst_1, fconst_2, fcmpg, ireturn
+    let instructions = class_file_parser::bytes_to_bytecode(vec![0x0c, 0x0d, 0x96, 0xac]).unwrap();
+    assert_eq!(run_instructions(instructions), Ok(Some(Primitive::Int(-1))));
+}
+
+#[test]
+fn static_init_class_file_test() {
+    // Config must be initialized by Derived's initializer, and only once main has started
+    test_class_set(
+        vec!["StaticInit.class", "Derived.class", "Config.class"],
+        "0120",
+    );
+}
+
+#[test]
+fn static_default_class_file_test() {
+    test_class("StaticDefault.class", "01false7");
+}
+
+#[test]
+fn corrupt_field_ref_test() {
+    // The field reference's second index points at a Utf8 entry instead of a NameAndType
+    let constant_pool = vec![
+        ConstantPoolEntry::Utf8(String::from("Test")),
+        ConstantPoolEntry::Class(1),
+        ConstantPoolEntry::FieldRef(2, 1),
+    ];
+
+    let result = run_instructions_with_pool(
+        vec![
+            Instruction::GetStatic(3),
+            Instruction::Return(PrimitiveType::Null),
+        ],
+        constant_pool,
+    );
+
+    assert!(result.unwrap_err().contains("is not a NameAndType"));
+}
+
+#[test]
+fn dynamic_constant_class_file_test() {
+    // Condy.class was assembled by hand since javac doesn't emit dynamic constants
+    let class = class_file_parser::parse_file_to_class(file_path("Condy.class")).unwrap();
+
+    assert!(matches!(
+        class.constant_pool[10],
+        ConstantPoolEntry::Dynamic(0, 10)
+    ));
+
+    // Loading the class works, but resolving the constant isn't implemented yet
+    let mut jvm = jvm::Jvm::new(vec![class]);
+    assert!(jvm
+        .invoke_static("Condy", "value()Ljava/lang/Object;", Vec::new())
+        .is_err());
+}
+
+#[test]
+fn invalid_class_file_test() {
+    // A Java source file is not a class file
+    let result = class_file_parser::parse_file_to_class(file_path("Add.java"));
+
+    assert!(result.unwrap_err().contains("invalid magic number"));
+}
+
+#[test]
+fn missing_class_file_test() {
+    let result = class_file_parser::parse_file_to_class(file_path("Missing.class"));
+
+    assert!(result.unwrap_err().starts_with("Unable to read"));
+}
+
+#[test]
+fn malformed_code_test() {
+    let decode_error = |code: Vec<u8>| class_file_parser::bytes_to_bytecode(code).unwrap_err();
+
+    // sipush and goto_w cut off before their operands end
+    assert_eq!(
+        decode_error(vec![0x00, 0x11, 0x01]),
+        "Code ends in the middle of the operands after byte offset 1"
+    );
+    assert_eq!(
+        decode_error(vec![0xc8, 0x00, 0x00]),
+        "Code ends in the middle of the operands after byte offset 0"
+    );
+    assert_eq!(
+        decode_error(vec![0x00, 0xcb]),
+        "Unsupported instruction 203 at byte offset 1"
+    );
+    // wide can't prefix iadd
+    assert_eq!(
+        decode_error(vec![0xc4, 0x60, 0x00, 0x01]),
+        "Unsupported instruction 96 after wide at byte offset 0"
+    );
+    // goto +4 lands in the middle of sipush
+    assert_eq!(
+        decode_error(vec![0xa7, 0x00, 0x04, 0x11, 0x00, 0x01]),
+        "Branch to byte offset 4 is not an instruction"
+    );
+}
+
+#[test]
+fn truncated_class_file_test() {
+    let bytes = std::fs::read(file_path("Add.class")).unwrap();
+    let path =
+        std::env::temp_dir().join(format!("rustjava_truncated_{}.class", std::process::id()));
+    let path_string = path.to_string_lossy().into_owned();
+
+    // Cutting the class off anywhere after its header is reported rather than panicking
+    for length in 8..bytes.len() {
+        std::fs::write(&path, &bytes[..length]).unwrap();
+        let err = class_file_parser::parse_file_to_class(path_string.clone()).unwrap_err();
+        assert!(
+            err.contains("Class file ends unexpectedly"),
+            "{}: {}",
+            length,
+            err
+        );
+    }
+
+    // Including in the middle of the code of a method
+    std::fs::write(&path, &bytes[..bytes.len() - 40]).unwrap();
+    let err = class_file_parser::parse_file_to_class(path_string.clone()).unwrap_err();
+    assert!(err.contains("Code attribute"), "{}", err);
+
+    // Lengths inside an attribute can run past the end of the file, or past the end of the
+    // attribute. The default constructor is aload_0, invokespecial #1 and return, which follow
+    // its code length of 5
+    let code = [0, 0, 0, 5, 0x2a, 0xb7, 0, 1, 0xb1];
+    let code_length_position = bytes
+        .windows(code.len())
+        .position(|window| window == code)
+        .unwrap();
+    let corrupt_code_length = |code_length: u32| {
+        let mut corrupted = bytes.clone();
+        corrupted[code_length_position..code_length_position + 4]
+            .copy_from_slice(&code_length.to_be_bytes());
+        std::fs::write(&path, corrupted).unwrap();
+        class_file_parser::parse_file_to_class(path_string.clone()).unwrap_err()
+    };
+
+    let err = corrupt_code_length(0xFFFFFFF0);
+    assert!(err.contains("Class file ends unexpectedly"), "{}", err);
+    let err = corrupt_code_length(33);
+    assert!(
+        err.contains("Code attribute is longer than its length"),
+        "{}",
+        err
+    );
+
+    // Bytes after the end of the class are rejected too
+    std::fs::write(&path, [&bytes[..], &[0]].concat()).unwrap();
+    let err = class_file_parser::parse_file_to_class(path_string).unwrap_err();
+    assert!(err.contains("1 extra bytes"), "{}", err);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn typed_array_store_test() {
+    let store_and_load = |element_type: PrimitiveType, value: i32| {
+        run_instructions(vec![
+            Instruction::Const(Primitive::Int(1)),
+            Instruction::NewArray(element_type.clone()),
+            Instruction::Dup,
+            Instruction::Const(Primitive::Int(0)),
+            Instruction::Const(Primitive::Int(value)),
+            Instruction::AStore(element_type.clone()),
+            Instruction::Const(Primitive::Int(0)),
+            Instruction::ALoad(element_type),
+            Instruction::Return(PrimitiveType::Int),
+        ])
+    };
+
+    assert_eq!(
+        store_and_load(PrimitiveType::Byte, 300),
+        Ok(Some(Primitive::Int(44)))
+    );
+    assert_eq!(
+        store_and_load(PrimitiveType::Byte, 200),
+        Ok(Some(Primitive::Int(-56)))
+    );
+    assert_eq!(
+        store_and_load(PrimitiveType::Char, -1),
+        Ok(Some(Primitive::Int(65535)))
+    );
+    assert_eq!(
+        store_and_load(PrimitiveType::Short, 70000),
+        Ok(Some(Primitive::Int(4464)))
+    );
+    assert_eq!(
+        store_and_load(PrimitiveType::Int, 300),
+        Ok(Some(Primitive::Int(300)))
+    );
+
+    // New arrays are filled with default values, and indices past the end throw
+    let out_of_bounds = run_instructions(vec![
+        Instruction::Const(Primitive::Int(2)),
+        Instruction::NewArray(PrimitiveType::Int),
+        Instruction::Dup,
+        Instruction::ArrayLength,
+        Instruction::ALoad(PrimitiveType::Int),
+        Instruction::Return(PrimitiveType::Int),
+    ]);
+    assert!(out_of_bounds
+        .unwrap_err()
+        .starts_with("java/lang/ArrayIndexOutOfBoundsException"));
+
+    // Values which the element type can't hold are rejected rather than kept as they are
+    let store_into_int_array = |value: Primitive| {
+        run_instructions(vec![
+            Instruction::Const(Primitive::Int(1)),
+            Instruction::NewArray(PrimitiveType::Int),
+            Instruction::Const(Primitive::Int(0)),
+            Instruction::Const(value),
+            Instruction::AStore(PrimitiveType::Int),
+            Instruction::Return(PrimitiveType::Null),
+        ])
+    };
+
+    assert_eq!(
+        store_into_int_array(Primitive::Float(1.5)),
+        Err(String::from("Stored Float(1.5) into an array of Int"))
+    );
+    assert_eq!(
+        store_into_int_array(Primitive::Long(2)),
+        Err(String::from("Stored Long(2) into an array of Int"))
+    );
+    assert_eq!(
+        store_into_int_array(Primitive::Null),
+        Err(String::from("Stored Null into an array of Int"))
+    );
+}
+
+#[test]
+fn logical_value_test() {
+    // Conditions assigned to locals or printed directly evaluate to booleans
+    test_class("Logical.class", "falsetruetruetrue");
+    compile_and_run_test("Logical.java", "falsetruetruetrue");
+}
+
+#[test]
+fn object_array_class_file_test() {
+    test_class("ObjectArray.class", "a3c4");
+
+    // The arrays are given the classes of their elements
+    let class = class_file_parser::parse_file_to_class(file_path("ObjectArray.class")).unwrap();
+    let mut jvm = jvm::Jvm::new(vec![class]);
+    jvm.run().unwrap();
+
+    let arrays = jvm
+        .heap
+        .iter()
+        .filter_map(|object| Some((object.class_name.as_str(), object.as_array()?.len())))
+        .collect::<Vec<_>>();
+    assert!(arrays.contains(&("[Ljava/lang/String;", 3)));
+    assert!(arrays.contains(&("[[I", 2)));
+}
+
+#[test]
+fn negative_array_size_test() {
+    let new_array = |count: i32| {
+        run_instructions(vec![
+            Instruction::Const(Primitive::Int(count)),
+            Instruction::NewArray(PrimitiveType::Int),
+            Instruction::ArrayLength,
+            Instruction::Return(PrimitiveType::Int),
+        ])
+    };
+
+    assert_eq!(new_array(0), Ok(Some(Primitive::Int(0))));
+    assert_eq!(
+        new_array(-1),
+        Err(String::from("java/lang/NegativeArraySizeException: -1"))
+    );
+    assert_eq!(
+        new_array(i32::MIN),
+        Err(format!(
+            "java/lang/NegativeArraySizeException: {}",
+            i32::MIN
+        ))
+    );
+}
+
+#[test]
+fn float_remainder_test() {
+    let rem = |a: f64, b: f64| match Primitive::eval2(
+        Primitive::Double(a),
+        Primitive::Double(b),
+        Operator::Rem,
+    ) {
+        Ok(Primitive::Double(result)) => result,
+        other => panic!("Expected a double, got {:?}", other),
+    };
+
+    assert_eq!(rem(5.0, 3.0), 2.0);
+    assert_eq!(rem(-5.0, 3.0), -2.0);
+    assert_eq!(rem(5.0, -3.0), 2.0);
+    assert!(rem(5.0, 0.0).is_nan());
+    assert!(rem(f64::INFINITY, 3.0).is_nan());
+    assert_eq!(rem(5.0, f64::INFINITY), 5.0);
+    // The sign of a zero result comes from the dividend
+    assert!(rem(-6.0, 3.0).is_sign_negative());
+
+    assert!(matches!(
+        Primitive::eval2(
+            Primitive::Float(-5.5),
+            Primitive::Float(2.0),
+            Operator::Rem
+        ),
+        Ok(Primitive::Float(result)) if result == -1.5
+    ));
+}
+
+#[test]
+fn float_to_int_conversion_test() {
+    let to_int = |value: f64| {
+        Primitive::Double(value).eval(Operator::Convert(PrimitiveType::Double, PrimitiveType::Int))
+    };
+
+    assert_eq!(to_int(f64::NAN), Ok(Primitive::Int(0)));
+    assert_eq!(to_int(1e20), Ok(Primitive::Int(i32::MAX)));
+    assert_eq!(to_int(-1e20), Ok(Primitive::Int(i32::MIN)));
+    assert_eq!(to_int(-2.9), Ok(Primitive::Int(-2)));
+    assert_eq!(to_int(f64::INFINITY), Ok(Primitive::Int(i32::MAX)));
+
+    assert_eq!(
+        Primitive::Float(f32::NAN)
+            .eval(Operator::Convert(PrimitiveType::Float, PrimitiveType::Long)),
+        Ok(Primitive::Long(0))
+    );
+    assert_eq!(
+        Primitive::Float(-1e20).eval(Operator::Convert(PrimitiveType::Float, PrimitiveType::Long)),
+        Ok(Primitive::Long(i64::MIN))
+    );
+}
+
+#[test]
+fn exception_table_class_file_test() {
+    let class = class_file_parser::parse_file_to_class(file_path("TryCatch.class")).unwrap();
+    let check = &class.methods["check(I)I"];
+
+    let handlers = check
+        .exception_table
+        .iter()
+        .map(|entry| (entry.start_pc, entry.end_pc, entry.handler_pc))
+        .collect::<Vec<_>>();
+    assert_eq!(handlers, vec![(0, 9, 13), (0, 9, 24), (13, 19, 24)]);
+
+    // The first handler catches Failure and the others are finally blocks catching anything
+    assert_eq!(
+        class
+            .constant_pool
+            .class_parser(&(check.exception_table[0].catch_type as usize)),
+        Ok(String::from("Failure"))
+    );
+    assert_eq!(check.exception_table[1].catch_type, 0);
+    assert!(matches!(
+        check.instructions[13],
+        Instruction::Store(1, PrimitiveType::Reference)
+    ));
+
+    test_class_set(vec!["TryCatch.class", "Failure.class"], "5991-1990");
+}
+
+#[test]
+fn constant_value_class_file_test() {
+    let class = class_file_parser::parse_file_to_class(file_path("Constants.class")).unwrap();
+    let mut jvm = jvm::Jvm::new(vec![class]);
+
+    assert_eq!(
+        jvm.get_static_field("Constants", "ANSWER"),
+        Some(Primitive::Int(42))
+    );
+    assert_eq!(
+        jvm.get_static_field("Constants", "HALF"),
+        Some(Primitive::Float(0.5))
+    );
+    assert_eq!(
+        jvm.get_static_field("Constants", "LETTER"),
+        Some(Primitive::Int('j' as i32))
+    );
+    assert_eq!(
+        jvm.get_static_field("Constants", "ENABLED"),
+        Some(Primitive::Int(1))
+    );
+    assert_eq!(
+        jvm.get_static_field("Constants", "counter"),
+        Some(Primitive::Int(0))
+    );
+
+    // A string constant needs a string on the heap, which is allocated when the class is
+    // initialized
+    assert_eq!(
+        jvm.get_static_field("Constants", "NAME"),
+        Some(Primitive::Null)
+    );
+
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "43");
+
+    let name = match jvm.get_static_field("Constants", "NAME") {
+        Some(Primitive::Reference(reference)) => reference,
+        other => panic!("Expected a string reference, got {:?}", other),
+    };
+    assert_eq!(jvm.heap[name].as_string(), Some("rustjava"));
+}
+
+#[test]
+fn inherited_static_field_test() {
+    test_class_set(vec!["StaticInherit.class", "StaticBase.class"], "77");
+
+    // Without the superclass the field can't be resolved
+    test_class_error(
+        "StaticInherit.class",
+        "java/lang/NoSuchFieldError: StaticInherit.count",
+    );
+}
+
+#[test]
+fn final_field_test() {
+    // Final fields can be assigned by the constructor and the static initializer
+    test_class("Final.class", "7");
+
+    // Marking the field assigned by update() as final makes the write fail after construction
+    let mut class = class_file_parser::parse_file_to_class(file_path("Final.class")).unwrap();
+    assert!(class.final_fields.contains("value"));
+    assert!(class.final_fields.contains("TABLE"));
+    class.final_fields.insert(String::from("mutable"));
+
+    let mut jvm = jvm::Jvm::new(vec![class]);
+    let err = jvm.run().unwrap_err();
+    assert!(
+        err.contains("java/lang/IllegalAccessError: Update to final field Final.mutable"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn wide_constant_class_file_test() {
+    let class = class_file_parser::parse_file_to_class(file_path("Wide.class")).unwrap();
+
+    // Longs and doubles take up two constant pool indices, so later entries keep their numbers
+    assert!(matches!(
+        class.constant_pool[7 - 1],
+        ConstantPoolEntry::Long(9876543210)
+    ));
+    assert!(matches!(
+        class.constant_pool[9 - 1],
+        ConstantPoolEntry::Double(d) if d == 2.5
+    ));
+    assert!(matches!(
+        class.constant_pool[11 - 1],
+        ConstantPoolEntry::Integer(70000)
+    ));
+    assert!(matches!(
+        class.methods["main([Ljava/lang/String;)V"].instructions[0],
+        Instruction::LoadConst(7)
+    ));
+
+    let mut jvm = jvm::Jvm::new(vec![class]);
+    assert_eq!(
+        jvm.get_static_field("Wide", "BIG"),
+        Some(Primitive::Long(1234567890123))
+    );
+
+    jvm.run().unwrap();
+    assert_eq!(jvm.stdout, "98765432115.070000");
+}
+
+#[test]
+fn ldc_class_file_test() {
+    test_class("Ldc.class", "hijava.lang.StringLdc1");
+}
+
+#[test]
+fn small_integer_promotion_test() {
+    assert_eq!(
+        Primitive::eval2(
+            Primitive::Char('a' as u16),
+            Primitive::Char('b' as u16),
+            Operator::Add
+        ),
+        Ok(Primitive::Int(195))
+    );
+    assert_eq!(
+        Primitive::eval2(Primitive::Byte(-1), Primitive::Short(300), Operator::Mul),
+        Ok(Primitive::Int(-300))
+    );
+    assert_eq!(
+        Primitive::eval2(Primitive::Byte(1), Primitive::Int(9), Operator::Shl),
+        Ok(Primitive::Int(512))
+    );
+    assert_eq!(
+        Primitive::Byte(-128).eval(Operator::Neg),
+        Ok(Primitive::Int(128))
+    );
+    assert_eq!(
+        Primitive::Char(65535).eval(Operator::Convert(PrimitiveType::Int, PrimitiveType::Short)),
+        Ok(Primitive::Short(-1))
+    );
+    assert_eq!(
+        Primitive::Char('z' as u16).integer_compare(Primitive::Int(100), Comparison::GreaterThan),
+        Ok(true)
+    );
+}
+
+#[test]
+fn newarray_type_code_test() {
+    let element_types = [
+        PrimitiveType::Boolean,
+        PrimitiveType::Char,
+        PrimitiveType::Float,
+        PrimitiveType::Double,
+        PrimitiveType::Byte,
+        PrimitiveType::Short,
+        PrimitiveType::Int,
+        PrimitiveType::Long,
+    ];
+
+    // bipush 3, newarray <atype>, areturn for each type code from 4 to 11
+    for (atype, element_type) in (4u8..=11).zip(element_types) {
+        let instructions =
+            class_file_parser::bytes_to_bytecode(vec![0x10, 0x03, 0xbc, atype, 0xb0]).unwrap();
+
+        assert_eq!(instructions.len(), 3);
+        match &instructions[1] {
+            Instruction::NewArray(decoded) => assert!(decoded.matches(&element_type)),
+            other => panic!("Expected newarray for type code {}, got {}", atype, other),
+        }
+    }
+
+    // Type code 12 does not name a primitive type
+    assert_eq!(
+        class_file_parser::bytes_to_bytecode(vec![0x10, 0x03, 0xbc, 12, 0xb0]).unwrap_err(),
+        "Invalid newarray type code 12 at byte offset 2"
+    );
+}
+
+#[test]
+fn primitive_type_slot_count_test() {
+    let single_slot_types = [
+        PrimitiveType::Boolean,
+        PrimitiveType::Byte,
+        PrimitiveType::Char,
+        PrimitiveType::Short,
+        PrimitiveType::Int,
+        PrimitiveType::Float,
+        PrimitiveType::Reference,
+    ];
+
+    for primitive_type in single_slot_types {
+        assert_eq!(primitive_type.slot_count(), 1, "{:?}", primitive_type);
+        assert!(!primitive_type.is_category_two());
+    }
+
+    // Longs and doubles take up two slots
+    for primitive_type in [PrimitiveType::Long, PrimitiveType::Double] {
+        assert_eq!(primitive_type.slot_count(), 2, "{:?}", primitive_type);
+        assert!(primitive_type.is_category_two());
+    }
 }
 
-#[cfg(not(target_os = "windows"))]
+// Test Utils
+
+// The path of a test file, joined with the separator of the host platform.
 fn file_path(file_name: &str) -> String {
-    return format!("./src/java_tests/{}", file_name);
+    Path::new("src")
+        .join("java_tests")
+        .join(file_name)
+        .to_string_lossy()
+        .into_owned()
 }
 
 fn test_class(class_name: &str, expected: &str) {
@@ -88,7 +3757,7 @@ fn test_class(class_name: &str, expected: &str) {
 
     let class_name_and_path = file_path(class_name);
 
-    let classes = vec![class_file_parser::parse_file_to_class(class_name_and_path)];
+    let classes = vec![class_file_parser::parse_file_to_class(class_name_and_path).unwrap()];
 
     let mut jvm = jvm::Jvm::new(classes);
 
@@ -100,6 +3769,19 @@ fn test_class(class_name: &str, expected: &str) {
     assert!(jvm.stdout.eq(expected));
 }
 
+fn test_class_error(class_name: &str, expected_error: &str) {
+    println!("Running {} | Expected error {}", class_name, expected_error);
+
+    let classes = vec![class_file_parser::parse_file_to_class(file_path(class_name)).unwrap()];
+
+    let mut jvm = jvm::Jvm::new(classes);
+
+    match jvm.run() {
+        Ok(_) => panic!("{} ran without producing {}", class_name, expected_error),
+        Err(e) => assert!(e.contains(expected_error), "Unexpected error: {}", e),
+    };
+}
+
 fn test_class_set(class_names: Vec<&str>, expected: &str) {
     let mut classes = vec![];
 
@@ -111,7 +3793,7 @@ fn test_class_set(class_names: Vec<&str>, expected: &str) {
 
     for class_name in class_names {
         let class_name_and_path = file_path(class_name);
-        classes.push(class_file_parser::parse_file_to_class(class_name_and_path));
+        classes.push(class_file_parser::parse_file_to_class(class_name_and_path).unwrap());
     }
 
     let mut jvm = jvm::Jvm::new(classes);
@@ -148,3 +3830,67 @@ fn compile_and_run_test(class_name: &str, expected: &str) {
 
     assert!(jvm.stdout.eq(expected));
 }
+
+// Run a static method made of the passed instructions, returning its result.
+fn run_instructions(instructions: Vec<Instruction>) -> Result<Option<Primitive>, String> {
+    run_instructions_with_pool(instructions, Vec::new())
+}
+
+fn run_instructions_with_pool(
+    instructions: Vec<Instruction>,
+    constant_pool: Vec<ConstantPoolEntry>,
+) -> Result<Option<Primitive>, String> {
+    run_methods(vec![("test", instructions)], constant_pool)
+}
+
+// Run the method named test out of several methods made of the passed instructions.
+fn run_methods(
+    methods: Vec<(&str, Vec<Instruction>)>,
+    constant_pool: Vec<ConstantPoolEntry>,
+) -> Result<Option<Primitive>, String> {
+    let methods = methods
+        .into_iter()
+        .map(|(signature, instructions)| (signature, MethodFlags::Static as u16, instructions))
+        .collect();
+
+    run_methods_with_flags(methods, constant_pool)
+}
+
+// Run the method named test out of several methods with the passed access flags.
+fn run_methods_with_flags(
+    methods: Vec<(&str, u16, Vec<Instruction>)>,
+    constant_pool: Vec<ConstantPoolEntry>,
+) -> Result<Option<Primitive>, String> {
+    let methods = methods
+        .into_iter()
+        .map(|(signature, access_flags, instructions)| {
+            (
+                signature.to_string(),
+                Rc::new(jvm::Method {
+                    access_flags,
+                    instructions,
+                    stack_map: Vec::new(),
+                    exception_table: Vec::new(),
+                }),
+            )
+        })
+        .collect();
+
+    let class = jvm::Class {
+        name: String::from("Test"),
+        access_flags: ClassFlags::Public as u16,
+        super_class: Some(String::from("java/lang/Object")),
+        interfaces: Vec::new(),
+        bootstrap_methods: Vec::new(),
+        initialized: false,
+        constant_pool,
+        static_fields: HashMap::new(),
+        instance_fields: IndexMap::new(),
+        string_constants: HashMap::new(),
+        final_fields: HashSet::new(),
+        methods,
+        resolved_refs: HashMap::new(),
+    };
+
+    jvm::Jvm::new(vec![class]).invoke_static("Test", "test", Vec::new())
+}
@@ -0,0 +1,96 @@
+//! Cooperative threads. Only one thread runs at a time, and the scheduler hands the processor to
+//! the next thread in round-robin order once the running one has used up its time slice, blocked
+//! or finished.
+use crate::jvm::{Jvm, ObjectData, StackFrame};
+use crate::Primitive;
+use std::mem;
+
+/// A thread which is waiting for its turn, with the stack frames it will continue running.
+#[derive(Debug, Clone)]
+pub struct Thread {
+    pub id: usize,
+    pub stack_frames: Vec<StackFrame>,
+}
+
+impl Jvm {
+    /// Start a thread which calls `run()V` on the passed object, returning the new thread's id.
+    /// The thread gets its first turn after every thread which is already waiting.
+    pub fn start_thread(&mut self, runnable: usize) -> Result<usize, String> {
+        let data = match self.heap.get(runnable) {
+            Some(object) => object.data.clone(),
+            None => return Err(format!("Reference {} not found on heap", runnable)),
+        };
+
+        // The first frame of the thread is set up on an empty stack, which then becomes the
+        // thread's own
+        let caller_frames = mem::take(&mut self.stack_frames);
+
+        let result = match data {
+            ObjectData::Lambda(lambda) => self.invoke_lambda(lambda, Vec::new()),
+            _ => {
+                let class_name = self.heap[runnable].class_name.clone();
+
+                match self.find_method(&class_name, "run()V") {
+                    Some((class_name, method)) => {
+                        self.stack_frames.push(StackFrame {
+                            pc: 0,
+                            locals: vec![Primitive::Reference(runnable)],
+                            stack: Vec::new(),
+                            method,
+                            class_name,
+                        });
+                        Ok(())
+                    }
+                    None => Err(format!("Could not find method {}.run()V", class_name)),
+                }
+            }
+        };
+
+        let stack_frames = mem::replace(&mut self.stack_frames, caller_frames);
+        result?;
+
+        self.threads_started += 1;
+        self.threads.push(Thread {
+            id: self.threads_started,
+            stack_frames,
+        });
+
+        Ok(self.threads_started)
+    }
+
+    /// Whether the thread with the passed id has been started and hasn't finished yet.
+    pub fn is_thread_alive(&self, id: usize) -> bool {
+        id == self.thread_id || self.threads.iter().any(|thread| thread.id == id)
+    }
+
+    /// Switch to the next waiting thread if the running one has finished, yielded or used up its
+    /// time slice. Returns whether there is a thread left to run.
+    pub fn schedule(&mut self) -> bool {
+        let turn_length = self
+            .instructions_executed
+            .saturating_sub(self.turn_started_at);
+        let turn_over =
+            self.stack_frames.is_empty() || self.yield_requested || turn_length >= self.time_slice;
+
+        if turn_over {
+            if !self.threads.is_empty() {
+                let next = self.threads.remove(0);
+                let current = Thread {
+                    id: self.thread_id,
+                    stack_frames: mem::replace(&mut self.stack_frames, next.stack_frames),
+                };
+
+                // Finished threads are dropped, the others wait at the back of the queue
+                if !current.stack_frames.is_empty() {
+                    self.threads.push(current);
+                }
+                self.thread_id = next.id;
+            }
+
+            self.yield_requested = false;
+            self.turn_started_at = self.instructions_executed;
+        }
+
+        !self.stack_frames.is_empty()
+    }
+}